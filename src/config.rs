@@ -0,0 +1,524 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+use crate::db::SearchOptions;
+use crate::food::{UnknownUnit, Units};
+use crate::output::ConfirmStream;
+
+/// User settings, loaded once at startup and passed to whatever needs them.
+///
+/// Precedence, lowest to highest: built-in defaults, ~/.chomp/config.toml, then
+/// `CHOMP_<KEY>` environment variables (e.g. `CHOMP_LOG_MATCH=fuzzy`). Command-line
+/// flags like `--show-today` or `search --no-aliases` override all of these.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// Store foods per 100g on add when the serving weight is known
+    pub normalize_per_100g: bool,
+    /// How logging resolves a food name that has no exact or alias match
+    pub log_match: LogMatch,
+    /// Whether search matches aliases as well as names
+    pub search_aliases: bool,
+    /// Show the day's new totals after logging (same as --show-today)
+    pub show_today_after_log: bool,
+    /// Where confirmations like "Logged: ..." go: stdout, or stderr (same as --confirm-stderr)
+    pub confirmations: ConfirmStream,
+    /// How food names are shown in human-readable output
+    pub name_case: NameCase,
+    /// A macro to highlight in human-readable output: "protein", or "none"
+    pub emphasize: Emphasize,
+    /// Show calories next to macros adjusted for how the macros are rounded
+    pub reconcile_calories: bool,
+    /// Where `export` writes dated files when no --output is given (stdout if unset)
+    pub export_dir: Option<PathBuf>,
+    /// Round fractional counts of discrete units ("1.4 bars") to whole ones when logging
+    pub round_discrete: bool,
+    /// Search score bonus for foods logged recently (0 to rank by name match only)
+    pub search_recency_weight: f64,
+    /// Search score bonus for foods logged often (0 to rank by name match only)
+    pub search_frequency_weight: f64,
+    /// Serving for new foods added without one (`add` without --per, MCP `add_food`)
+    pub default_serving: String,
+    /// How many close matches a "Food not found" error suggests (0 for none)
+    pub suggestion_count: usize,
+    /// Log a bare food name at the amount last logged for it, when it has no default amount
+    pub remember_amount: bool,
+    /// What logging does with an amount in a unit it doesn't recognize
+    pub unknown_unit: UnknownUnit,
+    /// What logging to a date after today does (`log --future` allows it once)
+    pub future_dates: FutureDates,
+    /// Whether logging reads "a couple eggs" or "some rice" as a rough count
+    pub quantity_words: QuantityWords,
+    /// Bodyweight in pounds, for suggesting protein when the only goal is calories
+    pub bodyweight_lb: Option<f64>,
+    /// Grams of protein per pound of bodyweight in that suggestion
+    pub protein_per_lb: f64,
+    /// Database file to use instead of ~/.chomp/foods.db
+    pub db_path: Option<PathBuf>,
+    /// Keep a separate database per person, in ~/.chomp/profiles/<name>/foods.db
+    /// (ignored when db_path is set)
+    pub profile: Option<String>,
+    /// Time zone for "today", e.g. "America/New_York" (default: `TZ`, else the system's)
+    pub timezone: Option<String>,
+    /// Keep a record of every change (adds, logs, edits, deletes) for `chomp audit`
+    pub audit_log: bool,
+    /// Back up the database to a `backups` folder beside it before deletes, merges,
+    /// recomputes, and bulk edits
+    pub auto_backup_before_destructive: bool,
+    /// How many of those backups to keep, newest first
+    pub backups_kept: usize,
+    /// Refuse the MCP server's write tools (log_food, add_food), leaving lookups and totals
+    pub mcp_read_only: bool,
+    /// File `chomp serve` appends a line to for every request, with any error
+    pub mcp_log_file: Option<PathBuf>,
+    /// Extra unit conversions in grams per unit, e.g. `scoop = 32`, over the built-in ones
+    pub units: Units,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            normalize_per_100g: false,
+            log_match: LogMatch::default(),
+            search_aliases: true,
+            show_today_after_log: false,
+            confirmations: ConfirmStream::default(),
+            name_case: NameCase::default(),
+            emphasize: Emphasize::default(),
+            reconcile_calories: false,
+            export_dir: None,
+            round_discrete: false,
+            search_recency_weight: SearchOptions::default().recency_weight,
+            search_frequency_weight: SearchOptions::default().frequency_weight,
+            default_serving: "100g".to_string(),
+            suggestion_count: 3,
+            remember_amount: false,
+            unknown_unit: UnknownUnit::default(),
+            future_dates: FutureDates::default(),
+            quantity_words: QuantityWords::default(),
+            bodyweight_lb: None,
+            protein_per_lb: 1.0,
+            db_path: None,
+            profile: None,
+            timezone: None,
+            audit_log: false,
+            auto_backup_before_destructive: false,
+            backups_kept: 10,
+            mcp_read_only: false,
+            mcp_log_file: None,
+            units: Units::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogMatch {
+    /// Only exact names and aliases; anything else is an error
+    #[default]
+    Exact,
+    /// Fall back to the best fuzzy search match
+    Fuzzy,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NameCase {
+    /// As the name was entered
+    #[default]
+    Stored,
+    /// Title Case, whatever the stored casing
+    Title,
+}
+
+impl NameCase {
+    /// Format a food name for display. Matching and storage are unaffected.
+    pub fn apply(self, name: &str) -> String {
+        match self {
+            NameCase::Stored => name.to_string(),
+            NameCase::Title => crate::food::title_case(name),
+        }
+    }
+}
+
+/// A macro to make stand out wherever macros are listed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Emphasize {
+    #[default]
+    None,
+    /// Protein (always listed first) in bold, when the terminal shows color
+    Protein,
+}
+
+/// What to do when an entry is logged to a date after today. Backdating is
+/// common; a future date is usually a typo, except when planning meals.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FutureDates {
+    /// Refuse unless `--future` is given
+    #[default]
+    Reject,
+    /// Log it, with a warning
+    Warn,
+    /// Log it quietly
+    Allow,
+}
+
+/// Vague quantities at the start of a food ("a couple eggs"). They're guesses,
+/// so they're off unless asked for, and an entry logged from one says so.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum QuantityWords {
+    /// Take them as part of the food name
+    #[default]
+    Off,
+    /// Read them as a count, with a note
+    On,
+    /// As `On`, but ask before logging when there's a terminal to ask on
+    Confirm,
+}
+
+impl Config {
+    /// Every settable key, in file order
+    pub const KEYS: &'static [&'static str] = &[
+        "normalize_per_100g",
+        "log_match",
+        "search_aliases",
+        "show_today_after_log",
+        "confirmations",
+        "name_case",
+        "emphasize",
+        "reconcile_calories",
+        "export_dir",
+        "round_discrete",
+        "search_recency_weight",
+        "search_frequency_weight",
+        "default_serving",
+        "suggestion_count",
+        "remember_amount",
+        "unknown_unit",
+        "future_dates",
+        "quantity_words",
+        "bodyweight_lb",
+        "protein_per_lb",
+        "db_path",
+        "profile",
+        "timezone",
+        "audit_log",
+        "auto_backup_before_destructive",
+        "backups_kept",
+        "mcp_read_only",
+        "mcp_log_file",
+        "units",
+    ];
+
+    /// Current value of `key` as it would appear in the file, or None if unset
+    pub fn get(&self, key: &str) -> Result<Option<String>> {
+        check_key(key)?;
+        let table = toml::Table::try_from(self)?;
+        Ok(table.get(key).map(|value| match value {
+            toml::Value::String(s) => s.clone(),
+            other => other.to_string(),
+        }))
+    }
+
+    pub fn search_options(&self) -> SearchOptions {
+        SearchOptions {
+            include_aliases: self.search_aliases,
+            recency_weight: self.search_recency_weight,
+            frequency_weight: self.search_frequency_weight,
+            ..SearchOptions::default()
+        }
+    }
+
+    /// `export_dir` with a leading "~/" expanded to the home directory
+    pub fn export_dir(&self) -> Option<PathBuf> {
+        self.export_dir.as_deref().map(expand_home)
+    }
+
+    /// The database file: `db_path` (with "~/" expanded) if set, else the profile's,
+    /// else the default
+    pub fn db_path(&self) -> Result<PathBuf> {
+        match (&self.db_path, &self.profile) {
+            (Some(path), _) => Ok(expand_home(path)),
+            (None, Some(profile)) => {
+                anyhow::ensure!(
+                    !profile.is_empty() && !profile.contains(['/', '\\']) && profile != "." && profile != "..",
+                    "Invalid profile '{}': use a plain name like \"sam\"", profile
+                );
+                let default = crate::db::Database::default_path()?;
+                let dir = default.parent().unwrap_or(std::path::Path::new("."));
+                Ok(dir.join("profiles").join(profile).join("foods.db"))
+            }
+            (None, None) => crate::db::Database::default_path(),
+        }
+    }
+
+    /// Where automatic backups go: `backups` next to the database (~/.chomp/backups)
+    pub fn backup_dir(&self) -> Result<PathBuf> {
+        let db_path = self.db_path()?;
+        Ok(db_path.parent().unwrap_or(std::path::Path::new(".")).join("backups"))
+    }
+
+    /// `mcp_log_file` with a leading "~/" expanded to the home directory
+    pub fn mcp_log_file(&self) -> Option<PathBuf> {
+        self.mcp_log_file.as_deref().map(expand_home)
+    }
+
+    /// Load the config file (defaults if it doesn't exist) with environment overrides applied
+    pub fn load() -> Result<Self> {
+        let path = Self::path()?;
+        let contents = if path.exists() { std::fs::read_to_string(&path)? } else { String::new() };
+        let table: toml::Table = toml::from_str(&contents)
+            .map_err(|e| anyhow::anyhow!("Invalid config file {}: {}", path.display(), e))?;
+        // Validate the file on its own first so errors point at the right place
+        let _: Config = table.clone().try_into()
+            .map_err(|e| anyhow::anyhow!("Invalid config file {}: {}", path.display(), e))?;
+        merge(table, std::env::vars())
+    }
+
+    /// Environment variable that overrides `key`, e.g. "CHOMP_LOG_MATCH"
+    pub fn env_var(key: &str) -> String {
+        format!("CHOMP_{}", key.to_uppercase())
+    }
+
+    pub fn path() -> Result<PathBuf> {
+        let home = dirs::home_dir().ok_or_else(|| anyhow::anyhow!("Could not find home directory"))?;
+        Ok(home.join(".chomp").join("config.toml"))
+    }
+
+    /// Set `key` in the config file, keeping its other settings, comments, and
+    /// layout. The value is validated against the typed config first, so a bad value
+    /// never reaches the file. Returns the resulting config.
+    pub fn set(key: &str, value: &str) -> Result<Self> {
+        let path = Self::path()?;
+        let contents = if path.exists() { std::fs::read_to_string(&path)? } else { String::new() };
+        let (contents, config) = set_in_toml(&contents, key, value)?;
+
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&path, contents)?;
+        Ok(config)
+    }
+}
+
+/// `path` with a leading "~/" expanded to the home directory
+fn expand_home(path: &std::path::Path) -> PathBuf {
+    match (path.strip_prefix("~"), dirs::home_dir()) {
+        (Ok(rest), Some(home)) => home.join(rest),
+        _ => path.to_path_buf(),
+    }
+}
+
+fn check_key(key: &str) -> Result<()> {
+    anyhow::ensure!(
+        Config::KEYS.contains(&key),
+        "Unknown config key '{}' (known keys: {})", key, Config::KEYS.join(", ")
+    );
+    Ok(())
+}
+
+/// Read a setting typed on the command line or in the environment as a TOML value
+/// (`true`, `3`, `"x"`), falling back to a plain string so `fuzzy` and `"fuzzy"` both work
+fn parse_value(value: &str) -> toml::Value {
+    toml::from_str::<toml::Table>(&format!("value = {}", value))
+        .ok()
+        .and_then(|mut t| t.remove("value"))
+        .unwrap_or_else(|| toml::Value::String(value.to_string()))
+}
+
+/// Apply `CHOMP_<KEY>` overrides from `env` on top of the file's settings
+fn merge(mut table: toml::Table, env: impl IntoIterator<Item = (String, String)>) -> Result<Config> {
+    let env: std::collections::HashMap<String, String> = env.into_iter().collect();
+    for key in Config::KEYS {
+        let var = Config::env_var(key);
+        if let Some(value) = env.get(&var) {
+            table.insert(key.to_string(), parse_value(value));
+            // Checked one at a time so the error names the variable
+            let _: Config = table.clone().try_into()
+                .map_err(|e| anyhow::anyhow!("Invalid value in {}: {}", var, e))?;
+        }
+    }
+    Ok(table.try_into()?)
+}
+
+/// Set `key` to `value` in the TOML document `contents`, editing it in place so
+/// comments and key order survive, and check the result is a valid config
+fn set_in_toml(contents: &str, key: &str, value: &str) -> Result<(String, Config)> {
+    check_key(key)?;
+    let mut doc: toml_edit::DocumentMut = contents.parse()
+        .map_err(|e| anyhow::anyhow!("Invalid config file: {}", e))?;
+    // As `parse_value` reads it: a TOML value, or else a plain string
+    let mut new: toml_edit::Value = value.parse().unwrap_or_else(|_| value.into());
+    // A comment after the old value stays after the new one
+    if let Some(old) = doc.get(key).and_then(toml_edit::Item::as_value) {
+        *new.decor_mut() = old.decor().clone();
+    }
+    doc[key] = toml_edit::Item::Value(new);
+
+    let contents = doc.to_string();
+    let config: Config = toml::from_str(&contents)
+        .map_err(|e| anyhow::anyhow!("Invalid value for {}: {}", key, e))?;
+    Ok((contents, config))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_config() {
+        let config: Config = toml::from_str("normalize_per_100g = true").unwrap();
+        assert!(config.normalize_per_100g);
+
+        let config: Config = toml::from_str("").unwrap();
+        assert!(!config.normalize_per_100g);
+        assert_eq!(config.log_match, LogMatch::Exact);
+        assert!(config.search_aliases);
+
+        let config: Config = toml::from_str("log_match = \"fuzzy\"").unwrap();
+        assert_eq!(config.log_match, LogMatch::Fuzzy);
+        assert!(toml::from_str::<Config>("log_match = \"sometimes\"").is_err());
+
+        let config: Config = toml::from_str("export_dir = \"/tmp/chomp\"").unwrap();
+        assert_eq!(config.export_dir(), Some(PathBuf::from("/tmp/chomp")));
+        assert_eq!(Config::default().export_dir(), None);
+
+        let config: Config = toml::from_str("[units]\nscoop = 32").unwrap();
+        assert_eq!(config.units.grams_per("scoops"), Some(32.0));
+        assert!(toml::from_str::<Config>("[units]\nscoop = 0").is_err());
+
+        let config: Config = toml::from_str("name_case = \"title\"").unwrap();
+        assert_eq!(config.name_case.apply("chicken breast"), "Chicken Breast");
+        assert_eq!(Config::default().name_case.apply("chicken breast"), "chicken breast");
+    }
+
+    #[test]
+    fn test_keys_cover_config() {
+        let config = Config {
+            export_dir: Some(PathBuf::from("x")),
+            bodyweight_lb: Some(1.0),
+            db_path: Some(PathBuf::from("x")),
+            profile: Some("x".to_string()),
+            timezone: Some("x".to_string()),
+            mcp_log_file: Some(PathBuf::from("x")),
+            ..Config::default()
+        };
+        let table = toml::Table::try_from(&config).unwrap();
+        let mut keys: Vec<&str> = table.keys().map(String::as_str).collect();
+        let mut expected = Config::KEYS.to_vec();
+        keys.sort();
+        expected.sort();
+        assert_eq!(keys, expected);
+    }
+
+    #[test]
+    fn test_set_round_trip() {
+        let (contents, config) = set_in_toml("", "log_match", "fuzzy").unwrap();
+        assert_eq!(config.log_match, LogMatch::Fuzzy);
+        let (contents, config) = set_in_toml(&contents, "normalize_per_100g", "true").unwrap();
+        assert!(config.normalize_per_100g);
+        assert_eq!(config.log_match, LogMatch::Fuzzy);
+        let (contents, _) = set_in_toml(&contents, "export_dir", "~/exports").unwrap();
+
+        let reloaded: Config = toml::from_str(&contents).unwrap();
+        assert_eq!(reloaded.get("log_match").unwrap().as_deref(), Some("fuzzy"));
+        assert_eq!(reloaded.get("normalize_per_100g").unwrap().as_deref(), Some("true"));
+        assert_eq!(reloaded.get("export_dir").unwrap().as_deref(), Some("~/exports"));
+        assert_eq!(Config::default().get("export_dir").unwrap(), None);
+    }
+
+    #[test]
+    fn test_merge_precedence() {
+        let env = |pairs: &[(&str, &str)]| -> Vec<(String, String)> {
+            pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+        };
+        let file: toml::Table = toml::from_str("log_match = \"fuzzy\"\nsearch_aliases = false").unwrap();
+
+        // Defaults < file
+        let config = merge(file.clone(), env(&[])).unwrap();
+        assert_eq!(config.log_match, LogMatch::Fuzzy);
+        assert!(!config.search_aliases);
+        assert_eq!(config.name_case, NameCase::Stored);
+
+        // File < env; unrelated variables are ignored
+        let config = merge(file.clone(), env(&[
+            ("CHOMP_LOG_MATCH", "exact"),
+            ("CHOMP_NAME_CASE", "title"),
+            ("HOME", "/root"),
+        ])).unwrap();
+        assert_eq!(config.log_match, LogMatch::Exact);
+        assert_eq!(config.name_case, NameCase::Title);
+        assert!(!config.search_aliases);
+
+        let err = merge(file, env(&[("CHOMP_LOG_MATCH", "sometimes")])).unwrap_err();
+        assert!(err.to_string().contains("CHOMP_LOG_MATCH"));
+    }
+
+    #[test]
+    fn test_serve_settings_from_env() {
+        // Claude Desktop starts `chomp serve` with an `env` block and no flags
+        let env: Vec<(String, String)> = [
+            ("CHOMP_DB_PATH", "/data/chomp/foods.db"),
+            ("CHOMP_MCP_READ_ONLY", "true"),
+            ("CHOMP_MCP_LOG_FILE", "~/chomp-mcp.log"),
+            ("CHOMP_TIMEZONE", "America/New_York"),
+        ].iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+
+        let defaults = merge(toml::Table::new(), vec![]).unwrap();
+        assert!(!defaults.mcp_read_only);
+        assert_eq!(defaults.db_path().unwrap(), crate::db::Database::default_path().unwrap());
+        assert_eq!(defaults.mcp_log_file(), None);
+
+        let file: toml::Table = toml::from_str("db_path = \"/elsewhere.db\"\nmcp_read_only = false").unwrap();
+        let config = merge(file, env).unwrap();
+        assert_eq!(config.db_path().unwrap(), PathBuf::from("/data/chomp/foods.db"));
+        assert!(config.mcp_read_only);
+        let log = config.mcp_log_file().unwrap();
+        assert!(log.ends_with("chomp-mcp.log") && !log.starts_with("~"));
+        assert_eq!(config.timezone.as_deref(), Some("America/New_York"));
+
+        // A profile picks its own database, unless db_path names one outright
+        let profile = merge(toml::Table::new(), vec![("CHOMP_PROFILE".to_string(), "sam".to_string())]).unwrap();
+        assert!(profile.db_path().unwrap().ends_with(".chomp/profiles/sam/foods.db"));
+        let file: toml::Table = toml::from_str("db_path = \"/elsewhere.db\"").unwrap();
+        let both = merge(file, vec![("CHOMP_PROFILE".to_string(), "sam".to_string())]).unwrap();
+        assert_eq!(both.db_path().unwrap(), PathBuf::from("/elsewhere.db"));
+        let bad = merge(toml::Table::new(), vec![("CHOMP_PROFILE".to_string(), "../sam".to_string())]).unwrap();
+        assert!(bad.db_path().is_err());
+
+        let err = merge(toml::Table::new(), vec![("CHOMP_MCP_READ_ONLY".to_string(), "sure".to_string())]).unwrap_err();
+        assert!(err.to_string().contains("CHOMP_MCP_READ_ONLY"));
+    }
+
+    #[test]
+    fn test_set_rejects_invalid_values() {
+        assert!(set_in_toml("", "log_match", "sometimes").is_err());
+        assert!(set_in_toml("", "search_aliases", "maybe").is_err());
+        assert!(set_in_toml("", "colour", "blue").is_err());
+        assert!(Config::default().get("colour").is_err());
+
+        // A rejected value leaves the existing document untouched
+        let (contents, _) = set_in_toml("", "name_case", "title").unwrap();
+        assert!(set_in_toml(&contents, "name_case", "shouty").is_err());
+        let config: Config = toml::from_str(&contents).unwrap();
+        assert_eq!(config.name_case, NameCase::Title);
+    }
+
+    #[test]
+    fn test_set_keeps_comments() {
+        let contents = "# My settings\n\n# how names match when logging\nlog_match = \"exact\"  # strict for now\nsuggestion_count = 3\n";
+        let (contents, config) = set_in_toml(contents, "log_match", "fuzzy").unwrap();
+        assert_eq!(config.log_match, LogMatch::Fuzzy);
+        assert_eq!(contents,
+            "# My settings\n\n# how names match when logging\nlog_match = \"fuzzy\"  # strict for now\nsuggestion_count = 3\n");
+
+        // A new key goes at the end, leaving the rest alone
+        let (contents, _) = set_in_toml(&contents, "search_aliases", "false").unwrap();
+        assert!(contents.starts_with("# My settings\n\n# how names match when logging\n"), "{}", contents);
+        assert!(contents.ends_with("suggestion_count = 3\nsearch_aliases = false\n"), "{}", contents);
+    }
+}
@@ -1,18 +1,211 @@
 use anyhow::Result;
-use chrono::{Local, NaiveDate};
+use chrono::{Datelike, Local, NaiveDate, NaiveTime, Timelike, Weekday};
 use fuzzy_matcher::skim::SkimMatcherV2;
 use fuzzy_matcher::FuzzyMatcher;
 use rusqlite::{params, Connection};
 use serde::{Deserialize, Serialize};
 
-use crate::food::{Food, Macros};
+use crate::food::{atwater_calories, format_number, Food, FoodSource, Macros, Nutrient};
+use crate::recipe::{Recipe, RecipeItem};
 
 pub struct Database {
     conn: Connection,
+    /// Record each change in the audit table (`audit_log` in the config)
+    audit: bool,
+}
+
+/// How long a single statement waits on a locked database
+const BUSY_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(2);
+/// How many more times a write is tried when the database stays locked
+const BUSY_RETRIES: u32 = 3;
+
+/// Whether an error (or anything in its chain) is SQLite reporting a busy or locked database
+pub fn is_busy_error(err: &anyhow::Error) -> bool {
+    err.chain().any(|e| {
+        matches!(
+            e.downcast_ref::<rusqlite::Error>(),
+            Some(rusqlite::Error::SqliteFailure(failure, _))
+                if matches!(failure.code, rusqlite::ErrorCode::DatabaseBusy | rusqlite::ErrorCode::DatabaseLocked)
+        )
+    })
+}
+
+/// A busy database reported as something to act on; other errors unchanged
+pub fn explain_busy(err: anyhow::Error) -> anyhow::Error {
+    if is_busy_error(&err) {
+        anyhow::anyhow!("database is busy (is `chomp serve` running?); try again in a moment")
+    } else {
+        err
+    }
+}
+
+/// A scratch directory for tests that need real database files, removed with
+/// everything in it (WAL and shared-memory files too) when dropped
+#[cfg(test)]
+pub struct TempDir(std::path::PathBuf);
+
+#[cfg(test)]
+impl TempDir {
+    /// A fresh, empty directory unique to this test and process
+    pub fn new(name: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!("chomp-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        TempDir(dir)
+    }
+
+    pub fn join(&self, file: &str) -> std::path::PathBuf {
+        self.0.join(file)
+    }
+}
+
+#[cfg(test)]
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+/// Schema changes applied in order on top of the tables created in `init`.
+/// `PRAGMA user_version` records how many have run; only ever append.
+const MIGRATIONS: &[&str] = &[
+    // 1: food density (g/ml) for mass <-> volume conversion
+    "ALTER TABLE foods ADD COLUMN density REAL;",
+    // 2: daily and weekly targets, a single current row
+    "CREATE TABLE goals (
+        id INTEGER PRIMARY KEY CHECK (id = 1),
+        protein REAL,
+        fat REAL,
+        carbs REAL,
+        calories REAL,
+        weekly_calories REAL
+    );",
+    // 3: per-weekday goal overrides; weekday is 0 = Monday .. 6 = Sunday
+    "CREATE TABLE goal_overrides (
+        weekday INTEGER PRIMARY KEY CHECK (weekday BETWEEN 0 AND 6),
+        protein REAL,
+        fat REAL,
+        carbs REAL,
+        calories REAL
+    );",
+    // 4: time of day ("HH:MM") and meal label, for ordering entries within a day
+    "ALTER TABLE log ADD COLUMN time TEXT;
+     ALTER TABLE log ADD COLUMN meal TEXT;",
+    // 5: entries logged together ("3 eggs + 2 toast") share the id of the first one
+    "ALTER TABLE log ADD COLUMN group_id INTEGER;",
+    // 6: where each food came from (manual, usda, csv, mfp, cronometer)
+    "ALTER TABLE foods ADD COLUMN source TEXT NOT NULL DEFAULT 'manual';",
+    // 7: how amounts convert: through weights and volumes (auto) or by counting servings
+    "ALTER TABLE foods ADD COLUMN unit_mode TEXT NOT NULL DEFAULT 'auto';",
+    // 8: every change to the default goals, from the date it took effect; a row with
+    // `cleared` set means no goals. Goals set before this start today.
+    "CREATE TABLE goal_history (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        effective_date TEXT NOT NULL,
+        changed_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+        cleared INTEGER NOT NULL DEFAULT 0,
+        protein REAL,
+        fat REAL,
+        carbs REAL,
+        calories REAL,
+        weekly_calories REAL
+    );
+     INSERT INTO goal_history (effective_date, protein, fat, carbs, calories, weekly_calories)
+        SELECT date('now', 'localtime'), protein, fat, carbs, calories, weekly_calories FROM goals;",
+    // 9: append-only record of changes, written only with `audit_log` on
+    "CREATE TABLE audit (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+        action TEXT NOT NULL,
+        payload TEXT NOT NULL
+    );",
+    // 10: saved recipes, each ingredient with the macros it resolved to
+    "CREATE TABLE recipes (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        name TEXT NOT NULL UNIQUE,
+        servings REAL NOT NULL,
+        created_at TEXT DEFAULT CURRENT_TIMESTAMP
+    );
+     CREATE TABLE recipe_items (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        recipe_id INTEGER NOT NULL,
+        food_id INTEGER NOT NULL,
+        amount TEXT NOT NULL,
+        protein REAL NOT NULL,
+        fat REAL NOT NULL,
+        carbs REAL NOT NULL,
+        calories REAL NOT NULL,
+        FOREIGN KEY (recipe_id) REFERENCES recipes(id) ON DELETE CASCADE
+    );",
+    // 11: grams of fiber, for net carbs; zero where it was never given
+    "ALTER TABLE foods ADD COLUMN fiber REAL NOT NULL DEFAULT 0;
+     ALTER TABLE log ADD COLUMN fiber REAL NOT NULL DEFAULT 0;
+     ALTER TABLE recipe_items ADD COLUMN fiber REAL NOT NULL DEFAULT 0;",
+    // 12: a food's own count units as JSON ({"bar": 60}), kept when a "1 bar"
+    // serving is normalized to per-100g
+    "ALTER TABLE foods ADD COLUMN units TEXT;",
+];
+
+/// Columns selected for a `Food`, in the order `food_from_row` expects.
+/// Queries must alias the foods table as `f`.
+const FOOD_COLUMNS: &str =
+    "f.id, f.name, f.protein, f.fat, f.carbs, f.calories, f.serving, f.default_amount, f.density, f.source,
+     f.unit_mode, f.fiber, f.units";
+
+fn food_from_row(row: &rusqlite::Row) -> rusqlite::Result<Food> {
+    Ok(Food {
+        id: Some(row.get(0)?),
+        name: row.get(1)?,
+        protein: row.get(2)?,
+        fat: row.get(3)?,
+        carbs: row.get(4)?,
+        calories: row.get(5)?,
+        serving: row.get(6)?,
+        default_amount: row.get(7)?,
+        density: row.get(8)?,
+        source: row.get::<_, String>(9)?.parse().unwrap_or_default(),
+        unit_mode: row.get::<_, String>(10)?.parse().unwrap_or_default(),
+        fiber: row.get(11)?,
+        units: row.get::<_, Option<String>>(12)?
+            .and_then(|units| serde_json::from_str(&units).ok())
+            .unwrap_or_default(),
+        aliases: vec![],
+    })
+}
+
+/// A food's own units as stored, NULL when it has none
+fn units_json(units: &crate::food::Units) -> Result<Option<String>> {
+    Ok(if units.is_empty() { None } else { Some(serde_json::to_string(units)?) })
+}
+
+/// Columns selected for a `LogEntry`, in the order `log_entry_from_row` expects.
+/// Queries must alias the log table as `l` and join foods as `f`.
+const LOG_COLUMNS: &str =
+    "l.id, l.date, f.name, l.food_id, l.amount, l.protein, l.fat, l.carbs, l.calories, l.time, l.meal, l.group_id,
+     l.fiber, l.created_at";
+
+fn log_entry_from_row(row: &rusqlite::Row) -> rusqlite::Result<LogEntry> {
+    Ok(LogEntry {
+        id: Some(row.get(0)?),
+        date: row.get(1)?,
+        food_name: row.get(2)?,
+        food_id: row.get(3)?,
+        amount: row.get(4)?,
+        protein: row.get(5)?,
+        fat: row.get(6)?,
+        carbs: row.get(7)?,
+        calories: row.get(8)?,
+        time: row.get(9)?,
+        meal: row.get::<_, Option<String>>(10)?.and_then(|m| m.parse().ok()),
+        group_id: row.get(11)?,
+        fiber: row.get(12)?,
+        created_at: row.get(13)?,
+    })
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct LogEntry {
+    #[serde(default)]
     pub id: Option<i64>,
     pub date: String,
     pub food_name: String,
@@ -22,6 +215,331 @@ pub struct LogEntry {
     pub fat: f64,
     pub carbs: f64,
     pub calories: f64,
+    /// Grams of fiber, counted within `carbs`
+    #[serde(default)]
+    pub fiber: f64,
+    /// Time of day eaten, "HH:MM"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub time: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub meal: Option<Meal>,
+    /// Shared by entries logged together in one combo, the id of its first entry
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub group_id: Option<i64>,
+    /// When the entry was written (UTC), which tells a restored entry from a new one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created_at: Option<String>,
+}
+
+impl LogEntry {
+    pub fn macros(&self) -> Macros {
+        Macros {
+            protein: self.protein,
+            fat: self.fat,
+            carbs: self.carbs,
+            calories: self.calories,
+            fiber: self.fiber,
+        }
+    }
+}
+
+/// Meal label for a log entry
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Meal {
+    Breakfast,
+    Lunch,
+    Dinner,
+    Snack,
+}
+
+impl Meal {
+    /// Typical time of day for the meal, used when no time is given (snacks have none)
+    pub fn default_time(self) -> Option<NaiveTime> {
+        match self {
+            Meal::Breakfast => NaiveTime::from_hms_opt(8, 0, 0),
+            Meal::Lunch => NaiveTime::from_hms_opt(12, 30, 0),
+            Meal::Dinner => NaiveTime::from_hms_opt(18, 30, 0),
+            Meal::Snack => None,
+        }
+    }
+}
+
+impl std::fmt::Display for Meal {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(match self {
+            Meal::Breakfast => "breakfast",
+            Meal::Lunch => "lunch",
+            Meal::Dinner => "dinner",
+            Meal::Snack => "snack",
+        })
+    }
+}
+
+impl std::str::FromStr for Meal {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "breakfast" => Ok(Meal::Breakfast),
+            "lunch" => Ok(Meal::Lunch),
+            "dinner" => Ok(Meal::Dinner),
+            "snack" => Ok(Meal::Snack),
+            _ => Err(format!("unknown meal '{}' (expected breakfast, lunch, dinner, or snack)", s)),
+        }
+    }
+}
+
+/// When an entry is logged: the date, plus an optional time of day and meal
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LogMeta {
+    pub date: NaiveDate,
+    pub time: Option<NaiveTime>,
+    pub meal: Option<Meal>,
+}
+
+impl LogMeta {
+    /// A date with no time or meal
+    #[cfg(test)]
+    pub fn on(date: NaiveDate) -> Self {
+        Self { date, time: None, meal: None }
+    }
+
+    /// Today at the current minute
+    pub fn now() -> Self {
+        let now = Local::now().naive_local();
+        Self {
+            date: now.date(),
+            time: now.time().with_second(0).and_then(|t| t.with_nanosecond(0)),
+            meal: None,
+        }
+    }
+}
+
+/// Nutrition targets. Unset fields have no target.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Goals {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub protein: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fat: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub carbs: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub calories: Option<f64>,
+    /// Calorie budget for a Monday–Sunday week
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub weekly_calories: Option<f64>,
+}
+
+impl Goals {
+    /// These goals with any fields set in `other` taking their place
+    pub fn overridden_by(&self, other: &Goals) -> Goals {
+        Goals {
+            protein: other.protein.or(self.protein),
+            fat: other.fat.or(self.fat),
+            carbs: other.carbs.or(self.carbs),
+            calories: other.calories.or(self.calories),
+            weekly_calories: other.weekly_calories.or(self.weekly_calories),
+        }
+    }
+}
+
+/// One recorded change from the audit table
+#[derive(Debug, Clone, Serialize)]
+pub struct AuditEntry {
+    pub id: i64,
+    /// When, "YYYY-MM-DD HH:MM:SS" UTC
+    pub at: String,
+    /// What kind of change: add_food, edit_food, log, unlog, ...
+    pub action: String,
+    /// What changed, as JSON
+    pub payload: serde_json::Value,
+}
+
+/// A change to the default goals: new ones, or all of them cleared
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GoalChange {
+    /// First day the change applies to, YYYY-MM-DD
+    pub effective_date: String,
+    /// When it was made, "YYYY-MM-DD HH:MM:SS" UTC
+    pub changed_at: String,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub cleared: bool,
+    #[serde(flatten)]
+    pub goals: Goals,
+}
+
+/// Goals that replace the defaults on one weekday (e.g. more carbs on training days)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DayGoals {
+    pub weekday: Weekday,
+    #[serde(flatten)]
+    pub goals: Goals,
+}
+
+/// Summed macros for one date
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DayTotals {
+    pub date: String,
+    #[serde(flatten)]
+    pub totals: Macros,
+}
+
+/// Foods (with aliases) and log entries, as written by `export --format json`
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Backup {
+    pub foods: Vec<Food>,
+    pub log: Vec<LogEntry>,
+}
+
+/// JSON accepted by `import json`: a full backup, or the bare log array
+/// that older versions of `export --format json` wrote
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum BackupFile {
+    Full(Backup),
+    Entries(Vec<LogEntry>),
+}
+
+/// First date of a `days`-long history window that ends with (and includes) `today`,
+/// or None when that reaches back past the earliest representable date (i.e. all history)
+pub fn history_start(today: NaiveDate, days: u32) -> Option<NaiveDate> {
+    today.checked_sub_days(chrono::Days::new(days.saturating_sub(1) as u64))
+}
+
+/// First and last dates for `history`: an explicit `since`/`until` range when given,
+/// otherwise `days` days ending with `until` (or today). None means unbounded.
+pub fn history_window(
+    today: NaiveDate,
+    days: u32,
+    since: Option<NaiveDate>,
+    until: Option<NaiveDate>,
+) -> Result<(Option<NaiveDate>, Option<NaiveDate>)> {
+    if let (Some(since), Some(until)) = (since, until) {
+        anyhow::ensure!(since <= until, "--since ({}) must not be after --until ({})", since, until);
+    }
+    Ok(match (since, until) {
+        (Some(since), until) => (Some(since), until),
+        (None, Some(until)) => (history_start(until, days), Some(until)),
+        (None, None) => (history_start(today, days), None),
+    })
+}
+
+/// File name `export` uses inside `export_dir`, e.g. "chomp-export-2024-06-01.csv"
+pub fn export_filename(format: &str, date: NaiveDate) -> String {
+    let extension = match format {
+        "text" => "txt",
+        "sqlite" => "db",
+        format => format,
+    };
+    format!("chomp-export-{}.{}", date.format("%Y-%m-%d"), extension)
+}
+
+/// Placeholder food that `chomp quick` logs bare calories against: 1 kcal per serving, no macros
+pub const QUICK_ADD_FOOD: &str = "Quick Add (calories)";
+
+/// Version of the `chomp dump` document layout. Bump on incompatible changes.
+pub const DUMP_FORMAT_VERSION: u32 = 1;
+
+/// A complete, portable copy of the database written by `chomp dump`.
+/// Sections added after format 1 must default when missing so older dumps load.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Dump {
+    pub format_version: u32,
+    /// Number of schema migrations applied in the database it came from
+    #[serde(default)]
+    pub schema_version: usize,
+    #[serde(default)]
+    pub foods: Vec<Food>,
+    #[serde(default)]
+    pub log: Vec<LogEntry>,
+    #[serde(default)]
+    pub goals: Option<Goals>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub goal_overrides: Vec<DayGoals>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub goal_history: Vec<GoalChange>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub recipes: Vec<Recipe>,
+}
+
+#[derive(Debug, Clone)]
+pub struct SearchOptions {
+    /// Match the query against aliases as well as names
+    pub include_aliases: bool,
+    /// Points added to the fuzzy score for a food logged today, scaled by
+    /// 1 / (1 + days since) for older ones (yesterday gets half, two days ago a third)
+    pub recency_weight: f64,
+    /// Points added per natural-log unit of how many times a food has been logged
+    pub frequency_weight: f64,
+    /// Only foods from this source
+    pub source: Option<FoodSource>,
+    /// Match the query against aliases and not names, leaving out foods without one
+    pub aliases_only: bool,
+}
+
+impl Default for SearchOptions {
+    fn default() -> Self {
+        Self { include_aliases: true, recency_weight: 20.0, frequency_weight: 5.0, source: None, aliases_only: false }
+    }
+}
+
+/// A food found by search, with the alias it matched by if that scored best
+#[derive(Debug, Clone, Serialize)]
+pub struct SearchMatch {
+    #[serde(flatten)]
+    pub food: Food,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub matched_alias: Option<String>,
+}
+
+/// Extra search score for a food logged `count` times, most recently on `last`
+fn usage_boost(count: i64, last: Option<NaiveDate>, today: NaiveDate, options: &SearchOptions) -> f64 {
+    let recency = match last {
+        Some(last) => 1.0 / (1.0 + (today - last).num_days().max(0) as f64),
+        None => 0.0,
+    };
+    options.recency_weight * recency + options.frequency_weight * (count as f64).ln_1p()
+}
+
+/// What `chomp info` reports
+#[derive(Debug, Serialize)]
+pub struct Info {
+    pub version: &'static str,
+    pub db_path: std::path::PathBuf,
+    /// Number of schema migrations applied
+    pub schema_version: usize,
+    pub food_count: i64,
+    pub log_count: i64,
+    pub config_path: std::path::PathBuf,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct ImportSummary {
+    pub foods_added: usize,
+    pub foods_existing: usize,
+    pub log_entries: usize,
+    /// Backup entries already in the log, so not added again
+    pub log_skipped: usize,
+    /// Aliases left out by `dedupe_aliases` because another food has them, and why
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub aliases_skipped: Vec<String>,
+}
+
+/// What `merge_foods` did
+#[derive(Debug, Serialize)]
+pub struct MergeSummary {
+    pub from: String,
+    pub into: String,
+    /// Log entries re-pointed at `into`
+    pub log_entries: usize,
+    /// Recipe ingredients re-pointed at `into`
+    pub recipe_items: usize,
+    /// Aliases now on `into`, including `from`'s old name
+    pub aliases_moved: Vec<String>,
+    /// Aliases not moved because `into` already answers to them, or another food owns them
+    pub aliases_dropped: Vec<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -30,22 +548,134 @@ pub struct Stats {
     pub log_count: i64,
     pub first_entry: Option<String>,
     pub last_entry: Option<String>,
+    /// Distinct dates with at least one log entry
+    pub days_logged: i64,
+    /// Percentage of days from first to last entry that have something logged
+    pub adherence: Option<f64>,
+}
+
+/// Days logged as a percentage of the days in the first..=last span, or None with no entries
+pub fn adherence_percent(days_logged: i64, span_days: i64) -> Option<f64> {
+    if span_days <= 0 {
+        return None;
+    }
+    Some(days_logged as f64 / span_days as f64 * 100.0)
 }
 
 impl Database {
-    pub fn open() -> Result<Self> {
-        let db_path = Self::db_path()?;
-        
+    /// Open the database at `path` (see `Config::db_path`), creating its directory if needed
+    pub fn open(path: &std::path::Path) -> Result<Self> {
         // Create parent directory if needed
-        if let Some(parent) = db_path.parent() {
+        if let Some(parent) = path.parent() {
             std::fs::create_dir_all(parent)?;
         }
         
-        let conn = Connection::open(&db_path)?;
-        Ok(Self { conn })
+        Self::open_at(path)
+    }
+
+    /// Open the database file at `path`, creating it if needed
+    pub fn open_at(path: &std::path::Path) -> Result<Self> {
+        let conn = Connection::open(path)?;
+        // Wait for short-lived locks (e.g. `chomp serve` writing) instead of failing at once
+        conn.busy_timeout(BUSY_TIMEOUT)?;
+        // Write-ahead logging lets readers and a writer (`chomp serve` and a CLI command)
+        // work at the same time. It's a property of the file, kept once set, and adds
+        // foods.db-wal and foods.db-shm next to it while connections are open.
+        // The pragma answers with the resulting mode, which isn't needed here.
+        conn.query_row("PRAGMA journal_mode = WAL", [], |_| Ok(()))?;
+        Ok(Self { conn, audit: false })
+    }
+
+    #[cfg(test)]
+    pub fn open_in_memory() -> Result<Self> {
+        let conn = Connection::open_in_memory()?;
+        let db = Self { conn, audit: false };
+        db.init()?;
+        Ok(db)
+    }
+
+    /// Turn recording changes in the audit table on or off
+    pub fn set_audit(&mut self, enabled: bool) {
+        self.audit = enabled;
+    }
+
+    /// Append a row to the audit table, if auditing is on
+    pub fn record_audit(&self, action: &str, payload: &serde_json::Value) -> Result<()> {
+        if self.audit {
+            self.conn.execute(
+                "INSERT INTO audit (action, payload) VALUES (?1, ?2)",
+                params![action, payload.to_string()],
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Run the change `f` and audit it as `action`, under one savepoint so the change
+    /// never lands without its record (or the other way round) when a command is retried
+    fn audited<T>(
+        &self,
+        action: &str,
+        mut f: impl FnMut() -> Result<T>,
+        payload: impl Fn(&T) -> serde_json::Value,
+    ) -> Result<T> {
+        self.retry_if_busy(|| {
+            if !self.audit {
+                return f();
+            }
+            self.conn.execute_batch("SAVEPOINT audit")?;
+            let result = f().and_then(|value| {
+                self.record_audit(action, &payload(&value))?;
+                Ok(value)
+            });
+            self.conn.execute_batch(if result.is_ok() { "RELEASE audit" } else { "ROLLBACK TO audit; RELEASE audit" })?;
+            result
+        })
+    }
+
+    /// Run the write `f`, again after a growing pause while the database stays busy,
+    /// up to `BUSY_RETRIES` more times. Only a whole write is retried, never a command:
+    /// inside a transaction `f` runs once, and the transaction's own retry starts over.
+    fn retry_if_busy<T>(&self, mut f: impl FnMut() -> Result<T>) -> Result<T> {
+        if !self.conn.is_autocommit() {
+            return f();
+        }
+        let mut attempt = 0;
+        loop {
+            match f() {
+                Err(e) if is_busy_error(&e) => {
+                    attempt += 1;
+                    if attempt > BUSY_RETRIES {
+                        return Err(explain_busy(e));
+                    }
+                    eprintln!("database is busy (is `chomp serve` running?); retrying...");
+                    std::thread::sleep(std::time::Duration::from_millis(250 * attempt as u64));
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Audit rows from the last `days` days, oldest first
+    pub fn audit_entries(&self, days: u32) -> Result<Vec<AuditEntry>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, at, action, payload FROM audit WHERE at >= datetime('now', ?1) ORDER BY id"
+        )?;
+        let entries = stmt
+            .query_map(params![format!("-{} days", days)], |row| {
+                let payload: String = row.get(3)?;
+                Ok(AuditEntry {
+                    id: row.get(0)?,
+                    at: row.get(1)?,
+                    action: row.get(2)?,
+                    payload: serde_json::from_str(&payload).unwrap_or(serde_json::Value::String(payload)),
+                })
+            })?
+            .collect::<rusqlite::Result<_>>()?;
+        Ok(entries)
     }
 
-    fn db_path() -> Result<std::path::PathBuf> {
+    /// Where the database lives unless `db_path` is configured
+    pub fn default_path() -> Result<std::path::PathBuf> {
         let home = dirs::home_dir().ok_or_else(|| anyhow::anyhow!("Could not find home directory"))?;
         Ok(home.join(".chomp").join("foods.db"))
     }
@@ -90,14 +720,100 @@ impl Database {
             CREATE INDEX IF NOT EXISTS idx_aliases_alias ON aliases(alias);
             "
         )?;
+        self.migrate()?;
         Ok(())
     }
 
+    /// Apply any schema migrations newer than the database's `user_version`
+    fn migrate(&self) -> Result<()> {
+        self.retry_if_busy(|| {
+            let version: usize = self.conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+            for (i, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+                let tx = self.conn.unchecked_transaction()?;
+                tx.execute_batch(migration)?;
+                tx.pragma_update(None, "user_version", i + 1)?;
+                tx.commit()?;
+            }
+            Ok(())
+        })
+    }
+
     pub fn add_food(&self, food: &Food) -> Result<i64> {
+        self.check_name_collisions(food)?;
+        self.audited("add_food", || self.insert_food(None, food), |id| {
+            serde_json::json!({ "id": id, "food": food })
+        })
+    }
+
+    /// Add a food without the collision checks, to set up data from before they existed
+    #[cfg(test)]
+    pub fn add_food_unchecked(&self, food: &Food) -> Result<i64> {
+        self.insert_food(None, food)
+    }
+
+    /// Backdate a log entry's creation time, to set up entries logged at known moments
+    #[cfg(test)]
+    pub fn set_log_created_at(&self, id: i64, created_at: &str) {
+        self.conn.execute("UPDATE log SET created_at = ?1 WHERE id = ?2", params![created_at, id]).unwrap();
+    }
+
+    /// Reject a new food whose name is already another food's alias, or whose aliases
+    /// are already taken as a name or alias, so no term can resolve to two foods
+    fn check_name_collisions(&self, food: &Food) -> Result<()> {
+        if let Some((_, owner)) = self.alias_owner(&food.name)? {
+            anyhow::bail!("'{}' is already an alias of '{}'", food.name, owner);
+        }
+        for alias in &food.aliases {
+            if alias.eq_ignore_ascii_case(&food.name) {
+                continue;
+            }
+            if let Some((_, owner)) = self.name_owner(alias)? {
+                anyhow::bail!("Alias '{}' is already the name of another food ('{}')", alias, owner);
+            }
+            if let Some((_, owner)) = self.alias_owner(alias)? {
+                anyhow::bail!("Alias '{}' already belongs to '{}'", alias, owner);
+            }
+        }
+        Ok(())
+    }
+
+    /// Id and name of the food named `name`, ignoring case
+    fn name_owner(&self, name: &str) -> Result<Option<(i64, String)>> {
+        let owner = self.conn.query_row(
+            "SELECT id, name FROM foods WHERE LOWER(name) = LOWER(?1)",
+            params![name],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        );
+        match owner {
+            Ok(owner) => Ok(Some(owner)),
+            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Id and name of the food with alias `alias`, ignoring case
+    fn alias_owner(&self, alias: &str) -> Result<Option<(i64, String)>> {
+        let owner = self.conn.query_row(
+            "SELECT f.id, f.name FROM aliases a JOIN foods f ON f.id = a.food_id
+             WHERE LOWER(a.alias) = LOWER(?1)",
+            params![alias],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        );
+        match owner {
+            Ok(owner) => Ok(Some(owner)),
+            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Insert a food and its aliases, with an explicit id or a new one when `id` is None
+    fn insert_food(&self, id: Option<i64>, food: &Food) -> Result<i64> {
         self.conn.execute(
-            "INSERT INTO foods (name, protein, fat, carbs, calories, serving, default_amount)
-             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            "INSERT INTO foods (id, name, protein, fat, carbs, calories, serving, default_amount, density, source, unit_mode,
+                                fiber, units)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)",
             params![
+                id,
                 food.name,
                 food.protein,
                 food.fat,
@@ -105,6 +821,11 @@ impl Database {
                 food.calories,
                 food.serving,
                 food.default_amount,
+                food.density,
+                food.source.to_string(),
+                food.unit_mode.to_string(),
+                food.fiber,
+                units_json(&food.units)?,
             ],
         )?;
         
@@ -121,121 +842,192 @@ impl Database {
         Ok(food_id)
     }
 
+    /// Id of the quick-add placeholder food, created on first use
+    pub fn quick_add_food_id(&self) -> Result<i64> {
+        match self.name_owner(QUICK_ADD_FOOD)? {
+            Some((id, _)) => Ok(id),
+            None => self.add_food(&Food::new(QUICK_ADD_FOOD, 0.0, 0.0, 0.0, 1.0, "1 kcal", vec![])),
+        }
+    }
+
+    pub fn get_food(&self, id: i64) -> Result<Food> {
+        let food = self.conn.query_row(
+            &format!("SELECT {} FROM foods f WHERE f.id = ?1", FOOD_COLUMNS),
+            params![id],
+            food_from_row,
+        )?;
+        Ok(food)
+    }
+
     pub fn get_food_by_name(&self, name: &str) -> Result<Option<Food>> {
         let name_lower = name.to_lowercase();
         
         // Try exact match first
-        let mut stmt = self.conn.prepare(
-            "SELECT id, name, protein, fat, carbs, calories, serving, default_amount 
-             FROM foods WHERE LOWER(name) = ?1"
-        )?;
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {} FROM foods f WHERE LOWER(f.name) = ?1", FOOD_COLUMNS
+        ))?;
         
-        if let Some(food) = stmt.query_row(params![&name_lower], |row| {
-            Ok(Food {
-                id: Some(row.get(0)?),
-                name: row.get(1)?,
-                protein: row.get(2)?,
-                fat: row.get(3)?,
-                carbs: row.get(4)?,
-                calories: row.get(5)?,
-                serving: row.get(6)?,
-                default_amount: row.get(7)?,
-                aliases: vec![],
-            })
-        }).ok() {
+        if let Ok(food) = stmt.query_row(params![&name_lower], food_from_row) {
+            // A different food's alias spelled the same would be unreachable; refuse to guess
+            if let Some((owner_id, owner)) = self.alias_owner(name)? {
+                anyhow::ensure!(Some(owner_id) == food.id,
+                    "'{}' is ambiguous: it's the name of '{}' and an alias of '{}'. Rename one or remove the alias",
+                    name, food.name, owner);
+            }
             return Ok(Some(food));
         }
         
         // Try alias match
-        let mut stmt = self.conn.prepare(
-            "SELECT f.id, f.name, f.protein, f.fat, f.carbs, f.calories, f.serving, f.default_amount 
-             FROM foods f
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {} FROM foods f
              JOIN aliases a ON f.id = a.food_id
-             WHERE LOWER(a.alias) = ?1"
-        )?;
+             WHERE LOWER(a.alias) = ?1", FOOD_COLUMNS
+        ))?;
         
-        if let Some(food) = stmt.query_row(params![&name_lower], |row| {
-            Ok(Food {
-                id: Some(row.get(0)?),
-                name: row.get(1)?,
-                protein: row.get(2)?,
-                fat: row.get(3)?,
-                carbs: row.get(4)?,
-                calories: row.get(5)?,
-                serving: row.get(6)?,
-                default_amount: row.get(7)?,
-                aliases: vec![],
-            })
-        }).ok() {
+        if let Ok(food) = stmt.query_row(params![&name_lower], food_from_row) {
             return Ok(Some(food));
         }
         
         Ok(None)
     }
 
-    pub fn search_foods(&self, query: &str) -> Result<Vec<Food>> {
-        let mut stmt = self.conn.prepare(
-            "SELECT id, name, protein, fat, carbs, calories, serving, default_amount FROM foods"
-        )?;
+    pub fn all_foods(&self) -> Result<Vec<Food>> {
+        let mut stmt = self.conn.prepare(&format!("SELECT {} FROM foods f", FOOD_COLUMNS))?;
         
         let foods: Vec<Food> = stmt
-            .query_map([], |row| {
-                Ok(Food {
-                    id: Some(row.get(0)?),
-                    name: row.get(1)?,
-                    protein: row.get(2)?,
-                    fat: row.get(3)?,
-                    carbs: row.get(4)?,
-                    calories: row.get(5)?,
-                    serving: row.get(6)?,
-                    default_amount: row.get(7)?,
-                    aliases: vec![],
-                })
-            })?
+            .query_map([], food_from_row)?
             .filter_map(|r| r.ok())
             .collect();
         
-        // Fuzzy match
+        Ok(foods)
+    }
+
+    /// The `limit` foods with the most of `nutrient`, per serving or, with `per100`,
+    /// per 100g. Per-100g ranking leaves out foods whose serving isn't a weight.
+    pub fn top_foods(&self, nutrient: Nutrient, per100: bool, limit: usize) -> Result<Vec<Food>> {
+        if per100 {
+            let mut foods: Vec<Food> = self.all_foods()?
+                .into_iter()
+                .filter(|food| food.name != QUICK_ADD_FOOD)
+                .filter_map(|food| Some(food.normalized_per_100g(crate::food::serving_grams(&food.serving)?)))
+                .collect();
+            foods.sort_by(|a, b| nutrient.of(b).total_cmp(&nutrient.of(a))
+                .then_with(|| a.name.to_lowercase().cmp(&b.name.to_lowercase())));
+            foods.truncate(limit);
+            return Ok(foods);
+        }
+
+        // Only ever one of these fixed names is put into the SQL
+        let column = match nutrient {
+            Nutrient::Protein => "f.protein",
+            Nutrient::Fat => "f.fat",
+            Nutrient::Carbs => "f.carbs",
+            Nutrient::Calories => "f.calories",
+        };
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {} FROM foods f WHERE f.name != ?1 ORDER BY {} DESC, LOWER(f.name) LIMIT ?2",
+            FOOD_COLUMNS, column
+        ))?;
+        let foods = stmt
+            .query_map(params![QUICK_ADD_FOOD, limit as i64], food_from_row)?
+            .collect::<rusqlite::Result<Vec<Food>>>()?;
+        Ok(foods)
+    }
+
+    pub fn search_foods(&self, query: &str, options: &SearchOptions) -> Result<Vec<Food>> {
+        Ok(self.search_matches(query, options)?.into_iter().map(|m| m.food).collect())
+    }
+
+    /// `search_foods`, keeping which alias each food matched by
+    pub fn search_matches(&self, query: &str, options: &SearchOptions) -> Result<Vec<SearchMatch>> {
+        let mut foods = self.all_foods()?;
+        foods.retain(|f| f.name != QUICK_ADD_FOOD);
+        if let Some(source) = options.source {
+            foods.retain(|f| f.source == source);
+        }
+        if options.include_aliases || options.aliases_only {
+            for (food_id, alias) in self.all_aliases()? {
+                if let Some(food) = foods.iter_mut().find(|f| f.id == Some(food_id)) {
+                    food.aliases.push(alias);
+                }
+            }
+        }
+        
+        // How often and how recently each food has been logged
+        let mut stmt = self.conn.prepare("SELECT food_id, COUNT(*), MAX(date) FROM log GROUP BY food_id")?;
+        let usage: std::collections::HashMap<i64, (i64, Option<NaiveDate>)> = stmt
+            .query_map([], |row| {
+                let last: Option<String> = row.get(2)?;
+                let last = last.and_then(|d| NaiveDate::parse_from_str(&d, "%Y-%m-%d").ok());
+                Ok((row.get(0)?, (row.get(1)?, last)))
+            })?
+            .collect::<rusqlite::Result<_>>()?;
+        let today = Local::now().date_naive();
+        
+        // Fuzzy match against the name (unless aliases only), and aliases if included;
+        // best one wins, the name on a tie, then foods logged recently and often are boosted
         let matcher = SkimMatcherV2::default();
         let query_lower = query.to_lowercase();
         
         let mut scored: Vec<_> = foods
             .into_iter()
             .filter_map(|food| {
-                let score = matcher.fuzzy_match(&food.name.to_lowercase(), &query_lower);
-                score.map(|s| (s, food))
+                let name = (!options.aliases_only).then_some((&food.name, None));
+                let (score, alias) = name.into_iter()
+                    .chain(food.aliases.iter().map(|alias| (alias, Some(alias))))
+                    .filter_map(|(candidate, alias)| {
+                        matcher.fuzzy_match(&candidate.to_lowercase(), &query_lower).map(|score| (score, alias))
+                    })
+                    .reduce(|best, next| if next.0 > best.0 { next } else { best })?;
+                let matched_alias = alias.cloned();
+                let boost = food.id
+                    .and_then(|id| usage.get(&id))
+                    .map(|(count, last)| usage_boost(*count, *last, today, options))
+                    .unwrap_or(0.0);
+                Some((score as f64 + boost, SearchMatch { food, matched_alias }))
             })
             .collect();
         
-        scored.sort_by(|a, b| b.0.cmp(&a.0));
+        // Ties go alphabetically, so equal matches come out in the same order every run
+        scored.sort_by(|(a, ma), (b, mb)| b.total_cmp(a)
+            .then_with(|| ma.food.name.to_lowercase().cmp(&mb.food.name.to_lowercase()))
+            .then_with(|| ma.food.name.cmp(&mb.food.name)));
         
-        Ok(scored.into_iter().map(|(_, f)| f).take(10).collect())
+        Ok(scored.into_iter().map(|(_, m)| m).take(10).collect())
     }
 
-    pub fn log_food(&self, food_id: i64, amount: &str, macros: &Macros) -> Result<LogEntry> {
-        let date = Local::now().format("%Y-%m-%d").to_string();
-        
-        self.conn.execute(
-            "INSERT INTO log (date, food_id, amount, protein, fat, carbs, calories)
-             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
-            params![
-                date,
-                food_id,
-                amount,
-                macros.protein,
-                macros.fat,
-                macros.carbs,
-                macros.calories,
-            ],
+    #[cfg(test)]
+    pub fn log_food_on(
+        &self,
+        date: NaiveDate,
+        food_id: i64,
+        amount: &str,
+        macros: &Macros,
+    ) -> Result<LogEntry> {
+        self.log_food_at(&LogMeta::on(date), food_id, amount, macros)
+    }
+
+    pub fn log_food_at(
+        &self,
+        meta: &LogMeta,
+        food_id: i64,
+        amount: &str,
+        macros: &Macros,
+    ) -> Result<LogEntry> {
+        let date = meta.date.format("%Y-%m-%d").to_string();
+        let time = meta.time.map(|t| t.format("%H:%M").to_string());
+        let id = self.audited(
+            "log",
+            || self.insert_log(None, &date, time.as_deref(), meta.meal, food_id, amount, macros, None),
+            |id| serde_json::json!({
+                "id": id, "date": date, "time": time, "food_id": food_id, "amount": amount, "macros": macros,
+            }),
         )?;
         
-        let id = self.conn.last_insert_rowid();
-        
-        // Get food name
-        let food_name: String = self.conn.query_row(
-            "SELECT name FROM foods WHERE id = ?1",
-            params![food_id],
-            |row| row.get(0),
+        let (food_name, created_at): (String, Option<String>) = self.conn.query_row(
+            "SELECT f.name, l.created_at FROM log l JOIN foods f ON l.food_id = f.id WHERE l.id = ?1",
+            params![id],
+            |row| Ok((row.get(0)?, row.get(1)?)),
         )?;
         
         Ok(LogEntry {
@@ -248,79 +1040,370 @@ impl Database {
             fat: macros.fat,
             carbs: macros.carbs,
             calories: macros.calories,
+            fiber: macros.fiber,
+            time,
+            meal: meta.meal,
+            group_id: None,
+            created_at,
+        })
+    }
+
+    /// Log several foods as one group, all or nothing. Each entry's `group_id` is
+    /// the first entry's id.
+    pub fn log_group(&self, meta: &LogMeta, items: &[(i64, String, Macros)]) -> Result<Vec<LogEntry>> {
+        self.retry_if_busy(|| {
+            let tx = self.conn.unchecked_transaction()?;
+            let mut entries = Vec::with_capacity(items.len());
+            for (food_id, amount, macros) in items {
+                entries.push(self.log_food_at(meta, *food_id, amount, macros)?);
+            }
+            if let Some(group_id) = entries.first().and_then(|e| e.id) {
+                for entry in &mut entries {
+                    tx.execute("UPDATE log SET group_id = ?1 WHERE id = ?2", params![group_id, entry.id])?;
+                    entry.group_id = Some(group_id);
+                }
+            }
+            tx.commit()?;
+            Ok(entries)
         })
     }
 
+    /// Insert a log row, with an explicit id or a new one when `id` is None, and
+    /// `created_at` kept from a backup or else now
+    #[allow(clippy::too_many_arguments)]
+    fn insert_log(
+        &self,
+        id: Option<i64>,
+        date: &str,
+        time: Option<&str>,
+        meal: Option<Meal>,
+        food_id: i64,
+        amount: &str,
+        macros: &Macros,
+        created_at: Option<&str>,
+    ) -> Result<i64> {
+        self.conn.execute(
+            "INSERT INTO log (id, date, time, meal, food_id, amount, protein, fat, carbs, calories, fiber, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, COALESCE(?12, CURRENT_TIMESTAMP))",
+            params![
+                id,
+                date,
+                time,
+                meal.map(|m| m.to_string()),
+                food_id,
+                amount,
+                macros.protein,
+                macros.fat,
+                macros.carbs,
+                macros.calories,
+                macros.fiber,
+                created_at,
+            ],
+        )?;
+        Ok(self.conn.last_insert_rowid())
+    }
+
+    #[cfg(test)]
     pub fn get_today_totals(&self) -> Result<Macros> {
-        let date = Local::now().format("%Y-%m-%d").to_string();
+        self.get_totals_for_date(Local::now().date_naive(), None)
+    }
+
+    /// SQLite's data version: changes whenever another connection (another chomp
+    /// process) commits to the database, but not for writes on this connection
+    pub fn data_version(&self) -> Result<i64> {
+        Ok(self.conn.query_row("PRAGMA data_version", [], |row| row.get(0))?)
+    }
+
+    /// Totals for `date`, or for just the entries tagged `meal` that day
+    pub fn get_totals_for_date(&self, date: NaiveDate, meal: Option<Meal>) -> Result<Macros> {
+        let date = date.format("%Y-%m-%d").to_string();
         
         let mut stmt = self.conn.prepare(
             "SELECT COALESCE(SUM(protein), 0), COALESCE(SUM(fat), 0), 
-                    COALESCE(SUM(carbs), 0), COALESCE(SUM(calories), 0)
-             FROM log WHERE date = ?1"
+                    COALESCE(SUM(carbs), 0), COALESCE(SUM(calories), 0), COALESCE(SUM(fiber), 0)
+             FROM log WHERE date = ?1 AND (?2 IS NULL OR meal = ?2)"
         )?;
         
-        let macros = stmt.query_row(params![date], |row| {
+        let macros = stmt.query_row(params![date, meal.map(|m| m.to_string())], |row| {
             Ok(Macros {
                 protein: row.get(0)?,
                 fat: row.get(1)?,
                 carbs: row.get(2)?,
                 calories: row.get(3)?,
+                fiber: row.get(4)?,
             })
         })?;
         
         Ok(macros)
     }
 
-    pub fn get_history(&self, days: u32) -> Result<Vec<LogEntry>> {
-        let start_date = Local::now()
-            .checked_sub_signed(chrono::Duration::days(days as i64))
-            .unwrap()
-            .format("%Y-%m-%d")
-            .to_string();
-        
+    /// Per-day totals for dates in `from..=to` that have entries, oldest first
+    pub fn get_daily_totals(&self, from: NaiveDate, to: NaiveDate) -> Result<Vec<DayTotals>> {
         let mut stmt = self.conn.prepare(
-            "SELECT l.id, l.date, f.name, l.food_id, l.amount, l.protein, l.fat, l.carbs, l.calories
-             FROM log l
-             JOIN foods f ON l.food_id = f.id
-             WHERE l.date >= ?1
-             ORDER BY l.date DESC, l.id DESC"
+            "SELECT date, SUM(protein), SUM(fat), SUM(carbs), SUM(calories), SUM(fiber)
+             FROM log WHERE date >= ?1 AND date <= ?2
+             GROUP BY date ORDER BY date"
         )?;
         
-        let entries = stmt
-            .query_map(params![start_date], |row| {
-                Ok(LogEntry {
-                    id: Some(row.get(0)?),
-                    date: row.get(1)?,
-                    food_name: row.get(2)?,
-                    food_id: row.get(3)?,
-                    amount: row.get(4)?,
-                    protein: row.get(5)?,
-                    fat: row.get(6)?,
-                    carbs: row.get(7)?,
-                    calories: row.get(8)?,
-                })
-            })?
+        let days = stmt
+            .query_map(
+                params![from.format("%Y-%m-%d").to_string(), to.format("%Y-%m-%d").to_string()],
+                |row| {
+                    Ok(DayTotals {
+                        date: row.get(0)?,
+                        totals: Macros {
+                            protein: row.get(1)?,
+                            fat: row.get(2)?,
+                            carbs: row.get(3)?,
+                            calories: row.get(4)?,
+                            fiber: row.get(5)?,
+                        },
+                    })
+                },
+            )?
             .filter_map(|r| r.ok())
             .collect();
         
-        Ok(entries)
+        Ok(days)
     }
 
-    pub fn edit_food(
-        &self, 
-        name: &str, 
-        protein: Option<f64>, 
-        fat: Option<f64>, 
-        carbs: Option<f64>, 
-        serving: Option<&str>
-    ) -> Result<()> {
-        // Get the current food
-        let food = self.get_food_by_name(name)?
-            .ok_or_else(|| anyhow::anyhow!("Food not found: '{}'", name))?;
+    pub fn get_goals(&self) -> Result<Option<Goals>> {
+        let goals = self.conn.query_row(
+            "SELECT protein, fat, carbs, calories, weekly_calories FROM goals WHERE id = 1",
+            [],
+            |row| {
+                Ok(Goals {
+                    protein: row.get(0)?,
+                    fat: row.get(1)?,
+                    carbs: row.get(2)?,
+                    calories: row.get(3)?,
+                    weekly_calories: row.get(4)?,
+                })
+            },
+        );
         
-        // Build update query based on which fields are provided
-        let mut updates = Vec::new();
+        match goals {
+            Ok(goals) => Ok(Some(goals)),
+            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Replace the current goals, from today on
+    pub fn set_goals(&self, goals: &Goals) -> Result<()> {
+        self.set_goals_from(goals, Local::now().date_naive())
+    }
+
+    /// Replace the current goals, recording that they apply from `date`
+    pub fn set_goals_from(&self, goals: &Goals, date: NaiveDate) -> Result<()> {
+        let date = date.format("%Y-%m-%d").to_string();
+        self.audited("set_goals", || {
+            self.replace_current_goals(goals)?;
+            self.conn.execute(
+                "INSERT INTO goal_history (effective_date, protein, fat, carbs, calories, weekly_calories)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                params![date, goals.protein, goals.fat, goals.carbs, goals.calories, goals.weekly_calories],
+            )?;
+            Ok(())
+        }, |_| serde_json::json!({ "effective_date": date, "goals": goals }))
+    }
+
+    fn replace_current_goals(&self, goals: &Goals) -> Result<()> {
+        self.conn.execute(
+            "INSERT OR REPLACE INTO goals (id, protein, fat, carbs, calories, weekly_calories)
+             VALUES (1, ?1, ?2, ?3, ?4, ?5)",
+            params![goals.protein, goals.fat, goals.carbs, goals.calories, goals.weekly_calories],
+        )?;
+        Ok(())
+    }
+
+    /// Remove the current default goals from `date` on. Past days keep the goals
+    /// they had; weekday overrides are left alone (see `clear_goal_overrides`).
+    pub fn clear_goals(&self, date: NaiveDate) -> Result<()> {
+        let date = date.format("%Y-%m-%d").to_string();
+        self.audited("clear_goals", || {
+            self.conn.execute("DELETE FROM goals", [])?;
+            self.conn.execute("INSERT INTO goal_history (effective_date, cleared) VALUES (?1, 1)", params![date])?;
+            Ok(())
+        }, |_| serde_json::json!({ "effective_date": date }))
+    }
+
+    /// Every change to the default goals, oldest first
+    pub fn goal_history(&self) -> Result<Vec<GoalChange>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT effective_date, changed_at, cleared, protein, fat, carbs, calories, weekly_calories
+             FROM goal_history ORDER BY effective_date, id"
+        )?;
+        let history = stmt
+            .query_map([], |row| {
+                Ok(GoalChange {
+                    effective_date: row.get(0)?,
+                    changed_at: row.get(1)?,
+                    cleared: row.get(2)?,
+                    goals: Goals {
+                        protein: row.get(3)?,
+                        fat: row.get(4)?,
+                        carbs: row.get(5)?,
+                        calories: row.get(6)?,
+                        weekly_calories: row.get(7)?,
+                    },
+                })
+            })?
+            .collect::<rusqlite::Result<_>>()?;
+        Ok(history)
+    }
+
+    /// Default goals in effect on `date`: the last change made on or before it, or
+    /// None before the first one
+    pub fn get_default_goals_on(&self, date: NaiveDate) -> Result<Option<Goals>> {
+        let history = self.goal_history()?;
+        let date = date.format("%Y-%m-%d").to_string();
+        let change = history.iter().rev().find(|c| c.effective_date <= date);
+        Ok(match change {
+            Some(change) if !change.cleared => Some(change.goals.clone()),
+            _ => None,
+        })
+    }
+
+    /// Goals that apply on `date`: the defaults in effect then, with that weekday's
+    /// override on top
+    pub fn get_goals_for(&self, date: NaiveDate) -> Result<Option<Goals>> {
+        let default = self.get_default_goals_on(date)?;
+        let weekday = date.weekday();
+        let day = self.get_goal_overrides()?.into_iter().find(|d| d.weekday == weekday);
+        Ok(match (default, day) {
+            (Some(default), Some(day)) => Some(default.overridden_by(&day.goals)),
+            (None, Some(day)) => Some(day.goals),
+            (default, None) => default,
+        })
+    }
+
+    /// Per-weekday overrides, Monday first
+    pub fn get_goal_overrides(&self) -> Result<Vec<DayGoals>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT weekday, protein, fat, carbs, calories FROM goal_overrides ORDER BY weekday"
+        )?;
+        let overrides = stmt
+            .query_map([], |row| {
+                let weekday: u8 = row.get(0)?;
+                Ok(DayGoals {
+                    weekday: Weekday::try_from(weekday).unwrap_or(Weekday::Mon),
+                    goals: Goals {
+                        protein: row.get(1)?,
+                        fat: row.get(2)?,
+                        carbs: row.get(3)?,
+                        calories: row.get(4)?,
+                        weekly_calories: None,
+                    },
+                })
+            })?
+            .collect::<rusqlite::Result<_>>()?;
+        Ok(overrides)
+    }
+
+    /// Replace the override for each of `weekdays` with `goals` (weekly_calories is ignored)
+    pub fn set_goal_overrides(&self, weekdays: &[Weekday], goals: &Goals) -> Result<()> {
+        self.audited("set_goal_overrides", || {
+            let mut stmt = self.conn.prepare(
+                "INSERT OR REPLACE INTO goal_overrides (weekday, protein, fat, carbs, calories)
+                 VALUES (?1, ?2, ?3, ?4, ?5)"
+            )?;
+            for weekday in weekdays {
+                stmt.execute(params![weekday.num_days_from_monday(), goals.protein, goals.fat, goals.carbs, goals.calories])?;
+            }
+            Ok(())
+        }, |_| serde_json::json!({ "weekdays": weekdays, "goals": goals }))
+    }
+
+    /// Remove the override for each of `weekdays`. Overrides aren't dated, so past
+    /// days lose them too.
+    pub fn clear_goal_overrides(&self, weekdays: &[Weekday]) -> Result<()> {
+        self.audited("clear_goal_overrides", || {
+            let mut stmt = self.conn.prepare("DELETE FROM goal_overrides WHERE weekday = ?1")?;
+            for weekday in weekdays {
+                stmt.execute(params![weekday.num_days_from_monday()])?;
+            }
+            Ok(())
+        }, |_| serde_json::json!({ "weekdays": weekdays }))
+    }
+
+    /// Entries from the last `days` days, today included (`days` = 1 is just today),
+    /// newest first and at most `limit` of them, optionally only those tagged `meal`
+    pub fn get_history(&self, days: u32, limit: Option<u32>, meal: Option<Meal>) -> Result<Vec<LogEntry>> {
+        anyhow::ensure!(days >= 1, "days must be at least 1 (1 = today only)");
+        self.get_log_range(history_start(Local::now().date_naive(), days), None, limit, meal)
+    }
+
+    /// Entries dated from `since` through `until`, both inclusive (None = unbounded),
+    /// newest first and at most `limit` of them, optionally only those tagged `meal`
+    pub fn get_log_range(
+        &self,
+        since: Option<NaiveDate>,
+        until: Option<NaiveDate>,
+        limit: Option<u32>,
+        meal: Option<Meal>,
+    ) -> Result<Vec<LogEntry>> {
+        let format = |d: Option<NaiveDate>| d.map(|d| d.format("%Y-%m-%d").to_string());
+        
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {} FROM log l
+             JOIN foods f ON l.food_id = f.id
+             WHERE (?1 IS NULL OR l.date >= ?1) AND (?2 IS NULL OR l.date <= ?2)
+               AND (?4 IS NULL OR l.meal = ?4)
+             ORDER BY l.date DESC, l.time DESC, l.id DESC
+             LIMIT COALESCE(?3, -1)", LOG_COLUMNS
+        ))?;
+        
+        let entries = stmt
+            .query_map(params![format(since), format(until), limit, meal.map(|m| m.to_string())], log_entry_from_row)?
+            .filter_map(|r| r.ok())
+            .collect();
+        
+        Ok(entries)
+    }
+
+    /// Every entry logged on `date`, by time of day (untimed entries first), then in logging order
+    pub fn get_log_for_date(&self, date: NaiveDate) -> Result<Vec<LogEntry>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {} FROM log l
+             JOIN foods f ON l.food_id = f.id
+             WHERE l.date = ?1
+             ORDER BY l.time, l.id", LOG_COLUMNS
+        ))?;
+        
+        let entries = stmt
+            .query_map(params![date.format("%Y-%m-%d").to_string()], log_entry_from_row)?
+            .collect::<rusqlite::Result<_>>()?;
+        
+        Ok(entries)
+    }
+
+    /// Change the given fields of a food. Calories are set when given, otherwise
+    /// recomputed from the macros if any of them changed, otherwise left alone.
+    #[allow(clippy::too_many_arguments)]
+    pub fn edit_food(
+        &self, 
+        name: &str, 
+        protein: Option<f64>, 
+        fat: Option<f64>, 
+        carbs: Option<f64>, 
+        calories: Option<f64>,
+        fiber: Option<f64>,
+        serving: Option<&str>
+    ) -> Result<()> {
+        // Get the current food
+        let food = self.get_food_by_name(name)?
+            .ok_or_else(|| anyhow::anyhow!("Food not found: '{}'", name))?;
+
+        // Fiber is part of the carbs: fewer carbs than fiber takes the fiber down with them
+        let new_fiber = fiber.or_else(|| carbs.filter(|c| *c < food.fiber));
+        if let Some(fiber) = new_fiber {
+            anyhow::ensure!(fiber >= 0.0, "Fiber can't be negative");
+            anyhow::ensure!(fiber <= carbs.unwrap_or(food.carbs), "Fiber can't be more than the carbs");
+        }
+        
+        // Build update query based on which fields are provided
+        let mut updates = Vec::new();
         let mut params_vec: Vec<Box<dyn rusqlite::ToSql>> = Vec::new();
         
         if let Some(p) = protein {
@@ -335,19 +1418,27 @@ impl Database {
             updates.push("carbs = ?");
             params_vec.push(Box::new(c));
         }
+        if let Some(f) = new_fiber {
+            updates.push("fiber = ?");
+            params_vec.push(Box::new(f));
+        }
         if let Some(s) = serving {
             updates.push("serving = ?");
             params_vec.push(Box::new(s.to_string()));
         }
         
-        // Calculate new calories if macros changed
-        let new_protein = protein.unwrap_or(food.protein);
-        let new_fat = fat.unwrap_or(food.fat);
-        let new_carbs = carbs.unwrap_or(food.carbs);
-        let new_calories = (new_protein * 4.0) + (new_fat * 9.0) + (new_carbs * 4.0);
-        
-        updates.push("calories = ?");
-        params_vec.push(Box::new(new_calories));
+        // Explicit calories win; otherwise recalculate if macros changed
+        let new_calories = calories.or_else(|| {
+            (protein.is_some() || fat.is_some() || carbs.is_some()).then(|| atwater_calories(
+                protein.unwrap_or(food.protein),
+                fat.unwrap_or(food.fat),
+                carbs.unwrap_or(food.carbs),
+            ))
+        });
+        if let Some(c) = new_calories {
+            updates.push("calories = ?");
+            params_vec.push(Box::new(c));
+        }
         
         if updates.is_empty() {
             return Ok(());
@@ -363,22 +1454,348 @@ impl Database {
         
         let params_refs: Vec<&dyn rusqlite::ToSql> = params_vec.iter().map(|p| p.as_ref()).collect();
         
-        self.conn.execute(&query, params_refs.as_slice())?;
-        Ok(())
+        self.audited("edit_food", || {
+            self.conn.execute(&query, params_refs.as_slice())?;
+            Ok(())
+        }, |_| serde_json::json!({
+            "name": food.name, "before": food, "protein": protein, "fat": fat, "carbs": carbs,
+            "calories": new_calories, "fiber": new_fiber, "serving": serving,
+        }))
+    }
+
+    /// Add and remove aliases of the food `name` (or alias), all or nothing. Adding an
+    /// alias the food already has, or its own name, changes nothing; one that names or
+    /// belongs to another food is refused, as is removing one the food doesn't have.
+    /// Returns the food's aliases afterwards.
+    pub fn edit_aliases(&self, name: &str, add: &[String], remove: &[String]) -> Result<Vec<String>> {
+        self.retry_if_busy(|| {
+            let food = self.get_food_by_name(name)?
+                .ok_or_else(|| anyhow::anyhow!("Food not found: '{}'", name))?;
+            let food_id = food.id.ok_or_else(|| anyhow::anyhow!("Food has no id"))?;
+
+            let tx = self.conn.unchecked_transaction()?;
+            for alias in remove {
+                let removed = tx.execute(
+                    "DELETE FROM aliases WHERE food_id = ?1 AND LOWER(alias) = LOWER(?2)",
+                    params![food_id, alias],
+                )?;
+                anyhow::ensure!(removed > 0, "'{}' is not an alias of '{}'", alias, food.name);
+            }
+            for alias in add {
+                let alias = alias.trim();
+                anyhow::ensure!(!alias.is_empty(), "Alias can't be empty");
+                match self.name_owner(alias)? {
+                    Some((id, _)) if id == food_id => continue,
+                    Some((_, owner)) => anyhow::bail!("Alias '{}' is already the name of another food ('{}')", alias, owner),
+                    None => {}
+                }
+                match self.alias_owner(alias)? {
+                    Some((id, _)) if id == food_id => continue,
+                    Some((_, owner)) => anyhow::bail!("Alias '{}' already belongs to '{}'", alias, owner),
+                    None => {}
+                }
+                tx.execute("INSERT INTO aliases (food_id, alias) VALUES (?1, ?2)", params![food_id, alias])?;
+            }
+            self.record_audit("edit_aliases", &serde_json::json!({ "food": food.name, "added": add, "removed": remove }))?;
+            tx.commit()?;
+
+            Ok(self.all_aliases()?.into_iter()
+                .filter(|(id, _)| *id == food_id)
+                .map(|(_, alias)| alias)
+                .collect())
+        })
+    }
+
+    /// All aliases as (food_id, alias) pairs
+    pub fn all_aliases(&self) -> Result<Vec<(i64, String)>> {
+        let mut stmt = self.conn.prepare("SELECT food_id, alias FROM aliases ORDER BY alias")?;
+        let aliases = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .filter_map(|r| r.ok())
+            .collect();
+        Ok(aliases)
+    }
+
+    pub fn set_food_calories(&self, food_id: i64, calories: f64) -> Result<()> {
+        self.audited("set_food_calories", || {
+            self.conn.execute(
+                "UPDATE foods SET calories = ?1 WHERE id = ?2",
+                params![calories, food_id],
+            )?;
+            Ok(())
+        }, |_| serde_json::json!({ "id": food_id, "calories": calories }))
+    }
+
+    /// Convert every food with a mass serving ("30g", "4oz") to per-100g macros,
+    /// except those measured only in servings. Returns the foods that were changed.
+    pub fn normalize_foods(&self) -> Result<Vec<Food>> {
+        self.retry_if_busy(|| {
+            let tx = self.conn.unchecked_transaction()?;
+            let mut normalized = Vec::new();
+            let mut before = Vec::new();
+
+            for food in self.all_foods()? {
+                if food.unit_mode == crate::food::UnitMode::Serving {
+                    continue;
+                }
+                let Some(grams) = crate::food::serving_grams(&food.serving) else {
+                    continue;
+                };
+                if (grams - 100.0).abs() < f64::EPSILON {
+                    continue;
+                }
+
+                let updated = food.clone().normalized_per_100g(grams);
+                tx.execute(
+                    "UPDATE foods SET protein = ?1, fat = ?2, carbs = ?3, calories = ?4, fiber = ?5,
+                            serving = ?6, default_amount = ?7, units = ?8
+                     WHERE id = ?9",
+                    params![
+                        updated.protein,
+                        updated.fat,
+                        updated.carbs,
+                        updated.calories,
+                        updated.fiber,
+                        updated.serving,
+                        updated.default_amount,
+                        units_json(&updated.units)?,
+                        updated.id,
+                    ],
+                )?;
+                before.push(food);
+                normalized.push(updated);
+            }
+
+            if !normalized.is_empty() {
+                self.record_audit("normalize_foods", &serde_json::json!({ "before": before }))?;
+            }
+            tx.commit()?;
+            Ok(normalized)
+        })
+    }
+
+    /// Run `f` in one transaction. It's committed only if `f` succeeds and `commit`
+    /// is set; otherwise everything `f` wrote is rolled back, which makes dry runs
+    /// of multi-step writes exact.
+    pub fn transaction<T>(&self, commit: bool, mut f: impl FnMut() -> Result<T>) -> Result<T> {
+        self.retry_if_busy(|| {
+            let tx = self.conn.unchecked_transaction()?;
+            let result = f()?;
+            if commit {
+                tx.commit()?;
+            }
+            Ok(result)
+        })
+    }
+
+    /// Multiply the macros (fiber too) and calories of every food whose name contains `pattern`
+    /// (case-insensitive; all foods if None) by `factor`, in one transaction.
+    /// With `dry_run` nothing is written. Returns the foods with their new values.
+    pub fn scale_foods(&self, pattern: Option<&str>, factor: f64, dry_run: bool) -> Result<Vec<Food>> {
+        self.retry_if_busy(|| {
+            anyhow::ensure!(factor.is_finite() && factor > 0.0, "Scale factor must be positive");
+            let pattern = pattern.map(str::to_lowercase);
+
+            let tx = self.conn.unchecked_transaction()?;
+            let mut scaled = Vec::new();
+
+            for mut food in self.all_foods()? {
+                if let Some(pattern) = &pattern {
+                    if !food.name.to_lowercase().contains(pattern.as_str()) {
+                        continue;
+                    }
+                }
+                food.protein *= factor;
+                food.fat *= factor;
+                food.carbs *= factor;
+                food.calories *= factor;
+                food.fiber *= factor;
+
+                if !dry_run {
+                    tx.execute(
+                        "UPDATE foods SET protein = ?1, fat = ?2, carbs = ?3, calories = ?4, fiber = ?5 WHERE id = ?6",
+                        params![food.protein, food.fat, food.carbs, food.calories, food.fiber, food.id],
+                    )?;
+                }
+                scaled.push(food);
+            }
+
+            if !dry_run && !scaled.is_empty() {
+                let foods: Vec<&str> = scaled.iter().map(|f| f.name.as_str()).collect();
+                self.record_audit("scale_foods", &serde_json::json!({ "foods": foods, "factor": factor }))?;
+            }
+            tx.commit()?;
+            Ok(scaled)
+        })
     }
 
     pub fn search_food(&self, name: &str) -> Result<Option<Food>> {
         self.get_food_by_name(name)
     }
 
+    /// Delete a food that nothing refers to. One that's been logged, or is in a
+    /// recipe, is refused: merging it into another food keeps those.
     pub fn delete_food(&self, name: &str) -> Result<()> {
+        let food = self.get_food_by_name(name)?;
+        if let Some(id) = food.as_ref().and_then(|f| f.id) {
+            let (log_entries, recipes): (usize, usize) = self.conn.query_row(
+                "SELECT (SELECT COUNT(*) FROM log WHERE food_id = ?1),
+                        (SELECT COUNT(DISTINCT recipe_id) FROM recipe_items WHERE food_id = ?1)",
+                params![id],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )?;
+            let uses: Vec<String> = [(log_entries, "log entry", "log entries"), (recipes, "recipe", "recipes")]
+                .into_iter()
+                .filter(|(count, _, _)| *count > 0)
+                .map(|(count, one, many)| format!("{} {}", count, if count == 1 { one } else { many }))
+                .collect();
+            anyhow::ensure!(uses.is_empty(),
+                "'{}' is used by {}. Fold it into another food instead: chomp merge \"{}\" --into <food>",
+                name, uses.join(" and "), name);
+        }
+        self.audited("delete_food", || {
+            self.conn.execute(
+                "DELETE FROM foods WHERE LOWER(name) = LOWER(?1)",
+                params![name],
+            )?;
+            Ok(())
+        }, |_| serde_json::json!({ "name": name, "food": food }))
+    }
+
+    /// Fold the food `from` into `into`: its log entries and aliases move over, its
+    /// name becomes an alias of `into`, and it is deleted. Either may be given by an
+    /// alias; a name wins over another food's alias spelled the same, so merging is a way
+    /// out of that ambiguity. Aliases `into` already answers to, or that another food
+    /// owns, are dropped.
+    pub fn merge_foods(&self, from: &str, into: &str) -> Result<MergeSummary> {
+        self.retry_if_busy(|| {
+            let find = |name: &str| -> Result<(i64, String)> {
+                match self.name_owner(name)? {
+                    Some(owner) => Ok(owner),
+                    None => self.alias_owner(name)?.ok_or_else(|| anyhow::anyhow!("Food not found: '{}'", name)),
+                }
+            };
+            let (source_id, source_name) = find(from)?;
+            let (target_id, target_name) = find(into)?;
+            anyhow::ensure!(source_id != target_id, "'{}' and '{}' are the same food", from, into);
+            anyhow::ensure!(source_name != QUICK_ADD_FOOD && target_name != QUICK_ADD_FOOD,
+                "Quick adds can't be merged");
+
+            let tx = self.conn.unchecked_transaction()?;
+            let log_entries = tx.execute(
+                "UPDATE log SET food_id = ?1 WHERE food_id = ?2",
+                params![target_id, source_id],
+            )?;
+            let recipe_items = tx.execute(
+                "UPDATE recipe_items SET food_id = ?1 WHERE food_id = ?2",
+                params![target_id, source_id],
+            )?;
+
+            let source_aliases: Vec<String> = self.all_aliases()?.into_iter()
+                .filter(|(food_id, _)| *food_id == source_id)
+                .map(|(_, alias)| alias)
+                .collect();
+            tx.execute("DELETE FROM aliases WHERE food_id = ?1", params![source_id])?;
+            tx.execute("DELETE FROM foods WHERE id = ?1", params![source_id])?;
+
+            let mut aliases_moved = Vec::new();
+            let mut aliases_dropped = Vec::new();
+            for alias in source_aliases.into_iter().chain([source_name.clone()]) {
+                // Any food's name, `into`'s included, or anyone's alias (the source's are gone)
+                if self.name_owner(&alias)?.is_some() || self.alias_owner(&alias)?.is_some() {
+                    aliases_dropped.push(alias);
+                } else {
+                    tx.execute("INSERT INTO aliases (food_id, alias) VALUES (?1, ?2)", params![target_id, alias])?;
+                    aliases_moved.push(alias);
+                }
+            }
+            let summary = MergeSummary {
+                from: source_name,
+                into: target_name,
+                log_entries,
+                recipe_items,
+                aliases_moved,
+                aliases_dropped,
+            };
+            self.record_audit("merge_foods", &serde_json::json!(summary))?;
+            tx.commit()?;
+            Ok(summary)
+        })
+    }
+
+    /// Save a new recipe; its name can't already be taken by another recipe
+    pub fn add_recipe(&self, recipe: &Recipe) -> Result<()> {
+        self.retry_if_busy(|| {
+            anyhow::ensure!(self.get_recipe(&recipe.name)?.is_none(), "Recipe '{}' already exists", recipe.name);
+            let tx = self.conn.unchecked_transaction()?;
+            self.insert_recipe(recipe)?;
+            self.record_audit("add_recipe", &serde_json::json!(recipe))?;
+            tx.commit()?;
+            Ok(())
+        })
+    }
+
+    fn insert_recipe(&self, recipe: &Recipe) -> Result<()> {
         self.conn.execute(
-            "DELETE FROM foods WHERE LOWER(name) = LOWER(?1)",
-            params![name],
+            "INSERT INTO recipes (name, servings) VALUES (?1, ?2)",
+            params![recipe.name, recipe.servings],
         )?;
+        let recipe_id = self.conn.last_insert_rowid();
+        for item in &recipe.items {
+            let m = &item.macros;
+            self.conn.execute(
+                "INSERT INTO recipe_items (recipe_id, food_id, amount, protein, fat, carbs, calories, fiber)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+                params![recipe_id, item.food_id, item.amount, m.protein, m.fat, m.carbs, m.calories, m.fiber],
+            )?;
+        }
         Ok(())
     }
 
+    /// The recipe called `name` (any case), with its ingredients in the order given
+    pub fn get_recipe(&self, name: &str) -> Result<Option<Recipe>> {
+        let recipe = self.conn.query_row(
+            "SELECT id, name, servings FROM recipes WHERE LOWER(name) = LOWER(?1)",
+            params![name],
+            |row| Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?, row.get::<_, f64>(2)?)),
+        );
+        let (id, name, servings) = match recipe {
+            Ok(recipe) => recipe,
+            Err(rusqlite::Error::QueryReturnedNoRows) => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        // Ingredients keep their macros even if the food has since been deleted
+        let mut stmt = self.conn.prepare(
+            "SELECT i.food_id, COALESCE(f.name, '(deleted food)'), i.amount, i.protein, i.fat, i.carbs, i.calories, i.fiber
+             FROM recipe_items i LEFT JOIN foods f ON f.id = i.food_id
+             WHERE i.recipe_id = ?1 ORDER BY i.id"
+        )?;
+        let items = stmt
+            .query_map(params![id], |row| {
+                Ok(RecipeItem {
+                    food_id: row.get(0)?,
+                    food_name: row.get(1)?,
+                    amount: row.get(2)?,
+                    macros: Macros {
+                        protein: row.get(3)?,
+                        fat: row.get(4)?,
+                        carbs: row.get(5)?,
+                        calories: row.get(6)?,
+                        fiber: row.get(7)?,
+                    },
+                })
+            })?
+            .collect::<rusqlite::Result<_>>()?;
+        Ok(Some(Recipe { name, servings, items }))
+    }
+
+    /// Every recipe, by name
+    pub fn all_recipes(&self) -> Result<Vec<Recipe>> {
+        let mut stmt = self.conn.prepare("SELECT name FROM recipes ORDER BY name")?;
+        let names: Vec<String> = stmt.query_map([], |row| row.get(0))?.collect::<rusqlite::Result<_>>()?;
+        names.iter().filter_map(|name| self.get_recipe(name).transpose()).collect()
+    }
+
     pub fn get_stats(&self) -> Result<Stats> {
         let food_count: i64 = self.conn.query_row(
             "SELECT COUNT(*) FROM foods",
@@ -404,15 +1821,68 @@ impl Database {
             |row| row.get(0),
         ).ok();
         
+        // Span counts both ends, so a single day of logging is a span of one
+        let (days_logged, span_days): (i64, i64) = self.conn.query_row(
+            "SELECT COUNT(DISTINCT date),
+                    COALESCE(CAST(julianday(MAX(date)) - julianday(MIN(date)) AS INTEGER) + 1, 0)
+             FROM log",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )?;
+        
         Ok(Stats {
             food_count,
             log_count,
             first_entry,
             last_entry,
+            days_logged,
+            adherence: adherence_percent(days_logged, span_days),
         })
     }
 
-    pub fn export_csv(&self) -> Result<()> {
+    /// Copy the whole database to a standalone SQLite file at `path`, using the online
+    /// backup API so the copy is consistent even while another chomp is writing. The
+    /// copy is switched out of WAL mode so it's a single file.
+    pub fn backup_to(&self, path: &std::path::Path) -> Result<()> {
+        self.conn.backup(rusqlite::DatabaseName::Main, path, None)?;
+        Connection::open(path)?.query_row("PRAGMA journal_mode = DELETE", [], |_| Ok(()))?;
+        Ok(())
+    }
+
+    /// `backup_to` a new file in `dir` named for the time and the `operation` about to
+    /// run, then delete all but the newest `keep` such backups. Returns the new file.
+    pub fn backup_rotated(&self, dir: &std::path::Path, operation: &str, keep: usize) -> Result<std::path::PathBuf> {
+        std::fs::create_dir_all(dir)?;
+        let path = dir.join(format!("foods-{}-{}.db", Local::now().format("%Y%m%d-%H%M%S%.3f"), operation));
+        self.backup_to(&path)?;
+
+        let mut backups: Vec<std::path::PathBuf> = std::fs::read_dir(dir)?
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|p| p.file_name().and_then(|n| n.to_str()).is_some_and(|n| n.starts_with("foods-") && n.ends_with(".db")))
+            .collect();
+        // Named by time, so sorting puts the oldest first
+        backups.sort();
+        for old in &backups[..backups.len().saturating_sub(keep.max(1))] {
+            std::fs::remove_file(old)?;
+        }
+        Ok(path)
+    }
+
+    /// Write an export in `format` ("csv" or "json") to `out`
+    pub fn export(&self, format: &str, out: &mut impl std::io::Write) -> Result<()> {
+        match format {
+            "csv" => self.write_csv(out),
+            "json" => {
+                let backup = self.export_backup()?;
+                writeln!(out, "{}", serde_json::to_string_pretty(&backup)?)?;
+                Ok(())
+            }
+            _ => anyhow::bail!("Unknown format: {}", format),
+        }
+    }
+
+    /// Rows keep full precision so totals summed from the CSV match `today`/`history`
+    fn write_csv(&self, out: &mut impl std::io::Write) -> Result<()> {
         let mut stmt = self.conn.prepare(
             "SELECT l.date, f.name, l.amount, l.protein, l.fat, l.carbs, l.calories
              FROM log l
@@ -420,7 +1890,7 @@ impl Database {
              ORDER BY l.date, l.id"
         )?;
         
-        println!("date,food,amount,protein,fat,carbs,calories");
+        writeln!(out, "date,food,amount,protein,fat,carbs,calories")?;
         
         let mut rows = stmt.query([])?;
         while let Some(row) = rows.next()? {
@@ -432,69 +1902,407 @@ impl Database {
             let carbs: f64 = row.get(5)?;
             let calories: f64 = row.get(6)?;
             
-            println!("{},{},{},{:.1},{:.1},{:.1},{:.0}", 
-                date, name, amount, protein, fat, carbs, calories);
+            writeln!(out, "{},{},{},{},{},{},{}", 
+                date, name, amount, protein, fat, carbs, calories)?;
         }
         
         Ok(())
     }
 
-    pub fn export_json(&self) -> Result<()> {
-        let entries = self.get_history(365)?;
-        println!("{}", serde_json::to_string_pretty(&entries)?);
-        Ok(())
+    /// All foods with their aliases, and the full log, oldest first
+    pub fn export_backup(&self) -> Result<Backup> {
+        let mut foods = self.all_foods()?;
+        foods.sort_by_key(|f| f.id);
+        for (food_id, alias) in self.all_aliases()? {
+            if let Some(food) = foods.iter_mut().find(|f| f.id == Some(food_id)) {
+                food.aliases.push(alias);
+            }
+        }
+
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {} FROM log l
+             JOIN foods f ON l.food_id = f.id
+             ORDER BY l.id", LOG_COLUMNS
+        ))?;
+        let log = stmt
+            .query_map([], log_entry_from_row)?
+            .filter_map(|r| r.ok())
+            .collect();
+
+        Ok(Backup { foods, log })
+    }
+
+    pub fn schema_version(&self) -> Result<usize> {
+        Ok(self.conn.query_row("PRAGMA user_version", [], |row| row.get(0))?)
+    }
+
+    /// Where things are and what's in them, for bug reports
+    pub fn info(&self, db_path: std::path::PathBuf, config_path: std::path::PathBuf) -> Result<Info> {
+        let stats = self.get_stats()?;
+        Ok(Info {
+            version: env!("CARGO_PKG_VERSION"),
+            db_path,
+            schema_version: self.schema_version()?,
+            food_count: stats.food_count,
+            log_count: stats.log_count,
+            config_path,
+        })
+    }
+
+    pub fn dump(&self) -> Result<Dump> {
+        let Backup { foods, log } = self.export_backup()?;
+        Ok(Dump {
+            format_version: DUMP_FORMAT_VERSION,
+            schema_version: self.schema_version()?,
+            foods,
+            log,
+            goals: self.get_goals()?,
+            goal_overrides: self.get_goal_overrides()?,
+            goal_history: self.goal_history()?,
+            recipes: self.all_recipes()?,
+        })
+    }
+
+    /// Restore a dump into this database, keeping ids. The database must be
+    /// empty; `init` has already brought its schema up to date.
+    pub fn load(&self, dump: &Dump) -> Result<()> {
+        self.retry_if_busy(|| {
+            anyhow::ensure!(
+                dump.format_version <= DUMP_FORMAT_VERSION,
+                "Dump format {} is newer than this chomp supports ({}); upgrade chomp first",
+                dump.format_version, DUMP_FORMAT_VERSION
+            );
+            // A newer schema may hold data this version would silently drop
+            anyhow::ensure!(
+                dump.schema_version <= MIGRATIONS.len(),
+                "Dump is from a newer database (schema {}, this chomp has {}); upgrade chomp first",
+                dump.schema_version, MIGRATIONS.len()
+            );
+            let stats = self.get_stats()?;
+            anyhow::ensure!(
+                stats.food_count == 0 && stats.log_count == 0,
+                "chomp load needs an empty database (found {} foods, {} log entries)",
+                stats.food_count, stats.log_count
+            );
+
+            let tx = self.conn.unchecked_transaction()?;
+            for food in &dump.foods {
+                self.insert_food(food.id, food)?;
+            }
+            for entry in &dump.log {
+                let macros = entry.macros();
+                self.insert_log(entry.id, &entry.date, entry.time.as_deref(), entry.meal,
+                    entry.food_id, &entry.amount, &macros, entry.created_at.as_deref())?;
+                if let Some(group_id) = entry.group_id {
+                    self.conn.execute("UPDATE log SET group_id = ?1 WHERE id = ?2",
+                        params![group_id, self.conn.last_insert_rowid()])?;
+                }
+            }
+            for change in &dump.goal_history {
+                let g = &change.goals;
+                self.conn.execute(
+                    "INSERT INTO goal_history (effective_date, changed_at, cleared, protein, fat, carbs, calories, weekly_calories)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+                    params![change.effective_date, change.changed_at, change.cleared,
+                        g.protein, g.fat, g.carbs, g.calories, g.weekly_calories],
+                )?;
+            }
+            if let Some(goals) = &dump.goals {
+                // Dumps from before goal history start it from today, like the migration
+                if dump.goal_history.is_empty() {
+                    self.set_goals(goals)?;
+                } else {
+                    self.replace_current_goals(goals)?;
+                }
+            }
+            for day in &dump.goal_overrides {
+                self.set_goal_overrides(&[day.weekday], &day.goals)?;
+            }
+            for recipe in &dump.recipes {
+                self.insert_recipe(recipe)?;
+            }
+            self.record_audit("load", &serde_json::json!({ "foods": dump.foods.len(), "log_entries": dump.log.len() }))?;
+            tx.commit()?;
+            Ok(())
+        })
+    }
+
+    pub fn import_json(&self, path: &str, dedupe_aliases: bool) -> Result<ImportSummary> {
+        let contents = std::fs::read_to_string(path)?;
+        let backup = match serde_json::from_str(&contents)? {
+            BackupFile::Full(backup) => backup,
+            BackupFile::Entries(log) => Backup { foods: vec![], log },
+        };
+        self.import_backup(&backup, dedupe_aliases)
+    }
+
+    /// Restore a backup. Foods that already exist by name are kept as they are;
+    /// log entries are re-pointed at them. Entries whose food is missing from
+    /// both the backup and the database recreate it from the entry itself.
+    /// Entries already in the database (same date, food, amount, and time written)
+    /// are skipped, so importing a backup twice doesn't double the log. Entries
+    /// from backups too old to record when they were written can't be told apart
+    /// and are always added.
+    pub fn import_backup(&self, backup: &Backup, dedupe_aliases: bool) -> Result<ImportSummary> {
+        self.retry_if_busy(|| {
+            let tx = self.conn.unchecked_transaction()?;
+            let mut summary = ImportSummary::default();
+            let mut id_map = std::collections::HashMap::new();
+
+            for food in &backup.foods {
+                let new_id = match self.get_food_by_name(&food.name)? {
+                    Some(existing) => {
+                        summary.foods_existing += 1;
+                        existing.id.unwrap()
+                    }
+                    None => {
+                        let food = match dedupe_aliases {
+                            true => self.dedupe_aliases(food, &mut summary.aliases_skipped)?,
+                            false => food.clone(),
+                        };
+                        let id = self.add_food(&Food { aliases: vec![], ..food.clone() })?;
+                        for alias in &food.aliases {
+                            tx.execute(
+                                "INSERT OR IGNORE INTO aliases (food_id, alias) VALUES (?1, ?2)",
+                                params![id, alias],
+                            )?;
+                        }
+                        summary.foods_added += 1;
+                        id
+                    }
+                };
+                if let Some(old_id) = food.id {
+                    id_map.insert(old_id, new_id);
+                }
+            }
+
+            for entry in &backup.log {
+                let food_id = match id_map.get(&entry.food_id) {
+                    Some(id) => *id,
+                    None => match self.get_food_by_name(&entry.food_name)? {
+                        Some(food) => food.id.unwrap(),
+                        None => {
+                            let food = Food::new(&entry.food_name, entry.protein, entry.fat,
+                                entry.carbs, entry.calories, &entry.amount, vec![]);
+                            summary.foods_added += 1;
+                            self.add_food(&food)?
+                        }
+                    },
+                };
+                id_map.insert(entry.food_id, food_id);
+
+                NaiveDate::parse_from_str(&entry.date, "%Y-%m-%d")
+                    .map_err(|_| anyhow::anyhow!("Invalid date in backup: '{}'", entry.date))?;
+                if let Some(created_at) = &entry.created_at {
+                    let exists: bool = self.conn.query_row(
+                        "SELECT EXISTS (SELECT 1 FROM log
+                         WHERE date = ?1 AND food_id = ?2 AND amount = ?3 AND created_at = ?4)",
+                        params![entry.date, food_id, entry.amount, created_at],
+                        |row| row.get(0),
+                    )?;
+                    if exists {
+                        summary.log_skipped += 1;
+                        continue;
+                    }
+                }
+                let macros = entry.macros();
+                self.insert_log(None, &entry.date, entry.time.as_deref(), entry.meal, food_id, &entry.amount,
+                    &macros, entry.created_at.as_deref())?;
+                summary.log_entries += 1;
+            }
+
+            self.record_audit("import", &serde_json::json!(summary))?;
+            tx.commit()?;
+            Ok(summary)
+        })
     }
 
     pub fn import_usda(&self) -> Result<()> {
-        // TODO: Implement USDA FoodData Central import
-        println!("USDA import not yet implemented");
+        // TODO: Implement USDA FoodData Central API import
+        println!("USDA online import not yet implemented; import a downloaded dataset with --file");
         Ok(())
     }
 
+    /// Add foods read by an importer. Foods whose name is already taken (by a
+    /// food or an alias) are left as they are. With `dedupe_aliases`, aliases are
+    /// cleaned up first so a taken one is skipped instead of failing the import.
+    pub fn import_foods(&self, foods: &[Food], dedupe_aliases: bool) -> Result<ImportSummary> {
+        self.retry_if_busy(|| {
+            let tx = self.conn.unchecked_transaction()?;
+            let mut summary = ImportSummary::default();
+            for food in foods {
+                if self.get_food_by_name(&food.name)?.is_some() {
+                    summary.foods_existing += 1;
+                } else if dedupe_aliases {
+                    self.add_food(&self.dedupe_aliases(food, &mut summary.aliases_skipped)?)?;
+                    summary.foods_added += 1;
+                } else {
+                    self.add_food(food)?;
+                    summary.foods_added += 1;
+                }
+            }
+            self.record_audit("import_foods", &serde_json::json!(summary))?;
+            tx.commit()?;
+            Ok(summary)
+        })
+    }
+
+    /// `food` with its aliases normalized for import: trimmed and lowercased, with
+    /// empty ones, repeats, and the food's own name dropped. Aliases that are already
+    /// another food's name or alias are dropped too, each noted in `skipped`.
+    pub fn dedupe_aliases(&self, food: &Food, skipped: &mut Vec<String>) -> Result<Food> {
+        let mut aliases: Vec<String> = Vec::new();
+        for alias in &food.aliases {
+            let alias = alias.trim().to_lowercase();
+            if alias.is_empty() || aliases.contains(&alias) || alias == food.name.to_lowercase() {
+                continue;
+            }
+            let owner = match self.name_owner(&alias)? {
+                Some((_, owner)) => Some(format!("the name of '{}'", owner)),
+                None => self.alias_owner(&alias)?.map(|(_, owner)| format!("an alias of '{}'", owner)),
+            };
+            match owner {
+                Some(owner) => skipped.push(format!("'{}' for '{}' (already {})", alias, food.name, owner)),
+                None => aliases.push(alias),
+            }
+        }
+        Ok(Food { aliases, ..food.clone() })
+    }
+
     pub fn import_csv(&self, path: &str) -> Result<()> {
         // TODO: Implement CSV import
         println!("CSV import from {} not yet implemented", path);
         Ok(())
     }
 
-    pub fn delete_log_entry(&self, id: i64) -> Result<LogEntry> {
-        // Get the entry before deleting for confirmation
-        let entry: LogEntry = self.conn.query_row(
-            "SELECT l.id, l.date, f.name, l.food_id, l.amount, l.protein, l.fat, l.carbs, l.calories
-             FROM log l
-             JOIN foods f ON l.food_id = f.id
-             WHERE l.id = ?1",
+    /// The amount of the most recent entry for a food, if it has ever been logged
+    pub fn last_amount(&self, food_id: i64) -> Result<Option<String>> {
+        let amount = self.conn.query_row(
+            "SELECT amount FROM log WHERE food_id = ?1
+             ORDER BY date DESC, time DESC, id DESC LIMIT 1",
+            params![food_id],
+            |row| row.get(0),
+        );
+        match amount {
+            Ok(amount) => Ok(Some(amount)),
+            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn get_log_entry(&self, id: i64) -> Result<LogEntry> {
+        let entry = self.conn.query_row(
+            &format!(
+                "SELECT {} FROM log l
+                 JOIN foods f ON l.food_id = f.id
+                 WHERE l.id = ?1", LOG_COLUMNS
+            ),
             params![id],
-            |row| {
-                Ok(LogEntry {
-                    id: Some(row.get(0)?),
-                    date: row.get(1)?,
-                    food_name: row.get(2)?,
-                    food_id: row.get(3)?,
-                    amount: row.get(4)?,
-                    protein: row.get(5)?,
-                    fat: row.get(6)?,
-                    carbs: row.get(7)?,
-                    calories: row.get(8)?,
-                })
-            },
+            log_entry_from_row,
         )?;
+        Ok(entry)
+    }
+
+    pub fn delete_log_entry(&self, id: i64) -> Result<LogEntry> {
+        // Get the entry before deleting for confirmation
+        let entry = self.get_log_entry(id)?;
         
-        self.conn.execute("DELETE FROM log WHERE id = ?1", params![id])?;
+        self.audited("unlog", || {
+            self.conn.execute("DELETE FROM log WHERE id = ?1", params![id])?;
+            Ok(())
+        }, |_| serde_json::json!(entry))?;
         Ok(entry)
     }
 
-    pub fn delete_last_log_entry(&self) -> Result<LogEntry> {
-        // Get the most recent entry
-        let id: i64 = self.conn.query_row(
-            "SELECT id FROM log ORDER BY id DESC LIMIT 1",
+    /// Entries that look like one thing logged more than once (a double tap, or an MCP
+    /// client retrying): the same food and amount on the same date, each created within
+    /// a minute of the one before. Entries logged together in one combo are never
+    /// repeats of each other. Each group has at least two entries, oldest first.
+    pub fn duplicate_log_entries(&self) -> Result<Vec<Vec<LogEntry>>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {}, julianday(l.created_at) FROM log l
+             JOIN foods f ON l.food_id = f.id
+             WHERE EXISTS (
+                 SELECT 1 FROM log o
+                 WHERE o.id != l.id AND o.food_id = l.food_id AND o.amount = l.amount AND o.date = l.date
+                   AND abs(julianday(o.created_at) - julianday(l.created_at)) * 86400 < 60
+                   AND NOT (o.group_id IS NOT NULL AND o.group_id IS l.group_id)
+             )
+             ORDER BY l.date, l.food_id, l.amount, julianday(l.created_at), l.id", LOG_COLUMNS
+        ))?;
+        let rows = stmt.query_map([], |row| Ok((log_entry_from_row(row)?, row.get::<_, Option<f64>>(14)?)))?;
+
+        let mut groups: Vec<Vec<LogEntry>> = Vec::new();
+        let mut last: Option<(String, i64, String, Option<f64>)> = None;
+        for row in rows {
+            let (entry, created) = row?;
+            let follows = last.as_ref().is_some_and(|(date, food_id, amount, previous)| {
+                *date == entry.date && *food_id == entry.food_id && *amount == entry.amount
+                    && matches!((previous, created), (Some(a), Some(b)) if (b - a) * 86400.0 < 60.0)
+            });
+            last = Some((entry.date.clone(), entry.food_id, entry.amount.clone(), created));
+            match groups.last_mut() {
+                Some(group) if follows => {
+                    // Part of a combo already in this group: logged on purpose
+                    if entry.group_id.is_none() || group.iter().all(|e| e.group_id != entry.group_id) {
+                        group.push(entry);
+                    }
+                }
+                _ => groups.push(vec![entry]),
+            }
+        }
+        groups.retain(|group| group.len() > 1);
+        Ok(groups)
+    }
+
+    /// Delete all but the oldest entry of each `duplicate_log_entries` group, in one
+    /// transaction. Returns the entries deleted.
+    pub fn dedupe_log_entries(&self) -> Result<Vec<LogEntry>> {
+        self.retry_if_busy(|| {
+            let tx = self.conn.unchecked_transaction()?;
+            let mut deleted = Vec::new();
+            for group in self.duplicate_log_entries()? {
+                for entry in group.into_iter().skip(1) {
+                    tx.execute("DELETE FROM log WHERE id = ?1", params![entry.id])?;
+                    deleted.push(entry);
+                }
+            }
+            if !deleted.is_empty() {
+                self.record_audit("dedupe_log", &serde_json::json!(deleted))?;
+            }
+            tx.commit()?;
+            Ok(deleted)
+        })
+    }
+
+    /// The most recently logged entry (the one `unlog-last` would delete), if any
+    pub fn last_log_entry(&self) -> Result<Option<LogEntry>> {
+        let entry = self.conn.query_row(
+            &format!(
+                "SELECT {} FROM log l
+                 JOIN foods f ON l.food_id = f.id
+                 ORDER BY l.id DESC LIMIT 1", LOG_COLUMNS
+            ),
             [],
-            |row| row.get(0),
-        )?;
-        
-        self.delete_log_entry(id)
+            log_entry_from_row,
+        );
+        match entry {
+            Ok(entry) => Ok(Some(entry)),
+            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn delete_last_log_entry(&self) -> Result<LogEntry> {
+        let entry = self.last_log_entry()?.ok_or_else(|| anyhow::anyhow!("Nothing logged yet"))?;
+        self.delete_log_entry(entry.id.unwrap())
     }
 
+    /// Change a log entry's amount or macros. Fiber is part of the carbs, so it
+    /// follows them unless it's given. Calories, if given, are kept as they are;
+    /// otherwise they're recalculated when a macro changes. To work the macros out
+    /// again for a new amount, see `logging::edit_log_entry`.
+    #[allow(clippy::too_many_arguments)]
     pub fn edit_log_entry(
         &self,
         id: i64,
@@ -502,77 +2310,53 @@ impl Database {
         protein: Option<f64>,
         fat: Option<f64>,
         carbs: Option<f64>,
+        calories: Option<f64>,
+        fiber: Option<f64>,
     ) -> Result<LogEntry> {
         // Get the current entry
-        let entry: LogEntry = self.conn.query_row(
-            "SELECT l.id, l.date, f.name, l.food_id, l.amount, l.protein, l.fat, l.carbs, l.calories
-             FROM log l
-             JOIN foods f ON l.food_id = f.id
-             WHERE l.id = ?1",
-            params![id],
-            |row| {
-                Ok(LogEntry {
-                    id: Some(row.get(0)?),
-                    date: row.get(1)?,
-                    food_name: row.get(2)?,
-                    food_id: row.get(3)?,
-                    amount: row.get(4)?,
-                    protein: row.get(5)?,
-                    fat: row.get(6)?,
-                    carbs: row.get(7)?,
-                    calories: row.get(8)?,
-                })
-            },
-        )?;
+        let entry = self.get_log_entry(id)?;
+        // "500 kcal" is all a quick add has; a new amount would only relabel it
+        anyhow::ensure!(amount.is_none() || entry.food_name != QUICK_ADD_FOOD,
+            "Entry {} is a quick add, which has no amount to change. Edit its macros instead", id);
+        if amount.is_none() && [protein, fat, carbs, calories, fiber].iter().all(Option::is_none) {
+            return Ok(entry);
+        }
 
-        // Build update query based on which fields are provided
-        let mut updates = Vec::new();
-        let mut params_vec: Vec<Box<dyn rusqlite::ToSql>> = Vec::new();
-        
-        let new_amount = amount.clone().unwrap_or(entry.amount.clone());
+        let new_amount = match (&amount, calories) {
+            (Some(amount), _) => amount.clone(),
+            (None, Some(calories)) if entry.food_name == QUICK_ADD_FOOD => format!("{} kcal", format_number(calories)),
+            (None, _) => entry.amount.clone(),
+        };
         let new_protein = protein.unwrap_or(entry.protein);
         let new_fat = fat.unwrap_or(entry.fat);
         let new_carbs = carbs.unwrap_or(entry.carbs);
-        let new_calories = (new_protein * 4.0) + (new_fat * 9.0) + (new_carbs * 4.0);
-
-        if amount.is_some() {
-            updates.push("amount = ?");
-            params_vec.push(Box::new(new_amount.clone()));
-        }
-        if protein.is_some() {
-            updates.push("protein = ?");
-            params_vec.push(Box::new(new_protein));
-        }
-        if fat.is_some() {
-            updates.push("fat = ?");
-            params_vec.push(Box::new(new_fat));
-        }
-        if carbs.is_some() {
-            updates.push("carbs = ?");
-            params_vec.push(Box::new(new_carbs));
-        }
-        
-        // Always update calories if any macro changed
-        if protein.is_some() || fat.is_some() || carbs.is_some() {
-            updates.push("calories = ?");
-            params_vec.push(Box::new(new_calories));
+        let new_fiber = fiber.unwrap_or(match carbs {
+            Some(c) if entry.carbs > 0.0 => entry.fiber * c / entry.carbs,
+            Some(_) => 0.0,
+            None => entry.fiber,
+        });
+        if let Some(fiber) = fiber {
+            anyhow::ensure!(fiber >= 0.0, "Fiber can't be negative");
+            anyhow::ensure!(fiber <= new_carbs, "Fiber can't be more than the carbs");
         }
+        // Recalculated if any macro changed
+        let new_calories = match calories {
+            Some(calories) => calories,
+            None if protein.is_some() || fat.is_some() || carbs.is_some() => atwater_calories(new_protein, new_fat, new_carbs),
+            None => entry.calories,
+        };
 
-        if updates.is_empty() {
-            return Ok(entry);
-        }
-
-        // Add the id parameter for WHERE clause
-        params_vec.push(Box::new(id));
-        
-        let query = format!(
-            "UPDATE log SET {} WHERE id = ?",
-            updates.join(", ")
-        );
-        
-        let params_refs: Vec<&dyn rusqlite::ToSql> = params_vec.iter().map(|p| p.as_ref()).collect();
-        
-        self.conn.execute(&query, params_refs.as_slice())?;
+        self.audited("edit_log", || {
+            self.conn.execute(
+                "UPDATE log SET amount = ?1, protein = ?2, fat = ?3, carbs = ?4, calories = ?5, fiber = ?6
+                 WHERE id = ?7",
+                params![new_amount, new_protein, new_fat, new_carbs, new_calories, new_fiber, id],
+            )?;
+            Ok(())
+        }, |_| serde_json::json!({
+            "before": entry, "amount": amount, "protein": protein, "fat": fat, "carbs": carbs,
+            "calories": calories, "fiber": fiber,
+        }))?;
 
         // Return updated entry
         Ok(LogEntry {
@@ -585,6 +2369,1075 @@ impl Database {
             fat: new_fat,
             carbs: new_carbs,
             calories: new_calories,
+            fiber: new_fiber,
+            time: entry.time,
+            meal: entry.meal,
+            group_id: entry.group_id,
+            created_at: entry.created_at,
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn busy_error() -> anyhow::Error {
+        rusqlite::Error::SqliteFailure(
+            rusqlite::ffi::Error::new(rusqlite::ffi::SQLITE_BUSY),
+            Some("database is locked".to_string()),
+        )
+        .into()
+    }
+
+    #[test]
+    fn test_wal_read_after_write() {
+        let dir = TempDir::new("wal-test");
+        let path = dir.join("foods.db");
+        let writer = Database::open_at(&path).unwrap();
+        writer.init().unwrap();
+        let reader = Database::open_at(&path).unwrap();
+        let mode: String = reader.conn.query_row("PRAGMA journal_mode", [], |row| row.get(0)).unwrap();
+        assert_eq!(mode, "wal");
+
+        // The reader sees each write as soon as it's committed, with both connections open
+        let day = NaiveDate::from_ymd_opt(2024, 6, 5).unwrap();
+        assert_eq!(reader.get_totals_for_date(day, None).unwrap().calories, 0.0);
+        writer.add_food(&Food::new("egg", 6.0, 5.0, 0.5, 72.0, "1 egg", vec![])).unwrap();
+        let egg = reader.get_food_by_name("egg").unwrap().unwrap();
+        writer.log_food_on(day, egg.id.unwrap(), "2", &egg.scale(2.0)).unwrap();
+        assert_eq!(reader.get_totals_for_date(day, None).unwrap().calories, 144.0);
+        assert_eq!(reader.get_log_for_date(day).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_retry_if_busy() {
+        let db = Database::open_in_memory().unwrap();
+        let mut calls = 0;
+        let result = db.retry_if_busy(|| {
+            calls += 1;
+            if calls < 3 { Err(busy_error()) } else { Ok(42) }
+        });
+        assert_eq!(result.unwrap(), 42);
+        assert_eq!(calls, 3);
+
+        // Other errors are not retried
+        let mut calls = 0;
+        let result: Result<()> = db.retry_if_busy(|| {
+            calls += 1;
+            anyhow::bail!("Food not found")
+        });
+        assert!(result.is_err());
+        assert_eq!(calls, 1);
+
+        // Inside a transaction, only the transaction as a whole is retried
+        let mut calls = 0;
+        let result: Result<()> = db.transaction(true, || {
+            db.retry_if_busy(|| {
+                calls += 1;
+                Err(busy_error())
+            })
+        });
+        assert!(result.is_err());
+        assert_eq!(calls, BUSY_RETRIES + 1);
+    }
+
+    #[test]
+    fn test_retry_if_busy_gives_up() {
+        let db = Database::open_in_memory().unwrap();
+        let mut calls = 0;
+        let result: Result<()> = db.retry_if_busy(|| {
+            calls += 1;
+            Err(busy_error().context("logging food"))
+        });
+        assert_eq!(calls, BUSY_RETRIES + 1);
+        assert!(result.unwrap_err().to_string().contains("is `chomp serve` running?"));
+    }
+
+    #[test]
+    fn test_explain_busy() {
+        let err = explain_busy(busy_error().context("logging food"));
+        assert!(err.to_string().contains("is `chomp serve` running?"));
+
+        // Other errors pass through as they are
+        let err = explain_busy(anyhow::anyhow!("Food not found"));
+        assert_eq!(err.to_string(), "Food not found");
+    }
+
+    #[test]
+    fn test_edit_aliases() {
+        let db = Database::open_in_memory().unwrap();
+        db.add_food(&Food::new("chicken breast", 31.0, 3.6, 0.0, 165.0, "100g", vec!["cb".to_string()])).unwrap();
+        db.add_food(&Food::new("rice", 2.7, 0.3, 28.0, 130.0, "100g", vec!["white rice".to_string()])).unwrap();
+        let strings = |s: &[&str]| s.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+
+        let aliases = db.edit_aliases("chicken breast", &strings(&["chicken", "chx", "CB"]), &strings(&["cb"])).unwrap();
+        assert_eq!(aliases, vec!["CB", "chicken", "chx"]);
+        assert_eq!(db.get_food_by_name("chx").unwrap().unwrap().name, "chicken breast");
+        // Re-adding an alias it has, or its own name, is a no-op
+        let aliases = db.edit_aliases("chx", &strings(&["Chicken", "Chicken Breast"]), &[]).unwrap();
+        assert_eq!(aliases, vec!["CB", "chicken", "chx"]);
+
+        // Conflicts with another food, or removing what isn't there, change nothing
+        let err = db.edit_aliases("chicken breast", &strings(&["poultry", "white rice"]), &[]).unwrap_err();
+        assert_eq!(err.to_string(), "Alias 'white rice' already belongs to 'rice'");
+        let err = db.edit_aliases("chicken breast", &strings(&["poultry", "Rice"]), &[]).unwrap_err();
+        assert_eq!(err.to_string(), "Alias 'Rice' is already the name of another food ('rice')");
+        assert!(db.edit_aliases("chicken breast", &[], &strings(&["chx", "rice"])).is_err());
+        assert!(db.get_food_by_name("poultry").unwrap().is_none());
+        assert!(db.get_food_by_name("chx").unwrap().is_some());
+
+        db.edit_aliases("chicken breast", &[], &strings(&["CHX"])).unwrap();
+        assert!(db.get_food_by_name("chx").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_merge_foods() {
+        let db = Database::open_in_memory().unwrap();
+        db.add_food(&Food::new("chicken breast", 31.0, 3.6, 0.0, 165.0, "100g", vec!["cb".to_string()])).unwrap();
+        db.add_food(&Food::new("chicken, breast", 31.0, 3.6, 0.0, 165.0, "100g",
+            vec!["breast".to_string(), "chx".to_string()])).unwrap();
+        db.add_food(&Food::new("rice", 2.7, 0.3, 28.0, 130.0, "100g", vec![])).unwrap();
+        let target = db.get_food_by_name("chicken breast").unwrap().unwrap();
+        let source = db.get_food_by_name("chicken, breast").unwrap().unwrap();
+        let rice = db.get_food_by_name("rice").unwrap().unwrap();
+        let day = NaiveDate::from_ymd_opt(2024, 6, 5).unwrap();
+        db.log_food_on(day, target.id.unwrap(), "100g", &target.scale(1.0)).unwrap();
+        db.log_food_on(day, source.id.unwrap(), "200g", &source.scale(2.0)).unwrap();
+        db.log_food_on(day, source.id.unwrap(), "150g", &source.scale(1.5)).unwrap();
+        db.log_food_on(day, rice.id.unwrap(), "100g", &rice.scale(1.0)).unwrap();
+        let ingredients = ["chicken, breast 200g".to_string(), "rice 150g".to_string()];
+        let config = crate::config::Config::default();
+        db.add_recipe(&Recipe::build(&db, &config, "chicken and rice", 2.0, &ingredients).unwrap()).unwrap();
+
+        // Merging by alias works; a food can't be merged into itself
+        assert!(db.merge_foods("chx", "chicken, breast").is_err());
+        assert!(db.merge_foods("nope", "chicken breast").is_err());
+        let summary = db.merge_foods("chx", "cb").unwrap();
+        assert_eq!((summary.from.as_str(), summary.into.as_str()), ("chicken, breast", "chicken breast"));
+        assert_eq!((summary.log_entries, summary.recipe_items), (2, 1));
+        assert_eq!(summary.aliases_moved, vec!["breast", "chx", "chicken, breast"]);
+        assert!(summary.aliases_dropped.is_empty());
+
+        // Every entry kept its amount and macros, now under the target
+        let log = db.get_log_for_date(day).unwrap();
+        assert_eq!(log.iter().filter(|e| e.food_id == target.id.unwrap()).count(), 3);
+        assert!(log.iter().all(|e| e.food_name != "chicken, breast"));
+        assert_eq!(db.get_totals_for_date(day, None).unwrap().calories, 165.0 * 4.5 + 130.0);
+
+        // The old name, and its aliases, now find the target
+        for name in ["chicken, breast", "breast", "chx", "cb"] {
+            assert_eq!(db.get_food_by_name(name).unwrap().unwrap().id, target.id, "{}", name);
+        }
+        assert_eq!(db.all_foods().unwrap().len(), 2);
+
+        // The recipe's ingredient follows, macros and all
+        let recipe = db.get_recipe("chicken and rice").unwrap().unwrap();
+        assert_eq!(recipe.items[0].food_name, "chicken breast");
+        assert!((recipe.items[0].macros.calories - 330.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_delete_food_in_use() {
+        let db = Database::open_in_memory().unwrap();
+        db.add_food(&Food::new("egg", 6.0, 5.0, 0.6, 72.0, "1 large", vec![])).unwrap();
+        db.add_food(&Food::new("rice", 2.7, 0.3, 28.0, 130.0, "100g", vec![])).unwrap();
+        db.add_food(&Food::new("toast", 3.0, 1.0, 13.0, 75.0, "1 slice", vec![])).unwrap();
+        let egg = db.get_food_by_name("egg").unwrap().unwrap();
+        db.log_food_on(NaiveDate::from_ymd_opt(2024, 6, 5).unwrap(), egg.id.unwrap(), "2 large", &egg.scale(2.0)).unwrap();
+        let config = crate::config::Config::default();
+        db.add_recipe(&Recipe::build(&db, &config, "rice bowl", 1.0, &["rice 200g".to_string()]).unwrap()).unwrap();
+
+        // Logged or in a recipe: refused, pointing at merge
+        let err = db.delete_food("egg").unwrap_err().to_string();
+        assert!(err.contains("'egg' is used by 1 log entry. Fold it into another food instead: chomp merge"), "{}", err);
+        let err = db.delete_food("rice").unwrap_err().to_string();
+        assert!(err.contains("'rice' is used by 1 recipe."), "{}", err);
+        assert_eq!(db.all_foods().unwrap().len(), 3);
+
+        db.delete_food("toast").unwrap();
+        assert!(db.get_food_by_name("toast").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_merge_foods_alias_conflicts() {
+        let db = Database::open_in_memory().unwrap();
+        db.add_food(&Food::new("oats", 13.0, 7.0, 68.0, 389.0, "100g", vec!["oatmeal".to_string()])).unwrap();
+        db.add_food(&Food::new("rolled oats", 13.0, 7.0, 68.0, 389.0, "100g", vec![])).unwrap();
+        // Aliases are unique only by exact spelling; lookups ignore case, so "Oatmeal"
+        // on the source would clash with the target's "oatmeal"
+        let source = db.get_food_by_name("rolled oats").unwrap().unwrap();
+        db.conn.execute("INSERT INTO aliases (food_id, alias) VALUES (?1, 'Oatmeal'), (?1, 'OATS'), (?1, 'porridge oats')",
+            params![source.id]).unwrap();
+
+        let summary = db.merge_foods("rolled oats", "oats").unwrap();
+        assert_eq!(summary.aliases_moved, vec!["porridge oats", "rolled oats"]);
+        assert_eq!(summary.aliases_dropped, vec!["OATS", "Oatmeal"]);
+        let aliases: Vec<String> = db.all_aliases().unwrap().into_iter().map(|(_, a)| a).collect();
+        assert_eq!(aliases, vec!["oatmeal", "porridge oats", "rolled oats"]);
+    }
+
+    #[test]
+    fn test_alias_name_collisions_rejected() {
+        let db = Database::open_in_memory().unwrap();
+        db.add_food(&Food::new("egg", 6.0, 5.0, 0.6, 72.0, "1 large", vec![])).unwrap();
+        db.add_food(&Food::new("ribeye", 24.0, 20.0, 0.0, 291.0, "100g", vec!["steak".to_string()])).unwrap();
+
+        // An alias that is another food's name, or another food's alias
+        let err = db.add_food(&Food::new("egg whites", 11.0, 0.0, 0.7, 52.0, "100g", vec!["Egg".to_string()])).unwrap_err();
+        assert!(err.to_string().contains("already the name"), "{}", err);
+        let err = db.add_food(&Food::new("sirloin", 27.0, 8.0, 0.0, 183.0, "100g", vec!["steak".to_string()])).unwrap_err();
+        assert!(err.to_string().contains("already belongs to 'ribeye'"), "{}", err);
+        // A name that is another food's alias
+        let err = db.add_food(&Food::new("Steak", 25.0, 15.0, 0.0, 250.0, "100g", vec![])).unwrap_err();
+        assert!(err.to_string().contains("alias of 'ribeye'"), "{}", err);
+
+        // Aliasing a food to its own name is harmless
+        db.add_food(&Food::new("tofu", 8.0, 4.8, 1.9, 76.0, "100g", vec!["tofu".to_string()])).unwrap();
+        assert_eq!(db.all_foods().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn test_lookup_ambiguous_alias() {
+        let db = Database::open_in_memory().unwrap();
+        // Existing data from before collisions were rejected
+        let egg = db.add_food_unchecked(&Food::new("egg", 6.0, 5.0, 0.6, 72.0, "1 large", vec![])).unwrap();
+        db.add_food_unchecked(&Food::new("egg whites", 11.0, 0.0, 0.7, 52.0, "100g", vec!["egg".to_string()])).unwrap();
+
+        let err = db.get_food_by_name("egg").unwrap_err();
+        assert!(err.to_string().contains("ambiguous"), "{}", err);
+        // Other terms still resolve
+        assert!(db.get_food_by_name("egg whites").unwrap().is_some());
+
+        // An alias matching its own food's name is not ambiguous
+        db.conn.execute("DELETE FROM aliases", []).unwrap();
+        db.conn.execute("INSERT INTO aliases (food_id, alias) VALUES (?1, 'EGG')", params![egg]).unwrap();
+        assert_eq!(db.get_food_by_name("egg").unwrap().unwrap().id, Some(egg));
+    }
+
+    #[test]
+    fn test_search_aliases() {
+        let db = Database::open_in_memory().unwrap();
+        db.add_food(&Food::new("ribeye", 24.0, 20.0, 0.0, 291.0, "100g", vec!["steak".to_string()])).unwrap();
+        db.add_food(&Food::new("salmon", 25.0, 13.0, 0.0, 217.0, "100g", vec![])).unwrap();
+
+        let results = db.search_foods("steak", &SearchOptions::default()).unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].name, "ribeye");
+        assert_eq!(results[0].aliases, vec!["steak".to_string()]);
+
+        let name_only = SearchOptions { include_aliases: false, ..SearchOptions::default() };
+        assert!(db.search_foods("steak", &name_only).unwrap().is_empty());
+        assert_eq!(db.search_foods("ribeye", &name_only).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_search_aliases_only() {
+        let db = Database::open_in_memory().unwrap();
+        db.add_food(&Food::new("steak frites", 20.0, 18.0, 30.0, 362.0, "1 plate", vec!["bistro".to_string()])).unwrap();
+        db.add_food(&Food::new("ribeye", 24.0, 20.0, 0.0, 291.0, "100g", vec!["steak".to_string()])).unwrap();
+        db.add_food(&Food::new("sirloin", 27.0, 8.0, 0.0, 183.0, "100g", vec![])).unwrap();
+
+        // "steak frites" matches by name, and would rank alongside ribeye normally
+        assert_eq!(db.search_foods("steak", &SearchOptions::default()).unwrap().len(), 2);
+        let aliases_only = SearchOptions { aliases_only: true, ..SearchOptions::default() };
+        let matches = db.search_matches("steak", &aliases_only).unwrap();
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].food.name, "ribeye");
+        assert_eq!(matches[0].matched_alias.as_deref(), Some("steak"));
+        // Even with aliases otherwise turned off
+        let aliases_only = SearchOptions { include_aliases: false, ..aliases_only };
+        assert_eq!(db.search_matches("steak", &aliases_only).unwrap().len(), 1);
+        assert!(db.search_matches("sirloin", &aliases_only).unwrap().is_empty());
+
+        // A name match ties with an alias match in favour of the name
+        assert!(db.search_matches("ribeye", &SearchOptions::default()).unwrap()[0].matched_alias.is_none());
+    }
+
+    #[test]
+    fn test_top_foods() {
+        let db = Database::open_in_memory().unwrap();
+        db.add_food(&Food::new("chicken breast", 31.0, 3.6, 0.0, 165.0, "100g", vec![])).unwrap();
+        db.add_food(&Food::new("protein bar", 20.0, 8.0, 22.0, 240.0, "60g", vec![])).unwrap();
+        db.add_food(&Food::new("whey", 24.0, 1.5, 3.0, 120.0, "1 scoop", vec![])).unwrap();
+        db.add_food(&Food::new("egg", 6.0, 5.0, 0.6, 72.0, "50g", vec![])).unwrap();
+        db.quick_add_food_id().unwrap();
+        let names = |foods: Vec<Food>| -> Vec<String> { foods.into_iter().map(|f| f.name).collect() };
+
+        // Per serving, as stored
+        assert_eq!(names(db.top_foods(Nutrient::Protein, false, 10).unwrap()),
+            vec!["chicken breast", "whey", "protein bar", "egg"]);
+        assert_eq!(names(db.top_foods(Nutrient::Calories, false, 2).unwrap()), vec!["protein bar", "chicken breast"]);
+
+        // Per 100g: the bar and egg are scaled up, and the scoop can't be weighed
+        let top = db.top_foods(Nutrient::Protein, true, 10).unwrap();
+        assert_eq!(names(top.clone()), vec!["protein bar", "chicken breast", "egg"]);
+        assert!((top[0].protein - 20.0 / 0.6).abs() < 1e-9);
+        assert_eq!(top[0].serving, "100g");
+        assert!((top[2].protein - 12.0).abs() < 1e-9);
+        assert_eq!(names(db.top_foods(Nutrient::Fat, true, 1).unwrap()), vec!["protein bar"]);
+    }
+
+    #[test]
+    fn test_search_prefers_recently_logged() {
+        let db = Database::open_in_memory().unwrap();
+        let plain = db.add_food(&Food::new("greek yogurt plain", 10.0, 0.4, 3.6, 59.0, "100g", vec![])).unwrap();
+        let vanilla = db.add_food(&Food::new("greek yogurt vanilla", 8.0, 2.0, 11.0, 95.0, "100g", vec![])).unwrap();
+        let names = |options: &SearchOptions| -> Vec<String> {
+            db.search_foods("greek yogurt", options).unwrap().into_iter().map(|f| f.name).collect()
+        };
+        let fuzzy_only = SearchOptions { recency_weight: 0.0, frequency_weight: 0.0, ..SearchOptions::default() };
+        let unlogged_first = names(&fuzzy_only)[0].clone();
+        let (other, other_name) = if unlogged_first == "greek yogurt plain" {
+            (vanilla, "greek yogurt vanilla")
+        } else {
+            (plain, "greek yogurt plain")
+        };
+
+        // Logging the other one yesterday lifts it to the top
+        let yesterday = Local::now().date_naive() - chrono::Duration::days(1);
+        db.log_food_on(yesterday, other, "100g", &Macros::default()).unwrap();
+        assert_eq!(names(&SearchOptions::default())[0], other_name);
+        // ...unless the weights are zero
+        assert_eq!(names(&fuzzy_only)[0], unlogged_first);
+    }
+
+    #[test]
+    fn test_search_ties_sort_by_name() {
+        let db = Database::open_in_memory().unwrap();
+        // Inserted out of order; both match "bar" equally well
+        for name in ["zone bar", "Apex bar", "mars bar"] {
+            db.add_food(&Food::new(name, 10.0, 8.0, 20.0, 200.0, "1 bar", vec![])).unwrap();
+        }
+        let names = |db: &Database| db.search_foods("bar", &SearchOptions::default()).unwrap()
+            .into_iter().map(|f| f.name).collect::<Vec<_>>();
+        assert_eq!(names(&db), vec!["Apex bar", "mars bar", "zone bar"]);
+        assert_eq!(names(&db), names(&db));
+    }
+
+    #[test]
+    fn test_search_unicode_names() {
+        let db = Database::open_in_memory().unwrap();
+        for name in ["jalapeño", "crème fraîche", "🍓 jam", "jalapeno poppers"] {
+            db.add_food(&Food::new(name, 1.0, 1.0, 1.0, 17.0, "100g", vec![])).unwrap();
+        }
+        let names = |query: &str| db.search_foods(query, &SearchOptions::default()).unwrap()
+            .into_iter().map(|f| f.name).collect::<Vec<_>>();
+        assert_eq!(names("jalapeño")[0], "jalapeño");
+        assert_eq!(names("JALAPEÑO")[0], "jalapeño");
+        assert_eq!(names("jalap"), vec!["jalapeno poppers", "jalapeño"]);
+        assert_eq!(names("crème")[0], "crème fraîche");
+        assert_eq!(names("🍓"), vec!["🍓 jam"]);
+        assert_eq!(names("jam"), vec!["🍓 jam"]);
+        assert_eq!(db.get_food_by_name("Crème Fraîche").unwrap().unwrap().name, "crème fraîche");
+    }
+
+    #[test]
+    fn test_usage_boost() {
+        let today = NaiveDate::from_ymd_opt(2024, 6, 5).unwrap();
+        let options = SearchOptions::default();
+        assert_eq!(usage_boost(0, None, today, &options), 0.0);
+        let logged_today = usage_boost(1, Some(today), today, &options);
+        let logged_last_week = usage_boost(1, today.checked_sub_days(chrono::Days::new(7)), today, &options);
+        assert!(logged_today > logged_last_week);
+        assert!(usage_boost(20, Some(today), today, &options) > logged_today);
+    }
+
+    #[test]
+    fn test_adherence_percent() {
+        assert_eq!(adherence_percent(0, 0), None);
+        assert_eq!(adherence_percent(1, 1), Some(100.0));
+        assert_eq!(adherence_percent(3, 4), Some(75.0));
+        assert_eq!(adherence_percent(7, 7), Some(100.0));
+    }
+
+    #[test]
+    fn test_stats_days_logged() {
+        let db = Database::open_in_memory().unwrap();
+        let stats = db.get_stats().unwrap();
+        assert_eq!(stats.days_logged, 0);
+        assert_eq!(stats.adherence, None);
+
+        let id = db.add_food(&Food::new("egg", 6.0, 5.0, 0.6, 72.0, "1 large", vec![])).unwrap();
+        let date = |s| NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap();
+        let macros = Macros::default();
+        db.log_food_on(date("2024-06-01"), id, "1", &macros).unwrap();
+        db.log_food_on(date("2024-06-01"), id, "1", &macros).unwrap();
+        let stats = db.get_stats().unwrap();
+        assert_eq!(stats.days_logged, 1);
+        assert_eq!(stats.adherence, Some(100.0));
+
+        // Logged on the 1st, 2nd and 4th of a four-day span
+        db.log_food_on(date("2024-06-02"), id, "1", &macros).unwrap();
+        db.log_food_on(date("2024-06-04"), id, "1", &macros).unwrap();
+        let stats = db.get_stats().unwrap();
+        assert_eq!(stats.days_logged, 3);
+        assert_eq!(stats.adherence, Some(75.0));
+    }
+
+    #[test]
+    fn test_stats_json_shape() {
+        let db = Database::open_in_memory().unwrap();
+        let value = serde_json::to_value(db.get_stats().unwrap()).unwrap();
+        assert_eq!(value, serde_json::json!({
+            "food_count": 0,
+            "log_count": 0,
+            "first_entry": null,
+            "last_entry": null,
+            "days_logged": 0,
+            "adherence": null,
+        }));
+    }
+
+    #[test]
+    fn test_no_per_row_rounding_drift() {
+        let db = Database::open_in_memory().unwrap();
+        let id = db.add_food(&Food::new("mint", 0.0, 0.0, 0.1, 0.4, "1 piece", vec![])).unwrap();
+        let today = Local::now().date_naive();
+        let macros = Macros { protein: 0.0, fat: 0.0, carbs: 0.1, calories: 0.4, fiber: 0.0 };
+        for _ in 0..25 {
+            db.log_food_on(today, id, "1", &macros).unwrap();
+        }
+
+        // Rounding each 0.4 kcal row first would total 0
+        let totals = db.get_today_totals().unwrap();
+        assert!((totals.calories - 10.0).abs() < 1e-9);
+        assert!((totals.carbs - 2.5).abs() < 1e-9);
+
+        let mut csv = Vec::new();
+        db.write_csv(&mut csv).unwrap();
+        let csv_calories: f64 = String::from_utf8(csv).unwrap()
+            .lines()
+            .skip(1)
+            .map(|line| line.rsplit(',').next().unwrap().parse::<f64>().unwrap())
+            .sum();
+        assert!((csv_calories - totals.calories).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_last_log_entry() {
+        let db = Database::open_in_memory().unwrap();
+        let egg = db.add_food(&Food::new("egg", 6.0, 5.0, 0.6, 72.0, "1 large", vec![])).unwrap();
+        assert!(db.last_log_entry().unwrap().is_none());
+        assert!(db.delete_last_log_entry().is_err());
+
+        let date = |s| NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap();
+        let macros = Macros::default();
+        let newest = db.log_food_on(date("2024-06-05"), egg, "2 large", &macros).unwrap();
+        // Logged last but dated earlier: still the last one logged
+        let backfilled = db.log_food_on(date("2024-06-01"), egg, "1 large", &macros).unwrap();
+
+        let last = db.last_log_entry().unwrap().unwrap();
+        assert_eq!(last.id, backfilled.id);
+        assert_eq!(last.food_name, "egg");
+        // Looking doesn't delete
+        assert_eq!(db.last_log_entry().unwrap().unwrap().id, backfilled.id);
+        assert_eq!(db.delete_last_log_entry().unwrap().id, backfilled.id);
+        assert_eq!(db.last_log_entry().unwrap().unwrap().id, newest.id);
+    }
+
+    #[test]
+    fn test_get_log_for_date() {
+        let db = Database::open_in_memory().unwrap();
+        let egg = db.add_food(&Food::new("egg", 6.0, 5.0, 0.6, 72.0, "1 large", vec![])).unwrap();
+        let date = |s| NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap();
+        let macros = Macros::default();
+        db.log_food_on(date("2024-06-01"), egg, "2", &macros).unwrap();
+        let first = db.log_food_on(date("2024-06-02"), egg, "1", &macros).unwrap();
+        let second = db.log_food_on(date("2024-06-02"), egg, "3", &macros).unwrap();
+
+        let entries = db.get_log_for_date(date("2024-06-02")).unwrap();
+        let ids: Vec<_> = entries.iter().map(|e| e.id).collect();
+        assert_eq!(ids, vec![first.id, second.id]);
+        assert_eq!(entries[0].food_name, "egg");
+        assert!(db.get_log_for_date(date("2024-06-03")).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_entries_sort_by_time_within_date() {
+        let db = Database::open_in_memory().unwrap();
+        let egg = db.add_food(&Food::new("egg", 6.0, 5.0, 0.6, 72.0, "1 large", vec![])).unwrap();
+        let today = Local::now().date_naive();
+        let at = |h, m| LogMeta { date: today, time: NaiveTime::from_hms_opt(h, m, 0), meal: None };
+        let macros = Macros::default();
+        // Logged out of order: dinner first, then breakfast, then lunch
+        let dinner = db.log_food_at(&at(19, 0), egg, "3", &macros).unwrap();
+        let breakfast = db.log_food_at(&at(8, 0), egg, "1", &macros).unwrap();
+        let lunch = db.log_food_at(&LogMeta { meal: Some(Meal::Lunch), ..at(12, 30) }, egg, "2", &macros).unwrap();
+
+        let ids = |entries: Vec<LogEntry>| entries.iter().map(|e| e.id).collect::<Vec<_>>();
+        assert_eq!(ids(db.get_log_for_date(today).unwrap()), vec![breakfast.id, lunch.id, dinner.id]);
+        assert_eq!(ids(db.get_history(1, None, None).unwrap()), vec![dinner.id, lunch.id, breakfast.id]);
+
+        let stored = db.get_log_entry(lunch.id.unwrap()).unwrap();
+        assert_eq!(stored.time.as_deref(), Some("12:30"));
+        assert_eq!(stored.meal, Some(Meal::Lunch));
+    }
+
+    #[test]
+    fn test_filter_by_meal() {
+        let db = Database::open_in_memory().unwrap();
+        db.add_food(&Food::new("egg", 6.0, 5.0, 0.6, 72.0, "1 large", vec![])).unwrap();
+        let egg = db.get_food_by_name("egg").unwrap().unwrap();
+        let today = Local::now().date_naive();
+        let meal = |meal| LogMeta { date: today, time: None, meal };
+        let breakfast = db.log_food_at(&meal(Some(Meal::Breakfast)), egg.id.unwrap(), "2", &egg.scale(2.0)).unwrap();
+        db.log_food_at(&meal(Some(Meal::Dinner)), egg.id.unwrap(), "3", &egg.scale(3.0)).unwrap();
+        db.log_food_at(&meal(None), egg.id.unwrap(), "1", &egg.scale(1.0)).unwrap();
+        let yesterday = today.pred_opt().unwrap();
+        let earlier = db.log_food_at(&LogMeta { date: yesterday, time: None, meal: Some(Meal::Breakfast) },
+            egg.id.unwrap(), "4", &egg.scale(4.0)).unwrap();
+
+        let ids = |entries: Vec<LogEntry>| entries.iter().map(|e| e.id).collect::<Vec<_>>();
+        assert_eq!(ids(db.get_history(2, None, Some(Meal::Breakfast)).unwrap()), vec![breakfast.id, earlier.id]);
+        assert_eq!(ids(db.get_log_range(Some(today), None, None, Some(Meal::Breakfast)).unwrap()), vec![breakfast.id]);
+        assert!(db.get_history(2, None, Some(Meal::Lunch)).unwrap().is_empty());
+        assert_eq!(db.get_history(2, None, None).unwrap().len(), 4);
+
+        assert_eq!(db.get_totals_for_date(today, Some(Meal::Breakfast)).unwrap().protein, 12.0);
+        assert_eq!(db.get_totals_for_date(today, Some(Meal::Dinner)).unwrap().calories, 216.0);
+        assert_eq!(db.get_totals_for_date(today, Some(Meal::Snack)).unwrap().calories, 0.0);
+        assert_eq!(db.get_totals_for_date(today, None).unwrap().protein, 36.0);
+    }
+
+    #[test]
+    fn test_scale_foods() {
+        let db = Database::open_in_memory().unwrap();
+        db.add_food(&Food::new("USDA chicken", 62.0, 7.2, 0.0, 330.0, "1 breast", vec![])).unwrap();
+        db.add_food(&Food { fiber: 1.8, ..Food::new("USDA rice", 5.4, 0.6, 56.0, 260.0, "1 cup", vec![]) }).unwrap();
+        db.add_food(&Food::new("egg", 6.0, 5.0, 0.6, 72.0, "1 large", vec![])).unwrap();
+
+        // Dry run reports the new values but changes nothing
+        let preview = db.scale_foods(Some("usda"), 0.5, true).unwrap();
+        assert_eq!(preview.len(), 2);
+        assert!((preview[0].protein - 31.0).abs() < 1e-9);
+        assert_eq!(db.get_food_by_name("USDA chicken").unwrap().unwrap().protein, 62.0);
+
+        db.scale_foods(Some("usda"), 0.5, false).unwrap();
+        let chicken = db.get_food_by_name("USDA chicken").unwrap().unwrap();
+        assert!((chicken.protein - 31.0).abs() < 1e-9);
+        assert!((chicken.calories - 165.0).abs() < 1e-9);
+        assert_eq!(chicken.serving, "1 breast");
+        // Fiber scales with the carbs it's part of
+        let rice = db.get_food_by_name("USDA rice").unwrap().unwrap();
+        assert!((rice.carbs - 28.0).abs() < 1e-9);
+        assert!((rice.fiber - 0.9).abs() < 1e-9);
+        assert_eq!(db.get_food_by_name("egg").unwrap().unwrap().protein, 6.0);
+
+        assert_eq!(db.scale_foods(None, 2.0, true).unwrap().len(), 3);
+        assert!(db.scale_foods(None, 0.0, true).is_err());
+    }
+
+    #[test]
+    fn test_load_minimal_old_dump() {
+        // Only the fields the first releases wrote, plus one from some future version
+        let json = r#"{
+            "format_version": 1,
+            "foods": [
+                {"id": 3, "name": "egg", "protein": 6, "fat": 5, "carbs": 0.6, "calories": 72,
+                 "serving": "1 large", "fiber": 0}
+            ],
+            "log": [
+                {"date": "2024-06-01", "food_name": "egg", "food_id": 3, "amount": "2",
+                 "protein": 12, "fat": 10, "carbs": 1.2, "calories": 144}
+            ]
+        }"#;
+        let dump: Dump = serde_json::from_str(json).unwrap();
+        assert_eq!(dump.schema_version, 0);
+        assert!(dump.goals.is_none());
+        assert!(dump.foods[0].aliases.is_empty());
+        assert!(dump.log[0].id.is_none());
+
+        let db = Database::open_in_memory().unwrap();
+        db.load(&dump).unwrap();
+        let egg = db.get_food_by_name("egg").unwrap().unwrap();
+        assert_eq!(egg.id, Some(3));
+        assert_eq!(db.get_stats().unwrap().log_count, 1);
+
+        let food: Food = serde_json::from_str(r#"{"name": "rice", "protein": 2.7, "fat": 0.3, "carbs": 28, "calories": 130}"#).unwrap();
+        assert_eq!(food.serving, "100g");
+    }
+
+    #[test]
+    fn test_load_rejects_newer_schema() {
+        let db = Database::open_in_memory().unwrap();
+        let mut dump = db.dump().unwrap();
+        dump.schema_version = MIGRATIONS.len() + 1;
+        let err = db.load(&dump).unwrap_err().to_string();
+        assert!(err.contains("newer database"), "{}", err);
+        dump.schema_version = MIGRATIONS.len();
+        db.load(&dump).unwrap();
+    }
+
+    #[test]
+    fn test_export_filename_has_date() {
+        let date = NaiveDate::from_ymd_opt(2024, 6, 1).unwrap();
+        assert_eq!(export_filename("csv", date), "chomp-export-2024-06-01.csv");
+        assert_eq!(export_filename("json", date), "chomp-export-2024-06-01.json");
+        assert_eq!(export_filename("text", date), "chomp-export-2024-06-01.txt");
+    }
+
+    #[test]
+    fn test_history_with_huge_days() {
+        let today = Local::now().date_naive();
+        assert!(history_start(today, 4_000_000_000).is_none());
+        assert_eq!(history_start(today, 7), Some(today - chrono::Duration::days(6)));
+        assert_eq!(history_start(today, 1), Some(today));
+
+        let db = Database::open_in_memory().unwrap();
+        let egg = db.add_food(&Food::new("egg", 6.0, 5.0, 0.6, 72.0, "1 large", vec![])).unwrap();
+        db.log_food_on(NaiveDate::from_ymd_opt(1970, 1, 1).unwrap(), egg, "1", &Macros::default()).unwrap();
+        db.log_food_at(&LogMeta::now(), egg, "1", &Macros::default()).unwrap();
+
+        assert_eq!(db.get_history(4_000_000_000, None, None).unwrap().len(), 2);
+        assert_eq!(db.get_history(u32::MAX, None, None).unwrap().len(), 2);
+        assert_eq!(db.get_history(7, None, None).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_history_window_includes_today() {
+        let db = Database::open_in_memory().unwrap();
+        let egg = db.add_food(&Food::new("egg", 6.0, 5.0, 0.6, 72.0, "1 large", vec![])).unwrap();
+        let today = Local::now().date_naive();
+        for days_ago in 0..3 {
+            db.log_food_on(today - chrono::Duration::days(days_ago), egg, "1", &Macros::default()).unwrap();
+        }
+
+        assert_eq!(db.get_history(1, None, None).unwrap().len(), 1);
+        assert_eq!(db.get_history(2, None, None).unwrap().len(), 2);
+        assert_eq!(db.get_history(3, None, None).unwrap().len(), 3);
+        assert!(db.get_history(0, None, None).is_err());
+    }
+
+    #[test]
+    fn test_history_explicit_range() {
+        let date = |s| NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap();
+        let today = date("2024-07-10");
+        // Explicit range, open-ended since, and --days counted back from --until
+        assert_eq!(history_window(today, 7, Some(date("2024-06-01")), Some(date("2024-06-30"))).unwrap(),
+            (Some(date("2024-06-01")), Some(date("2024-06-30"))));
+        assert_eq!(history_window(today, 7, Some(date("2024-06-01")), None).unwrap(),
+            (Some(date("2024-06-01")), None));
+        assert_eq!(history_window(today, 7, None, Some(date("2024-06-30"))).unwrap(),
+            (Some(date("2024-06-24")), Some(date("2024-06-30"))));
+        assert_eq!(history_window(today, 7, None, None).unwrap(), (Some(date("2024-07-04")), None));
+        // A single day is fine; a backwards range is not
+        assert!(history_window(today, 7, Some(date("2024-06-30")), Some(date("2024-06-30"))).is_ok());
+        let err = history_window(today, 7, Some(date("2024-06-30")), Some(date("2024-06-01"))).unwrap_err();
+        assert!(err.to_string().contains("must not be after"));
+
+        let db = Database::open_in_memory().unwrap();
+        let egg = db.add_food(&Food::new("egg", 6.0, 5.0, 0.6, 72.0, "1 large", vec![])).unwrap();
+        for day in ["2024-05-31", "2024-06-01", "2024-06-15", "2024-06-30", "2024-07-01"] {
+            db.log_food_on(date(day), egg, "1", &Macros::default()).unwrap();
+        }
+        let dates = |entries: Vec<LogEntry>| entries.into_iter().map(|e| e.date).collect::<Vec<_>>();
+        assert_eq!(dates(db.get_log_range(Some(date("2024-06-01")), Some(date("2024-06-30")), None, None).unwrap()),
+            vec!["2024-06-30", "2024-06-15", "2024-06-01"]);
+        assert_eq!(db.get_log_range(None, Some(date("2024-06-01")), None, None).unwrap().len(), 2);
+        assert_eq!(db.get_log_range(None, None, None, None).unwrap().len(), 5);
+    }
+
+    #[test]
+    fn test_history_limit() {
+        let date = |s| NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap();
+        let db = Database::open_in_memory().unwrap();
+        let egg = db.add_food(&Food::new("egg", 6.0, 5.0, 0.6, 72.0, "1 large", vec![])).unwrap();
+        for (day, amount) in [("2024-06-01", "1"), ("2024-06-03", "2"), ("2024-06-03", "3"), ("2024-06-10", "4")] {
+            db.log_food_on(date(day), egg, amount, &Macros::default()).unwrap();
+        }
+        let amounts = |entries: Vec<LogEntry>| entries.into_iter().map(|e| e.amount).collect::<Vec<_>>();
+
+        // The N most recent, however old
+        assert_eq!(amounts(db.get_log_range(None, None, Some(3), None).unwrap()), vec!["4", "3", "2"]);
+        // Within a window
+        assert_eq!(amounts(db.get_log_range(None, Some(date("2024-06-05")), Some(2), None).unwrap()), vec!["3", "2"]);
+        assert_eq!(db.get_log_range(Some(date("2024-06-10")), None, Some(5), None).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_goals_for_training_and_rest_days() {
+        let db = Database::open_in_memory().unwrap();
+        let goals = Goals { protein: Some(180.0), carbs: Some(150.0), calories: Some(2200.0), ..Goals::default() };
+        db.set_goals_from(&goals, NaiveDate::from_ymd_opt(2024, 6, 1).unwrap()).unwrap();
+        let training = Goals { carbs: Some(300.0), calories: Some(2800.0), ..Goals::default() };
+        db.set_goal_overrides(&[Weekday::Mon, Weekday::Wed, Weekday::Fri], &training).unwrap();
+
+        // 2024-06-05 is a Wednesday, 2024-06-06 a Thursday
+        let wednesday = db.get_goals_for(NaiveDate::from_ymd_opt(2024, 6, 5).unwrap()).unwrap().unwrap();
+        assert_eq!(wednesday.carbs, Some(300.0));
+        assert_eq!(wednesday.calories, Some(2800.0));
+        assert_eq!(wednesday.protein, Some(180.0));
+
+        let thursday = db.get_goals_for(NaiveDate::from_ymd_opt(2024, 6, 6).unwrap()).unwrap().unwrap();
+        assert_eq!(thursday.carbs, Some(150.0));
+        assert_eq!(thursday.calories, Some(2200.0));
+
+        assert_eq!(db.get_goal_overrides().unwrap().len(), 3);
+        assert_eq!(db.get_goals().unwrap().unwrap().carbs, Some(150.0));
+
+        // Clearing the defaults leaves the overrides; they go one weekday at a time
+        db.clear_goals(NaiveDate::from_ymd_opt(2024, 6, 1).unwrap()).unwrap();
+        assert_eq!(db.get_goal_overrides().unwrap().len(), 3);
+        assert_eq!(db.get_goals_for(NaiveDate::from_ymd_opt(2024, 6, 5).unwrap()).unwrap(), Some(training));
+        db.clear_goal_overrides(&[Weekday::Mon, Weekday::Wed]).unwrap();
+        assert_eq!(db.get_goal_overrides().unwrap().iter().map(|d| d.weekday).collect::<Vec<_>>(), vec![Weekday::Fri]);
+    }
+
+    #[test]
+    fn test_audit_log() {
+        let mut db = Database::open_in_memory().unwrap();
+        let egg = db.add_food(&Food::new("egg", 6.0, 5.0, 0.5, 72.0, "1 large", vec![])).unwrap();
+        let macros = Macros { protein: 6.0, fat: 5.0, carbs: 0.5, calories: 72.0, fiber: 0.0 };
+        db.log_food_on(NaiveDate::from_ymd_opt(2024, 6, 1).unwrap(), egg, "1 large", &macros).unwrap();
+        // Off unless asked for
+        assert!(db.audit_entries(7).unwrap().is_empty());
+
+        db.set_audit(true);
+        let entry = db.log_food_on(NaiveDate::from_ymd_opt(2024, 6, 2).unwrap(), egg, "2 large", &macros).unwrap();
+        let group = LogMeta::on(NaiveDate::from_ymd_opt(2024, 6, 3).unwrap());
+        db.log_group(&group, &[(egg, "1 large".to_string(), macros.clone()), (egg, "1 large".to_string(), macros.clone())]).unwrap();
+        db.edit_food("egg", Some(6.5), None, None, None, None, None).unwrap();
+        db.delete_log_entry(entry.id.unwrap()).unwrap();
+
+        let audit = db.audit_entries(7).unwrap();
+        let actions: Vec<&str> = audit.iter().map(|a| a.action.as_str()).collect();
+        assert_eq!(actions, vec!["log", "log", "log", "edit_food", "unlog"]);
+        assert_eq!(audit[0].payload["id"], entry.id.unwrap());
+        assert_eq!(audit[0].payload["amount"], "2 large");
+        assert_eq!(audit[3].payload["before"]["protein"], 6.0);
+        assert_eq!(audit[3].payload["protein"], 6.5);
+
+        // Changes to many rows at once are recorded as one summary each; dry runs aren't
+        db.scale_foods(Some("egg"), 2.0, true).unwrap();
+        db.scale_foods(Some("egg"), 0.5, false).unwrap();
+        db.set_food_calories(egg, 40.0).unwrap();
+        db.set_goal_overrides(&[Weekday::Mon], &Goals { carbs: Some(300.0), ..Goals::default() }).unwrap();
+        let day = NaiveDate::from_ymd_opt(2024, 6, 5).unwrap();
+        db.log_food_on(day, egg, "1 large", &macros).unwrap();
+        db.log_food_on(day, egg, "1 large", &macros).unwrap();
+        db.dedupe_log_entries().unwrap();
+        let audit = db.audit_entries(7).unwrap();
+        let actions: Vec<&str> = audit[5..].iter().map(|a| a.action.as_str()).collect();
+        assert_eq!(actions, vec!["scale_foods", "set_food_calories", "set_goal_overrides", "log", "log", "dedupe_log"]);
+        assert_eq!(audit[5].payload, serde_json::json!({ "foods": ["egg"], "factor": 0.5 }));
+        assert_eq!(audit[7].payload["weekdays"], serde_json::json!(["Mon"]));
+        assert_eq!(audit[10].payload.as_array().unwrap().len(), 1);
+
+        // A change that fails leaves no record, and a rolled-back one takes its record with it
+        assert!(db.add_food(&Food::new("egg", 1.0, 1.0, 1.0, 17.0, "1 large", vec![])).is_err());
+        let rolled_back: Result<()> = db.transaction(false, || {
+            db.log_food_on(NaiveDate::from_ymd_opt(2024, 6, 4).unwrap(), egg, "1 large", &macros)?;
+            Ok(())
+        });
+        rolled_back.unwrap();
+        assert_eq!(db.audit_entries(7).unwrap().len(), 11);
+    }
+
+    #[test]
+    fn test_migrations_are_idempotent() {
+        let db = Database::open_in_memory().unwrap();
+        db.init().unwrap();
+        let version: usize = db.conn.query_row("PRAGMA user_version", [], |row| row.get(0)).unwrap();
+        assert_eq!(version, MIGRATIONS.len());
+
+        let mut cream = Food::new("heavy cream", 2.0, 36.0, 3.0, 340.0, "100ml", vec![]);
+        cream.density = Some(1.01);
+        db.add_food(&cream).unwrap();
+        let cream = db.get_food_by_name("heavy cream").unwrap().unwrap();
+        assert_eq!(cream.density, Some(1.01));
+
+        db.add_food(&Food::new("protein bar", 20.0, 8.0, 22.0, 240.0, "1 bar", vec![]).normalized_per_100g(60.0)).unwrap();
+        let bar = db.get_food_by_name("protein bar").unwrap().unwrap();
+        assert_eq!(bar.count_unit(), Some(("bar", 60.0)));
+    }
+
+    #[test]
+    fn test_fiber_and_net_carbs() {
+        let db = Database::open_in_memory().unwrap();
+        let mut oats = Food::new("oats", 13.0, 7.0, 68.0, 389.0, "100g", vec![]);
+        oats.fiber = 10.0;
+        let oats_id = db.add_food(&oats).unwrap();
+        assert_eq!(db.get_food_by_name("oats").unwrap().unwrap().fiber, 10.0);
+        let egg = Food::new("egg", 6.0, 5.0, 0.5, 72.0, "1 large", vec![]);
+        let egg_id = db.add_food(&egg).unwrap();
+
+        let day = NaiveDate::from_ymd_opt(2024, 6, 1).unwrap();
+        let entry = db.log_food_on(day, oats_id, "50g", &oats.scale(0.5)).unwrap();
+        assert_eq!(entry.fiber, 5.0);
+        assert_eq!(entry.macros().net_carbs(), 29.0);
+        db.log_food_on(day, egg_id, "2", &egg.scale(2.0)).unwrap();
+
+        let totals = db.get_totals_for_date(day, None).unwrap();
+        assert_eq!(totals.fiber, 5.0);
+        assert_eq!(totals.net_carbs(), 30.0);
+        assert_eq!(db.get_daily_totals(day, day).unwrap()[0].totals.fiber, 5.0);
+        assert_eq!(db.get_log_for_date(day).unwrap()[0].fiber, 5.0);
+
+        // Backups from before fiber was tracked load with none
+        let old: LogEntry = serde_json::from_str(
+            r#"{"date": "2024-06-01", "food_name": "egg", "food_id": 1, "amount": "2",
+                "protein": 12, "fat": 10, "carbs": 1, "calories": 144}"#).unwrap();
+        assert_eq!(old.fiber, 0.0);
+        assert_eq!(old.macros().net_carbs(), 1.0);
+    }
+
+    #[test]
+    fn test_backup_round_trip() {
+        let db = Database::open_in_memory().unwrap();
+        let ribeye = Food::new("ribeye", 24.0, 20.0, 0.0, 291.0, "100g", vec!["steak".to_string()]);
+        let ribeye_id = db.add_food(&ribeye).unwrap();
+        let mut egg = Food::new("egg", 6.0, 5.0, 0.5, 72.0, "1 egg", vec![]);
+        egg.default_amount = Some("2 egg".to_string());
+        let egg_id = db.add_food(&egg).unwrap();
+        let day = NaiveDate::from_ymd_opt(2024, 6, 1).unwrap();
+        db.log_food_on(day, ribeye_id, "8oz", &ribeye.scale(2.268)).unwrap();
+        db.log_food_on(day, egg_id, "2", &egg.scale(2.0)).unwrap();
+
+        let backup = db.export_backup().unwrap();
+        assert_eq!(backup.foods[0].aliases, vec!["steak".to_string()]);
+        let json = serde_json::to_string(&backup).unwrap();
+
+        let restored = Database::open_in_memory().unwrap();
+        let file: BackupFile = serde_json::from_str(&json).unwrap();
+        let BackupFile::Full(backup) = file else { panic!("expected a full backup") };
+        let summary = restored.import_backup(&backup, false).unwrap();
+        assert_eq!(summary.foods_added, 2);
+        assert_eq!(summary.log_entries, 2);
+
+        let round_tripped = serde_json::to_string(&restored.export_backup().unwrap()).unwrap();
+        assert_eq!(json, round_tripped);
+
+        // A second import of the same backup adds nothing
+        let again = restored.import_backup(&backup, false).unwrap();
+        assert_eq!((again.log_entries, again.log_skipped, again.foods_added), (0, 2, 0));
+        assert_eq!(restored.get_stats().unwrap().log_count, 2);
+        assert_eq!(restored.get_food_by_name("steak").unwrap().unwrap().name, "ribeye");
+    }
+
+    #[test]
+    fn test_dump_load_round_trip() {
+        let db = Database::open_in_memory().unwrap();
+        let ribeye = Food::new("ribeye", 24.0, 20.0, 0.0, 291.0, "100g", vec!["steak".to_string()]);
+        let ribeye_id = db.add_food(&ribeye).unwrap();
+        let mut cream = Food::new("heavy cream", 2.0, 36.0, 3.0, 340.0, "100ml", vec![]);
+        cream.density = Some(1.01);
+        let cream_id = db.add_food(&cream).unwrap();
+        // Deleted rows leave gaps in the ids that a load must preserve
+        let day = NaiveDate::from_ymd_opt(2024, 6, 1).unwrap();
+        let gone = db.log_food_on(day, ribeye_id, "1g", &ribeye.scale(0.01)).unwrap();
+        db.delete_log_entry(gone.id.unwrap()).unwrap();
+        db.log_food_on(day, ribeye_id, "8oz", &ribeye.scale(2.268)).unwrap();
+        db.log_food_on(day, cream_id, "30ml", &cream.scale(0.3)).unwrap();
+        db.set_goals(&Goals { protein: Some(180.0), ..Goals::default() }).unwrap();
+        db.set_goal_overrides(&[Weekday::Mon], &Goals { carbs: Some(300.0), ..Goals::default() }).unwrap();
+        let steak = Recipe::build(&db, &crate::config::Config::default(), "steak dinner", 2.0,
+            &["ribeye 16oz".to_string(), "heavy cream 60ml".to_string()]).unwrap();
+        db.add_recipe(&steak).unwrap();
+
+        let dump = serde_json::to_string_pretty(&db.dump().unwrap()).unwrap();
+
+        let restored = Database::open_in_memory().unwrap();
+        restored.load(&serde_json::from_str(&dump).unwrap()).unwrap();
+        let round_tripped = serde_json::to_string_pretty(&restored.dump().unwrap()).unwrap();
+        assert_eq!(dump, round_tripped);
+
+        // Loading over existing data is refused
+        assert!(restored.load(&serde_json::from_str(&dump).unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_import_old_log_only_export() {
+        let json = r#"[{"id": 7, "date": "2024-06-01", "food_name": "bare bar", "food_id": 3,
+            "amount": "1 bar", "protein": 10.0, "fat": 8.0, "carbs": 20.0, "calories": 200.0}]"#;
+        let BackupFile::Entries(log) = serde_json::from_str(json).unwrap() else {
+            panic!("expected a log array")
+        };
+
+        let db = Database::open_in_memory().unwrap();
+        let summary = db.import_backup(&Backup { foods: vec![], log }, false).unwrap();
+        assert_eq!(summary.foods_added, 1);
+        let bar = db.get_food_by_name("bare bar").unwrap().unwrap();
+        assert_eq!(bar.serving, "1 bar");
+        assert_eq!(db.get_stats().unwrap().log_count, 1);
+    }
+
+    #[test]
+    fn test_food_source() {
+        let db = Database::open_in_memory().unwrap();
+        db.add_food(&Food::new("egg", 6.0, 5.0, 0.6, 72.0, "1 large", vec![])).unwrap();
+        assert_eq!(db.get_food_by_name("egg").unwrap().unwrap().source, FoodSource::Manual);
+
+        // Imported foods keep the source they were recorded with; a backup
+        // from before sources existed comes in as manual
+        let json = r#"{"foods": [
+            {"id": 1, "name": "Egg, whole, raw", "protein": 12.6, "fat": 9.5, "carbs": 0.7,
+             "calories": 143.0, "serving": "100g", "source": "usda"},
+            {"id": 2, "name": "eggnog", "protein": 4.6, "fat": 4.2, "carbs": 8.1,
+             "calories": 88.0, "serving": "100g"}
+        ], "log": []}"#;
+        let BackupFile::Full(backup) = serde_json::from_str(json).unwrap() else {
+            panic!("expected a full backup")
+        };
+        db.import_backup(&backup, false).unwrap();
+        assert_eq!(db.get_food_by_name("Egg, whole, raw").unwrap().unwrap().source, FoodSource::Usda);
+        assert_eq!(db.get_food_by_name("eggnog").unwrap().unwrap().source, FoodSource::Manual);
+
+        let usda_only = SearchOptions { source: Some(FoodSource::Usda), ..SearchOptions::default() };
+        let names: Vec<String> = db.search_foods("egg", &usda_only).unwrap().into_iter().map(|f| f.name).collect();
+        assert_eq!(names, vec!["Egg, whole, raw"]);
+        assert_eq!(db.search_foods("egg", &SearchOptions::default()).unwrap().len(), 3);
+        assert_eq!("MFP".parse::<FoodSource>(), Ok(FoodSource::Mfp));
+        assert!("fatsecret".parse::<FoodSource>().is_err());
+    }
+
+    #[test]
+    fn test_edit_food_calories() {
+        let db = Database::open_in_memory().unwrap();
+        db.add_food(&Food::new("bar", 20.0, 10.0, 25.0, 230.0, "1 bar", vec![])).unwrap();
+
+        // Explicit calories are kept as given, even alongside macro changes
+        db.edit_food("bar", None, None, None, Some(240.0), None, None).unwrap();
+        assert_eq!(db.get_food_by_name("bar").unwrap().unwrap().calories, 240.0);
+        db.edit_food("bar", Some(21.0), None, None, Some(245.0), None, None).unwrap();
+        let bar = db.get_food_by_name("bar").unwrap().unwrap();
+        assert_eq!((bar.protein, bar.calories), (21.0, 245.0));
+
+        // Changing only the serving leaves label calories alone
+        db.edit_food("bar", None, None, None, None, None, Some("1 bar (60g)")).unwrap();
+        assert_eq!(db.get_food_by_name("bar").unwrap().unwrap().calories, 245.0);
+
+        // Changing macros without calories recalculates them
+        db.edit_food("bar", Some(20.0), None, None, None, None, None).unwrap();
+        assert_eq!(db.get_food_by_name("bar").unwrap().unwrap().calories, 270.0);
+    }
+
+    #[test]
+    fn test_edit_fiber() {
+        let db = Database::open_in_memory().unwrap();
+        let oats = Food { fiber: 10.0, ..Food::new("oats", 13.0, 7.0, 68.0, 389.0, "100g", vec![]) };
+        db.add_food(&oats).unwrap();
+
+        // Fiber can be set directly, and is capped at the carbs
+        db.edit_food("oats", None, None, None, None, Some(11.0), None).unwrap();
+        assert_eq!(db.get_food_by_name("oats").unwrap().unwrap().fiber, 11.0);
+        assert!(db.edit_food("oats", None, None, Some(60.0), None, Some(61.0), None).is_err());
+        db.edit_food("oats", None, None, Some(8.0), None, None, None).unwrap();
+        assert_eq!(db.get_food_by_name("oats").unwrap().unwrap().fiber, 8.0);
+
+        let oats = db.get_food_by_name("oats").unwrap().unwrap();
+        let day = NaiveDate::from_ymd_opt(2024, 6, 1).unwrap();
+        let entry = db.log_food_on(day, oats.id.unwrap(), "100g", &oats.scale(1.0)).unwrap();
+        let id = entry.id.unwrap();
+
+        // New carbs take the fiber along in proportion, unless it's given
+        let edited = db.edit_log_entry(id, None, None, None, Some(4.0), None, None).unwrap();
+        assert_eq!((edited.carbs, edited.fiber), (4.0, 4.0));
+        let edited = db.edit_log_entry(id, None, None, None, None, None, Some(1.0)).unwrap();
+        assert_eq!((edited.carbs, edited.fiber), (4.0, 1.0));
+        assert!(db.edit_log_entry(id, None, None, None, None, None, Some(5.0)).is_err());
+        let saved = db.get_log_entry(id).unwrap();
+        assert_eq!((saved.amount.as_str(), saved.fiber), ("100g", 1.0));
+    }
+
+    #[test]
+    fn test_import_usda_foods() {
+        let db = Database::open_in_memory().unwrap();
+        db.add_food(&Food::new("egg", 6.0, 5.0, 0.6, 72.0, "1 large", vec!["Egg, whole, raw".to_string()])).unwrap();
+        let json = r#"{"SRLegacyFoods": [
+            {"description": "Egg, whole, raw", "foodNutrients": [{"nutrient": {"id": 1003}, "amount": 12.6}]},
+            {"description": "Egg, white, raw", "foodNutrients": [{"nutrient": {"id": 1003}, "amount": 10.9}]}
+        ]}"#;
+        let foods = crate::usda::read_json(json.as_bytes(), None).unwrap();
+
+        let summary = db.import_foods(&foods, false).unwrap();
+        assert_eq!((summary.foods_added, summary.foods_existing), (1, 1));
+        let white = db.get_food_by_name("Egg, white, raw").unwrap().unwrap();
+        assert_eq!((white.protein, white.source, white.serving.as_str()), (10.9, FoodSource::Usda, "100g"));
+        // The alias match kept the user's own egg
+        assert_eq!(db.get_food_by_name("egg").unwrap().unwrap().source, FoodSource::Manual);
+    }
+
+    #[test]
+    fn test_backup_to_sqlite_file() {
+        let dir = TempDir::new("backup-test");
+        let path = dir.join("backup.db");
+        let db = Database::open_in_memory().unwrap();
+        db.add_food(&Food::new("egg", 6.0, 5.0, 0.5, 72.0, "1 large", vec!["eggs".to_string()])).unwrap();
+        db.add_food(&Food::new("oats", 13.0, 7.0, 68.0, 389.0, "100g", vec![])).unwrap();
+        let egg = db.get_food_by_name("egg").unwrap().unwrap();
+        db.log_food_on(NaiveDate::from_ymd_opt(2024, 6, 5).unwrap(), egg.id.unwrap(), "2 large", &egg.scale(2.0)).unwrap();
+
+        db.backup_to(&path).unwrap();
+        let copy = Database::open_at(&path).unwrap();
+        let (original, copied) = (db.get_stats().unwrap(), copy.get_stats().unwrap());
+        assert_eq!(copied.food_count, original.food_count);
+        assert_eq!(copied.log_count, 1);
+        assert_eq!(copy.get_food_by_name("eggs").unwrap().unwrap().name, "egg");
+        assert_eq!(copy.schema_version().unwrap(), MIGRATIONS.len());
+    }
+
+    #[test]
+    fn test_import_dedupe_aliases() {
+        let db = Database::open_in_memory().unwrap();
+        db.add_food(&Food::new("egg", 6.0, 5.0, 0.6, 72.0, "1 large", vec!["eggs".to_string()])).unwrap();
+        let aliases = |list: &[&str]| list.iter().map(|a| a.to_string()).collect::<Vec<_>>();
+        let foods = vec![
+            Food::new("Egg white", 11.0, 0.2, 0.7, 52.0, "100g", aliases(&[" Whites ", "whites", "EGGS", "", "egg white"])),
+            Food::new("Egg yolk", 16.0, 27.0, 0.6, 322.0, "100g", aliases(&["Egg", "yolks", "whites"])),
+        ];
+
+        // Without the pass, the taken alias fails the whole import
+        assert!(db.import_foods(&foods, false).is_err());
+        assert!(db.get_food_by_name("Egg white").unwrap().is_none());
+
+        let summary = db.import_foods(&foods, true).unwrap();
+        assert_eq!(summary.foods_added, 2);
+        let imported: Vec<String> = db.all_aliases().unwrap().into_iter().map(|(_, alias)| alias).collect();
+        assert_eq!(imported, vec!["eggs", "whites", "yolks"]);
+        assert_eq!(db.get_food_by_name("yolks").unwrap().unwrap().name, "Egg yolk");
+        assert_eq!(summary.aliases_skipped, vec![
+            "'eggs' for 'Egg white' (already an alias of 'egg')",
+            "'egg' for 'Egg yolk' (already the name of 'egg')",
+            "'whites' for 'Egg yolk' (already an alias of 'Egg white')",
+        ]);
+        // "eggs" still means the user's egg
+        assert_eq!(db.get_food_by_name("eggs").unwrap().unwrap().name, "egg");
+    }
+
+    #[test]
+    fn test_info() {
+        let db = Database::open_in_memory().unwrap();
+        db.add_food(&Food::new("egg", 6.0, 5.0, 0.6, 72.0, "1 large", vec![])).unwrap();
+        let info = db.info(Database::default_path().unwrap(), std::path::PathBuf::from("/tmp/config.toml")).unwrap();
+        assert_eq!(info.schema_version, MIGRATIONS.len());
+        assert_eq!((info.food_count, info.log_count), (1, 0));
+        assert!(info.db_path.ends_with(".chomp/foods.db"));
+
+        let value = serde_json::to_value(&info).unwrap();
+        for key in ["version", "db_path", "schema_version", "food_count", "log_count", "config_path"] {
+            assert!(value.get(key).is_some(), "missing {}", key);
+        }
+        assert_eq!(value["config_path"], "/tmp/config.toml");
+    }
+
+    #[test]
+    fn test_normalize_foods() {
+        let db = Database::open_in_memory().unwrap();
+        db.add_food(&Food { fiber: 1.5, ..Food::new("chips", 2.0, 10.0, 15.0, 160.0, "30g", vec![]) }).unwrap();
+        db.add_food(&Food::new("ribeye", 24.0, 20.0, 0.0, 291.0, "100g", vec![])).unwrap();
+        db.add_food(&Food::new("bare bar", 10.0, 8.0, 20.0, 200.0, "1 bar", vec![])).unwrap();
+
+        let normalized = db.normalize_foods().unwrap();
+        assert_eq!(normalized.len(), 1);
+
+        let chips = db.get_food_by_name("chips").unwrap().unwrap();
+        assert_eq!(chips.serving, "100g");
+        assert_eq!(chips.default_amount.as_deref(), Some("30g"));
+        assert!((chips.calories - 533.3333).abs() < 0.001);
+        assert!((chips.fiber - 5.0).abs() < 1e-9);
+
+        let bar = db.get_food_by_name("bare bar").unwrap().unwrap();
+        assert_eq!(bar.serving, "1 bar");
+    }
+}
@@ -0,0 +1,364 @@
+use anyhow::Result;
+use serde::Serialize;
+use std::collections::HashMap;
+
+use crate::db::{Database, LogEntry, QUICK_ADD_FOOD};
+use crate::food::{atwater_calories, parse_quantity, Food};
+
+/// Stored calories may differ from the Atwater estimate by this fraction
+/// (labels round, and fiber/alcohol skew things) before we flag them.
+const CALORIE_TOLERANCE: f64 = 0.25;
+/// ...and by at least this many kcal, so tiny foods aren't flagged for rounding.
+const CALORIE_TOLERANCE_KCAL: f64 = 20.0;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum FoodIssue {
+    /// A macro or calorie value is NaN, infinite, or negative
+    InvalidValue { field: String, value: f64 },
+    /// The serving size is zero or can't be parsed
+    BadServing { serving: String },
+    /// Stored calories are far from protein*4 + fat*9 + carbs*4
+    CaloriesMismatch { calories: f64, estimated: f64 },
+    /// Another food has the same name once punctuation and case are ignored
+    DuplicateName { other: String },
+    /// An alias of this food is another food's name
+    AliasCollision { alias: String, other: String },
+}
+
+impl FoodIssue {
+    /// Whether `doctor foods --fix` can repair this without a human
+    pub fn is_fixable(&self) -> bool {
+        matches!(self, FoodIssue::CaloriesMismatch { .. })
+    }
+
+    pub fn suggestion(&self) -> String {
+        match self {
+            FoodIssue::InvalidValue { field, .. } => {
+                format!("set a valid {} with `chomp edit`", field)
+            }
+            FoodIssue::BadServing { .. } => "set a serving like 100g with `chomp edit --per`".to_string(),
+            FoodIssue::CaloriesMismatch { estimated, .. } => {
+                format!("recompute calories to {:.0} (--fix)", estimated)
+            }
+            FoodIssue::DuplicateName { other } => format!("merge with '{}' or delete one", other),
+            FoodIssue::AliasCollision { other, .. } => {
+                format!("remove the alias; it shadows '{}'", other)
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for FoodIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FoodIssue::InvalidValue { field, value } => write!(f, "invalid {}: {}", field, value),
+            FoodIssue::BadServing { serving } => write!(f, "bad serving size '{}'", serving),
+            FoodIssue::CaloriesMismatch { calories, estimated } => {
+                write!(f, "{:.0} kcal stored but macros suggest {:.0}", calories, estimated)
+            }
+            FoodIssue::DuplicateName { other } => write!(f, "looks like a duplicate of '{}'", other),
+            FoodIssue::AliasCollision { alias, other } => {
+                write!(f, "alias '{}' collides with food '{}'", alias, other)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FoodProblem {
+    pub food_id: i64,
+    pub food: String,
+    pub issue: FoodIssue,
+    pub suggestion: String,
+}
+
+/// Checks that only need the food itself
+pub fn validate_food(food: &Food) -> Vec<FoodIssue> {
+    let mut issues = Vec::new();
+
+    let fields = [
+        ("protein", food.protein),
+        ("fat", food.fat),
+        ("carbs", food.carbs),
+        ("calories", food.calories),
+    ];
+    for (field, value) in fields {
+        if !value.is_finite() || value < 0.0 {
+            issues.push(FoodIssue::InvalidValue { field: field.to_string(), value });
+        }
+    }
+
+    match parse_quantity(&food.serving) {
+        Some((value, _)) if value > 0.0 && value.is_finite() => {}
+        _ => issues.push(FoodIssue::BadServing { serving: food.serving.clone() }),
+    }
+
+    if issues.is_empty() {
+        let estimated = atwater_calories(food.protein, food.fat, food.carbs);
+        let diff = (food.calories - estimated).abs();
+        if diff > CALORIE_TOLERANCE_KCAL && diff > estimated * CALORIE_TOLERANCE {
+            issues.push(FoodIssue::CaloriesMismatch { calories: food.calories, estimated });
+        }
+    }
+
+    issues
+}
+
+/// Lowercase and strip everything but letters and digits, so
+/// "Chicken, Breast" and "chicken breast" compare equal
+fn normalize_name(name: &str) -> String {
+    name.chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(|c| c.to_lowercase())
+        .collect()
+}
+
+/// Fail if `name` or any of `aliases` is already a food's name or alias, which
+/// adding would collide with
+pub fn ensure_names_free(db: &Database, name: &str, aliases: &[String]) -> Result<()> {
+    for candidate in std::iter::once(name).chain(aliases.iter().map(String::as_str)) {
+        if let Some(existing) = db.get_food_by_name(candidate)? {
+            if existing.name.eq_ignore_ascii_case(candidate.trim()) {
+                anyhow::bail!("'{}' already exists", existing.name);
+            }
+            anyhow::bail!("'{}' is already an alias of '{}'", candidate, existing.name);
+        }
+    }
+    Ok(())
+}
+
+/// Existing foods that adding `name` might duplicate: the same name once case,
+/// punctuation, and a plural "s" are ignored ("Chicken, Breasts"), or one sharing a
+/// whole word of three letters or more ("grilled chicken" and "chicken breast").
+/// A few letters in common isn't enough: "tea" is nothing like "steak".
+pub fn similar_foods(db: &Database, name: &str) -> Result<Vec<Food>> {
+    let key = singular(&normalize_name(name));
+    let words = name_words(name);
+    let foods = db.all_foods()?.into_iter()
+        .filter(|food| food.name != QUICK_ADD_FOOD)
+        .filter(|food| {
+            singular(&normalize_name(&food.name)) == key || name_words(&food.name).iter().any(|w| words.contains(w))
+        })
+        .collect();
+    Ok(foods)
+}
+
+/// The words of a name worth comparing, lowercased and singular
+fn name_words(name: &str) -> Vec<String> {
+    name.split(|c: char| !c.is_alphanumeric())
+        .filter(|w| w.chars().count() >= 3)
+        .map(|w| singular(&w.to_lowercase()))
+        .collect()
+}
+
+/// "eggs" as "egg"; a word of three letters or fewer is left alone
+fn singular(word: &str) -> String {
+    match word.strip_suffix('s') {
+        Some(stem) if stem.chars().count() >= 3 && !stem.ends_with('s') => stem.to_string(),
+        _ => word.to_string(),
+    }
+}
+
+/// Scan the foods table for data-quality problems
+pub fn diagnose_foods(db: &Database) -> Result<Vec<FoodProblem>> {
+    let foods = db.all_foods()?;
+    let mut problems = Vec::new();
+
+    let mut push = |food: &Food, issue: FoodIssue| {
+        problems.push(FoodProblem {
+            food_id: food.id.unwrap_or_default(),
+            food: food.name.clone(),
+            suggestion: issue.suggestion(),
+            issue,
+        });
+    };
+
+    for food in &foods {
+        for issue in validate_food(food) {
+            push(food, issue);
+        }
+    }
+
+    let mut seen: HashMap<String, &Food> = HashMap::new();
+    for food in &foods {
+        let key = normalize_name(&food.name);
+        if let Some(first) = seen.get(&key) {
+            push(food, FoodIssue::DuplicateName { other: first.name.clone() });
+        } else {
+            seen.insert(key, food);
+        }
+    }
+
+    let by_name: HashMap<String, &Food> = foods.iter().map(|f| (f.name.to_lowercase(), f)).collect();
+    let by_id: HashMap<i64, &Food> = foods.iter().filter_map(|f| f.id.map(|id| (id, f))).collect();
+    for (food_id, alias) in db.all_aliases()? {
+        let (Some(owner), Some(other)) = (by_id.get(&food_id), by_name.get(&alias.to_lowercase())) else {
+            continue;
+        };
+        if other.id != owner.id {
+            push(owner, FoodIssue::AliasCollision { alias, other: other.name.clone() });
+        }
+    }
+
+    Ok(problems)
+}
+
+/// Apply the safe fixes (calorie recomputation). Returns the problems fixed.
+pub fn fix_foods(db: &Database, problems: &[FoodProblem]) -> Result<Vec<FoodProblem>> {
+    let mut fixed = Vec::new();
+    for problem in problems {
+        if let FoodIssue::CaloriesMismatch { estimated, .. } = problem.issue {
+            db.set_food_calories(problem.food_id, estimated)?;
+            fixed.push(problem.clone());
+        }
+    }
+    Ok(fixed)
+}
+
+/// Log entries that look like the same thing logged more than once
+#[derive(Debug, Serialize)]
+pub struct DuplicateEntries {
+    /// The first one, which `doctor log --dedupe` keeps
+    pub keep: LogEntry,
+    pub extras: Vec<LogEntry>,
+}
+
+/// Scan the log for entries repeated within a minute (see `Database::duplicate_log_entries`)
+pub fn diagnose_log(db: &Database) -> Result<Vec<DuplicateEntries>> {
+    Ok(db.duplicate_log_entries()?
+        .into_iter()
+        .filter_map(|mut group| {
+            let extras = group.split_off(1);
+            Some(DuplicateEntries { keep: group.pop()?, extras })
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    #[test]
+    fn test_similar_foods() {
+        let db = Database::open_in_memory().unwrap();
+        db.add_food(&Food::new("chicken breast", 31.0, 3.6, 0.0, 165.0, "100g", vec![])).unwrap();
+        db.add_food(&Food::new("white rice", 2.7, 0.3, 28.0, 130.0, "100g", vec![])).unwrap();
+
+        db.add_food(&Food::new("steak", 25.0, 15.0, 0.0, 250.0, "100g", vec![])).unwrap();
+        db.add_food(&Food::new("eggs", 6.0, 5.0, 0.6, 72.0, "1 large", vec![])).unwrap();
+
+        let names = |name: &str| -> Vec<String> {
+            similar_foods(&db, name).unwrap().into_iter().map(|f| f.name).collect()
+        };
+        assert_eq!(names("Chicken, Breasts"), vec!["chicken breast"]);
+        assert_eq!(names("grilled chicken"), vec!["chicken breast"]);
+        assert_eq!(names("rice"), vec!["white rice"]);
+        assert_eq!(names("egg"), vec!["eggs"]);
+        assert_eq!(names("steaks"), vec!["steak"]);
+        // Letters in common aren't enough
+        assert!(names("tea").is_empty());
+        assert_eq!(names("rice cakes"), vec!["white rice"]);
+        assert!(names("salmon").is_empty());
+        assert!(names("grilled salmon fillet").is_empty());
+
+        // Exact names and aliases are refused outright rather than asked about
+        db.add_food(&Food::new("oats", 13.0, 7.0, 68.0, 389.0, "100g", vec!["oatmeal".to_string()])).unwrap();
+        let err = ensure_names_free(&db, "Chicken Breast", &[]).unwrap_err().to_string();
+        assert_eq!(err, "'chicken breast' already exists");
+        let err = ensure_names_free(&db, "porridge", &["oatmeal".to_string()]).unwrap_err().to_string();
+        assert_eq!(err, "'oatmeal' is already an alias of 'oats'");
+        assert!(ensure_names_free(&db, "salmon", &["lox".to_string()]).is_ok());
+    }
+
+    #[test]
+    fn test_diagnose_and_dedupe_log() {
+        let db = Database::open_in_memory().unwrap();
+        db.add_food(&Food::new("egg", 6.0, 5.0, 0.5, 72.0, "1 egg", vec![])).unwrap();
+        db.add_food(&Food::new("oats", 13.0, 7.0, 68.0, 389.0, "100g", vec![])).unwrap();
+        let egg = db.get_food_by_name("egg").unwrap().unwrap();
+        let oats = db.get_food_by_name("oats").unwrap().unwrap();
+        let day = NaiveDate::from_ymd_opt(2024, 6, 5).unwrap();
+        let log = |food: &Food, amount: &str, created_at: &str| {
+            let id = db.log_food_on(day, food.id.unwrap(), amount, &food.scale(1.0)).unwrap().id.unwrap();
+            db.set_log_created_at(id, created_at);
+            id
+        };
+
+        // Three taps of the same eggs within a minute, and a retried oats
+        let first = log(&egg, "2", "2024-06-05 08:15:01");
+        log(&egg, "2", "2024-06-05 08:15:09");
+        log(&egg, "2", "2024-06-05 08:15:40");
+        let oats_first = log(&oats, "50g", "2024-06-05 08:16:00");
+        log(&oats, "50g", "2024-06-05 08:16:02");
+        // Not repeats: hours apart, another amount
+        log(&egg, "2", "2024-06-05 12:30:00");
+        log(&oats, "60g", "2024-06-05 08:16:05");
+        // A double tap across a minute boundary is still one
+        let toast_first = log(&oats, "1 slice", "2024-06-05 12:00:59");
+        log(&oats, "1 slice", "2024-06-05 12:01:00");
+        // Two of the same thing in one combo were meant
+        let meta = crate::db::LogMeta { date: day, time: None, meal: None };
+        let item = (oats.id.unwrap(), "30g".to_string(), oats.scale(0.3));
+        db.log_group(&meta, &[item.clone(), item]).unwrap();
+
+        let duplicates = diagnose_log(&db).unwrap();
+        assert_eq!(duplicates.len(), 3);
+        assert_eq!(duplicates[0].keep.id, Some(first));
+        assert_eq!(duplicates[0].extras.len(), 2);
+        assert_eq!(duplicates[1].keep.id, Some(toast_first));
+        assert_eq!(duplicates[1].extras.len(), 1);
+        assert_eq!(duplicates[2].keep.id, Some(oats_first));
+        assert_eq!(duplicates[2].extras.len(), 1);
+
+        let deleted = db.dedupe_log_entries().unwrap();
+        assert_eq!(deleted.len(), 4);
+        assert!(diagnose_log(&db).unwrap().is_empty());
+        let entries = db.get_log_for_date(day).unwrap();
+        assert_eq!(entries.len(), 7);
+        assert_eq!(entries.iter().filter(|e| e.food_name == "egg" && e.amount == "2").count(), 2);
+        assert!(entries.iter().any(|e| e.id == Some(first)));
+        assert!(entries.iter().any(|e| e.id == Some(oats_first)));
+    }
+
+    #[test]
+    fn test_validate_food() {
+        let ok = Food::new("ribeye", 24.0, 20.0, 0.0, 291.0, "100g", vec![]);
+        assert!(validate_food(&ok).is_empty());
+
+        let no_calories = Food::new("salmon", 25.0, 13.0, 0.0, 0.0, "100g", vec![]);
+        assert!(matches!(validate_food(&no_calories)[..], [FoodIssue::CaloriesMismatch { .. }]));
+
+        let zero_serving = Food::new("oil", 0.0, 14.0, 0.0, 126.0, "0g", vec![]);
+        assert_eq!(validate_food(&zero_serving), vec![FoodIssue::BadServing { serving: "0g".to_string() }]);
+
+        let nan = Food::new("mystery", f64::NAN, 0.0, 0.0, 0.0, "100g", vec![]);
+        assert!(matches!(&validate_food(&nan)[..], [FoodIssue::InvalidValue { field, .. }] if field == "protein"));
+    }
+
+    #[test]
+    fn test_diagnose_and_fix_foods() {
+        let db = Database::open_in_memory().unwrap();
+        db.add_food(&Food::new("chicken breast", 31.0, 3.6, 0.0, 165.0, "100g", vec![])).unwrap();
+        db.add_food(&Food::new("Chicken, Breast", 31.0, 3.6, 0.0, 165.0, "100g", vec![])).unwrap();
+        db.add_food(&Food::new("salmon", 25.0, 13.0, 0.0, 0.0, "100g", vec![])).unwrap();
+        db.add_food(&Food::new("egg", 6.0, 5.0, 0.5, 72.0, "1 egg", vec![])).unwrap();
+        db.add_food_unchecked(&Food::new("egg whites", 11.0, 0.0, 0.7, 52.0, "100g", vec!["egg".to_string()])).unwrap();
+
+        let problems = diagnose_foods(&db).unwrap();
+        let summary: Vec<_> = problems.iter().map(|p| (p.food.as_str(), &p.issue)).collect();
+        assert_eq!(problems.len(), 3, "{:?}", summary);
+        assert!(problems.iter().any(|p| p.food == "salmon" && p.issue.is_fixable()));
+        assert!(problems.iter().any(|p| p.food == "Chicken, Breast"
+            && p.issue == FoodIssue::DuplicateName { other: "chicken breast".to_string() }));
+        assert!(problems.iter().any(|p| p.food == "egg whites"
+            && matches!(p.issue, FoodIssue::AliasCollision { .. })));
+
+        let fixed = fix_foods(&db, &problems).unwrap();
+        assert_eq!(fixed.len(), 1);
+        let salmon = db.get_food_by_name("salmon").unwrap().unwrap();
+        assert!((salmon.calories - 217.0).abs() < 1e-9);
+        assert_eq!(diagnose_foods(&db).unwrap().len(), 2);
+    }
+}
@@ -1,17 +1,43 @@
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 
+/// Fields added after the first release carry `#[serde(default)]` so JSON from
+/// older versions still deserializes; unknown fields from newer versions are ignored.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Food {
+    #[serde(default)]
     pub id: Option<i64>,
     pub name: String,
     pub protein: f64,
     pub fat: f64,
     pub carbs: f64,
     pub calories: f64,
+    /// Grams of fiber, part of `carbs`; zero when unknown
+    #[serde(default)]
+    pub fiber: f64,
+    #[serde(default = "default_serving")]
     pub serving: String,
+    #[serde(default)]
     pub aliases: Vec<String>,
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub default_amount: Option<String>,
+    /// Grams per milliliter, for converting between weight and volume
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub density: Option<f64>,
+    /// Where the food came from
+    #[serde(default)]
+    pub source: FoodSource,
+    /// How amounts are converted against the serving
+    #[serde(default)]
+    pub unit_mode: UnitMode,
+    /// Grams in this food's own count units, e.g. a "bar" kept from a "1 bar"
+    /// serving after normalizing to per-100g
+    #[serde(default, skip_serializing_if = "Units::is_empty")]
+    pub units: Units,
+}
+
+fn default_serving() -> String {
+    "100g".to_string()
 }
 
 impl Food {
@@ -31,30 +57,205 @@ impl Food {
             fat,
             carbs,
             calories,
+            fiber: 0.0,
             serving: serving.to_string(),
             aliases,
             default_amount: None,
+            density: None,
+            source: FoodSource::Manual,
+            unit_mode: UnitMode::Auto,
+            units: Units::default(),
+        }
+    }
+
+    /// Calculate macros for a given amount, with any user-defined unit conversions
+    pub fn calculate(&self, amount: &Amount, units: &Units, unknown: UnknownUnit) -> Result<Macros, ConversionError> {
+        Ok(self.scale(self.multiplier(amount, units, unknown)?))
+    }
+
+    /// The unit of `amount`, if chomp doesn't know it and it isn't the serving's own
+    /// unit ("2 slices" of a "1 slice" food is fine), e.g. "gg" in a mistyped "100gg"
+    pub fn unknown_unit(&self, amount: &Amount, units: &Units) -> Option<String> {
+        let Amount::Measure(text) = amount else { return None };
+        let (_, unit) = parse_quantity(text)?;
+        let known = is_measure_unit(&unit) || is_count_word(&unit) || is_discrete_unit(&unit) || unit == "%"
+            || units.grams_per(&unit).is_some() || self.units.grams_per(&unit).is_some();
+        (!known && !self.is_serving_unit(&unit)).then_some(unit)
+    }
+
+    /// Whether `unit` is the serving's own unit, singular or plural ("slice" for "2 slices")
+    fn is_serving_unit(&self, unit: &str) -> bool {
+        let serving_unit = parse_quantity(&self.serving).map(|(_, u)| u).unwrap_or_default();
+        unit == serving_unit
+            || unit.strip_suffix('s') == Some(serving_unit.as_str())
+            || serving_unit.strip_suffix('s') == Some(unit)
+    }
+
+    /// How many servings an amount is. A bare count, like the 2 in "2 eggs",
+    /// is in the serving's own units against a count serving ("1 egg", "2 slices")
+    /// and a number of servings against a measured one ("100g").
+    pub fn multiplier(&self, amount: &Amount, units: &Units, unknown: UnknownUnit) -> Result<f64, ConversionError> {
+        // "50%" is half a serving, whatever the serving is
+        if let Amount::Measure(text) = amount {
+            if let Some(percent) = text.trim().strip_suffix('%') {
+                return parse_number(percent.trim())
+                    .filter(|p| p.is_finite() && *p > 0.0)
+                    .map(|p| p / 100.0)
+                    .ok_or_else(|| ConversionError::InvalidQuantity(text.clone()));
+            }
+        }
+        if self.unit_mode == UnitMode::Serving {
+            return self.serving_multiplier(amount, units);
+        }
+        let units = &self.units.over(units);
+        if unknown == UnknownUnit::Error {
+            if let Some(unit) = self.unknown_unit(amount, units) {
+                return Err(ConversionError::UnknownUnit { unit, amount: amount.to_string() });
+            }
+        }
+        match amount {
+            Amount::Measure(text) => parse_amount_multiplier(text, &self.serving, self.density, units),
+            Amount::Count(count) => {
+                let invalid = || ConversionError::InvalidQuantity(self.serving.clone());
+                let (serving_val, serving_unit) = parse_quantity(&self.serving).ok_or_else(invalid)?;
+                if let Some((unit, _)) = self.count_unit().filter(|_| is_measure_unit(&serving_unit)) {
+                    // Normalized from a "1 bar" serving: a bare count is still bars
+                    parse_amount_multiplier(&format!("{} {}", count, unit), &self.serving, self.density, units)
+                } else if is_measure_unit(&serving_unit) {
+                    Ok(*count)
+                } else if serving_val > 0.0 {
+                    Ok(count / serving_val)
+                } else {
+                    Err(invalid())
+                }
+            }
+        }
+    }
+
+    /// A bare count as it's stored, with the unit spelled out so it can't be read
+    /// back as grams: "2 large" for a "1 large" food, "2 servings" for a "100g" one.
+    /// Gives the same multiplier as the count itself.
+    pub fn count_amount(&self, count: f64) -> String {
+        if let Some((unit, _)) = self.count_unit() {
+            return format!("{} {}{}", format_number(count), unit, if count == 1.0 { "" } else { "s" });
+        }
+        let unit = parse_quantity(&self.serving)
+            .map(|(_, unit)| unit)
+            .filter(|unit| !unit.is_empty() && !is_measure_unit(unit));
+        match unit {
+            Some(unit) => format!("{} {}", format_number(count), unit),
+            None if count == 1.0 => "1 serving".to_string(),
+            None => format!("{} servings", format_number(count)),
         }
     }
 
-    /// Calculate macros for a given amount
-    pub fn calculate(&self, amount: &str) -> Option<Macros> {
-        let multiplier = parse_amount_multiplier(amount, &self.serving)?;
-        Some(Macros {
+    /// `multiplier` for a serving-mode food: the amount's number over the serving's,
+    /// whatever the units are called. Weights and volumes can't be converted.
+    fn serving_multiplier(&self, amount: &Amount, units: &Units) -> Result<f64, ConversionError> {
+        let invalid = || ConversionError::InvalidQuantity(self.serving.clone());
+        let serving_val = parse_fraction(&self.serving)
+            .or_else(|| parse_quantity(&self.serving).map(|(v, _)| v))
+            .filter(|v| *v > 0.0)
+            .ok_or_else(invalid)?;
+        let count = match amount {
+            Amount::Count(count) => *count,
+            // A bare number is a count, not grams; fractions like "1/2 scoop" work too
+            Amount::Measure(text) => {
+                let number = text.trim().trim_end_matches(|c: char| c.is_alphabetic() || c.is_whitespace());
+                let unit = text.trim()[number.len()..].trim().to_lowercase();
+                let measured = is_measure_unit(&unit) || units.grams_per(&unit).is_some();
+                if measured && !self.is_serving_unit(&unit) {
+                    return Err(ConversionError::ServingsOnly { amount: text.clone(), serving: self.serving.clone() });
+                }
+                parse_fraction(number).ok_or_else(|| ConversionError::InvalidQuantity(text.clone()))?
+            }
+        };
+        Ok(count / serving_val)
+    }
+
+    /// Grams of protein per 100 kcal, or None for zero-calorie foods (spices, water)
+    pub fn protein_density(&self) -> Option<f64> {
+        protein_density(self.protein, self.calories)
+    }
+
+    /// The count unit kept from a normalized "1 bar" serving, with its grams
+    pub fn count_unit(&self) -> Option<(&str, f64)> {
+        self.units.0.iter().next().map(|(unit, grams)| (unit.as_str(), *grams))
+    }
+
+    /// Convert this food to a per-100g basis, given the weight of one serving.
+    /// The original serving is kept as the default amount for bare logging; a
+    /// counted one ("1 bar") is also kept as a unit, so "2 bars" still works.
+    pub fn normalized_per_100g(&self, serving_grams: f64) -> Food {
+        let macros = self.scale(100.0 / serving_grams);
+        let mut units = self.units.clone();
+        let mut default_amount = self.default_amount.clone();
+        if let Some((count, unit)) = parse_quantity(&self.serving).filter(|(n, u)| *n > 0.0 && !is_measure_unit(u)) {
+            let singular = if count == 1.0 { unit.as_str() } else { unit.strip_suffix('s').unwrap_or(&unit) };
+            units.0.insert(singular.to_string(), serving_grams / count);
+            default_amount = default_amount.or_else(|| Some(self.serving.clone()));
+        }
+        Food {
+            protein: macros.protein,
+            fat: macros.fat,
+            carbs: macros.carbs,
+            calories: macros.calories,
+            fiber: macros.fiber,
+            serving: "100g".to_string(),
+            default_amount: default_amount.or_else(|| Some(format!("{}g", format_number(serving_grams)))),
+            units,
+            ..self.clone()
+        }
+    }
+
+    /// Scale the per-serving macros by a multiplier (e.g. 0.375 for 3/8 of a pizza)
+    pub fn scale(&self, multiplier: f64) -> Macros {
+        Macros {
             protein: self.protein * multiplier,
             fat: self.fat * multiplier,
             carbs: self.carbs * multiplier,
             calories: self.calories * multiplier,
-        })
+            fiber: self.fiber * multiplier,
+        }
+    }
+}
+
+/// An amount parsed from log input
+#[derive(Debug, Clone, PartialEq)]
+pub enum Amount {
+    /// A quantity with a unit, like "8oz" or "4 oz"
+    Measure(String),
+    /// A bare count, like the 2 in "2 eggs"
+    Count(f64),
+}
+
+impl From<&str> for Amount {
+    fn from(text: &str) -> Self {
+        Amount::Measure(text.to_string())
     }
 }
 
+impl std::fmt::Display for Amount {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Amount::Measure(text) => write!(f, "{}", text),
+            Amount::Count(count) => write!(f, "{}", format_number(*count)),
+        }
+    }
+}
+
+/// Macro and calorie amounts. These are stored and summed unrounded; rounding
+/// happens once, when a value is formatted for display. JSON and CSV output keep
+/// full precision.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Macros {
     pub protein: f64,
     pub fat: f64,
     pub carbs: f64,
     pub calories: f64,
+    /// Grams of fiber, counted within `carbs`
+    #[serde(default)]
+    pub fiber: f64,
 }
 
 impl Default for Macros {
@@ -64,6 +265,7 @@ impl Default for Macros {
             fat: 0.0,
             carbs: 0.0,
             calories: 0.0,
+            fiber: 0.0,
         }
     }
 }
@@ -74,51 +276,608 @@ impl Macros {
         self.fat += other.fat;
         self.carbs += other.carbs;
         self.calories += other.calories;
+        self.fiber += other.fiber;
+    }
+
+    /// Carbs less fiber, never below zero
+    pub fn net_carbs(&self) -> f64 {
+        (self.carbs - self.fiber).max(0.0)
+    }
+}
+
+fn protein_density(protein: f64, calories: f64) -> Option<f64> {
+    if calories > 0.0 && calories.is_finite() {
+        Some(protein / calories * 100.0)
+    } else {
+        None
+    }
+}
+
+/// Sort foods by protein density, densest first. Zero-calorie foods go last.
+pub fn sort_by_protein_density(foods: &mut [Food]) {
+    foods.sort_by(protein_density_order);
+}
+
+/// Order of two foods in `sort_by_protein_density`
+pub fn protein_density_order(a: &Food, b: &Food) -> std::cmp::Ordering {
+    match (a.protein_density(), b.protein_density()) {
+        (Some(x), Some(y)) => y.total_cmp(&x),
+        (Some(_), None) => std::cmp::Ordering::Less,
+        (None, Some(_)) => std::cmp::Ordering::Greater,
+        (None, None) => std::cmp::Ordering::Equal,
+    }
+}
+
+/// Estimate calories from macros using Atwater factors (4/9/4 kcal per gram)
+pub fn atwater_calories(protein: f64, fat: f64, carbs: f64) -> f64 {
+    protein * 4.0 + fat * 9.0 + carbs * 4.0
+}
+
+/// Where a food's data came from: typed in, or brought in by one of the importers
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FoodSource {
+    #[default]
+    Manual,
+    Usda,
+    Csv,
+    Mfp,
+    Cronometer,
+}
+
+impl std::fmt::Display for FoodSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            FoodSource::Manual => "manual",
+            FoodSource::Usda => "usda",
+            FoodSource::Csv => "csv",
+            FoodSource::Mfp => "mfp",
+            FoodSource::Cronometer => "cronometer",
+        })
+    }
+}
+
+impl std::str::FromStr for FoodSource {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "manual" => Ok(FoodSource::Manual),
+            "usda" => Ok(FoodSource::Usda),
+            "csv" => Ok(FoodSource::Csv),
+            "mfp" => Ok(FoodSource::Mfp),
+            "cronometer" => Ok(FoodSource::Cronometer),
+            _ => Err(format!("unknown source '{}' (expected manual, usda, csv, mfp, or cronometer)", s)),
+        }
+    }
+}
+
+/// How a food's amounts are converted: through weights and volumes, or only by
+/// counting servings, for foods like "1 scoop" whose weight isn't known
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UnitMode {
+    #[default]
+    Auto,
+    Serving,
+}
+
+impl std::fmt::Display for UnitMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            UnitMode::Auto => "auto",
+            UnitMode::Serving => "serving",
+        })
     }
 }
 
+impl std::str::FromStr for UnitMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "auto" => Ok(UnitMode::Auto),
+            "serving" => Ok(UnitMode::Serving),
+            _ => Err(format!("unknown unit mode '{}' (expected auto or serving)", s)),
+        }
+    }
+}
+
+/// One of the three macros, e.g. for `add --solve carbs`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MacroKind {
+    Protein,
+    Fat,
+    Carbs,
+}
+
+impl MacroKind {
+    fn kcal_per_gram(self) -> f64 {
+        match self {
+            MacroKind::Protein | MacroKind::Carbs => 4.0,
+            MacroKind::Fat => 9.0,
+        }
+    }
+}
+
+impl std::fmt::Display for MacroKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            MacroKind::Protein => "protein",
+            MacroKind::Fat => "fat",
+            MacroKind::Carbs => "carbs",
+        })
+    }
+}
+
+impl std::str::FromStr for MacroKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "protein" | "p" => Ok(MacroKind::Protein),
+            "fat" | "f" => Ok(MacroKind::Fat),
+            "carbs" | "carb" | "c" => Ok(MacroKind::Carbs),
+            _ => Err(format!("unknown macro '{}' (expected protein, fat, or carbs)", s)),
+        }
+    }
+}
+
+/// A value tracked per food, e.g. for ranking with `chomp top protein`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Nutrient {
+    Protein,
+    Fat,
+    Carbs,
+    Calories,
+}
+
+impl Nutrient {
+    /// This nutrient's amount in one serving of `food`
+    pub fn of(self, food: &Food) -> f64 {
+        match self {
+            Nutrient::Protein => food.protein,
+            Nutrient::Fat => food.fat,
+            Nutrient::Carbs => food.carbs,
+            Nutrient::Calories => food.calories,
+        }
+    }
+}
+
+impl std::fmt::Display for Nutrient {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Nutrient::Protein => "protein",
+            Nutrient::Fat => "fat",
+            Nutrient::Carbs => "carbs",
+            Nutrient::Calories => "calories",
+        })
+    }
+}
+
+impl std::str::FromStr for Nutrient {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "protein" | "p" => Ok(Nutrient::Protein),
+            "fat" | "f" => Ok(Nutrient::Fat),
+            "carbs" | "carb" | "c" => Ok(Nutrient::Carbs),
+            "calories" | "kcal" | "cal" => Ok(Nutrient::Calories),
+            _ => Err(format!("unknown nutrient '{}' (expected protein, fat, carbs, or calories)", s)),
+        }
+    }
+}
+
+/// Grams of `missing` implied by `calories` and the other two macros (Atwater).
+/// The value given for `missing` is ignored. May be negative when labels round.
+pub fn solve_macro(missing: MacroKind, calories: f64, protein: f64, fat: f64, carbs: f64) -> f64 {
+    let known = match missing {
+        MacroKind::Protein => atwater_calories(0.0, fat, carbs),
+        MacroKind::Fat => atwater_calories(protein, 0.0, carbs),
+        MacroKind::Carbs => atwater_calories(protein, fat, 0.0),
+    };
+    (calories - known) / missing.kcal_per_gram()
+}
+
+/// Macros for a new food from what the label gave. With `solve`, that macro is
+/// back-solved from calories; a negative result is clamped to zero and returned
+/// with a warning. Otherwise all three macros are required and missing calories
+/// are estimated. Calories given as an explicit 0 alongside nonzero macros (a
+/// label error) are kept with a warning, or replaced by the estimate with `fix_calories`.
+pub fn macros_for_new_food(
+    protein: Option<f64>,
+    fat: Option<f64>,
+    carbs: Option<f64>,
+    calories: Option<f64>,
+    solve: Option<MacroKind>,
+    fix_calories: bool,
+) -> anyhow::Result<(Macros, Option<String>)> {
+    let given = |kind: MacroKind, value: Option<f64>| -> anyhow::Result<f64> {
+        if solve == Some(kind) {
+            anyhow::ensure!(value.is_none(), "don't give {} when solving for it", kind);
+            return Ok(0.0);
+        }
+        value.ok_or_else(|| anyhow::anyhow!("missing {} (or solve for it with --solve)", kind))
+    };
+    let mut protein = given(MacroKind::Protein, protein)?;
+    let mut fat = given(MacroKind::Fat, fat)?;
+    let mut carbs = given(MacroKind::Carbs, carbs)?;
+
+    let Some(missing) = solve else {
+        let estimate = atwater_calories(protein, fat, carbs);
+        return Ok(match calories {
+            None => (Macros { protein, fat, carbs, calories: estimate, fiber: 0.0 }, None),
+            Some(calories) if calories == 0.0 && estimate > 0.0 => {
+                let (calories, warning) = if fix_calories {
+                    (estimate, format!("0 kcal given, but the macros come to {} kcal; using that", format_number(estimate)))
+                } else {
+                    (0.0, format!("0 kcal given, but the macros come to {} kcal (use --fix-calories to use the estimate)",
+                        format_number(estimate)))
+                };
+                (Macros { protein, fat, carbs, calories, fiber: 0.0 }, Some(warning))
+            }
+            Some(calories) => (Macros { protein, fat, carbs, calories, fiber: 0.0 }, None),
+        });
+    };
+
+    let calories = calories.ok_or_else(|| anyhow::anyhow!("solving for {} needs calories", missing))?;
+    let solved = solve_macro(missing, calories, protein, fat, carbs);
+    let warning = (solved < 0.0).then(|| format!(
+        "{} kcal is less than the other macros account for ({:.1}g {}); using 0",
+        format_number(calories), solved, missing
+    ));
+    let solved = solved.max(0.0);
+    match missing {
+        MacroKind::Protein => protein = solved,
+        MacroKind::Fat => fat = solved,
+        MacroKind::Carbs => carbs = solved,
+    }
+    Ok((Macros { protein, fat, carbs, calories, fiber: 0.0 }, warning))
+}
+
+/// Why an amount couldn't be converted against a food's serving
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConversionError {
+    /// The amount or serving isn't a usable quantity
+    InvalidQuantity(String),
+    /// Weight and volume can't be compared without the food's density
+    NeedsDensity { amount: String, serving: String },
+    /// The amount's unit isn't one chomp knows (with `unknown_unit = "error"`)
+    UnknownUnit { unit: String, amount: String },
+    /// A weight or volume for a food measured only in servings
+    ServingsOnly { amount: String, serving: String },
+}
+
+impl std::fmt::Display for ConversionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConversionError::InvalidQuantity(s) => write!(f, "'{}' is not a valid quantity", s),
+            ConversionError::NeedsDensity { amount, serving } => write!(
+                f,
+                "can't convert {} to a {} serving without the food's density (set one with --density, in g/ml)",
+                amount, serving
+            ),
+            ConversionError::UnknownUnit { unit, amount } => write!(f, "unknown unit '{}' in '{}'", unit, amount),
+            ConversionError::ServingsOnly { amount, serving } => write!(
+                f,
+                "can't convert {} to a {} serving: this food is measured only in servings",
+                amount, serving
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ConversionError {}
+
 /// Parse amount string and return multiplier relative to serving size
-/// e.g., "8oz" with serving "100g" -> calculate ratio
-fn parse_amount_multiplier(amount: &str, serving: &str) -> Option<f64> {
-    let (amount_val, amount_unit) = parse_quantity(amount)?;
-    let (serving_val, serving_unit) = parse_quantity(serving)?;
+/// e.g., "8oz" with serving "100g" -> calculate ratio.
+/// Weight and volume are converted through `density` (g/ml) when they differ.
+fn parse_amount_multiplier(
+    amount: &str,
+    serving: &str,
+    density: Option<f64>,
+    units: &Units,
+) -> Result<f64, ConversionError> {
+    let (amount_val, amount_unit) = parse_quantity(amount)
+        .ok_or_else(|| ConversionError::InvalidQuantity(amount.to_string()))?;
+    let (serving_val, serving_unit) = parse_quantity(serving)
+        .ok_or_else(|| ConversionError::InvalidQuantity(serving.to_string()))?;
+    
+    // "2 servings" is that many servings, whatever one serving is
+    if matches!(amount_unit.as_str(), "serving" | "servings") && !matches!(serving_unit.as_str(), "serving" | "servings") {
+        return Ok(amount_val);
+    }
+
+    // "3 each" against any counted serving ("1 large", "2 cookies") is a plain count
+    if is_count_word(&amount_unit) && !is_measure_unit(&serving_unit) {
+        if serving_val <= 0.0 {
+            return Err(ConversionError::InvalidQuantity(serving.to_string()));
+        }
+        return Ok(amount_val / serving_val);
+    }
     
-    // Convert both to grams for comparison
-    let amount_grams = to_grams(amount_val, &amount_unit)?;
-    let serving_grams = to_grams(serving_val, &serving_unit)?;
+    let (amount_base, amount_dim) = to_base(amount_val, &amount_unit, units);
+    let (serving_base, serving_dim) = to_base(serving_val, &serving_unit, units);
+    
+    // Bring both into the serving's dimension
+    let amount_base = match (amount_dim, serving_dim, density) {
+        (a, s, _) if a == s => amount_base,
+        (Dimension::Mass, Dimension::Volume, Some(d)) if d > 0.0 => amount_base / d,
+        (Dimension::Volume, Dimension::Mass, Some(d)) if d > 0.0 => amount_base * d,
+        _ => {
+            return Err(ConversionError::NeedsDensity {
+                amount: amount.to_string(),
+                serving: serving.to_string(),
+            })
+        }
+    };
     
-    Some(amount_grams / serving_grams)
+    if serving_base <= 0.0 {
+        return Err(ConversionError::InvalidQuantity(serving.to_string()));
+    }
+    
+    Ok(amount_base / serving_base)
+}
+
+/// Parse a fraction or mixed number: "3/8", "1 1/2", or a plain decimal like "0.5"
+pub fn parse_fraction(s: &str) -> Option<f64> {
+    let s = s.trim();
+    let mut parts = s.split_whitespace();
+    let first = parts.next()?;
+    let value = match (parts.next(), parts.next()) {
+        (None, _) => parse_simple_fraction(first)?,
+        (Some(frac), None) if frac.contains('/') && !first.contains('/') => {
+            let whole = parse_number(first)?;
+            whole + parse_simple_fraction(frac)?
+        }
+        _ => return None,
+    };
+
+    if value.is_finite() && value > 0.0 {
+        Some(value)
+    } else {
+        None
+    }
+}
+
+fn parse_simple_fraction(s: &str) -> Option<f64> {
+    match s.split_once('/') {
+        Some((num, den)) => {
+            let num = parse_number(num)?;
+            let den = parse_number(den)?;
+            if den == 0.0 {
+                return None;
+            }
+            Some(num / den)
+        }
+        None => parse_number(s),
+    }
 }
 
-fn parse_quantity(s: &str) -> Option<(f64, String)> {
+/// Parse a number, accepting a decimal comma ("1,5") as well as a point.
+/// A single comma counts as a decimal separator only when it can't be a thousands
+/// separator: "1,5" and "0,125" are decimals, "1,500" and "1,000,000" are rejected.
+pub fn parse_number(s: &str) -> Option<f64> {
+    match s.split_once(',') {
+        None => s.parse().ok(),
+        Some((whole, frac)) => {
+            let digits = |part: &str| !part.is_empty() && part.chars().all(|c| c.is_ascii_digit());
+            if !digits(whole) || !digits(frac) {
+                return None;
+            }
+            if frac.len() == 3 && !whole.trim_start_matches('0').is_empty() {
+                return None;
+            }
+            format!("{}.{}", whole, frac).parse().ok()
+        }
+    }
+}
+
+/// Weight in grams of a serving expressed in mass units ("30g", "4oz").
+/// Returns None for count or volume servings like "1 bar" or "1 cup".
+pub fn serving_grams(serving: &str) -> Option<f64> {
+    let (value, unit) = parse_quantity(serving)?;
+    match unit.as_str() {
+        "g" | "gram" | "grams" | "oz" | "ounce" | "ounces" | "lb" | "lbs" | "pound" | "pounds"
+        | "kg" | "kilogram" | "kilograms" => to_grams(value, &unit, &Units::default()).filter(|g| *g > 0.0),
+        _ => None,
+    }
+}
+
+/// Capitalize the first letter of each word and lowercase the rest:
+/// "chicken BREAST" -> "Chicken Breast", "peanut-butter" -> "Peanut-Butter"
+pub fn title_case(name: &str) -> String {
+    let mut out = String::with_capacity(name.len());
+    let mut start_of_word = true;
+    for c in name.chars() {
+        if c.is_alphanumeric() {
+            if start_of_word {
+                out.extend(c.to_uppercase());
+            } else {
+                out.extend(c.to_lowercase());
+            }
+            start_of_word = false;
+        } else {
+            out.push(c);
+            start_of_word = c != '\'';
+        }
+    }
+    out
+}
+
+/// Format a quantity without a trailing ".0" (30.0 -> "30", 28.35 -> "28.35")
+pub fn format_number(value: f64) -> String {
+    let rounded = (value * 100.0).round() / 100.0;
+    if rounded.fract() == 0.0 {
+        format!("{:.0}", rounded)
+    } else {
+        format!("{}", rounded)
+    }
+}
+
+/// User-defined unit conversions in grams per unit (`scoop = 32`), from the
+/// `[units]` config table. They take precedence over the built-in tables.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(transparent)]
+pub struct Units(BTreeMap<String, f64>);
+
+impl Units {
+    /// Conversions keyed by unit name (matched case-insensitively); factors must be positive
+    pub fn new(grams: BTreeMap<String, f64>) -> Result<Self, String> {
+        let mut units = BTreeMap::new();
+        for (unit, factor) in grams {
+            if !(factor.is_finite() && factor > 0.0) {
+                return Err(format!("unit '{}' must be a positive number of grams, got {}", unit, factor));
+            }
+            units.insert(unit.trim().to_lowercase(), factor);
+        }
+        Ok(Self(units))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// These conversions with `fallback`'s added for units not defined here
+    pub fn over(&self, fallback: &Units) -> Units {
+        let mut units = fallback.0.clone();
+        units.extend(self.0.iter().map(|(unit, grams)| (unit.clone(), *grams)));
+        Units(units)
+    }
+
+    /// Grams in one `unit`, also matching a plural "s" ("scoops" for "scoop")
+    pub fn grams_per(&self, unit: &str) -> Option<f64> {
+        let unit = unit.trim().to_lowercase();
+        self.0.get(&unit)
+            .or_else(|| unit.strip_suffix('s').and_then(|singular| self.0.get(singular)))
+            .copied()
+    }
+}
+
+impl<'de> Deserialize<'de> for Units {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let grams = BTreeMap::<String, f64>::deserialize(deserializer)?;
+        Units::new(grams).map_err(serde::de::Error::custom)
+    }
+}
+
+/// Units measured by weight or volume, as opposed to counts like "bar" or "egg"
+pub fn is_measure_unit(unit: &str) -> bool {
+    matches!(
+        unit,
+        "g" | "gram" | "grams" | "oz" | "ounce" | "ounces" | "lb" | "lbs" | "pound" | "pounds"
+            | "kg" | "kilogram" | "kilograms" | "ml" | "milliliter" | "milliliters"
+            | "l" | "liter" | "liters" | "cup" | "cups" | "tbsp" | "tablespoon" | "tablespoons"
+            | "tsp" | "teaspoon" | "teaspoons"
+    ) || fluid_ounces(unit) || cubic_centimeters(unit)
+}
+
+/// Units spelled as more than one word, longest first, as the parser looks for them
+/// at the end of the input ("milk 4 fluid ounces")
+pub const MULTI_WORD_UNITS: &[&str] = &[
+    "us fluid ounces", "us fluid ounce",
+    "fluid ounces", "fluid ounce", "fl oz",
+    "cubic centimeters", "cubic centimeter", "cubic centimetres", "cubic centimetre",
+];
+
+fn fluid_ounces(unit: &str) -> bool {
+    matches!(unit, "floz" | "fl oz" | "fluid ounce" | "fluid ounces" | "us fluid ounce" | "us fluid ounces")
+}
+
+fn cubic_centimeters(unit: &str) -> bool {
+    matches!(
+        unit,
+        "cc" | "cm3" | "cubic centimeter" | "cubic centimeters" | "cubic centimetre" | "cubic centimetres"
+    )
+}
+
+/// What to do with an amount in a unit chomp doesn't recognize
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UnknownUnit {
+    /// Refuse the amount
+    Error,
+    /// Treat the unit as grams, silently
+    Grams,
+    /// Treat the unit as grams, with a warning
+    #[default]
+    Warn,
+}
+
+/// Discrete things counted 1:1 against a serving, like "bar" or "scoop"
+pub fn is_discrete_unit(unit: &str) -> bool {
+    matches!(
+        unit,
+        "bar" | "bars" | "piece" | "pieces" | "serving" | "servings" | "scoop" | "scoops" | "slice" | "slices"
+    )
+}
+
+/// Generic count words that mean "this many of whatever the serving counts"
+fn is_count_word(unit: &str) -> bool {
+    matches!(unit, "each" | "ea" | "ct" | "count")
+}
+
+pub fn parse_quantity(s: &str) -> Option<(f64, String)> {
     let s = s.trim().to_lowercase();
     
     // Handle special cases like "1 bar", "1 piece"
-    if let Some(num_end) = s.find(|c: char| !c.is_numeric() && c != '.') {
+    if let Some(num_end) = s.find(|c: char| !c.is_numeric() && c != '.' && c != ',') {
         let num_str = &s[..num_end];
         let unit = s[num_end..].trim().to_string();
-        let num: f64 = num_str.parse().ok()?;
+        let num = parse_number(num_str)?;
         Some((num, unit))
     } else {
         // Just a number, assume grams
-        let num: f64 = s.parse().ok()?;
+        let num = parse_number(&s)?;
         Some((num, "g".to_string()))
     }
 }
 
-fn to_grams(value: f64, unit: &str) -> Option<f64> {
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Dimension {
+    Mass,
+    Volume,
+}
+
+/// Convert a quantity to milliliters if it's a volume, otherwise grams.
+/// User-defined units are weights, even when they shadow a built-in volume.
+fn to_base(value: f64, unit: &str, units: &Units) -> (f64, Dimension) {
+    if let Some(grams) = units.grams_per(unit) {
+        return (value * grams, Dimension::Mass);
+    }
+    match to_ml(value, unit) {
+        Some(ml) => (ml, Dimension::Volume),
+        None => (to_grams(value, unit, units).unwrap_or(value), Dimension::Mass),
+    }
+}
+
+fn to_ml(value: f64, unit: &str) -> Option<f64> {
+    let unit = unit.to_lowercase();
+    match unit.as_str() {
+        "ml" | "milliliter" | "milliliters" => Some(value),
+        "l" | "liter" | "liters" => Some(value * 1000.0),
+        "cup" | "cups" => Some(value * 240.0), // Approximate
+        "tbsp" | "tablespoon" | "tablespoons" => Some(value * 15.0),
+        "tsp" | "teaspoon" | "teaspoons" => Some(value * 5.0),
+        unit if fluid_ounces(unit) => Some(value * 29.5735),
+        unit if cubic_centimeters(unit) => Some(value),
+        _ => None,
+    }
+}
+
+fn to_grams(value: f64, unit: &str, units: &Units) -> Option<f64> {
+    if let Some(grams) = units.grams_per(unit) {
+        return Some(value * grams);
+    }
     let unit = unit.to_lowercase();
     match unit.as_str() {
         "g" | "gram" | "grams" => Some(value),
         "oz" | "ounce" | "ounces" => Some(value * 28.3495),
         "lb" | "lbs" | "pound" | "pounds" => Some(value * 453.592),
         "kg" | "kilogram" | "kilograms" => Some(value * 1000.0),
-        "ml" | "milliliter" | "milliliters" => Some(value), // Assume 1:1 for liquids
-        "cup" | "cups" => Some(value * 240.0), // Approximate
-        "tbsp" | "tablespoon" | "tablespoons" => Some(value * 15.0),
-        "tsp" | "teaspoon" | "teaspoons" => Some(value * 5.0),
         // For discrete items (bar, piece, etc.), treat as 1:1 multiplier
-        "bar" | "bars" | "piece" | "pieces" | "serving" | "servings" | "scoop" | "scoops" => Some(value * 100.0),
+        unit if is_discrete_unit(unit) || is_count_word(unit) => Some(value * 100.0),
         _ => Some(value), // Unknown unit, assume grams
     }
 }
@@ -132,11 +891,283 @@ mod tests {
         assert_eq!(parse_quantity("100g"), Some((100.0, "g".to_string())));
         assert_eq!(parse_quantity("8oz"), Some((8.0, "oz".to_string())));
         assert_eq!(parse_quantity("1 bar"), Some((1.0, "bar".to_string())));
+        assert_eq!(parse_quantity("1,5 cups"), Some((1.5, "cups".to_string())));
+        assert_eq!(parse_quantity("100,0g"), Some((100.0, "g".to_string())));
+        assert_eq!(parse_quantity("1,500g"), None);
+    }
+
+    #[test]
+    fn test_each_as_count_unit() {
+        let egg = Food::new("egg", 6.0, 5.0, 0.6, 72.0, "1 large", vec![]);
+        for amount in ["3 each", "3 ea", "3each", "3 ct"] {
+            let m = egg.multiplier(&Amount::Measure(amount.to_string()), &Units::default(), UnknownUnit::Grams).unwrap();
+            assert!((m - 3.0).abs() < 1e-9, "{}", amount);
+        }
+
+        let per_each = Food::new("tortilla", 4.0, 3.5, 22.0, 140.0, "2 each", vec![]);
+        assert!((per_each.multiplier(&Amount::Measure("3 ea".to_string()), &Units::default(), UnknownUnit::Grams).unwrap() - 1.5).abs() < 1e-9);
+        assert!((per_each.multiplier(&Amount::Measure("4 pieces".to_string()), &Units::default(), UnknownUnit::Grams).unwrap() - 2.0).abs() < 1e-9);
+
+        // Against a weighed serving, "each" has no meaning beyond the old 1:1 discrete rule
+        let ribeye = Food::new("ribeye", 24.0, 20.0, 0.0, 291.0, "100g", vec![]);
+        assert!((ribeye.multiplier(&Amount::Measure("1 each".to_string()), &Units::default(), UnknownUnit::Grams).unwrap() - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_solve_macro() {
+        assert!((solve_macro(MacroKind::Carbs, 200.0, 20.0, 10.0, 0.0) - 7.5).abs() < 1e-9);
+        assert!((solve_macro(MacroKind::Fat, 200.0, 20.0, 0.0, 7.5) - 10.0).abs() < 1e-9);
+        assert!((solve_macro(MacroKind::Protein, 200.0, 0.0, 10.0, 7.5) - 20.0).abs() < 1e-9);
+        assert_eq!("carbs".parse::<MacroKind>(), Ok(MacroKind::Carbs));
+        assert!("fiber".parse::<MacroKind>().is_err());
+    }
+
+    #[test]
+    fn test_macros_for_new_food() {
+        let (m, warning) = macros_for_new_food(Some(20.0), Some(10.0), None, Some(200.0), Some(MacroKind::Carbs), false).unwrap();
+        assert!((m.carbs - 7.5).abs() < 1e-9);
+        assert_eq!(m.calories, 200.0);
+        assert!(warning.is_none());
+
+        // Calories too low for the given macros: clamp to zero and warn
+        let (m, warning) = macros_for_new_food(Some(20.0), Some(10.0), None, Some(150.0), Some(MacroKind::Carbs), false).unwrap();
+        assert_eq!(m.carbs, 0.0);
+        assert!(warning.is_some());
+
+        assert!(macros_for_new_food(Some(20.0), Some(10.0), None, None, Some(MacroKind::Carbs), false).is_err());
+        assert!(macros_for_new_food(Some(20.0), Some(10.0), Some(5.0), Some(200.0), Some(MacroKind::Carbs), false).is_err());
+        assert!(macros_for_new_food(Some(20.0), Some(10.0), None, Some(200.0), None, false).is_err());
+
+        let (m, _) = macros_for_new_food(Some(20.0), Some(10.0), Some(5.0), None, None, false).unwrap();
+        assert_eq!(m.calories, 190.0);
+    }
+
+    #[test]
+    fn test_explicit_zero_calories() {
+        // Omitted: estimated, no warning
+        let (m, warning) = macros_for_new_food(Some(20.0), Some(10.0), Some(5.0), None, None, false).unwrap();
+        assert_eq!(m.calories, 190.0);
+        assert!(warning.is_none());
+
+        // Explicit zero with macros: kept, but flagged
+        let (m, warning) = macros_for_new_food(Some(20.0), Some(10.0), Some(5.0), Some(0.0), None, false).unwrap();
+        assert_eq!(m.calories, 0.0);
+        assert!(warning.unwrap().contains("--fix-calories"));
+
+        // ...or corrected to the estimate
+        let (m, warning) = macros_for_new_food(Some(20.0), Some(10.0), Some(5.0), Some(0.0), None, true).unwrap();
+        assert_eq!(m.calories, 190.0);
+        assert!(warning.unwrap().contains("190 kcal"));
+
+        // Zero is right for a food with no macros (water, spices)
+        let (m, warning) = macros_for_new_food(Some(0.0), Some(0.0), Some(0.0), Some(0.0), None, false).unwrap();
+        assert_eq!(m.calories, 0.0);
+        assert!(warning.is_none());
+    }
+
+    #[test]
+    fn test_title_case() {
+        assert_eq!(title_case("chicken breast"), "Chicken Breast");
+        assert_eq!(title_case("BARE bar"), "Bare Bar");
+        assert_eq!(title_case("peanut-butter  cup"), "Peanut-Butter  Cup");
+        assert_eq!(title_case("trader joe's 2% milk"), "Trader Joe's 2% Milk");
+    }
+
+    #[test]
+    fn test_parse_number_decimal_comma() {
+        assert_eq!(parse_number("1,5"), Some(1.5));
+        assert_eq!(parse_number("100,0"), Some(100.0));
+        assert_eq!(parse_number("0,125"), Some(0.125));
+        assert_eq!(parse_number("2.25"), Some(2.25));
+        // Could be a thousands separator
+        assert_eq!(parse_number("1,500"), None);
+        assert_eq!(parse_number("1,000,000"), None);
+        assert_eq!(parse_number("1,"), None);
+        assert_eq!(parse_number(",5"), None);
+        assert_eq!(parse_fraction("1 1,5/3"), Some(1.5));
+    }
+
+    #[test]
+    fn test_parse_fraction() {
+        assert_eq!(parse_fraction("3/8"), Some(0.375));
+        assert_eq!(parse_fraction("1 1/2"), Some(1.5));
+        assert_eq!(parse_fraction("0.25"), Some(0.25));
+        assert_eq!(parse_fraction("1/0"), None);
+        assert_eq!(parse_fraction("0"), None);
+        assert_eq!(parse_fraction("a/b"), None);
+        assert_eq!(parse_fraction("1/2 1/2"), None);
+    }
+
+    #[test]
+    fn test_scale_fraction() {
+        let pizza = Food::new("pizza", 80.0, 96.0, 240.0, 2144.0, "1 pizza", vec![]);
+        let macros = pizza.scale(parse_fraction("3/8").unwrap());
+        assert!((macros.protein - 30.0).abs() < 1e-9);
+        assert!((macros.calories - 804.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_protein_density() {
+        let chicken = Food::new("chicken breast", 31.0, 3.6, 0.0, 165.0, "100g", vec![]);
+        assert!((chicken.protein_density().unwrap() - 18.79).abs() < 0.01);
+
+        let salt = Food::new("salt", 0.0, 0.0, 0.0, 0.0, "1 tsp", vec![]);
+        assert_eq!(salt.protein_density(), None);
+    }
+
+    #[test]
+    fn test_sort_by_protein_density() {
+        let mut foods = vec![
+            Food::new("salt", 0.0, 0.0, 0.0, 0.0, "1 tsp", vec![]),
+            Food::new("ribeye", 24.0, 20.0, 0.0, 291.0, "100g", vec![]),
+            Food::new("chicken breast", 31.0, 3.6, 0.0, 165.0, "100g", vec![]),
+            Food::new("rice", 2.7, 0.3, 28.0, 130.0, "100g", vec![]),
+        ];
+        sort_by_protein_density(&mut foods);
+        let names: Vec<_> = foods.iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(names, vec!["chicken breast", "ribeye", "rice", "salt"]);
+    }
+
+    #[test]
+    fn test_serving_grams() {
+        assert_eq!(serving_grams("30g"), Some(30.0));
+        assert!((serving_grams("4oz").unwrap() - 113.398).abs() < 0.01);
+        assert_eq!(serving_grams("1 bar"), None);
+        assert_eq!(serving_grams("1 cup"), None);
+    }
+
+    #[test]
+    fn test_normalized_per_100g() {
+        let chips = Food::new("chips", 2.0, 10.0, 15.0, 160.0, "30g", vec![]);
+        let normalized = chips.normalized_per_100g(serving_grams(&chips.serving).unwrap());
+        assert_eq!(normalized.serving, "100g");
+        assert_eq!(normalized.default_amount.as_deref(), Some("30g"));
+        assert!((normalized.protein - 6.6667).abs() < 0.001);
+        assert!((normalized.fat - 33.3333).abs() < 0.001);
+        assert!((normalized.carbs - 50.0).abs() < 0.001);
+        assert!((normalized.calories - 533.3333).abs() < 0.001);
+
+        // Logging the remembered serving gives back the label values
+        let macros = normalized.calculate(&"30g".into(), &Units::default(), UnknownUnit::Grams).unwrap();
+        assert!((macros.calories - 160.0).abs() < 0.001);
+        assert!(normalized.units.is_empty());
+    }
+
+    #[test]
+    fn test_normalized_count_serving() {
+        let bar = Food::new("protein bar", 20.0, 8.0, 22.0, 240.0, "1 bar", vec![]);
+        let normalized = bar.normalized_per_100g(60.0);
+        assert_eq!(normalized.serving, "100g");
+        assert_eq!(normalized.default_amount.as_deref(), Some("1 bar"));
+        assert!((normalized.calories - 400.0).abs() < 0.001);
+
+        // Bars, a bare count, and grams all still work
+        let units = Units::default();
+        let two_bars = normalized.calculate(&"2 bars".into(), &units, UnknownUnit::Error).unwrap();
+        assert!((two_bars.calories - 480.0).abs() < 0.001);
+        let count = normalized.calculate(&Amount::Count(2.0), &units, UnknownUnit::Error).unwrap();
+        assert!((count.calories - 480.0).abs() < 0.001);
+        assert_eq!(normalized.count_amount(2.0), "2 bars");
+        let grams = normalized.calculate(&"30g".into(), &units, UnknownUnit::Error).unwrap();
+        assert!((grams.calories - 120.0).abs() < 0.001);
+
+        let toast = Food::new("toast", 6.0, 2.0, 30.0, 160.0, "2 slices", vec![]).normalized_per_100g(50.0);
+        let slice = toast.calculate(&"1 slice".into(), &units, UnknownUnit::Error).unwrap();
+        assert!((slice.calories - 80.0).abs() < 0.001);
+    }
+
+    #[test]
+    fn test_calculate_count() {
+        let egg = Food::new("egg", 6.0, 5.0, 0.5, 72.0, "1 egg", vec![]);
+        let macros = egg.calculate(&Amount::Count(2.0), &Units::default(), UnknownUnit::Grams).unwrap();
+        assert!((macros.protein - 12.0).abs() < 1e-9);
+        assert!((macros.calories - 144.0).abs() < 1e-9);
+
+        let toast = Food::new("toast", 6.0, 2.0, 30.0, 160.0, "2 slices", vec![]);
+        let macros = toast.calculate(&Amount::Count(3.0), &Units::default(), UnknownUnit::Grams).unwrap();
+        assert!((macros.carbs - 45.0).abs() < 1e-9);
+
+        // A count against a weighed serving means that many servings, not grams
+        let rice = Food::new("rice", 2.7, 0.3, 28.0, 130.0, "100g", vec![]);
+        let macros = rice.calculate(&Amount::Count(2.0), &Units::default(), UnknownUnit::Grams).unwrap();
+        assert!((macros.calories - 260.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_serving_unit_mode() {
+        let mut whey = Food::new("whey", 24.0, 1.5, 3.0, 120.0, "1 scoop", vec![]);
+        whey.unit_mode = UnitMode::Serving;
+        // Even with a weight configured for scoops, servings are only counted
+        let units = Units::new(BTreeMap::from([("scoop".to_string(), 32.0)])).unwrap();
+        for amount in [Amount::from("2 scoops"), Amount::from("2"), Amount::Count(2.0)] {
+            let macros = whey.calculate(&amount, &units, UnknownUnit::Error).unwrap();
+            assert!((macros.protein - 48.0).abs() < 1e-9, "{}", amount);
+            assert!((macros.calories - 240.0).abs() < 1e-9);
+        }
+        assert!((whey.multiplier(&"1/2 scoop".into(), &units, UnknownUnit::Error).unwrap() - 0.5).abs() < 1e-9);
+        assert_eq!(
+            whey.calculate(&"30g".into(), &units, UnknownUnit::Grams).unwrap_err(),
+            ConversionError::ServingsOnly { amount: "30g".to_string(), serving: "1 scoop".to_string() }
+        );
+
+        // In auto mode the same food goes through grams
+        whey.unit_mode = UnitMode::Auto;
+        assert!((whey.multiplier(&"2 scoops".into(), &Units::default(), UnknownUnit::Grams).unwrap() - 2.0).abs() < 1e-9);
+        assert!((whey.multiplier(&"2".into(), &Units::default(), UnknownUnit::Grams).unwrap() - 0.02).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_volume_serving_logged_by_weight() {
+        let mut milk = Food::new("milk", 8.0, 8.0, 12.0, 150.0, "240ml", vec![]);
+        assert_eq!(
+            milk.calculate(&"200g".into(), &Units::default(), UnknownUnit::Grams).unwrap_err(),
+            ConversionError::NeedsDensity { amount: "200g".to_string(), serving: "240ml".to_string() }
+        );
+
+        milk.density = Some(1.03);
+        let macros = milk.calculate(&"247.2g".into(), &Units::default(), UnknownUnit::Grams).unwrap();
+        assert!((macros.calories - 150.0).abs() < 1e-9);
+
+        // Same-dimension conversions don't need a density
+        let macros = milk.calculate(&"1 cup".into(), &Units::default(), UnknownUnit::Grams).unwrap();
+        assert!((macros.protein - 8.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_weight_serving_logged_by_volume() {
+        let mut oil = Food::new("olive oil", 0.0, 100.0, 0.0, 884.0, "100g", vec![]);
+        assert!(matches!(oil.calculate(&"1 tbsp".into(), &Units::default(), UnknownUnit::Grams), Err(ConversionError::NeedsDensity { .. })));
+
+        oil.density = Some(0.91);
+        let macros = oil.calculate(&"1 tbsp".into(), &Units::default(), UnknownUnit::Grams).unwrap();
+        assert!((macros.fat - 13.65).abs() < 1e-9);
     }
 
     #[test]
     fn test_to_grams() {
-        assert_eq!(to_grams(100.0, "g"), Some(100.0));
-        assert!((to_grams(1.0, "oz").unwrap() - 28.3495).abs() < 0.01);
+        assert_eq!(to_grams(100.0, "g", &Units::default()), Some(100.0));
+        assert!((to_grams(1.0, "oz", &Units::default()).unwrap() - 28.3495).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_configured_units() {
+        let units = Units::new(BTreeMap::from([("Scoop".to_string(), 32.0), ("cup".to_string(), 200.0)])).unwrap();
+        assert_eq!(units.grams_per("scoops"), Some(32.0));
+        assert_eq!(to_grams(2.0, "scoop", &units), Some(64.0));
+
+        // A scoop of protein powder logged by weight, and by scoops against a weighed serving
+        let whey = Food::new("whey", 80.0, 6.0, 8.0, 400.0, "100g", vec![]);
+        let macros = whey.calculate(&"2 scoops".into(), &units, UnknownUnit::Grams).unwrap();
+        assert!((macros.protein - 51.2).abs() < 1e-9);
+        // Without the table, "scoop" is the old 1:1 discrete unit
+        assert!((whey.calculate(&"2 scoops".into(), &Units::default(), UnknownUnit::Grams).unwrap().protein - 160.0).abs() < 1e-9);
+
+        // Overriding a built-in volume makes it a weight, no density needed
+        let oats = Food::new("oats", 13.0, 7.0, 68.0, 389.0, "100g", vec![]);
+        assert!(oats.calculate(&"1 cup".into(), &Units::default(), UnknownUnit::Grams).is_err());
+        assert!((oats.calculate(&"1 cup".into(), &units, UnknownUnit::Grams).unwrap().calories - 778.0).abs() < 1e-9);
+
+        assert!(Units::new(BTreeMap::from([("scoop".to_string(), 0.0)])).is_err());
+        assert!(Units::new(BTreeMap::from([("scoop".to_string(), -5.0)])).is_err());
     }
 }
@@ -0,0 +1,437 @@
+use anyhow::Result;
+use chrono::{Datelike, Duration, NaiveDate};
+use serde::Serialize;
+
+use crate::config::Config;
+use crate::db::{Database, Goals};
+use crate::food::{format_number, serving_grams, sort_by_protein_density, Food, Macros};
+
+/// A day's totals alongside whatever goals apply to it
+#[derive(Debug, Clone, Serialize)]
+pub struct DayProgress {
+    #[serde(flatten)]
+    pub totals: Macros,
+    /// Carbs less fiber
+    pub net_carbs: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub goals: Option<Goals>,
+    /// What's left of each daily goal
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub remaining: Option<Remaining>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub weekly: Option<WeeklyBudget>,
+    /// A suggested macro split, when the only daily goal is calories
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub derived_goals: Option<DerivedGoals>,
+    /// Filled in by `today --check-protein`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub protein_check: Option<ProteinCheck>,
+}
+
+pub fn day_progress(db: &Database, config: &Config, date: NaiveDate) -> Result<DayProgress> {
+    let goals = db.get_goals_for(date)?;
+    let totals = db.get_totals_for_date(date, None)?;
+    Ok(DayProgress {
+        derived_goals: goals.as_ref().and_then(|g| derived_goals(g, config)),
+        remaining: goals.as_ref().and_then(|g| remaining_against(g, &totals, date)),
+        net_carbs: totals.net_carbs(),
+        totals,
+        goals,
+        weekly: weekly_budget_remaining(db, date)?,
+        protein_check: None,
+    })
+}
+
+/// What's left of each daily goal on a date; negative when over. Macros without
+/// a goal are None.
+#[derive(Debug, Clone, Serialize)]
+pub struct Remaining {
+    pub date: String,
+    pub protein: Option<f64>,
+    pub fat: Option<f64>,
+    pub carbs: Option<f64>,
+    pub calories: Option<f64>,
+    /// The same, as a sentence for a person
+    pub summary: String,
+}
+
+/// Goals minus what's been eaten on `date`, or None if no daily goal applies to it
+pub fn remaining(db: &Database, date: NaiveDate) -> Result<Option<Remaining>> {
+    let Some(goals) = db.get_goals_for(date)? else {
+        return Ok(None);
+    };
+    Ok(remaining_against(&goals, &db.get_totals_for_date(date, None)?, date))
+}
+
+/// `goals` minus `eaten`, or None if none of them is a daily goal
+fn remaining_against(goals: &Goals, eaten: &Macros, date: NaiveDate) -> Option<Remaining> {
+    let left = |goal: Option<f64>, eaten: f64| goal.map(|g| g - eaten);
+    let (protein, fat, carbs, calories) = (
+        left(goals.protein, eaten.protein),
+        left(goals.fat, eaten.fat),
+        left(goals.carbs, eaten.carbs),
+        left(goals.calories, eaten.calories),
+    );
+
+    let parts: Vec<String> = [(protein, "g protein"), (fat, "g fat"), (carbs, "g carbs"), (calories, " kcal")]
+        .into_iter()
+        .filter_map(|(left, what)| {
+            let left = left?;
+            Some(if left >= 0.0 {
+                format!("{:.0}{} left", left, what)
+            } else {
+                format!("{:.0}{} over", -left, what)
+            })
+        })
+        .collect();
+    if parts.is_empty() {
+        return None;
+    }
+    let date = date.format("%Y-%m-%d").to_string();
+    Some(Remaining {
+        summary: format!("For {}: {}.", date, parts.join(", ")),
+        date,
+        protein,
+        fat,
+        carbs,
+        calories,
+    })
+}
+
+/// Share of calories from protein when there's no bodyweight to base it on
+const PROTEIN_SHARE: f64 = 0.3;
+/// Share of calories from fat; carbs get whatever is left
+const FAT_SHARE: f64 = 0.3;
+
+/// Macro targets worked out from a calorie goal rather than set by the user
+#[derive(Debug, Clone, Serialize)]
+pub struct DerivedGoals {
+    pub protein: f64,
+    pub fat: f64,
+    pub carbs: f64,
+    pub calories: f64,
+    /// Set when protein comes from bodyweight rather than a share of calories
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bodyweight_lb: Option<f64>,
+}
+
+/// A split for `goals` when calories is the only daily target set, or None
+pub fn derived_goals(goals: &Goals, config: &Config) -> Option<DerivedGoals> {
+    if goals.protein.is_some() || goals.fat.is_some() || goals.carbs.is_some() {
+        return None;
+    }
+    Some(suggest_split(goals.calories?, config.bodyweight_lb, config.protein_per_lb))
+}
+
+/// Split `calories` into macros. Protein is `protein_per_lb` grams per pound of
+/// bodyweight if that's known, otherwise 30% of calories; fat is 30% of calories
+/// and carbs the rest. Protein and fat never take more than the calories allow.
+pub fn suggest_split(calories: f64, bodyweight_lb: Option<f64>, protein_per_lb: f64) -> DerivedGoals {
+    let calories = calories.max(0.0);
+    let bodyweight_lb = bodyweight_lb.filter(|w| *w > 0.0);
+    let protein = match bodyweight_lb {
+        Some(weight) => (weight * protein_per_lb).min(calories / 4.0),
+        None => calories * PROTEIN_SHARE / 4.0,
+    };
+    let fat = (calories * FAT_SHARE).min(calories - protein * 4.0) / 9.0;
+    let carbs = (calories - protein * 4.0 - fat * 9.0) / 4.0;
+    DerivedGoals { protein, fat, carbs, calories, bodyweight_lb }
+}
+
+/// How far the day's protein is from the goal
+#[derive(Debug, Clone, Serialize)]
+pub struct ProteinCheck {
+    pub goal: f64,
+    pub eaten: f64,
+    /// Grams still needed; zero once the goal is met
+    pub deficit: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub suggestion: Option<ProteinSuggestion>,
+}
+
+/// A food that would close the protein gap, and how much of it
+#[derive(Debug, Clone, Serialize)]
+pub struct ProteinSuggestion {
+    pub food: String,
+    /// e.g. "150g" or "2 × 1 bar"
+    pub amount: String,
+    pub calories: f64,
+}
+
+/// Compare the day's protein to the goal, or None if there is no protein goal.
+/// When short, suggest the most protein-dense food in the database.
+pub fn protein_check(db: &Database, date: NaiveDate) -> Result<Option<ProteinCheck>> {
+    let Some(goal) = db.get_goals_for(date)?.and_then(|g| g.protein) else {
+        return Ok(None);
+    };
+    let eaten = db.get_totals_for_date(date, None)?.protein;
+    let deficit = (goal - eaten).max(0.0);
+
+    let suggestion = if deficit > 0.0 {
+        let mut foods: Vec<Food> = db.all_foods()?.into_iter().filter(|f| f.protein > 0.0).collect();
+        sort_by_protein_density(&mut foods);
+        foods.into_iter().next().map(|food| suggest_amount(&food, deficit))
+    } else {
+        None
+    };
+
+    Ok(Some(ProteinCheck { goal, eaten, deficit, suggestion }))
+}
+
+fn suggest_amount(food: &Food, protein: f64) -> ProteinSuggestion {
+    let multiplier = protein / food.protein;
+    let amount = match serving_grams(&food.serving) {
+        Some(grams) => format!("{}g", format_number((grams * multiplier).ceil())),
+        None => format!("{} × {}", format_number((multiplier * 10.0).ceil() / 10.0), food.serving),
+    };
+    ProteinSuggestion {
+        food: food.name.clone(),
+        amount,
+        calories: food.calories * multiplier,
+    }
+}
+
+/// Where the week stands against a weekly calorie budget.
+/// Weeks run Monday through Sunday.
+#[derive(Debug, Clone, Serialize)]
+pub struct WeeklyBudget {
+    pub weekly_calories: f64,
+    pub week_start: String,
+    /// Calories eaten this week before today
+    pub used_before_today: f64,
+    /// Calories eaten this week, including today
+    pub used: f64,
+    /// Budget left for the rest of the week, including what's left of today
+    pub remaining: f64,
+    /// Days left in the week, counting today
+    pub days_left: u32,
+    /// Today's share: what was left at the start of today, spread evenly over the days left
+    pub today_target: f64,
+    /// Today's share minus what's been eaten today
+    pub today_remaining: f64,
+}
+
+/// Compute the weekly budget position for `date`, or None if no weekly goal is set
+pub fn weekly_budget_remaining(db: &Database, date: NaiveDate) -> Result<Option<WeeklyBudget>> {
+    let Some(weekly_calories) = db.get_default_goals_on(date)?.and_then(|g| g.weekly_calories) else {
+        return Ok(None);
+    };
+
+    let days_from_monday = date.weekday().num_days_from_monday();
+    let week_start = date - Duration::days(days_from_monday as i64);
+
+    let mut before_today = Macros::default();
+    let mut today = Macros::default();
+    for day in db.get_daily_totals(week_start, date)? {
+        if day.date == date.format("%Y-%m-%d").to_string() {
+            today.add(&day.totals);
+        } else {
+            before_today.add(&day.totals);
+        }
+    }
+
+    let days_left = 7 - days_from_monday;
+    let today_target = (weekly_calories - before_today.calories) / days_left as f64;
+    let used = before_today.calories + today.calories;
+
+    Ok(Some(WeeklyBudget {
+        weekly_calories,
+        week_start: week_start.format("%Y-%m-%d").to_string(),
+        used_before_today: before_today.calories,
+        used,
+        remaining: weekly_calories - used,
+        days_left,
+        today_target,
+        today_remaining: today_target - today.calories,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::food::Food;
+
+    fn date(s: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap()
+    }
+
+    fn log_calories(db: &Database, day: &str, calories: f64) {
+        let food = db.get_food_by_name("meal").unwrap().unwrap();
+        let macros = Macros { protein: 0.0, fat: 0.0, carbs: 0.0, calories, fiber: 0.0 };
+        db.log_food_on(date(day), food.id.unwrap(), "1 serving", &macros).unwrap();
+    }
+
+    #[test]
+    fn test_remaining() {
+        let db = Database::open_in_memory().unwrap();
+        db.add_food(&Food::new("chicken breast", 31.0, 3.6, 0.0, 165.0, "100g", vec![])).unwrap();
+        let day = date("2024-06-05");
+        assert!(remaining(&db, day).unwrap().is_none());
+        // A weekly budget alone isn't a daily goal
+        db.set_goals_from(&Goals { weekly_calories: Some(14000.0), ..Goals::default() }, date("2024-06-01")).unwrap();
+        assert!(remaining(&db, day).unwrap().is_none());
+
+        db.set_goals_from(&Goals { protein: Some(150.0), fat: Some(10.0), calories: Some(2000.0), ..Goals::default() }, date("2024-06-01")).unwrap();
+        let chicken = db.get_food_by_name("chicken breast").unwrap().unwrap();
+        db.log_food_on(day, chicken.id.unwrap(), "300g", &chicken.scale(3.0)).unwrap();
+
+        let left = remaining(&db, day).unwrap().unwrap();
+        assert!((left.protein.unwrap() - 57.0).abs() < 1e-9);
+        assert!((left.fat.unwrap() + 0.8).abs() < 1e-9);
+        assert!(left.carbs.is_none());
+        assert!((left.calories.unwrap() - 1505.0).abs() < 1e-9);
+        assert_eq!(left.summary, "For 2024-06-05: 57g protein left, 1g fat over, 1505 kcal left.");
+
+        // `today` carries the same alongside the goals
+        let progress = day_progress(&db, &Config::default(), day).unwrap();
+        assert_eq!(progress.remaining.unwrap().summary, left.summary);
+        let json = serde_json::to_value(day_progress(&db, &Config::default(), day).unwrap()).unwrap();
+        assert_eq!(json["goals"]["protein"], 150.0);
+        assert_eq!(json["remaining"]["protein"], 57.0);
+        assert!(day_progress(&db, &Config::default(), date("2024-06-01")).unwrap().remaining.is_some());
+        db.clear_goals(date("2024-06-01")).unwrap();
+        let json = serde_json::to_value(day_progress(&db, &Config::default(), day).unwrap()).unwrap();
+        assert!(json.get("goals").is_none() && json.get("remaining").is_none());
+    }
+
+    #[test]
+    fn test_past_days_use_goals_in_effect_then() {
+        let db = Database::open_in_memory().unwrap();
+        db.set_goals_from(&Goals { protein: Some(150.0), calories: Some(2400.0), ..Goals::default() }, date("2024-06-01")).unwrap();
+        db.set_goals_from(&Goals { protein: Some(180.0), ..Goals::default() }, date("2024-06-10")).unwrap();
+        db.clear_goals(date("2024-06-20")).unwrap();
+
+        let goals_on = |day: &str| day_progress(&db, &Config::default(), date(day)).unwrap().goals;
+        assert_eq!(goals_on("2024-06-05").unwrap().calories, Some(2400.0));
+        assert_eq!(goals_on("2024-06-10").unwrap(), Goals { protein: Some(180.0), ..Goals::default() });
+        assert!(goals_on("2024-06-25").is_none());
+        // No goals before the first change
+        assert!(goals_on("2024-05-01").is_none());
+
+        assert!(db.get_goals().unwrap().is_none());
+        let history = db.goal_history().unwrap();
+        assert_eq!(history.iter().map(|c| c.effective_date.as_str()).collect::<Vec<_>>(), vec!["2024-06-01", "2024-06-10", "2024-06-20"]);
+        assert!(history[2].cleared);
+
+        // A dump carries the history along
+        let copy = Database::open_in_memory().unwrap();
+        copy.load(&db.dump().unwrap()).unwrap();
+        assert_eq!(copy.goal_history().unwrap(), history);
+    }
+
+    #[test]
+    fn test_protein_check() {
+        let db = Database::open_in_memory().unwrap();
+        db.add_food(&Food::new("chicken breast", 31.0, 3.6, 0.0, 165.0, "100g", vec![])).unwrap();
+        db.add_food(&Food::new("peanut butter", 25.0, 50.0, 20.0, 588.0, "100g", vec![])).unwrap();
+        db.add_food(&Food::new("meal", 0.0, 0.0, 0.0, 0.0, "1 serving", vec![])).unwrap();
+        let today = date("2024-06-05");
+        assert!(protein_check(&db, today).unwrap().is_none());
+
+        db.set_goals_from(&Goals { protein: Some(150.0), ..Goals::default() }, date("2024-06-01")).unwrap();
+        let chicken = db.get_food_by_name("chicken breast").unwrap().unwrap();
+        db.log_food_on(today, chicken.id.unwrap(), "300g", &chicken.scale(3.0)).unwrap();
+
+        let check = protein_check(&db, today).unwrap().unwrap();
+        assert!((check.eaten - 93.0).abs() < 1e-9);
+        assert!((check.deficit - 57.0).abs() < 1e-9);
+        let suggestion = check.suggestion.unwrap();
+        assert_eq!(suggestion.food, "chicken breast");
+        assert_eq!(suggestion.amount, "184g");
+
+        // Goal met: no deficit, no suggestion
+        db.log_food_on(today, chicken.id.unwrap(), "200g", &chicken.scale(2.0)).unwrap();
+        let check = protein_check(&db, today).unwrap().unwrap();
+        assert_eq!(check.deficit, 0.0);
+        assert!(check.suggestion.is_none());
+    }
+
+    #[test]
+    fn test_suggest_split_without_bodyweight() {
+        let split = suggest_split(2000.0, None, 1.0);
+        assert!((split.protein - 150.0).abs() < 1e-9);
+        assert!((split.fat - 600.0 / 9.0).abs() < 1e-9);
+        assert!((split.carbs - 200.0).abs() < 1e-9);
+        assert!(split.bodyweight_lb.is_none());
+        let kcal = split.protein * 4.0 + split.fat * 9.0 + split.carbs * 4.0;
+        assert!((kcal - 2000.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_suggest_split_with_bodyweight() {
+        // 180 lb at 1 g/lb: 720 kcal of protein, 600 of fat, 880 left for carbs
+        let split = suggest_split(2200.0, Some(180.0), 1.0);
+        assert!((split.protein - 180.0).abs() < 1e-9);
+        assert!((split.fat - 660.0 / 9.0).abs() < 1e-9);
+        assert!((split.carbs - 205.0).abs() < 1e-9);
+        assert_eq!(split.bodyweight_lb, Some(180.0));
+
+        let split = suggest_split(2200.0, Some(180.0), 0.8);
+        assert!((split.protein - 144.0).abs() < 1e-9);
+
+        // Protein can't outrun the calories; fat and carbs get nothing
+        let split = suggest_split(500.0, Some(200.0), 1.0);
+        assert!((split.protein - 125.0).abs() < 1e-9);
+        assert_eq!((split.fat, split.carbs), (0.0, 0.0));
+    }
+
+    #[test]
+    fn test_derived_goals_only_for_calories_alone() {
+        let config = Config { bodyweight_lb: Some(160.0), ..Config::default() };
+        let calories_only = Goals { calories: Some(2000.0), ..Goals::default() };
+        assert!((derived_goals(&calories_only, &config).unwrap().protein - 160.0).abs() < 1e-9);
+
+        let with_protein = Goals { protein: Some(150.0), ..calories_only.clone() };
+        assert!(derived_goals(&with_protein, &config).is_none());
+        assert!(derived_goals(&Goals::default(), &config).is_none());
+    }
+
+    #[test]
+    fn test_suggest_amount_for_count_serving() {
+        let bar = Food::new("protein bar", 20.0, 8.0, 22.0, 240.0, "1 bar", vec![]);
+        let suggestion = suggest_amount(&bar, 30.0);
+        assert_eq!(suggestion.amount, "1.5 × 1 bar");
+        assert!((suggestion.calories - 360.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_no_weekly_goal() {
+        let db = Database::open_in_memory().unwrap();
+        assert!(weekly_budget_remaining(&db, date("2024-06-05")).unwrap().is_none());
+
+        db.set_goals_from(&Goals { calories: Some(2000.0), ..Goals::default() }, date("2024-06-01")).unwrap();
+        assert!(weekly_budget_remaining(&db, date("2024-06-05")).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_weekly_budget_partial_week() {
+        let db = Database::open_in_memory().unwrap();
+        db.add_food(&Food::new("meal", 0.0, 0.0, 0.0, 0.0, "1 serving", vec![])).unwrap();
+        db.set_goals_from(&Goals { weekly_calories: Some(14000.0), ..Goals::default() }, date("2024-06-01")).unwrap();
+
+        // Mon 2024-06-03 and Tue 2024-06-04 went over; Wed is today
+        log_calories(&db, "2024-06-03", 2500.0);
+        log_calories(&db, "2024-06-04", 2500.0);
+        log_calories(&db, "2024-06-05", 800.0);
+        // Previous week's Sunday doesn't count
+        log_calories(&db, "2024-06-02", 9999.0);
+
+        let budget = weekly_budget_remaining(&db, date("2024-06-05")).unwrap().unwrap();
+        assert_eq!(budget.week_start, "2024-06-03");
+        assert_eq!(budget.days_left, 5);
+        assert!((budget.used_before_today - 5000.0).abs() < 1e-9);
+        assert!((budget.today_target - 1800.0).abs() < 1e-9);
+        assert!((budget.today_remaining - 1000.0).abs() < 1e-9);
+        assert!((budget.remaining - 8200.0).abs() < 1e-9);
+
+        // On Monday the whole budget is spread over seven days
+        let budget = weekly_budget_remaining(&db, date("2024-06-03")).unwrap().unwrap();
+        assert_eq!(budget.days_left, 7);
+        assert!((budget.today_target - 2000.0).abs() < 1e-9);
+
+        // On Sunday whatever is left is today's
+        let budget = weekly_budget_remaining(&db, date("2024-06-09")).unwrap().unwrap();
+        assert_eq!(budget.days_left, 1);
+        assert!((budget.today_target - 8200.0).abs() < 1e-9);
+    }
+}
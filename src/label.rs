@@ -0,0 +1,254 @@
+use anyhow::Result;
+use serde::Serialize;
+
+use crate::food::{format_number, parse_fraction, parse_number, Food};
+
+/// Kilojoules per kilocalorie, for labels that only give energy in kJ
+const KJ_PER_KCAL: f64 = 4.184;
+
+/// What could be read off a pasted nutrition label. Fields the label didn't
+/// show (or that couldn't be found) are None.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct LabelFacts {
+    pub serving: Option<String>,
+    pub calories: Option<f64>,
+    pub protein: Option<f64>,
+    pub fat: Option<f64>,
+    pub carbs: Option<f64>,
+}
+
+impl LabelFacts {
+    /// Names of the fields that weren't found
+    pub fn missing(&self) -> Vec<&'static str> {
+        [
+            ("serving", self.serving.is_none()),
+            ("calories", self.calories.is_none()),
+            ("protein", self.protein.is_none()),
+            ("fat", self.fat.is_none()),
+            ("carbs", self.carbs.is_none()),
+        ]
+        .into_iter()
+        .filter_map(|(name, missing)| missing.then_some(name))
+        .collect()
+    }
+
+    /// A food named `name` from these facts. All three macros are needed; a missing
+    /// serving falls back to `default_serving` and missing calories to the Atwater estimate.
+    pub fn to_food(&self, name: &str, default_serving: &str) -> Result<Food> {
+        let (Some(protein), Some(fat), Some(carbs)) = (self.protein, self.fat, self.carbs) else {
+            let missing: Vec<_> = self.missing().into_iter().filter(|m| !matches!(*m, "serving" | "calories")).collect();
+            anyhow::bail!("label is missing {}; add the food with `chomp add` instead", missing.join(", "));
+        };
+        let (macros, _) = crate::food::macros_for_new_food(Some(protein), Some(fat), Some(carbs), self.calories, None, false)?;
+        let serving = self.serving.as_deref().unwrap_or(default_serving);
+        Ok(Food::new(name, macros.protein, macros.fat, macros.carbs, macros.calories, serving, vec![]))
+    }
+}
+
+/// Read serving size, calories, and macros out of nutrition label text, as copied
+/// from a US "Nutrition Facts" panel ("Total Fat 8g") or a per-100g EU table
+/// ("Energy 1569kJ / 375kcal"). One line per nutrient or all on one line both work.
+pub fn parse_label(text: &str) -> LabelFacts {
+    let text = text.to_lowercase();
+
+    let calories = value_after(&text, &["calories"], &[], &["from"])
+        .or_else(|| energy_kcal(&text));
+
+    LabelFacts {
+        serving: serving_size(&text),
+        calories,
+        protein: value_after(&text, &["protein"], &[], &[]),
+        fat: value_after(&text, &["fat"], &["saturated", "sat", "trans", "from"], &[]),
+        carbs: value_after(&text, &["carbohydrates", "carbohydrate", "carbs", "carb"], &["net"], &[]),
+    }
+}
+
+/// The number following the first usable occurrence of any of `keywords`, taken as
+/// whole words. Occurrences right after a word in `not_after` ("saturated fat") or
+/// right before a word in `not_before` ("calories from fat") are passed over, as are
+/// ones not followed by a number ("2,000 calories a day").
+fn value_after(text: &str, keywords: &[&str], not_after: &[&str], not_before: &[&str]) -> Option<f64> {
+    for keyword in keywords {
+        for (start, _) in text.match_indices(keyword) {
+            let end = start + keyword.len();
+            let before = &text[..start];
+            let after = &text[end..];
+            if before.ends_with(|c: char| c.is_alphabetic()) || after.starts_with(|c: char| c.is_alphabetic()) {
+                continue;
+            }
+            let previous_word = before.split(|c: char| !c.is_alphabetic()).rfind(|w| !w.is_empty());
+            if previous_word.is_some_and(|w| not_after.contains(&w)) {
+                continue;
+            }
+            let next_word = after.split(|c: char| !c.is_alphabetic()).find(|w| !w.is_empty());
+            if next_word.is_some_and(|w| not_before.contains(&w)) {
+                continue;
+            }
+            if let Some((value, unit)) = leading_number(after) {
+                if unit != "mg" && unit != "kj" {
+                    return Some(value);
+                }
+            }
+        }
+    }
+    None
+}
+
+/// Calories from an "Energy" line, preferring the kcal figure over kJ
+fn energy_kcal(text: &str) -> Option<f64> {
+    let line = text.lines().find(|line| line.contains("energy"))?;
+    let numbers = numbers_in(&line[line.find("energy")? + "energy".len()..]);
+    if let Some((kcal, _)) = numbers.iter().find(|(_, unit)| unit == "kcal") {
+        return Some(*kcal);
+    }
+    numbers.iter().find(|(_, unit)| unit == "kj").map(|(kj, _)| kj / KJ_PER_KCAL)
+}
+
+/// The serving the figures are for: the weight in parentheses after "Serving size"
+/// if there is one ("2/3 cup (55g)" -> "55g"), else the quantity given ("1 bar").
+/// Labels with no serving size but a "per 100g" heading are per 100g.
+fn serving_size(text: &str) -> Option<String> {
+    let Some(start) = text.find("serving size") else {
+        let per_100g = ["per 100g", "per 100 g"].iter().any(|p| text.contains(p));
+        return per_100g.then(|| "100g".to_string());
+    };
+    let rest = text[start + "serving size".len()..].lines().next().unwrap_or("");
+    let rest = rest.trim_start_matches(|c: char| c == ':' || c.is_whitespace());
+
+    if let Some(open) = rest.find('(') {
+        let inside = &rest[open + 1..rest[open..].find(')').map_or(rest.len(), |i| open + i)];
+        if let Some((grams, _)) = numbers_in(inside).into_iter().find(|(_, unit)| unit == "g") {
+            return Some(format!("{}g", format_number(grams)));
+        }
+    }
+    let (value, unit) = leading_number(rest)?;
+    Some(match unit.as_str() {
+        "" => format_number(value),
+        "g" | "kg" | "oz" | "lb" | "ml" | "l" => format!("{}{}", format_number(value), unit),
+        unit => format!("{} {}", format_number(value), unit),
+    })
+}
+
+/// A number at the start of `s` (after spaces and punctuation like ":" or "<"),
+/// with the unit word right after it
+fn leading_number(s: &str) -> Option<(f64, String)> {
+    let start = s.find(|c: char| c.is_ascii_digit() || c.is_alphabetic())?;
+    if !s[start..].starts_with(|c: char| c.is_ascii_digit()) {
+        return None;
+    }
+    numbers_in(&s[start..]).into_iter().next()
+}
+
+/// Every number in `s`, each with the unit word that follows it (possibly empty).
+/// Handles decimal commas ("8,0 g"), thousands separators ("1,200"), and fractions ("2/3 cup").
+fn numbers_in(s: &str) -> Vec<(f64, String)> {
+    let mut numbers = Vec::new();
+    let mut rest = s;
+    while let Some(start) = rest.find(|c: char| c.is_ascii_digit()) {
+        let tail = &rest[start..];
+        let len = tail.find(|c: char| !(c.is_ascii_digit() || matches!(c, '.' | ',' | '/'))).unwrap_or(tail.len());
+        let raw = tail[..len].trim_end_matches(['.', ',', '/']);
+        let after = &tail[raw.len()..];
+        let unit: String = after.trim_start().chars().take_while(|c| c.is_alphabetic()).collect();
+
+        let value = if raw.contains('/') {
+            parse_fraction(raw)
+        } else {
+            parse_number(raw).or_else(|| parse_number(&raw.replace(',', "")))
+        };
+        if let Some(value) = value {
+            numbers.push((value, unit));
+        }
+        rest = after;
+    }
+    numbers
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_us_label() {
+        let label = "\
+Nutrition Facts
+8 servings per container
+Serving size 2/3 cup (55g)
+
+Amount per serving
+Calories 230
+                    % Daily Value*
+Total Fat 8g 10%
+    Saturated Fat 1g 5%
+    Trans Fat 0g
+Cholesterol 0mg 0%
+Sodium 160mg 7%
+Total Carbohydrate 37g 13%
+    Dietary Fiber 4g 14%
+    Total Sugars 12g
+Protein 3g
+
+* The % Daily Value (DV) tells you how much a nutrient in a serving of food
+contributes to a daily diet. 2,000 calories a day is used for general nutrition advice.";
+        assert_eq!(parse_label(label), LabelFacts {
+            serving: Some("55g".to_string()),
+            calories: Some(230.0),
+            protein: Some(3.0),
+            fat: Some(8.0),
+            carbs: Some(37.0),
+        });
+    }
+
+    #[test]
+    fn test_parse_eu_label() {
+        let label = "\
+Nutrition information    per 100g
+Energy    1569kJ / 375kcal
+Fat    8,0 g
+of which saturates    1,5 g
+Carbohydrate    60 g
+of which sugars    1,1 g
+Fibre    9 g
+Protein    13 g
+Salt    0,01 g";
+        let facts = parse_label(label);
+        assert_eq!(facts, LabelFacts {
+            serving: Some("100g".to_string()),
+            calories: Some(375.0),
+            protein: Some(13.0),
+            fat: Some(8.0),
+            carbs: Some(60.0),
+        });
+
+        // kJ only: converted
+        let facts = parse_label("Energy 1046 kJ\nFat 2g");
+        assert!((facts.calories.unwrap() - 250.0).abs() < 0.1);
+    }
+
+    #[test]
+    fn test_parse_one_line_label() {
+        let facts = parse_label("Serving Size: 1 bar (60g) Calories: 240 Calories from Fat 70 \
+            Total Fat: 8g Sat Fat: 3g Total Carb.: 22g Protein: <1g");
+        assert_eq!(facts.serving.as_deref(), Some("60g"));
+        assert_eq!(facts.calories, Some(240.0));
+        assert_eq!(facts.fat, Some(8.0));
+        assert_eq!(facts.carbs, Some(22.0));
+        assert_eq!(facts.protein, Some(1.0));
+
+        let facts = parse_label("Serving size 1 bar\nCalories 200\nProtein 20g");
+        assert_eq!(facts.serving.as_deref(), Some("1 bar"));
+    }
+
+    #[test]
+    fn test_missing_label_fields() {
+        let facts = parse_label("Protein 20g\nCalories 200");
+        assert_eq!(facts.missing(), vec!["serving", "fat", "carbs"]);
+        let err = facts.to_food("bar", "100g").unwrap_err().to_string();
+        assert!(err.contains("missing fat, carbs"), "{}", err);
+
+        // Calories and serving can be filled in
+        let food = parse_label("protein 20 g, fat 10 g, carbs 30 g").to_food("bar", "100g").unwrap();
+        assert_eq!(food.serving, "100g");
+        assert!((food.calories - 290.0).abs() < 1e-9);
+    }
+}
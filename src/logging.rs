@@ -1,41 +1,664 @@
 use anyhow::{anyhow, Result};
+use chrono::{Datelike, Duration, NaiveDate, NaiveDateTime, NaiveTime, Timelike};
+use serde::Serialize;
 
-use crate::db::{Database, LogEntry};
+use crate::config::{Config, FutureDates, LogMatch, QuantityWords};
+use crate::db::{Database, LogEntry, LogMeta, Meal, QUICK_ADD_FOOD};
+use crate::output::MacroFormat;
+use crate::food::{
+    format_number, is_discrete_unit, is_measure_unit, parse_fraction, parse_number, parse_quantity, serving_grams, Amount, Food,
+    Macros, UnknownUnit, MULTI_WORD_UNITS,
+};
 
-/// Parse input like "ribeye 8oz" or "bare bar" and log it
-pub fn parse_and_log(db: &Database, input: &str) -> Result<LogEntry> {
-    let (food_name, amount) = parse_input(input);
+/// How a log input resolves to a food, amount, and macros, before anything is written
+#[derive(Debug, Serialize)]
+pub struct Resolution {
+    pub input: String,
+    pub parsed_name: String,
+    /// Amount as typed, if any
+    pub parsed_amount: Option<String>,
+    /// Unit of the parsed amount ("count" for a bare leading number)
+    pub parsed_unit: Option<String>,
+    pub food: Food,
+    /// Name matching mode in effect
+    pub log_match: LogMatch,
+    /// Whether the food was found by fuzzy search rather than name or alias
+    pub fuzzy_matched: bool,
+    /// Amount actually used: the parsed amount, the food's default, or its serving
+    pub amount: String,
+    pub amount_source: AmountSource,
+    /// Number of servings the amount works out to
+    pub multiplier: f64,
+    pub macros: Macros,
+    /// Any adjustment made to the amount, e.g. rounding with `round_discrete`,
+    /// or a warning about it, like an unknown unit or a guessed quantity
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+    /// A vague quantity the amount was guessed from, like "a couple"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub quantity_word: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AmountSource {
+    Input,
+    DefaultAmount,
+    /// The food's most recent log entry, with `remember_amount`
+    LastUsed,
+    Serving,
+}
+
+/// Parse input like "ribeye 8oz" and resolve the food, amount, and macros without logging
+pub fn resolve(db: &Database, config: &Config, input: &str) -> Result<Resolution> {
+    let (mut food_name, mut amount) = parse_input(input);
     
-    // Look up the food
-    let food = db.get_food_by_name(&food_name)?
-        .ok_or_else(|| anyhow!("Food not found: '{}'. Add it with: chomp add \"{}\" --protein X --fat Y --carbs Z", food_name, food_name))?;
+    // Look up the food: exact name or alias, then fuzzy if enabled
+    let mut fuzzy_matched = false;
+    let mut food = db.get_food_by_name(&food_name)?;
+    // An amount in a unit the parser doesn't know ("rice 200 grms") is left in
+    // the name; if the rest names a food, take it as the amount after all
+    if food.is_none() && amount.is_none() {
+        if let Some((name, unknown)) = split_trailing_amount(&food_name) {
+            if let Some(found) = db.get_food_by_name(&name)? {
+                food = Some(found);
+                food_name = name;
+                amount = Some(unknown);
+            }
+        }
+    }
+    // "a couple eggs" is only read as a count once the whole input has failed to
+    // name a food, so a food called "some kind of stew" is still found by name.
+    // An explicit amount wins over the word: "some rice 200g" is 200g.
+    let mut quantity_word = None;
+    if food.is_none() && config.quantity_words != QuantityWords::Off {
+        if let Some((phrase, count, name)) = split_quantity_word(&food_name) {
+            if let Some(found) = db.get_food_by_name(&name)? {
+                food = Some(found);
+                food_name = name;
+                if amount.is_none() {
+                    amount = Some(Amount::Count(count));
+                    quantity_word = Some(phrase.to_string());
+                }
+            }
+        }
+    }
+    if food.is_none() && config.log_match == LogMatch::Fuzzy {
+        food = db.search_foods(&food_name, &config.search_options())?.into_iter().next();
+        fuzzy_matched = food.is_some();
+    }
+    let food = match food {
+        Some(food) => food,
+        None => {
+            let suggestions = db.search_foods(&food_name, &config.search_options())?
+                .into_iter()
+                .take(config.suggestion_count)
+                .map(|f| f.name)
+                .collect();
+            return Err(FoodNotFound { name: food_name, suggestions }.into());
+        }
+    };
+    
+    let parsed_amount = amount.as_ref().map(|a| a.to_string());
+    let parsed_unit = amount.as_ref().map(|a| match a {
+        Amount::Measure(text) => parse_quantity(text).map(|(_, unit)| unit).unwrap_or_default(),
+        Amount::Count(_) => "count".to_string(),
+    });
     
-    // Use provided amount, default amount, or serving size
-    let actual_amount = if let Some(amt) = amount {
-        amt
+    // Snap fractional counts of discrete things to whole ones if configured
+    let mut note = None;
+    let amount = match amount {
+        Some(amt) if config.round_discrete => match round_discrete(&amt, &food) {
+            Some((rounded, adjustment)) => {
+                note = Some(adjustment);
+                Some(rounded)
+            }
+            None => Some(amt),
+        },
+        amount => amount,
+    };
+    
+    // Use provided amount, default amount, last-used amount, or serving size
+    let last_amount = match (&amount, &food.default_amount, food.id) {
+        (None, None, Some(id)) if config.remember_amount => db.last_amount(id)?,
+        _ => None,
+    };
+    let (actual_amount, amount_source) = if let Some(amt) = amount {
+        (amt, AmountSource::Input)
     } else if let Some(default) = &food.default_amount {
-        default.clone()
+        (Amount::Measure(default.clone()), AmountSource::DefaultAmount)
+    } else if let Some(last) = last_amount {
+        // Read back the way it would be typed after the name: "salmon 6oz", "rice 200"
+        (Amount::Measure(last), AmountSource::LastUsed)
     } else {
-        food.serving.clone()
+        (Amount::Measure(food.serving.clone()), AmountSource::Serving)
     };
     
-    // Calculate macros
-    let macros = food.calculate(&actual_amount)
-        .ok_or_else(|| anyhow!("Could not calculate macros for {} of {}", actual_amount, food.name))?;
-    
-    // Log it
-    let entry = db.log_food(food.id.unwrap(), &actual_amount, &macros)?;
+    let (amount, macros) = amount_macros(&food, &actual_amount, config)?;
+    let multiplier = food.multiplier(&actual_amount, &config.units, config.unknown_unit)?;
+    let mut add_note = |extra: String| {
+        note = Some(match note.take() {
+            Some(note) => format!("{}; {}", note, extra),
+            None => extra,
+        });
+    };
+    if config.unknown_unit == UnknownUnit::Warn {
+        if let Some(unit) = food.unknown_unit(&actual_amount, &config.units) {
+            add_note(format!("Unknown unit '{}' in '{}', treated as grams", unit, actual_amount));
+        }
+    }
+    if let Some(phrase) = &quantity_word {
+        add_note(format!("Guessed {} from '{}'", amount, phrase));
+    }
     
-    Ok(entry)
+    Ok(Resolution {
+        input: input.to_string(),
+        parsed_name: food_name,
+        parsed_amount,
+        parsed_unit,
+        log_match: config.log_match,
+        fuzzy_matched,
+        amount,
+        amount_source,
+        multiplier,
+        macros,
+        note,
+        quantity_word,
+        food,
+    })
+}
+
+/// Macros for `amount` of `food`, and the amount as it's stored
+fn amount_macros(food: &Food, amount: &Amount, config: &Config) -> Result<(String, Macros)> {
+    let macros = food.calculate(amount, &config.units, config.unknown_unit)
+        .map_err(|e| anyhow!("Could not calculate macros for {} of {}: {}", amount, food.name, e))?;
+    // Counts are stored with their unit, so "2" can't later be taken for 2g
+    let amount = match amount {
+        Amount::Count(count) => food.count_amount(*count),
+        measure => measure.to_string(),
+    };
+    Ok((amount, macros))
+}
+
+/// Edit a log entry for `chomp edit-log`. A new amount is read as logging would
+/// read it, so "3" of a "1 large" food is "3 large", and on its own it works the
+/// macros out again from the food. Macros given alongside are taken as they are.
+#[allow(clippy::too_many_arguments)]
+pub fn edit_log_entry(
+    db: &Database,
+    config: &Config,
+    id: i64,
+    amount: Option<&str>,
+    protein: Option<f64>,
+    fat: Option<f64>,
+    carbs: Option<f64>,
+    calories: Option<f64>,
+    fiber: Option<f64>,
+) -> Result<LogEntry> {
+    let entry = db.get_log_entry(id)?;
+    let macros_given = [protein, fat, carbs, calories, fiber].iter().any(Option::is_some);
+    let (amount, worked_out) = match amount {
+        None => (None, None),
+        // A quick add has no food to work an amount out from, and the edit refuses it
+        Some(amount) if entry.food_name == QUICK_ADD_FOOD => (Some(amount.to_string()), None),
+        Some(amount) => {
+            let food = db.get_food(entry.food_id)?;
+            let amount = match parse_number(amount.trim()) {
+                Some(count) => Amount::Count(count),
+                None => Amount::Measure(amount.trim().to_string()),
+            };
+            if macros_given {
+                let amount = match amount {
+                    Amount::Count(count) => food.count_amount(count),
+                    measure => measure.to_string(),
+                };
+                (Some(amount), None)
+            } else {
+                let (amount, macros) = amount_macros(&food, &amount, config)?;
+                (Some(amount), Some(macros))
+            }
+        }
+    };
+    match worked_out {
+        Some(m) => db.edit_log_entry(id, amount, Some(m.protein), Some(m.fat), Some(m.carbs), Some(m.calories), Some(m.fiber)),
+        None => db.edit_log_entry(id, amount, protein, fat, carbs, calories, fiber),
+    }
+}
+
+/// A food name with no match, and the closest foods by fuzzy search
+#[derive(Debug, Clone, Serialize)]
+pub struct FoodNotFound {
+    pub name: String,
+    pub suggestions: Vec<String>,
+}
+
+impl std::fmt::Display for FoodNotFound {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Food not found: '{}'. ", self.name)?;
+        if !self.suggestions.is_empty() {
+            write!(f, "Did you mean: {}? ", self.suggestions.join(", "))?;
+        }
+        write!(f, "Add it with: chomp add \"{}\" --protein X --fat Y --carbs Z", self.name)
+    }
+}
+
+impl std::error::Error for FoodNotFound {}
+
+/// Macros for an amount of a food, as shown by `chomp nutrition` and the
+/// `calculate_macros` MCP tool
+#[derive(Debug, Clone, Serialize)]
+pub struct Nutrition {
+    pub food: String,
+    pub serving: String,
+    pub amount: String,
+    pub amount_source: AmountSource,
+    pub multiplier: f64,
+    pub macros: Macros,
+}
+
+/// Look up `food` and work out its macros for `amount` (default: as logging
+/// would), without logging anything
+pub fn nutrition(db: &Database, config: &Config, food: &str, amount: Option<&str>) -> Result<Nutrition> {
+    let input = match amount {
+        Some(amount) => format!("{} {}", food, amount),
+        None => food.to_string(),
+    };
+    let r = resolve(db, config, &input)?;
+    Ok(Nutrition {
+        food: r.food.name,
+        serving: r.food.serving,
+        amount: r.amount,
+        amount_source: r.amount_source,
+        multiplier: r.multiplier,
+        macros: r.macros,
+    })
+}
+
+/// How much of a food comes to a calorie target, as shown by `chomp recipe scale --to-calories`
+#[derive(Debug, Clone, Serialize)]
+pub struct Portion {
+    pub food: String,
+    pub serving: String,
+    pub target_calories: f64,
+    /// Servings that hit the target, usually fractional
+    pub servings: f64,
+    /// The same amount in grams, when the serving is a weight
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub grams: Option<f64>,
+    pub macros: Macros,
+}
+
+/// Servings of `food` (and grams, if its serving is a weight) that add up to `calories`
+pub fn portion_for_calories(food: &Food, calories: f64) -> Result<Portion> {
+    anyhow::ensure!(calories.is_finite() && calories > 0.0, "Calorie target must be a positive number");
+    anyhow::ensure!(food.calories > 0.0,
+        "{} has no calories per serving, so no amount of it reaches {} kcal", food.name, format_number(calories));
+    let servings = calories / food.calories;
+    Ok(Portion {
+        food: food.name.clone(),
+        serving: food.serving.clone(),
+        target_calories: calories,
+        servings,
+        grams: serving_grams(&food.serving).map(|grams| grams * servings),
+        macros: food.scale(servings),
+    })
+}
+
+/// Round a fractional number of discrete units ("1.4 bars", a bare count of 1.6
+/// of a "1 bar" food) to the nearest whole, and at least one. Weights, volumes,
+/// and bare counts of a weighed serving ("1.5" of a "100g" food) are left alone.
+/// Returns the rounded amount and a note describing the change, or None if unchanged.
+pub fn round_discrete(amount: &Amount, food: &Food) -> Option<(Amount, String)> {
+    let (value, rounded) = match amount {
+        Amount::Count(count) => {
+            let (_, serving_unit) = parse_quantity(&food.serving)?;
+            if is_measure_unit(&serving_unit) && food.count_unit().is_none() {
+                return None;
+            }
+            (*count, Amount::Count(count.round().max(1.0)))
+        }
+        Amount::Measure(text) => {
+            let (value, unit) = parse_quantity(text)?;
+            if is_measure_unit(&unit) || unit == "%" {
+                return None;
+            }
+            let whole = value.round().max(1.0);
+            (value, Amount::Measure(format!("{} {}", format_number(whole), unit_for_count(&unit, whole))))
+        }
+    };
+    if value.fract() == 0.0 && value >= 1.0 {
+        return None;
+    }
+    Some((rounded.clone(), format!("Rounded {} to {} (round_discrete is on)", amount, rounded)))
+}
+
+/// `unit` made singular or plural to go with `count`: "1 bar", "2 slices"
+fn unit_for_count(unit: &str, count: f64) -> String {
+    match unit.strip_suffix('s') {
+        Some(singular) if count == 1.0 && is_discrete_unit(singular) => singular.to_string(),
+        None if count != 1.0 && is_discrete_unit(&format!("{}s", unit)) => format!("{}s", unit),
+        _ => unit.to_string(),
+    }
+}
+
+/// Parse input like "ribeye 8oz" or "bare bar" and log it now
+pub fn parse_and_log(db: &Database, config: &Config, input: &str) -> Result<Vec<LogConfirmation>> {
+    parse_and_log_at(db, config, input, &LogMeta::now())
+}
+
+/// Parse input and log it with the given date, time, and meal. A combo like
+/// "3 eggs + 2 toast" logs one entry per item, grouped under a shared id; if any
+/// item fails to resolve, nothing is logged.
+pub fn parse_and_log_at(db: &Database, config: &Config, input: &str, meta: &LogMeta) -> Result<Vec<LogConfirmation>> {
+    parse_and_log_confirmed(db, config, input, meta, |_| Ok(true))
+}
+
+/// `parse_and_log_at`, asking `confirm` about each amount guessed from a quantity
+/// word first. If any is declined, nothing is logged.
+pub fn parse_and_log_confirmed(
+    db: &Database,
+    config: &Config,
+    input: &str,
+    meta: &LogMeta,
+    confirm: impl Fn(&Resolution) -> Result<bool>,
+) -> Result<Vec<LogConfirmation>> {
+    let confirmed = |resolution: Resolution| {
+        if resolution.quantity_word.is_some() && !confirm(&resolution)? {
+            anyhow::bail!("Not logged: {}", resolution.input);
+        }
+        Ok(resolution)
+    };
+    let items = split_combo(input);
+    if let [item] = &items[..] {
+        let resolution = confirmed(resolve(db, config, item)?)?;
+        let entry = db.log_food_at(meta, resolution.food.id.unwrap(), &resolution.amount, &resolution.macros)?;
+        return Ok(vec![LogConfirmation::resolved(entry, resolution)]);
+    }
+
+    let resolutions = items.iter()
+        .map(|item| resolve(db, config, item).and_then(confirmed))
+        .collect::<Result<Vec<_>>>()?;
+    let rows: Vec<_> = resolutions.iter()
+        .map(|r| (r.food.id.unwrap(), r.amount.clone(), r.macros.clone()))
+        .collect();
+    let entries = db.log_group(meta, &rows)?;
+    Ok(entries.into_iter().zip(resolutions)
+        .map(|(entry, r)| LogConfirmation::resolved(entry, r))
+        .collect())
+}
+
+/// Split "3 eggs + 2 toast" into its items at each standalone "+", so a "+" inside
+/// a name ("C+ drink") doesn't split it. Input with an empty item is kept whole.
+fn split_combo(input: &str) -> Vec<String> {
+    let words: Vec<&str> = input.split_whitespace().collect();
+    let items: Vec<String> = words.split(|w| *w == "+").map(|item| item.join(" ")).collect();
+    if items.len() > 1 && items.iter().all(|item| !item.is_empty()) {
+        items
+    } else {
+        vec![input.to_string()]
+    }
+}
+
+/// When to log an entry from `--time` and `--meal`, relative to `now`.
+/// Without a time, a meal's usual time is used, and otherwise the current time.
+pub fn log_meta(time: Option<&str>, meal: Option<Meal>, now: NaiveDateTime) -> Result<LogMeta> {
+    let at = match time {
+        Some(time) => parse_time(time, now)?,
+        None => match meal.and_then(Meal::default_time) {
+            Some(time) => now.date().and_time(time),
+            None => now,
+        },
+    };
+    Ok(LogMeta {
+        date: at.date(),
+        time: NaiveTime::from_hms_opt(at.hour(), at.minute(), 0),
+        meal,
+    })
+}
+
+/// Check the date an entry is about to be logged to. Past dates and today are
+/// always fine; a date after `today` is refused, allowed with a warning, or
+/// allowed, per `policy`. Returns the warning, if any.
+pub fn check_log_date(date: NaiveDate, today: NaiveDate, policy: FutureDates) -> Result<Option<String>> {
+    if date <= today {
+        return Ok(None);
+    }
+    match policy {
+        FutureDates::Reject => Err(anyhow!(
+            "{} is in the future; pass --future to log it anyway (or set future_dates = \"warn\")", date)),
+        FutureDates::Warn => Ok(Some(format!("Logged to {}, which is in the future", date))),
+        FutureDates::Allow => Ok(None),
+    }
+}
+
+/// Parse a time of day ("12:30", "7:05") or an offset back from `now`
+/// ("30 min ago", "2h ago", "1 hour ago"). Offsets can cross into yesterday.
+pub fn parse_time(input: &str, now: NaiveDateTime) -> Result<NaiveDateTime> {
+    let input = input.trim().to_lowercase();
+    if let Ok(time) = NaiveTime::parse_from_str(&input, "%H:%M") {
+        return Ok(now.date().and_time(time));
+    }
+    let invalid = || anyhow!("Invalid time: '{}' (expected e.g. 12:30 or \"30 min ago\")", input);
+    let offset = input.strip_suffix("ago").ok_or_else(invalid)?.trim();
+    let split = offset.find(|c: char| !c.is_ascii_digit()).ok_or_else(invalid)?;
+    let (value, unit) = offset.split_at(split);
+    let value: i64 = value.parse().map_err(|_| invalid())?;
+    let minutes = match unit.trim() {
+        "m" | "min" | "mins" | "minute" | "minutes" => value,
+        "h" | "hr" | "hrs" | "hour" | "hours" => value * 60,
+        _ => return Err(invalid()),
+    };
+    Ok(now - Duration::minutes(minutes))
+}
+
+/// Parse a date: ISO ("2024-06-01"), "today", "yesterday", a weekday ("monday",
+/// "mon") meaning its most recent occurrence before `today` (so 1 to 7 days back),
+/// or an offset like "3 days ago" or "2 weeks ago"
+pub fn parse_date(input: &str, today: NaiveDate) -> Result<NaiveDate> {
+    let input = input.trim().to_lowercase();
+    if let Ok(date) = NaiveDate::parse_from_str(&input, "%Y-%m-%d") {
+        return Ok(date);
+    }
+    match input.as_str() {
+        "today" => return Ok(today),
+        "yesterday" => return Ok(today - Duration::days(1)),
+        _ => {}
+    }
+    if let Ok(weekday) = input.parse::<chrono::Weekday>() {
+        let back = (today.weekday().num_days_from_monday() + 7 - weekday.num_days_from_monday()) % 7;
+        return Ok(today - Duration::days(if back == 0 { 7 } else { back as i64 }));
+    }
+
+    let invalid = || anyhow!(
+        "Invalid date: '{}' (expected YYYY-MM-DD, today, yesterday, a weekday, or e.g. \"3 days ago\" or -3d)", input
+    );
+    let (value, unit) = match input.strip_prefix('-') {
+        // Shorthand: "-2d", "-1w"
+        Some(short) => {
+            let split = short.len() - short.chars().last().map_or(0, char::len_utf8);
+            short.split_at(split)
+        }
+        None => input.strip_suffix("ago").ok_or_else(invalid)?.trim().split_once(' ').ok_or_else(invalid)?,
+    };
+    let value = value.parse::<u32>().map_err(|_| invalid())? as i64;
+    let days = match unit.trim() {
+        "d" | "day" | "days" => value,
+        "w" | "week" | "weeks" => value * 7,
+        _ => return Err(invalid()),
+    };
+    Duration::try_days(days)
+        .and_then(|offset| today.checked_sub_signed(offset))
+        .ok_or_else(invalid)
+}
+
+/// A logged entry, optionally with the day's totals including it
+#[derive(Debug, Serialize)]
+pub struct LogConfirmation {
+    #[serde(flatten)]
+    pub entry: LogEntry,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub today: Option<Macros>,
+    /// Any adjustment made to the input amount before logging
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+    /// A likely mistake in the food itself, e.g. no macros recorded
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub warning: Option<String>,
+    /// Servings the amount worked out to, which the macros were scaled by
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub multiplier: Option<f64>,
+    /// The food's serving the multiplier counts
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub serving: Option<String>,
+}
+
+impl From<LogEntry> for LogConfirmation {
+    fn from(entry: LogEntry) -> Self {
+        Self { entry, today: None, note: None, warning: None, multiplier: None, serving: None }
+    }
+}
+
+impl LogConfirmation {
+    /// An entry logged from `resolution`, carrying how its macros were derived
+    fn resolved(entry: LogEntry, resolution: Resolution) -> Self {
+        Self {
+            note: resolution.note,
+            warning: zero_macro_warning(&resolution.food),
+            multiplier: Some(resolution.multiplier),
+            serving: Some(resolution.food.serving),
+            ..Self::from(entry)
+        }
+    }
+}
+
+/// A warning for a food whose macros are all zero, usually a placeholder that
+/// was never filled in. Logging it still works; it just adds nothing.
+fn zero_macro_warning(food: &Food) -> Option<String> {
+    let empty = [food.protein, food.fat, food.carbs, food.calories].iter().all(|v| *v == 0.0);
+    empty.then(|| format!("{} has no macros recorded; did you mean to add them? (chomp edit \"{}\")", food.name, food.name))
+}
+
+/// Totals for the day `entry` was logged on, read back after the insert
+pub fn day_totals(db: &Database, entry: &LogEntry) -> Result<Macros> {
+    let date = NaiveDate::parse_from_str(&entry.date, "%Y-%m-%d")?;
+    db.get_totals_for_date(date, None)
+}
+
+/// Entries logged together from one combo input, with their sum
+#[derive(Debug, Serialize)]
+pub struct ComboConfirmation {
+    pub group_id: Option<i64>,
+    pub entries: Vec<LogConfirmation>,
+    pub total: Macros,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub today: Option<Macros>,
+}
+
+/// JSON for what one log call wrote: a single entry as is, or a combo's entries
+/// with their total. `today` is the day's totals, when wanted.
+pub fn logged_json(mut logged: Vec<LogConfirmation>, today: Option<Macros>) -> Result<serde_json::Value> {
+    if logged.len() == 1 {
+        let one = logged.remove(0);
+        return Ok(serde_json::to_value(LogConfirmation { today, ..one })?);
+    }
+    Ok(serde_json::to_value(ComboConfirmation {
+        group_id: logged.first().and_then(|c| c.entry.group_id),
+        total: combo_total(&logged),
+        entries: logged,
+        today,
+    })?)
+}
+
+/// Summed macros of the entries
+pub fn combo_total(logged: &[LogConfirmation]) -> Macros {
+    logged.iter().fold(Macros::default(), |sum, c| Macros {
+        protein: sum.protein + c.entry.protein,
+        fat: sum.fat + c.entry.fat,
+        carbs: sum.carbs + c.entry.carbs,
+        calories: sum.calories + c.entry.calories,
+        fiber: sum.fiber + c.entry.fiber,
+    })
+}
+
+/// One `history` line, showing the entry's date as `date` (ISO or relative).
+/// With `basis` (the logged food), the per-serving values it was scaled from are appended.
+pub fn history_line(entry: &LogEntry, date: &str, name: &str, basis: Option<&Food>, macros: &MacroFormat) -> String {
+    let mut when = date.to_string();
+    for part in [entry.time.clone(), entry.meal.map(|m| m.to_string())].into_iter().flatten() {
+        when.push(' ');
+        when.push_str(&part);
+    }
+    let net = if entry.fiber > 0.0 { format!(" ({:.0}g net carbs)", entry.macros().net_carbs()) } else { String::new() };
+    let mut line = format!("{} | {} {} | {}{} — {}",
+        when, entry.amount, name,
+        macros.compact(entry.protein, entry.fat, entry.carbs), net,
+        macros.kcal(entry.protein, entry.fat, entry.carbs, entry.calories));
+    if let Some(food) = basis {
+        line.push_str(&format!(" (per {}: {}, {})",
+            food.serving, macros.compact(food.protein, food.fat, food.carbs),
+            macros.kcal(food.protein, food.fat, food.carbs, food.calories)));
+    }
+    line
+}
+
+/// A log entry as `history --json` shows it, with net carbs worked out
+#[derive(Debug, Serialize)]
+pub struct HistoryEntry<'a> {
+    #[serde(flatten)]
+    pub entry: &'a LogEntry,
+    pub net_carbs: f64,
+}
+
+impl<'a> From<&'a LogEntry> for HistoryEntry<'a> {
+    fn from(entry: &'a LogEntry) -> Self {
+        HistoryEntry { entry, net_carbs: entry.macros().net_carbs() }
+    }
+}
+
+/// Friendly label for an ISO date relative to `today`: "today", "yesterday", the
+/// weekday for the rest of the past week ("Mon"), otherwise the ISO date unchanged
+pub fn relative_date(date: &str, today: NaiveDate) -> String {
+    let Ok(parsed) = NaiveDate::parse_from_str(date, "%Y-%m-%d") else {
+        return date.to_string();
+    };
+    match (today - parsed).num_days() {
+        0 => "today".to_string(),
+        1 => "yesterday".to_string(),
+        2..=6 => parsed.format("%a").to_string(),
+        _ => date.to_string(),
+    }
+}
+
+/// Log a fraction of a whole food, e.g. 3/8 of a pizza defined as the whole pie
+pub fn log_fraction(db: &Database, food_name: &str, fraction: &str, meta: &LogMeta) -> Result<LogEntry> {
+    let food = db.get_food_by_name(food_name)?
+        .ok_or_else(|| anyhow!("Food not found: '{}'. Add it with: chomp add \"{}\" --protein X --fat Y --carbs Z", food_name, food_name))?;
+
+    let multiplier = parse_fraction(fraction)
+        .ok_or_else(|| anyhow!("Invalid fraction: '{}' (expected e.g. 3/8 or 1 1/2)", fraction))?;
+
+    let macros = food.scale(multiplier);
+    let amount = format!("{} {}", fraction.trim(), food.name);
+
+    db.log_food_at(meta, food.id.unwrap(), &amount, &macros)
+}
+
+/// Log bare calories with no macros against the quick-add placeholder food
+pub fn log_quick(db: &Database, calories: f64, meta: &LogMeta) -> Result<LogEntry> {
+    anyhow::ensure!(calories.is_finite() && calories > 0.0, "Calories must be a positive number");
+    let food_id = db.quick_add_food_id()?;
+    let macros = Macros { calories, ..Macros::default() };
+    db.log_food_at(meta, food_id, &format!("{} kcal", format_number(calories)), &macros)
 }
 
 /// Parse input into food name and optional amount
 /// Examples:
-///   "ribeye 8oz" -> ("ribeye", Some("8oz"))
+///   "ribeye 8oz" -> ("ribeye", Some(Measure("8oz")))
 ///   "bare bar" -> ("bare bar", None)
-///   "salmon 4 oz" -> ("salmon", Some("4 oz"))
-///   "heavy cream 50ml" -> ("heavy cream", Some("50ml"))
-fn parse_input(input: &str) -> (String, Option<String>) {
+///   "salmon 4 oz" -> ("salmon", Some(Measure("4 oz")))
+///   "heavy cream 50ml" -> ("heavy cream", Some(Measure("50ml")))
+///   "2 eggs" -> ("eggs", Some(Count(2.0)))
+fn parse_input(input: &str) -> (String, Option<Amount>) {
     let input = input.trim();
     
     // Try to find an amount at the end
@@ -51,6 +674,20 @@ fn parse_input(input: &str) -> (String, Option<String>) {
         return (words[0].to_string(), None);
     }
     
+    // Pattern: "milk 4 fluid ounces" (number then a unit of two or three words)
+    for len in [3, 2] {
+        if words.len() < len + 2 {
+            continue;
+        }
+        let unit_start = words.len() - len;
+        let unit = words[unit_start..].join(" ").to_lowercase();
+        if MULTI_WORD_UNITS.contains(&unit.as_str()) && is_number(words[unit_start - 1]) {
+            let amount = words[unit_start - 1..].join(" ");
+            let food_name = words[..unit_start - 1].join(" ");
+            return (food_name, Some(Amount::Measure(amount)));
+        }
+    }
+    
     // Check if last word is a unit or number+unit
     let last = words[words.len() - 1];
     let second_last = if words.len() > 1 { Some(words[words.len() - 2]) } else { None };
@@ -60,29 +697,70 @@ fn parse_input(input: &str) -> (String, Option<String>) {
         if is_number(sl) && is_unit(last) {
             let amount = format!("{} {}", sl, last);
             let food_name = words[..words.len() - 2].join(" ");
-            return (food_name, Some(amount));
+            return (food_name, Some(Amount::Measure(amount)));
         }
     }
     
     // Pattern: "salmon 4oz" (number+unit combined)
     if is_amount(last) {
         let food_name = words[..words.len() - 1].join(" ");
-        return (food_name, Some(last.to_string()));
+        return (food_name, Some(Amount::Measure(last.to_string())));
     }
     
-    // Pattern: "2 eggs" (number at start)
-    if is_number(words[0]) && words.len() >= 2 {
-        let amount = words[0].to_string();
+    // Pattern: "2 eggs" (count at start)
+    if let Some(count) = parse_number(words[0]) {
         let food_name = words[1..].join(" ");
-        return (food_name, Some(amount));
+        return (food_name, Some(Amount::Count(count)));
     }
     
     // No amount found, entire input is food name
     (input.to_string(), None)
 }
 
+/// Vague quantities read as counts with `quantity_words`, longest first so
+/// "a couple of" is taken whole. "some" is a single serving.
+const QUANTITY_WORDS: &[(&str, f64)] = &[
+    ("a couple of", 2.0),
+    ("a couple", 2.0),
+    ("a few", 3.0),
+    ("some", 1.0),
+];
+
+/// Split a leading quantity word off `input`: "a couple eggs" is ("a couple", 2, "eggs")
+fn split_quantity_word(input: &str) -> Option<(&'static str, f64, String)> {
+    let words: Vec<&str> = input.split_whitespace().collect();
+    QUANTITY_WORDS.iter().find_map(|&(phrase, count)| {
+        let len = phrase.split_whitespace().count();
+        let leading = words.len() > len && words[..len].join(" ").eq_ignore_ascii_case(phrase);
+        leading.then(|| (phrase, count, words[len..].join(" ")))
+    })
+}
+
+/// Split a trailing "200 grms" or "200grms" off `input`, whatever the unit
+fn split_trailing_amount(input: &str) -> Option<(String, Amount)> {
+    let words: Vec<&str> = input.split_whitespace().collect();
+    let last = *words.last()?;
+    let is_word = |s: &str| s.chars().all(char::is_alphabetic);
+    // The unit has to be typed: parse_quantity reads a bare "2" as 2g
+    let amount_words = match parse_quantity(last) {
+        Some((_, unit))
+            if last.starts_with(|c: char| c.is_ascii_digit()) && last.chars().any(char::is_alphabetic)
+                && is_word(&unit) =>
+        {
+            1
+        }
+        _ if words.len() >= 2 && is_number(words[words.len() - 2]) && is_word(last) => 2,
+        _ => return None,
+    };
+    if words.len() <= amount_words {
+        return None;
+    }
+    let split = words.len() - amount_words;
+    Some((words[..split].join(" "), Amount::Measure(words[split..].join(" "))))
+}
+
 fn is_number(s: &str) -> bool {
-    s.parse::<f64>().is_ok()
+    parse_number(s).is_some()
 }
 
 fn is_unit(s: &str) -> bool {
@@ -96,11 +774,14 @@ fn is_unit(s: &str) -> bool {
         "cup", "cups",
         "tbsp", "tablespoon", "tablespoons",
         "tsp", "teaspoon", "teaspoons",
+        "floz", "cc", "cm3",
         "bar", "bars",
         "piece", "pieces",
         "serving", "servings",
         "scoop", "scoops",
         "slice", "slices",
+        "each", "ea", "ct", "count",
+        "%",
     ];
     units.contains(&s.to_lowercase().as_str())
 }
@@ -109,10 +790,10 @@ fn is_amount(s: &str) -> bool {
     // Check if it's a number followed by a unit, like "8oz" or "100g"
     let s = s.to_lowercase();
     
-    for unit in ["g", "oz", "ml", "lb", "kg", "l"] {
+    for unit in ["g", "oz", "ml", "lb", "kg", "l", "cc", "%"] {
         if s.ends_with(unit) {
             let num_part = &s[..s.len() - unit.len()];
-            if num_part.parse::<f64>().is_ok() {
+            if parse_number(num_part).is_some() {
                 return true;
             }
         }
@@ -127,10 +808,636 @@ mod tests {
 
     #[test]
     fn test_parse_input() {
-        assert_eq!(parse_input("ribeye 8oz"), ("ribeye".to_string(), Some("8oz".to_string())));
-        assert_eq!(parse_input("salmon 4 oz"), ("salmon".to_string(), Some("4 oz".to_string())));
+        let measure = |s: &str| Some(Amount::Measure(s.to_string()));
+        assert_eq!(parse_input("ribeye 8oz"), ("ribeye".to_string(), measure("8oz")));
+        assert_eq!(parse_input("salmon 4 oz"), ("salmon".to_string(), measure("4 oz")));
         assert_eq!(parse_input("bare bar"), ("bare bar".to_string(), None));
-        assert_eq!(parse_input("heavy cream 50ml"), ("heavy cream".to_string(), Some("50ml".to_string())));
-        assert_eq!(parse_input("2 eggs"), ("eggs".to_string(), Some("2".to_string())));
+        assert_eq!(parse_input("heavy cream 50ml"), ("heavy cream".to_string(), measure("50ml")));
+        assert_eq!(parse_input("2 eggs"), ("eggs".to_string(), Some(Amount::Count(2.0))));
+        assert_eq!(parse_input("milk 1,5 cups"), ("milk".to_string(), measure("1,5 cups")));
+        assert_eq!(parse_input("rice 100,0g"), ("rice".to_string(), measure("100,0g")));
+        assert_eq!(parse_input("1,5 bagels"), ("bagels".to_string(), Some(Amount::Count(1.5))));
+        assert_eq!(parse_input("egg 3 each"), ("egg".to_string(), measure("3 each")));
+        assert_eq!(parse_input("tortilla 2 ea"), ("tortilla".to_string(), measure("2 ea")));
+        assert_eq!(parse_input("salmon 50%"), ("salmon".to_string(), measure("50%")));
+        assert_eq!(parse_input("salmon 150 %"), ("salmon".to_string(), measure("150 %")));
+        assert_eq!(parse_input("salmon abc%"), ("salmon abc%".to_string(), None));
+    }
+
+    #[test]
+    fn test_portion_for_calories() {
+        let chili = Food::new("chili", 20.0, 10.0, 30.0, 290.0, "1 bowl", vec![]);
+        let portion = portion_for_calories(&chili, 435.0).unwrap();
+        assert!((portion.servings - 1.5).abs() < 1e-9);
+        assert!(portion.grams.is_none());
+        assert!((portion.macros.protein - 30.0).abs() < 1e-9);
+        assert!((portion.macros.calories - 435.0).abs() < 1e-9);
+
+        // A weighed serving gives grams too
+        let rice = Food::new("rice", 2.7, 0.3, 28.0, 130.0, "100g", vec![]);
+        let portion = portion_for_calories(&rice, 600.0).unwrap();
+        assert!((portion.grams.unwrap() - 600.0 / 1.3).abs() < 1e-9);
+        let oats = Food::new("oats", 5.0, 3.0, 27.0, 150.0, "1.5oz", vec![]);
+        assert!((portion_for_calories(&oats, 300.0).unwrap().grams.unwrap() - 85.048).abs() < 1e-3);
+
+        let water = Food::new("water", 0.0, 0.0, 0.0, 0.0, "250ml", vec![]);
+        assert!(portion_for_calories(&water, 600.0).unwrap_err().to_string().contains("no calories"));
+        assert!(portion_for_calories(&chili, 0.0).is_err());
+        assert!(portion_for_calories(&chili, f64::NAN).is_err());
+    }
+
+    #[test]
+    fn test_quantity_words() {
+        assert_eq!(split_quantity_word("a couple of eggs"), Some(("a couple of", 2.0, "eggs".to_string())));
+        assert_eq!(split_quantity_word("A Few  crackers"), Some(("a few", 3.0, "crackers".to_string())));
+        assert_eq!(split_quantity_word("some"), None);
+        assert_eq!(split_quantity_word("somen noodles"), None);
+
+        let db = Database::open_in_memory().unwrap();
+        db.add_food(&Food::new("egg", 6.0, 5.0, 0.5, 72.0, "1 large", vec![])).unwrap();
+        db.add_food(&Food::new("rice", 2.7, 0.3, 28.0, 130.0, "100g", vec![])).unwrap();
+        db.add_food(&Food::new("some kind of stew", 10.0, 5.0, 10.0, 125.0, "1 bowl", vec![])).unwrap();
+
+        // Off by default: the words are part of the name
+        assert!(parse_and_log(&db, &Config::default(), "a couple egg").is_err());
+
+        let config = Config { quantity_words: QuantityWords::On, ..Config::default() };
+        let eggs = parse_and_log(&db, &config, "a couple egg").unwrap().remove(0);
+        assert_eq!(eggs.entry.amount, "2 large");
+        assert!((eggs.entry.calories - 144.0).abs() < 1e-9);
+        assert_eq!(eggs.note.as_deref(), Some("Guessed 2 large from 'a couple'"));
+        let rice = parse_and_log(&db, &config, "some rice").unwrap().remove(0);
+        assert_eq!(rice.entry.amount, "1 serving");
+        assert!(rice.note.is_some());
+
+        // A food whose name starts with one is still found by its name
+        let stew = parse_and_log(&db, &config, "some kind of stew").unwrap().remove(0);
+        assert_eq!(stew.entry.amount, "1 bowl");
+        assert!(stew.note.is_none());
+        // An explicit amount wins, and nothing is guessed
+        let rice = parse_and_log(&db, &config, "some rice 200g").unwrap().remove(0);
+        assert_eq!(rice.entry.amount, "200g");
+        assert!((rice.entry.carbs - 56.0).abs() < 1e-9);
+        assert!(rice.note.is_none());
+
+        // Declining a guess logs nothing, even alongside other items
+        let meta = LogMeta { date: NaiveDate::from_ymd_opt(2024, 6, 1).unwrap(), time: None, meal: None };
+        let err = parse_and_log_confirmed(&db, &config, "rice 50g + a few egg", &meta, |r| {
+            assert_eq!(r.quantity_word.as_deref(), Some("a few"));
+            Ok(false)
+        });
+        assert_eq!(err.unwrap_err().to_string(), "Not logged: a few egg");
+        assert!(db.get_log_for_date(meta.date).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_log_percent_of_serving() {
+        let db = Database::open_in_memory().unwrap();
+        db.add_food(&Food::new("salmon", 20.0, 13.0, 0.0, 208.0, "100g", vec![])).unwrap();
+        db.add_food(&Food::new("protein bar", 20.0, 10.0, 20.0, 250.0, "1 bar", vec![])).unwrap();
+        let config = Config { round_discrete: true, ..Config::default() };
+
+        let half = parse_and_log(&db, &config, "salmon 50%").unwrap().remove(0).entry;
+        assert_eq!(half.amount, "50%");
+        assert!((half.protein - 10.0).abs() < 1e-9);
+        let more = parse_and_log(&db, &config, "protein bar 150%").unwrap().remove(0);
+        assert_eq!(more.entry.amount, "150%");
+        assert!((more.entry.calories - 375.0).abs() < 1e-9);
+        assert!(more.note.is_none());
+
+        assert!(parse_and_log(&db, &config, "salmon abc%").is_err());
+        assert!(parse_and_log(&db, &config, "salmon 0%").is_err());
+        assert!(parse_and_log(&db, &config, "salmon -5%").is_err());
+    }
+
+    #[test]
+    fn test_log_zero_macro_food_warns() {
+        let db = Database::open_in_memory().unwrap();
+        db.add_food(&Food::new("salmon", 0.0, 0.0, 0.0, 0.0, "100g", vec![])).unwrap();
+        db.add_food(&Food::new("water", 0.0, 0.0, 0.0, 0.0, "1 cup", vec![])).unwrap();
+        db.add_food(&Food::new("diet soda", 0.0, 0.0, 0.0, 1.0, "1 can", vec![])).unwrap();
+
+        // Logged anyway, with the warning alongside
+        let logged = parse_and_log(&db, &Config::default(), "salmon 200g").unwrap().remove(0);
+        assert_eq!(logged.entry.amount, "200g");
+        assert_eq!(logged.warning.as_deref(),
+            Some("salmon has no macros recorded; did you mean to add them? (chomp edit \"salmon\")"));
+        let json = serde_json::to_value(&logged).unwrap();
+        assert!(json["warning"].as_str().unwrap().starts_with("salmon has no macros"));
+
+        let combo = parse_and_log(&db, &Config::default(), "water + diet soda").unwrap();
+        assert!(combo[0].warning.is_some());
+        assert!(combo[1].warning.is_none());
+        assert!(serde_json::to_value(&combo[1]).unwrap().get("warning").is_none());
+    }
+
+    #[test]
+    fn test_unknown_unit_modes() {
+        let db = Database::open_in_memory().unwrap();
+        db.add_food(&Food::new("rice", 2.7, 0.3, 28.0, 130.0, "100g", vec![])).unwrap();
+        db.add_food(&Food::new("toast", 3.0, 1.0, 13.0, 75.0, "1 slice", vec![])).unwrap();
+        let config = |unknown_unit| Config { unknown_unit, ..Config::default() };
+
+        // error: refused, naming the unit
+        let err = resolve(&db, &config(UnknownUnit::Error), "rice 200gg").unwrap_err();
+        assert!(err.to_string().contains("unknown unit 'gg' in '200gg'"), "{}", err);
+        assert!(parse_and_log(&db, &config(UnknownUnit::Error), "rice 200 grms").is_err());
+
+        // grams: today's behavior, silently
+        let r = resolve(&db, &config(UnknownUnit::Grams), "rice 200 grms").unwrap();
+        assert!((r.multiplier - 2.0).abs() < 1e-9);
+        assert!(r.note.is_none());
+
+        // warn (the default): same amount, with a note
+        let r = resolve(&db, &Config::default(), "rice 200 grms").unwrap();
+        assert!((r.multiplier - 2.0).abs() < 1e-9);
+        assert_eq!(r.note.as_deref(), Some("Unknown unit 'grms' in '200 grms', treated as grams"));
+
+        // Known units and the serving's own unit are fine in every mode
+        for input in ["rice 200g", "rice 1 cup", "toast 2 slices", "toast 1 slice", "rice 2 servings"] {
+            let r = resolve(&db, &config(UnknownUnit::Error), input);
+            assert!(r.is_ok() || input == "rice 1 cup", "{}", input);
+        }
+        assert!(resolve(&db, &Config::default(), "toast 2 slices").unwrap().note.is_none());
+
+        // A bare trailing number isn't an amount in grams: "toast 2" isn't a food,
+        // and "2 toast" is two slices
+        let err = resolve(&db, &Config::default(), "toast 2").unwrap_err();
+        assert!(err.to_string().starts_with("Food not found: 'toast 2'"), "{}", err);
+        assert_eq!(resolve(&db, &Config::default(), "2 toast").unwrap().amount, "2 slice");
+    }
+
+    #[test]
+    fn test_multi_word_units() {
+        let measure = |s: &str| Some(Amount::Measure(s.to_string()));
+        assert_eq!(parse_input("whole milk 4 fluid ounces"), ("whole milk".to_string(), measure("4 fluid ounces")));
+        assert_eq!(parse_input("milk 8 US Fluid Ounces"), ("milk".to_string(), measure("8 US Fluid Ounces")));
+        assert_eq!(parse_input("cough syrup 30 cubic centimeters"), ("cough syrup".to_string(), measure("30 cubic centimeters")));
+        assert_eq!(parse_input("milk 4 fl oz"), ("milk".to_string(), measure("4 fl oz")));
+        assert_eq!(parse_input("cough syrup 30 cc"), ("cough syrup".to_string(), measure("30 cc")));
+        assert_eq!(parse_input("cough syrup 30cc"), ("cough syrup".to_string(), measure("30cc")));
+        // A unit phrase needs a number in front; otherwise it's part of the name
+        assert_eq!(parse_input("fluid ounces"), ("fluid ounces".to_string(), None));
+
+        let db = Database::open_in_memory().unwrap();
+        db.add_food(&Food::new("milk", 3.4, 3.3, 4.8, 61.0, "100ml", vec![])).unwrap();
+        let r = resolve(&db, &Config::default(), "milk 4 fluid ounces").unwrap();
+        assert!((r.multiplier - 1.18294).abs() < 1e-9);
+        let r = resolve(&db, &Config::default(), "milk 30 cc").unwrap();
+        assert!((r.multiplier - 0.3).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_totals_include_logged_entry() {
+        let db = Database::open_in_memory().unwrap();
+        db.add_food(&Food::new("egg", 6.0, 5.0, 0.6, 72.0, "1 large", vec![])).unwrap();
+        parse_and_log(&db, &Config::default(), "egg").unwrap();
+
+        let logged = parse_and_log(&db, &Config::default(), "2 egg").unwrap();
+        let today = day_totals(&db, &logged[0].entry).unwrap();
+        assert!((today.protein - 18.0).abs() < 1e-9);
+        assert!((today.calories - 216.0).abs() < 1e-9);
+
+        let value = logged_json(logged, Some(today)).unwrap();
+        assert_eq!(value["food_name"], "egg");
+        assert_eq!(value["today"]["calories"], 216.0);
+        let value = logged_json(parse_and_log(&db, &Config::default(), "egg").unwrap(), None).unwrap();
+        assert!(value.get("today").is_none());
+    }
+
+    #[test]
+    fn test_history_line() {
+        use crate::config::Emphasize;
+        let db = Database::open_in_memory().unwrap();
+        db.add_food(&Food::new("egg", 6.0, 5.0, 0.6, 72.0, "1 large", vec![])).unwrap();
+        let day = NaiveDate::from_ymd_opt(2024, 6, 5).unwrap();
+        let entry = parse_and_log_at(&db, &Config::default(), "2 egg", &LogMeta::on(day)).unwrap().remove(0).entry;
+        let egg = db.get_food_by_name("egg").unwrap().unwrap();
+        let plain = MacroFormat { emphasize: Emphasize::None, bold: true, reconcile: false, decimals: 0 };
+
+        let line = history_line(&entry, &entry.date, "egg", None, &plain);
+        assert_eq!(line, "2024-06-05 | 2 large egg | 12p/10f/1c — 144 kcal");
+        let line = history_line(&entry, "today", "egg", None, &plain);
+        assert!(line.starts_with("today | 2 large egg"));
+        let verbose = history_line(&entry, &entry.date, "egg", Some(&egg), &plain);
+        assert!(verbose.ends_with("— 144 kcal (per 1 large: 6p/5f/1c, 72 kcal)"));
+
+        let meta = LogMeta { date: day, time: NaiveTime::from_hms_opt(8, 15, 0), meal: Some(Meal::Breakfast) };
+        let entry = parse_and_log_at(&db, &Config::default(), "2 egg", &meta).unwrap().remove(0).entry;
+        let line = history_line(&entry, "today", "egg", None, &plain);
+        assert!(line.starts_with("today 08:15 breakfast | 2 large egg"));
+
+        // Emphasized protein is bold only where color is on
+        let bold = MacroFormat { emphasize: Emphasize::Protein, bold: true, reconcile: false, decimals: 0 };
+        assert_eq!(history_line(&entry, "today", "egg", Some(&egg), &bold),
+            "today 08:15 breakfast | 2 large egg | \x1b[1m12p\x1b[0m/10f/1c — 144 kcal \
+             (per 1 large: \x1b[1m6p\x1b[0m/5f/1c, 72 kcal)");
+        let no_color = MacroFormat { bold: false, ..bold };
+        assert!(history_line(&entry, "today", "egg", None, &no_color).ends_with("| 12p/10f/1c — 144 kcal"));
+    }
+
+    #[test]
+    fn test_parse_time() {
+        let now = NaiveDate::from_ymd_opt(2024, 6, 5).unwrap().and_hms_opt(13, 10, 0).unwrap();
+        let at = |h, m| NaiveDate::from_ymd_opt(2024, 6, 5).unwrap().and_hms_opt(h, m, 0).unwrap();
+        assert_eq!(parse_time("12:30", now).unwrap(), at(12, 30));
+        assert_eq!(parse_time("7:05", now).unwrap(), at(7, 5));
+        assert_eq!(parse_time("30 min ago", now).unwrap(), at(12, 40));
+        assert_eq!(parse_time("2h ago", now).unwrap(), at(11, 10));
+        assert_eq!(parse_time("1 hour ago", now).unwrap(), at(12, 10));
+        // Offsets can reach back into yesterday
+        let yesterday = NaiveDate::from_ymd_opt(2024, 6, 4).unwrap().and_hms_opt(23, 10, 0).unwrap();
+        assert_eq!(parse_time("14 hours ago", now).unwrap(), yesterday);
+        assert!(parse_time("lunchtime", now).is_err());
+        assert!(parse_time("25:00", now).is_err());
+        assert!(parse_time("30 days ago", now).is_err());
+    }
+
+    #[test]
+    fn test_parse_date() {
+        // A Wednesday
+        let today = NaiveDate::from_ymd_opt(2024, 6, 5).unwrap();
+        let day = |d| NaiveDate::from_ymd_opt(2024, 6, d).unwrap();
+        assert_eq!(parse_date("2024-06-01", today).unwrap(), day(1));
+        assert_eq!(parse_date("2025-01-01", today).unwrap(), NaiveDate::from_ymd_opt(2025, 1, 1).unwrap());
+        assert_eq!(parse_date("today", today).unwrap(), today);
+        assert_eq!(parse_date(" Yesterday ", today).unwrap(), day(4));
+        assert_eq!(parse_date("3 days ago", today).unwrap(), day(2));
+        assert_eq!(parse_date("1 day ago", today).unwrap(), day(4));
+        assert_eq!(parse_date("0 days ago", today).unwrap(), today);
+        assert_eq!(parse_date("1 week ago", today).unwrap(), NaiveDate::from_ymd_opt(2024, 5, 29).unwrap());
+        assert_eq!(parse_date("-2d", today).unwrap(), day(3));
+        assert_eq!(parse_date("-1W", today).unwrap(), NaiveDate::from_ymd_opt(2024, 5, 29).unwrap());
+
+        // Weekdays are the most recent one before today
+        assert_eq!(parse_date("tuesday", today).unwrap(), day(4));
+        assert_eq!(parse_date("Monday", today).unwrap(), day(3));
+        assert_eq!(parse_date("thu", today).unwrap(), NaiveDate::from_ymd_opt(2024, 5, 30).unwrap());
+        assert_eq!(parse_date("sunday", today).unwrap(), day(2));
+        // Today's weekday means a week ago
+        assert_eq!(parse_date("wednesday", today).unwrap(), NaiveDate::from_ymd_opt(2024, 5, 29).unwrap());
+
+        for bad in ["June", "2024-13-01", "3 days", "days ago", "three days ago", "3 months ago", "-1 days ago",
+                    "99999999999 days ago", "", "-2", "-d", "-2 d", "-2 days ago", "--2d", "-2é", "2d"] {
+            assert!(parse_date(bad, today).is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn test_log_meta() {
+        let now = NaiveDate::from_ymd_opt(2024, 6, 5).unwrap().and_hms_opt(13, 10, 42).unwrap();
+        let time = |h, m| NaiveTime::from_hms_opt(h, m, 0);
+        // Defaults to now, to the minute
+        assert_eq!(log_meta(None, None, now).unwrap().time, time(13, 10));
+        // A meal sets its usual time; an explicit time wins
+        let dinner = log_meta(None, Some(Meal::Dinner), now).unwrap();
+        assert_eq!((dinner.time, dinner.meal), (time(18, 30), Some(Meal::Dinner)));
+        assert_eq!(log_meta(Some("19:45"), Some(Meal::Dinner), now).unwrap().time, time(19, 45));
+        assert_eq!(log_meta(None, Some(Meal::Snack), now).unwrap().time, time(13, 10));
+    }
+
+    #[test]
+    fn test_check_log_date() {
+        let today = NaiveDate::from_ymd_opt(2024, 6, 5).unwrap();
+        let yesterday = today.pred_opt().unwrap();
+        let tomorrow = today.succ_opt().unwrap();
+        for policy in [FutureDates::Reject, FutureDates::Warn, FutureDates::Allow] {
+            assert_eq!(check_log_date(yesterday, today, policy).unwrap(), None);
+            assert_eq!(check_log_date(today, today, policy).unwrap(), None);
+        }
+
+        let err = check_log_date(tomorrow, today, FutureDates::Reject).unwrap_err().to_string();
+        assert!(err.starts_with("2024-06-06 is in the future; pass --future"), "{}", err);
+        assert_eq!(check_log_date(tomorrow, today, FutureDates::Warn).unwrap().as_deref(),
+            Some("Logged to 2024-06-06, which is in the future"));
+        assert_eq!(check_log_date(tomorrow, today, FutureDates::Allow).unwrap(), None);
+    }
+
+    #[test]
+    fn test_relative_date() {
+        // A Wednesday
+        let today = NaiveDate::from_ymd_opt(2024, 6, 5).unwrap();
+        assert_eq!(relative_date("2024-06-05", today), "today");
+        assert_eq!(relative_date("2024-06-04", today), "yesterday");
+        assert_eq!(relative_date("2024-06-03", today), "Mon");
+        assert_eq!(relative_date("2024-05-30", today), "Thu");
+        assert_eq!(relative_date("2024-05-29", today), "2024-05-29");
+        // Future dates and anything unparseable are left alone
+        assert_eq!(relative_date("2024-06-06", today), "2024-06-06");
+        assert_eq!(relative_date("someday", today), "someday");
+    }
+
+    #[test]
+    fn test_resolve_does_not_log() {
+        let db = Database::open_in_memory().unwrap();
+        db.add_food(&Food::new("ribeye", 24.0, 20.0, 0.0, 291.0, "100g", vec![])).unwrap();
+
+        let resolution = resolve(&db, &Config::default(), "ribeye 8oz").unwrap();
+        assert_eq!(resolution.parsed_name, "ribeye");
+        assert_eq!(resolution.parsed_amount.as_deref(), Some("8oz"));
+        assert_eq!(resolution.parsed_unit.as_deref(), Some("oz"));
+        assert_eq!(resolution.amount_source, AmountSource::Input);
+        assert!((resolution.multiplier - 2.26796).abs() < 1e-4);
+        assert!((resolution.macros.protein - 24.0 * resolution.multiplier).abs() < 1e-9);
+
+        let resolution = resolve(&db, &Config::default(), "ribeye").unwrap();
+        assert_eq!(resolution.amount, "100g");
+        assert_eq!(resolution.amount_source, AmountSource::Serving);
+
+        assert_eq!(db.get_history(1, None, None).unwrap().len(), 0);
+    }
+
+    #[test]
+    fn test_remember_amount() {
+        let db = Database::open_in_memory().unwrap();
+        db.add_food(&Food::new("salmon", 25.0, 13.0, 0.0, 208.0, "100g", vec![])).unwrap();
+        let config = Config { remember_amount: true, ..Config::default() };
+
+        // Never logged: one serving
+        assert_eq!(resolve(&db, &config, "salmon").unwrap().amount_source, AmountSource::Serving);
+
+        parse_and_log(&db, &config, "salmon 6oz").unwrap();
+        let r = resolve(&db, &config, "salmon").unwrap();
+        assert_eq!(r.amount, "6oz");
+        assert_eq!(r.amount_source, AmountSource::LastUsed);
+        assert!((r.multiplier - 1.70097).abs() < 1e-4);
+
+        // Off by default
+        assert_eq!(resolve(&db, &Config::default(), "salmon").unwrap().amount, "100g");
+    }
+
+    #[test]
+    fn test_log_match_modes() {
+        let db = Database::open_in_memory().unwrap();
+        db.add_food(&Food::new("chicken breast", 31.0, 3.6, 0.0, 165.0, "100g", vec![])).unwrap();
+
+        let exact = Config::default();
+        assert!(resolve(&db, &exact, "chiken breast 200g").is_err());
+        assert!(!resolve(&db, &exact, "chicken breast").unwrap().fuzzy_matched);
+
+        let fuzzy = Config { log_match: LogMatch::Fuzzy, ..Config::default() };
+        let resolution = resolve(&db, &fuzzy, "chiken breast 200g").unwrap();
+        assert_eq!(resolution.food.name, "chicken breast");
+        assert!(resolution.fuzzy_matched);
+        assert!((resolution.macros.protein - 62.0).abs() < 1e-9);
+
+        assert!(resolve(&db, &fuzzy, "salmon").is_err());
+    }
+
+    #[test]
+    fn test_round_discrete() {
+        let db = Database::open_in_memory().unwrap();
+        db.add_food(&Food::new("protein bar", 20.0, 10.0, 20.0, 250.0, "1 bar", vec![])).unwrap();
+        db.add_food(&Food::new("ribeye", 24.0, 20.0, 0.0, 291.0, "100g", vec![])).unwrap();
+        let config = Config { round_discrete: true, ..Config::default() };
+
+        let logged = parse_and_log(&db, &config, "protein bar 1.4 bars").unwrap().remove(0);
+        assert_eq!(logged.entry.amount, "1 bar");
+        assert!((logged.entry.protein - 20.0).abs() < 1e-9);
+        assert_eq!(logged.note.as_deref(), Some("Rounded 1.4 bars to 1 bar (round_discrete is on)"));
+        let logged = parse_and_log(&db, &config, "protein bar 1.6 bar").unwrap().remove(0);
+        assert_eq!(logged.entry.amount, "2 bars");
+
+        let logged = parse_and_log(&db, &config, "1.6 protein bar").unwrap().remove(0);
+        assert_eq!(logged.entry.amount, "2 bar");
+        assert!((logged.entry.protein - 40.0).abs() < 1e-9);
+        assert_eq!(logged.note.as_deref(), Some("Rounded 1.6 to 2 (round_discrete is on)"));
+
+        // Whole counts and weights are untouched, as is everything with the setting off
+        assert!(parse_and_log(&db, &config, "protein bar 2 bars").unwrap()[0].note.is_none());
+        assert_eq!(parse_and_log(&db, &config, "ribeye 150.5g").unwrap()[0].entry.amount, "150.5g");
+        // A bare count of a weighed serving is servings, which can be fractional
+        let logged = parse_and_log(&db, &config, "1.5 ribeye").unwrap().remove(0);
+        assert_eq!((logged.entry.amount.as_str(), logged.note), ("1.5 servings", None));
+        let logged = parse_and_log(&db, &Config::default(), "1.5 protein bar").unwrap().remove(0);
+        assert_eq!((logged.entry.amount.as_str(), logged.note), ("1.5 bar", None));
+        // Never rounds down to nothing
+        let bar = Food::new("protein bar", 20.0, 10.0, 20.0, 250.0, "1 bar", vec![]);
+        assert_eq!(round_discrete(&Amount::Count(0.3), &bar).unwrap().0, Amount::Count(1.0));
+    }
+
+    #[test]
+    fn test_log_json_shows_basis() {
+        let db = Database::open_in_memory().unwrap();
+        db.add_food(&Food::new("salmon", 20.0, 13.0, 0.0, 208.0, "100g", vec![])).unwrap();
+
+        let logged = parse_and_log(&db, &Config::default(), "salmon 200g").unwrap();
+        let value = logged_json(logged, None).unwrap();
+        assert!((value["multiplier"].as_f64().unwrap() - 2.0).abs() < 1e-9);
+        assert_eq!(value["serving"], "100g");
+        assert_eq!(value["amount"], "200g");
+        assert!((value["protein"].as_f64().unwrap() - 40.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_log_combo() {
+        let db = Database::open_in_memory().unwrap();
+        db.add_food(&Food::new("eggs", 6.0, 5.0, 0.5, 72.0, "1 egg", vec![])).unwrap();
+        db.add_food(&Food::new("toast", 3.0, 1.0, 13.0, 75.0, "1 slice", vec![])).unwrap();
+
+        let logged = parse_and_log(&db, &Config::default(), "3 eggs + 2 toast").unwrap();
+        assert_eq!(logged.len(), 2);
+        let group = logged[0].entry.id;
+        assert!(group.is_some());
+        assert!(logged.iter().all(|c| c.entry.group_id == group));
+        assert_eq!(logged[1].entry.food_name, "toast");
+
+        // Separate rows, each still editable on its own, sharing the group id
+        let rows = db.get_log_for_date(chrono::Local::now().date_naive()).unwrap();
+        assert_eq!(rows.len(), 2);
+        assert!(rows.iter().all(|e| e.group_id == group));
+
+        let value = logged_json(logged, None).unwrap();
+        assert_eq!(value["entries"].as_array().unwrap().len(), 2);
+        assert!((value["total"]["protein"].as_f64().unwrap() - 24.0).abs() < 1e-9);
+        assert!((value["total"]["calories"].as_f64().unwrap() - 366.0).abs() < 1e-9);
+
+        // One bad item logs nothing
+        assert!(parse_and_log(&db, &Config::default(), "2 eggs + bagel").is_err());
+        assert_eq!(db.get_stats().unwrap().log_count, 2);
+        // A lone "+" in a name isn't a combo
+        assert_eq!(split_combo("C+ drink"), vec!["C+ drink"]);
+        assert_eq!(split_combo("3 eggs  +  2 toast + coffee"), vec!["3 eggs", "2 toast", "coffee"]);
+        assert_eq!(split_combo("eggs +"), vec!["eggs +"]);
+    }
+
+    #[test]
+    fn test_log_quick() {
+        let db = Database::open_in_memory().unwrap();
+        db.add_food(&Food::new("egg", 6.0, 5.0, 0.6, 72.0, "1 large", vec![])).unwrap();
+        parse_and_log(&db, &Config::default(), "2 egg").unwrap();
+
+        let entry = log_quick(&db, 500.0, &LogMeta::now()).unwrap();
+        assert_eq!(entry.food_name, QUICK_ADD_FOOD);
+        assert_eq!(entry.amount, "500 kcal");
+        log_quick(&db, 120.5, &LogMeta::now()).unwrap();
+
+        // Calories count toward the day; macros are only the egg's
+        let today = db.get_today_totals().unwrap();
+        assert!((today.calories - 764.5).abs() < 1e-9);
+        assert!((today.protein - 12.0).abs() < 1e-9);
+        assert!((today.fat - 10.0).abs() < 1e-9);
+        assert!((today.carbs - 1.2).abs() < 1e-9);
+
+        // One placeholder food, reused
+        assert_eq!(db.all_foods().unwrap().len(), 2);
+        assert!(log_quick(&db, 0.0, &LogMeta::now()).is_err());
+        assert!(log_quick(&db, f64::NAN, &LogMeta::now()).is_err());
+
+        // The placeholder isn't a food to search for, or to change the amount of
+        assert!(db.search_foods("quick", &Default::default()).unwrap().is_empty());
+        let id = entry.id.unwrap();
+        let err = edit_log_entry(&db, &Config::default(), id, Some("2"), None, None, None, None, None).unwrap_err();
+        assert!(err.to_string().contains("is a quick add"), "{}", err);
+        let edited = edit_log_entry(&db, &Config::default(), id, None, None, None, None, Some(450.0), None).unwrap();
+        assert_eq!((edited.amount.as_str(), edited.calories), ("450 kcal", 450.0));
+    }
+
+    #[test]
+    fn test_not_found_suggestions() {
+        let db = Database::open_in_memory().unwrap();
+        for name in ["salmon", "salami", "salsa", "oats"] {
+            db.add_food(&Food::new(name, 10.0, 10.0, 1.0, 134.0, "100g", vec![])).unwrap();
+        }
+
+        let err = parse_and_log(&db, &Config::default(), "salmn 200g").unwrap_err();
+        let not_found = err.downcast_ref::<FoodNotFound>().unwrap();
+        assert_eq!(not_found.name, "salmn");
+        assert_eq!(not_found.suggestions[0], "salmon");
+        assert!(!not_found.suggestions.contains(&"oats".to_string()));
+        assert!(err.to_string().starts_with("Food not found: 'salmn'. Did you mean: salmon"));
+
+        let config = Config { suggestion_count: 1, ..Config::default() };
+        let err = parse_and_log(&db, &config, "salmn").unwrap_err();
+        assert_eq!(err.downcast_ref::<FoodNotFound>().unwrap().suggestions, vec!["salmon"]);
+
+        // Nothing close: no suggestions, just the hint to add it
+        let config = Config { suggestion_count: 0, ..Config::default() };
+        let err = parse_and_log(&db, &config, "salmn").unwrap_err();
+        assert_eq!(err.to_string(), "Food not found: 'salmn'. Add it with: chomp add \"salmn\" --protein X --fat Y --carbs Z");
+        assert_eq!(db.get_stats().unwrap().log_count, 0);
+    }
+
+    #[test]
+    fn test_nutrition_lookup() {
+        let db = Database::open_in_memory().unwrap();
+        db.add_food(&Food::new("salmon", 20.0, 13.0, 0.0, 208.0, "100g", vec![])).unwrap();
+        let config = Config::default();
+
+        let n = nutrition(&db, &config, "salmon", Some("6oz")).unwrap();
+        assert_eq!(n.amount, "6oz");
+        assert!((n.multiplier - 1.70097).abs() < 1e-4);
+        assert!((n.macros.protein - 34.019).abs() < 1e-3);
+        assert_eq!(n.amount_source, AmountSource::Input);
+        // The amount can also come in with the name, and defaults to a serving
+        assert_eq!(nutrition(&db, &config, "salmon 6oz", None).unwrap().amount, "6oz");
+        assert_eq!(nutrition(&db, &config, "salmon", None).unwrap().amount_source, AmountSource::Serving);
+        assert_eq!(db.get_stats().unwrap().log_count, 0);
+
+        let err = nutrition(&db, &config, "tuna", Some("6oz")).unwrap_err().to_string();
+        assert!(err.contains("Food not found: 'tuna'"));
+        let err = nutrition(&db, &config, "salmon", Some("1 cup")).unwrap_err().to_string();
+        assert!(err.contains("Could not calculate macros for 1 cup of salmon"));
+    }
+
+    #[test]
+    fn test_configured_unit_in_log() {
+        let db = Database::open_in_memory().unwrap();
+        db.add_food(&Food::new("whey", 80.0, 6.0, 8.0, 400.0, "100g", vec![])).unwrap();
+        let config: Config = toml::from_str("[units]\nscoop = 32").unwrap();
+        let entry = parse_and_log(&db, &config, "whey 1 scoop").unwrap().remove(0).entry;
+        assert!((entry.protein - 25.6).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_serving_unit_mode_in_log() {
+        let db = Database::open_in_memory().unwrap();
+        let mut preworkout = Food::new("preworkout", 0.0, 0.0, 6.0, 25.0, "1 scoop", vec![]);
+        preworkout.unit_mode = crate::food::UnitMode::Serving;
+        db.add_food(&preworkout).unwrap();
+        assert_eq!(db.get_food_by_name("preworkout").unwrap().unwrap().unit_mode, crate::food::UnitMode::Serving);
+
+        let entry = parse_and_log(&db, &Config::default(), "preworkout 2 scoops").unwrap().remove(0).entry;
+        assert!((entry.carbs - 12.0).abs() < 1e-9);
+        assert!((entry.calories - 50.0).abs() < 1e-9);
+        assert!(parse_and_log(&db, &Config::default(), "preworkout 10g").is_err());
+    }
+
+    #[test]
+    fn test_log_leading_count() {
+        let db = Database::open_in_memory().unwrap();
+        db.add_food(&Food::new("eggs", 6.0, 5.0, 0.5, 72.0, "1 egg", vec![])).unwrap();
+
+        db.add_food(&Food::new("ribeye", 24.0, 20.0, 0.0, 291.0, "100g", vec![])).unwrap();
+
+        // Stored with the serving's unit, or as servings of a weighed food, never a bare "2"
+        let entry = parse_and_log(&db, &Config::default(), "2 eggs").unwrap().remove(0).entry;
+        assert_eq!(entry.amount, "2 egg");
+        assert!((entry.protein - 12.0).abs() < 1e-9);
+        assert!((entry.calories - 144.0).abs() < 1e-9);
+        let steak = parse_and_log(&db, &Config::default(), "1.5 ribeye").unwrap().remove(0).entry;
+        assert_eq!(steak.amount, "1.5 servings");
+        assert!((steak.protein - 36.0).abs() < 1e-9);
+
+        // The stored amount works out to the same macros when read back
+        let units = Default::default();
+        for (name, amount) in [("eggs", &entry.amount), ("ribeye", &steak.amount)] {
+            let food = db.get_food_by_name(name).unwrap().unwrap();
+            let multiplier = food.multiplier(&Amount::Measure(amount.clone()), &units, UnknownUnit::Error).unwrap();
+            assert!((food.scale(multiplier).protein - if name == "eggs" { 12.0 } else { 36.0 }).abs() < 1e-9);
+        }
+        let config = Config { remember_amount: true, ..Config::default() };
+        let again = parse_and_log(&db, &config, "ribeye").unwrap().remove(0).entry;
+        assert_eq!(again.amount, "1.5 servings");
+        assert!((again.protein - 36.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_edit_log_entry_amount() {
+        let db = Database::open_in_memory().unwrap();
+        db.add_food(&Food::new("egg", 6.0, 5.0, 0.6, 72.0, "1 large", vec![])).unwrap();
+        let rice = Food { fiber: 0.6, ..Food::new("rice", 2.7, 0.3, 28.0, 130.0, "100g", vec![]) };
+        db.add_food(&rice).unwrap();
+        let config = Config::default();
+
+        // A bare count is read against the serving, and stored with its unit
+        let eggs = parse_and_log(&db, &config, "2 egg").unwrap().remove(0).entry;
+        let edited = edit_log_entry(&db, &config, eggs.id.unwrap(), Some("3"), None, None, None, None, None).unwrap();
+        assert_eq!(edited.amount, "3 large");
+        assert!((edited.protein - 18.0).abs() < 1e-9);
+        assert!((edited.calories - 216.0).abs() < 1e-9);
+
+        // A different unit converts, fiber included
+        let bowl = parse_and_log(&db, &config, "rice 200g").unwrap().remove(0).entry;
+        let edited = edit_log_entry(&db, &config, bowl.id.unwrap(), Some("0.5 lb"), None, None, None, None, None).unwrap();
+        assert_eq!(edited.amount, "0.5 lb");
+        assert!((edited.carbs - 28.0 * 2.26796).abs() < 1e-3);
+        assert!((edited.fiber - 0.6 * 2.26796).abs() < 1e-3);
+        let saved = db.get_log_entry(bowl.id.unwrap()).unwrap();
+        assert_eq!((saved.amount.as_str(), saved.calories), ("0.5 lb", edited.calories));
+
+        // An amount that can't be worked out leaves the entry alone
+        assert!(edit_log_entry(&db, &config, eggs.id.unwrap(), Some("1 cup"), None, None, None, None, None).is_err());
+        assert_eq!(db.get_log_entry(eggs.id.unwrap()).unwrap().amount, "3 large");
+
+        // Given macros are kept, with the count still stored with its unit
+        let edited = edit_log_entry(&db, &config, eggs.id.unwrap(), Some("4"), Some(20.0), None, None, None, None).unwrap();
+        assert_eq!((edited.amount.as_str(), edited.protein, edited.fat), ("4 large", 20.0, 15.0));
+    }
+
+    #[test]
+    fn test_log_fraction() {
+        let db = Database::open_in_memory().unwrap();
+        let pizza = Food::new("pizza", 80.0, 96.0, 240.0, 2144.0, "1 pizza", vec![]);
+        db.add_food(&pizza).unwrap();
+
+        let entry = log_fraction(&db, "pizza", "3/8", &LogMeta::now()).unwrap();
+        assert_eq!(entry.amount, "3/8 pizza");
+        assert!((entry.protein - 30.0).abs() < 1e-9);
+        assert!((entry.carbs - 90.0).abs() < 1e-9);
+        assert!((entry.calories - 804.0).abs() < 1e-9);
+
+        assert!(log_fraction(&db, "pizza", "3/0", &LogMeta::now()).is_err());
+        assert!(log_fraction(&db, "calzone", "1/2", &LogMeta::now()).is_err());
     }
 }
@@ -1,10 +1,28 @@
 use anyhow::Result;
-use clap::{Parser, Subcommand};
+use clap::{Parser, Subcommand, ValueEnum};
 
+mod config;
 mod db;
+mod doctor;
 mod food;
+mod goals;
+mod label;
 mod logging;
 mod mcp;
+mod output;
+mod plan;
+mod recipe;
+mod report;
+mod usda;
+
+use output::{OutputFormat, Table};
+
+/// Print a confirmation line ("Added: ...") where the `confirmations` setting says
+macro_rules! confirm {
+    ($config:expr, $($arg:tt)*) => {
+        $config.confirmations.print(&format!($($arg)*))
+    };
+}
 
 #[derive(Parser)]
 #[command(name = "chomp")]
@@ -18,9 +36,37 @@ struct Cli {
     #[arg(trailing_var_arg = true)]
     food: Vec<String>,
 
-    /// Output as JSON
+    /// Output as JSON (deprecated alias for --format json on commands that take --format)
     #[arg(long, global = true)]
     json: bool,
+
+    /// After logging, also show the day's new totals
+    #[arg(long, global = true)]
+    show_today: bool,
+
+    /// With --json, report an unknown food as JSON with a `suggestions` array
+    #[arg(long, global = true)]
+    suggestions: bool,
+
+    /// Print confirmations like "Logged: ..." to stderr, leaving stdout for data
+    #[arg(long, global = true)]
+    confirm_stderr: bool,
+
+    /// Time eaten, e.g. 12:30 or "30 min ago" (default: now)
+    #[arg(long)]
+    time: Option<String>,
+
+    /// Meal: breakfast, lunch, dinner, or snack (sets a typical time unless --time is given)
+    #[arg(long)]
+    meal: Option<db::Meal>,
+
+    /// Day eaten (YYYY-MM-DD, yesterday, monday, "3 days ago", -3d; default today)
+    #[arg(long, value_parser = parse_date_arg, allow_hyphen_values = true)]
+    date: Option<chrono::NaiveDate>,
+
+    /// Allow --date to be after today, e.g. when planning meals
+    #[arg(long, requires = "date")]
+    future: bool,
 }
 
 #[derive(Subcommand)]
@@ -30,55 +76,222 @@ enum Commands {
         /// Food name
         name: String,
         /// Protein in grams
-        #[arg(long, short)]
-        protein: f64,
+        #[arg(long, short, required_unless_present = "solve")]
+        protein: Option<f64>,
         /// Fat in grams
-        #[arg(long, short)]
-        fat: f64,
+        #[arg(long, short, required_unless_present = "solve")]
+        fat: Option<f64>,
         /// Carbs in grams
-        #[arg(long, short)]
-        carbs: f64,
-        /// Serving size (e.g., "100g", "1 bar", "3oz")
-        #[arg(long, default_value = "100g")]
-        per: String,
+        #[arg(long, short, required_unless_present = "solve")]
+        carbs: Option<f64>,
+        /// Fiber in grams, part of the carbs; used for net carbs
+        #[arg(long)]
+        fiber: Option<f64>,
+        /// Serving size (e.g., "100g", "1 bar", "3oz"; default from config, normally 100g)
+        #[arg(long)]
+        per: Option<String>,
         /// Calories (calculated if not provided)
         #[arg(long)]
         calories: Option<f64>,
+        /// If --calories is 0 but the macros aren't, use the calculated calories instead
+        #[arg(long)]
+        fix_calories: bool,
+        /// Work out this macro (protein, fat, or carbs) from calories and the other two
+        #[arg(long)]
+        solve: Option<food::MacroKind>,
         /// Aliases for this food
         #[arg(long, short)]
         alias: Vec<String>,
+        /// Weight of one serving in grams (used to normalize to per-100g)
+        #[arg(long)]
+        serving_grams: Option<f64>,
+        /// Density in g/ml, for logging by volume against a weighed serving or vice versa
+        #[arg(long)]
+        density: Option<f64>,
+        /// "serving" to scale only by the number of servings ("2 scoops" of a "1 scoop"
+        /// food), never through grams; default "auto"
+        #[arg(long)]
+        unit_mode: Option<food::UnitMode>,
+        /// Add even if a similar food already exists, without asking (or warning)
+        #[arg(long, short)]
+        yes: bool,
+    },
+    /// Read serving size and macros from pasted nutrition label text, and optionally add it
+    ParseLabel {
+        /// Label text (reads stdin if not given)
+        text: Vec<String>,
+        /// Add the parsed food under this name, after showing it and asking
+        #[arg(long, value_name = "NAME")]
+        add: Option<String>,
+        /// With --add, add without asking
+        #[arg(long, short)]
+        yes: bool,
+    },
+    /// Log food (same as the default action), or `log list` to see a day's entries
+    #[command(args_conflicts_with_subcommands = true)]
+    Log {
+        #[command(subcommand)]
+        action: Option<LogAction>,
+        /// Food to log, with optional amount
+        #[arg(required = true)]
+        food: Vec<String>,
+        /// Log a fraction of the whole food instead of an amount (e.g. 3/8)
+        #[arg(long)]
+        fraction: Option<String>,
+        /// Time eaten, e.g. 12:30 or "30 min ago" (default: now)
+        #[arg(long)]
+        time: Option<String>,
+        /// Meal: breakfast, lunch, dinner, or snack (sets a typical time unless --time is given)
+        #[arg(long)]
+        meal: Option<db::Meal>,
+        /// Day eaten (YYYY-MM-DD, yesterday, monday, "3 days ago", -3d; default today)
+        #[arg(long, value_parser = parse_date_arg, allow_hyphen_values = true)]
+        date: Option<chrono::NaiveDate>,
+        /// Allow --date to be after today, e.g. when planning meals
+        #[arg(long, requires = "date")]
+        future: bool,
+    },
+    /// Log calories with no macros, e.g. `chomp quick 500`
+    Quick {
+        /// Calories to log
+        calories: f64,
+        /// Time eaten, e.g. 12:30 or "30 min ago" (default: now)
+        #[arg(long)]
+        time: Option<String>,
+        /// Meal: breakfast, lunch, dinner, or snack (sets a typical time unless --time is given)
+        #[arg(long)]
+        meal: Option<db::Meal>,
+    },
+    /// Show how an input would be parsed and calculated, without logging it
+    Explain {
+        /// Food and optional amount, e.g. "ribeye 8oz"
+        #[arg(required = true)]
+        input: Vec<String>,
+    },
+    /// Show the macros for an amount of a food, without logging it
+    Nutrition {
+        /// Food and optional amount, e.g. "salmon 6oz" (default amount: as logging would use)
+        #[arg(required = true, value_name = "FOOD [AMOUNT]")]
+        input: Vec<String>,
     },
     /// Search foods in database
     Search {
         /// Search query
         query: String,
+        /// Sort order for results
+        #[arg(long, value_enum, default_value_t = SearchSort::Relevance)]
+        sort: SearchSort,
+        /// Match names only, not aliases
+        #[arg(long)]
+        no_aliases: bool,
+        /// Match aliases only, not names, showing the alias each food matched by
+        #[arg(long, conflicts_with = "no_aliases")]
+        alias_only: bool,
+        /// Only foods from this source: manual, usda, csv, mfp, or cronometer
+        #[arg(long)]
+        source: Option<food::FoodSource>,
+        /// Output format
+        #[arg(long, value_enum)]
+        format: Option<OutputFormat>,
     },
     /// Show today's totals
-    Today,
+    Today {
+        /// Flag a protein shortfall against the goal and suggest a food to close it
+        #[arg(long)]
+        check_protein: bool,
+        /// Only count entries logged as this meal (breakfast, lunch, dinner, or snack)
+        #[arg(long, conflicts_with = "check_protein")]
+        meal: Option<db::Meal>,
+        /// Output format
+        #[arg(long, value_enum)]
+        format: Option<OutputFormat>,
+    },
     /// Show recent log entries
     History {
-        /// Number of days to show
-        #[arg(short, long, default_value = "7")]
-        days: u32,
+        /// Number of days to show, counting today or --until (1 = that day only; default 7,
+        /// or every day with --limit)
+        #[arg(short, long, value_parser = clap::value_parser!(u32).range(1..))]
+        days: Option<u32>,
+        /// First date to show (YYYY-MM-DD, yesterday, monday, "3 days ago", -3d), instead of --days
+        #[arg(long, conflicts_with = "days", value_parser = parse_date_arg, allow_hyphen_values = true)]
+        since: Option<chrono::NaiveDate>,
+        /// Last date to show (YYYY-MM-DD, yesterday, monday, "3 days ago", -3d)
+        #[arg(long, value_parser = parse_date_arg, allow_hyphen_values = true)]
+        until: Option<chrono::NaiveDate>,
+        /// Show only the N most recent entries (within --days/--since/--until if given)
+        #[arg(short = 'n', long, value_parser = clap::value_parser!(u32).range(1..))]
+        limit: Option<u32>,
+        /// Also show each food's per-serving macros
+        #[arg(short, long)]
+        verbose: bool,
+        /// Show dates as "today", "yesterday", or a weekday for the past week
+        #[arg(long)]
+        relative: bool,
+        /// Only show entries logged as this meal (breakfast, lunch, dinner, or snack)
+        #[arg(long)]
+        meal: Option<db::Meal>,
+        /// Output format
+        #[arg(long, value_enum)]
+        format: Option<OutputFormat>,
     },
     /// Export data
     Export {
-        /// Export format
+        /// Export format: csv or json (the whole log), text (one day's summary), or
+        /// sqlite (a standalone copy of the database; needs a file)
         #[arg(long, default_value = "csv")]
         format: String,
+        /// Day to summarize with --format text (YYYY-MM-DD, yesterday, monday, "3 days ago", -3d; default today)
+        #[arg(long, value_parser = parse_date_arg, allow_hyphen_values = true)]
+        date: Option<chrono::NaiveDate>,
+        /// File to write (default: a dated file in export_dir if configured, else stdout)
+        #[arg(long, short)]
+        output: Option<String>,
+        /// With --format sqlite: replace the file if it exists
+        #[arg(long)]
+        force: bool,
     },
     /// Import from USDA or other sources
     Import {
-        /// Source (usda, csv)
+        /// Source (usda, csv, json)
         source: String,
-        /// Path for csv import
-        #[arg(long)]
+        /// Path for csv or json import, or a downloaded USDA FoodData Central
+        /// dataset (its .json file, or the CSV bundle's directory)
+        #[arg(long, visible_alias = "file")]
         path: Option<String>,
+        /// With usda --file: only foods whose description contains all these words
+        #[arg(long)]
+        query: Option<String>,
+        /// Trim and lowercase aliases, drop repeats, and skip (with a warning) any
+        /// already taken by another food instead of failing the import
+        #[arg(long)]
+        dedupe_aliases: bool,
+    },
+    /// Lay out meals ahead of time
+    Plan {
+        #[command(subcommand)]
+        action: PlanAction,
+    },
+    /// Save recipes and portion them
+    Recipe {
+        #[command(subcommand)]
+        action: RecipeAction,
+    },
+    /// Write a complete, versioned JSON copy of the database
+    Dump {
+        /// File to write (default: stdout)
+        #[arg(long, short)]
+        output: Option<String>,
+    },
+    /// Restore a `chomp dump` file into an empty database
+    Load {
+        /// Dump file to read
+        path: String,
     },
     /// Edit a food entry
     Edit {
         /// Food name to edit
-        name: String,
+        #[arg(required_unless_present = "all")]
+        name: Option<String>,
         /// Protein in grams
         #[arg(long, short)]
         protein: Option<f64>,
@@ -88,15 +301,52 @@ enum Commands {
         /// Carbs in grams
         #[arg(long, short)]
         carbs: Option<f64>,
+        /// Calories, e.g. from the label (default: recalculated when macros change)
+        #[arg(long)]
+        calories: Option<f64>,
+        /// Fiber in grams, counted within the carbs
+        #[arg(long)]
+        fiber: Option<f64>,
         /// Serving size (e.g., "100g", "1 bar", "3oz")
         #[arg(long)]
         per: Option<String>,
+        /// Add an alias (repeatable)
+        #[arg(long)]
+        add_alias: Vec<String>,
+        /// Remove an alias (repeatable)
+        #[arg(long)]
+        remove_alias: Vec<String>,
+        /// Edit every food (optionally narrowed by --matching) instead of one by name
+        #[arg(long, conflicts_with_all = ["name", "protein", "fat", "carbs", "calories", "fiber", "per", "add_alias", "remove_alias"],
+            requires = "scale")]
+        all: bool,
+        /// With --all: only foods whose name contains this text
+        #[arg(long, requires = "all")]
+        matching: Option<String>,
+        /// With --all: multiply macros and calories by this factor
+        #[arg(long, requires = "all")]
+        scale: Option<f64>,
+        /// With --all: apply the changes
+        #[arg(long, requires = "all", conflicts_with = "dry_run")]
+        yes: bool,
+        /// With --all: show what would change without writing
+        #[arg(long, requires = "all")]
+        dry_run: bool,
     },
     /// Delete a food entry
     Delete {
         /// Food name to delete
         name: String,
     },
+    /// Merge a duplicate food into another: its log entries and aliases move over,
+    /// and its name becomes an alias
+    Merge {
+        /// Food to merge away
+        from: String,
+        /// Food to keep
+        #[arg(long)]
+        into: String,
+    },
     /// Delete a log entry by ID
     Unlog {
         /// Log entry ID to delete
@@ -104,11 +354,13 @@ enum Commands {
     },
     /// Delete the most recent log entry
     UnlogLast,
+    /// Show the most recent log entry (the one unlog-last would delete)
+    Last,
     /// Edit a log entry
     EditLog {
         /// Log entry ID to edit
         id: i64,
-        /// New amount
+        /// New amount; the macros are worked out again from the food unless given too
         #[arg(long)]
         amount: Option<String>,
         /// New protein in grams
@@ -120,74 +372,661 @@ enum Commands {
         /// New carbs in grams
         #[arg(long, short)]
         carbs: Option<f64>,
+        /// New calories (default: recalculated when macros change)
+        #[arg(long)]
+        calories: Option<f64>,
+        /// New fiber in grams (default: in proportion to the carbs)
+        #[arg(long)]
+        fiber: Option<f64>,
+    },
+    /// Check the database for data-quality problems
+    Doctor {
+        #[command(subcommand)]
+        target: DoctorTarget,
+    },
+    /// Convert foods with a weighed serving to per-100g macros
+    Normalize,
+    /// Show or set daily and weekly nutrition goals
+    Goals {
+        #[command(subcommand)]
+        action: Option<GoalsAction>,
     },
     /// Show database stats
-    Stats,
+    Stats {
+        /// Output format
+        #[arg(long, value_enum)]
+        format: Option<OutputFormat>,
+    },
+    /// Summarize a period: average macros, protein goal hits, extreme days, most-logged food
+    Report {
+        /// Number of days to cover, counting today
+        #[arg(short, long, default_value_t = 30, value_parser = clap::value_parser!(u32).range(1..))]
+        days: u32,
+        /// Output format
+        #[arg(long, value_enum)]
+        format: Option<OutputFormat>,
+    },
+    /// List the foods with the most of a nutrient, e.g. `chomp top protein`
+    Top {
+        /// protein, fat, carbs, or calories
+        nutrient: food::Nutrient,
+        /// How many foods to list
+        #[arg(short = 'n', long, default_value_t = 10, value_parser = clap::value_parser!(u32).range(1..))]
+        limit: u32,
+        /// Rank by amount per 100g instead of per serving (skips foods without a weighed serving)
+        #[arg(long)]
+        per100: bool,
+        /// Output format
+        #[arg(long, value_enum)]
+        format: Option<OutputFormat>,
+    },
+    /// Show chomp's version, database and config locations, and schema version
+    Info,
+    /// Show recorded changes (needs `audit_log = true` in the config)
+    Audit {
+        /// Number of days back to show
+        #[arg(short, long, default_value_t = 7, value_parser = clap::value_parser!(u32).range(1..))]
+        days: u32,
+    },
+    /// Show or change settings in ~/.chomp/config.toml
+    Config {
+        #[command(subcommand)]
+        action: Option<ConfigAction>,
+    },
     /// Start MCP server (for AI assistants like Claude Desktop)
-    Serve,
+    Serve {
+        /// Print the tools the server offers, with their input schemas, and exit
+        #[arg(long)]
+        print_schema: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum LogAction {
+    /// List a day's entries with their ids (default: today)
+    List {
+        /// Show today's entries (the default)
+        #[arg(long, conflicts_with = "date")]
+        today: bool,
+        /// Show entries for this date (YYYY-MM-DD, yesterday, monday, "3 days ago", -3d)
+        #[arg(long, value_parser = parse_date_arg, allow_hyphen_values = true)]
+        date: Option<chrono::NaiveDate>,
+        /// Show only the day's N most recent entries
+        #[arg(short = 'n', long, value_parser = clap::value_parser!(u32).range(1..))]
+        limit: Option<u32>,
+    },
+}
+
+#[derive(Subcommand)]
+enum ConfigAction {
+    /// Print one setting
+    Get {
+        key: String,
+    },
+    /// Change one setting (validated before it's written)
+    Set {
+        key: String,
+        value: String,
+    },
+    /// Print the config file's location
+    Path,
+}
+
+#[derive(Subcommand)]
+enum DoctorTarget {
+    /// Find foods with missing calories, bad servings, duplicates, or alias collisions
+    Foods {
+        /// Apply safe fixes (recompute calories from macros)
+        #[arg(long)]
+        fix: bool,
+    },
+    /// Find entries that look logged twice: same food, amount, and date within a minute
+    Log {
+        /// Delete the repeats, keeping the first of each
+        #[arg(long)]
+        dedupe: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum GoalsAction {
+    /// Replace the current goals (unset fields have no target)
+    Set {
+        /// Protein in grams per day
+        #[arg(long, short)]
+        protein: Option<f64>,
+        /// Fat in grams per day
+        #[arg(long, short)]
+        fat: Option<f64>,
+        /// Carbs in grams per day
+        #[arg(long, short)]
+        carbs: Option<f64>,
+        /// Calories per day
+        #[arg(long)]
+        calories: Option<f64>,
+        /// Calorie budget for the week (Monday–Sunday)
+        #[arg(long, conflicts_with = "day")]
+        weekly_calories: Option<f64>,
+        /// Only on these weekdays, e.g. mon,wed,fri (unset fields fall back to the defaults)
+        #[arg(long, value_delimiter = ',')]
+        day: Vec<chrono::Weekday>,
+    },
+    /// Remove the current goals from today (past days keep theirs), or with --day,
+    /// those weekday overrides (which aren't dated, so past days lose them too)
+    Clear {
+        /// Remove the overrides for these weekdays, e.g. mon,wed,fri
+        #[arg(long, value_delimiter = ',')]
+        day: Vec<chrono::Weekday>,
+    },
+    /// Show every change to the goals and the date it took effect
+    History,
+}
+
+#[derive(Subcommand)]
+enum PlanAction {
+    /// Log a plan from a CSV of date,food,amount,meal rows (add protein,fat,carbs
+    /// columns to create foods that don't exist yet)
+    Import {
+        /// Plan file to read
+        #[arg(long, visible_alias = "file")]
+        path: String,
+        /// Show what would be logged without writing
+        #[arg(long)]
+        dry_run: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum RecipeAction {
+    /// Save a recipe from its ingredients, each as you'd log it, e.g. "ground beef 500g"
+    Add {
+        /// Recipe name
+        name: String,
+        /// Ingredients, one per argument
+        #[arg(required = true)]
+        ingredients: Vec<String>,
+        /// Portions the recipe makes
+        #[arg(long, default_value_t = 1.0)]
+        servings: f64,
+    },
+    /// Show how many servings (and grams) of a recipe come to a calorie target
+    Scale {
+        /// Recipe name
+        name: String,
+        /// Calories to portion to
+        #[arg(long)]
+        to_calories: f64,
+    },
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum SearchSort {
+    /// Best fuzzy match first, boosted for foods logged recently and often
+    Relevance,
+    /// Most protein per calorie first
+    ProteinDensity,
 }
 
 fn main() -> Result<()> {
     let cli = Cli::parse();
-    
+    // Handled before loading, so `config set` can repair a broken file
+    if let Some(Commands::Config { action }) = &cli.command {
+        return run_config(action.as_ref(), cli.json);
+    }
+    let mut config = config::Config::load()?;
+    if cli.confirm_stderr {
+        config.confirmations = output::ConfirmStream::Stderr;
+    }
+    // chrono reads TZ for local time, so this moves "today" everywhere at once
+    if let Some(timezone) = &config.timezone {
+        std::env::set_var("TZ", timezone);
+    }
+
+    // The MCP server may hold the write lock. Each write is retried on its own
+    // (see `Database::retry_if_busy`), never the whole command, which could log
+    // something twice; a read that's still busy is explained the same way.
+    run(cli, &config).map_err(db::explain_busy)
+}
+
+fn run_config(action: Option<&ConfigAction>, json: bool) -> Result<()> {
+    use config::Config;
+
+    match action {
+        None => {
+            let config = Config::load()?;
+            if json {
+                println!("{}", serde_json::to_string_pretty(&config)?);
+            } else {
+                for key in Config::KEYS {
+                    println!("{} = {}", key, config.get(key)?.unwrap_or_else(|| "(unset)".to_string()));
+                }
+            }
+        }
+        Some(ConfigAction::Get { key }) => {
+            let value = Config::load()?.get(key)?;
+            if json {
+                println!("{}", serde_json::to_string_pretty(&value)?);
+            } else {
+                println!("{}", value.unwrap_or_else(|| "(unset)".to_string()));
+            }
+        }
+        Some(ConfigAction::Set { key, value }) => {
+            let config = Config::set(key, value)?;
+            println!("{} = {}", key, config.get(key)?.unwrap_or_default());
+        }
+        Some(ConfigAction::Path) => {
+            println!("{}", Config::path()?.display());
+        }
+    }
+    Ok(())
+}
+
+fn run(cli: Cli, config: &config::Config) -> Result<()> {
     // Initialize database
-    let db = db::Database::open()?;
+    let mut db = db::Database::open(&config.db_path()?)?;
     db.init()?;
+    db.set_audit(config.audit_log);
+
+    let display = |name: &str| config.name_case.apply(name);
 
     match cli.command {
-        Some(Commands::Add { name, protein, fat, carbs, per, calories, alias }) => {
-            let cals = calories.unwrap_or_else(|| protein * 4.0 + fat * 9.0 + carbs * 4.0);
-            let food = food::Food::new(&name, protein, fat, carbs, cals, &per, alias);
+        Some(Commands::Add { name, protein, fat, carbs, fiber, per, calories, fix_calories, solve, alias, serving_grams, density, unit_mode, yes }) => {
+            let per = per.unwrap_or_else(|| config.default_serving.clone());
+            let (macros, warning) = food::macros_for_new_food(protein, fat, carbs, calories, solve, fix_calories)?;
+            if let Some(warning) = warning {
+                eprintln!("Warning: {}", warning);
+            }
+
+            doctor::ensure_names_free(&db, &name, &alias)?;
+            if !yes {
+                use std::io::IsTerminal;
+                let similar = doctor::similar_foods(&db, &name)?;
+                if !similar.is_empty() {
+                    let names: Vec<_> = similar.iter().map(|f| f.name.as_str()).collect();
+                    // Nobody to ask without a terminal; a similar name alone isn't worth failing a script over
+                    if !std::io::stdin().is_terminal() {
+                        eprintln!("Warning: similar food exists: {}", names.join(", "));
+                    } else if !confirm(&format!("Similar food exists: {} — add anyway?", names.join(", ")))? {
+                        confirm!(config, "Not added.");
+                        return Ok(());
+                    }
+                }
+            }
+
+            let mut food = food::Food::new(&name, macros.protein, macros.fat, macros.carbs, macros.calories, &per, alias);
+            if let Some(fiber) = fiber {
+                anyhow::ensure!(fiber >= 0.0, "--fiber can't be negative");
+                anyhow::ensure!(fiber <= food.carbs, "--fiber can't be more than the carbs");
+                food.fiber = fiber;
+            }
+            if let Some(d) = density {
+                anyhow::ensure!(d > 0.0, "--density must be positive");
+                food.density = Some(d);
+            }
+            food.unit_mode = unit_mode.unwrap_or_default();
+            if config.normalize_per_100g && food.unit_mode == food::UnitMode::Auto {
+                if let Some(grams) = serving_grams.or_else(|| food::serving_grams(&per)) {
+                    anyhow::ensure!(grams > 0.0, "--serving-grams must be positive");
+                    food = food.normalized_per_100g(grams);
+                }
+            }
             db.add_food(&food)?;
             
             if cli.json {
                 println!("{}", serde_json::to_string_pretty(&food)?);
             } else {
-                println!("Added: {} ({:.0}p/{:.0}f/{:.0}c per {})", name, protein, fat, carbs, per);
+                let macros = output::MacroFormat::new(config, config.confirmations.shows_color());
+                confirm!(config, "Added: {}", macros.food_summary(&food, &display(&food.name)));
+            }
+        }
+        Some(Commands::ParseLabel { text, add, yes }) => {
+            let text = if text.is_empty() {
+                std::io::read_to_string(std::io::stdin())?
+            } else {
+                text.join(" ")
+            };
+            let facts = label::parse_label(&text);
+
+            let Some(name) = add else {
+                if cli.json {
+                    println!("{}", serde_json::to_string_pretty(&facts)?);
+                    return Ok(());
+                }
+                let grams = |v: Option<f64>| v.map_or("(not found)".to_string(), |v| format!("{}g", food::format_number(v)));
+                println!("Serving:  {}", facts.serving.as_deref().unwrap_or("(not found)"));
+                println!("Calories: {}", facts.calories.map_or("(not found)".to_string(), food::format_number));
+                println!("Protein:  {}", grams(facts.protein));
+                println!("Fat:      {}", grams(facts.fat));
+                println!("Carbs:    {}", grams(facts.carbs));
+                if facts.to_food("", &config.default_serving).is_ok() {
+                    println!("\nAdd it with --add \"NAME\"");
+                }
+                return Ok(());
+            };
+
+            let mut food = facts.to_food(&name, &config.default_serving)?;
+            let macros = output::MacroFormat::new(config, false);
+            if !yes && !confirm(&format!("Add {}?", macros.food_summary(&food, &display(&food.name))))? {
+                confirm!(config, "Not added.");
+                return Ok(());
+            }
+            if config.normalize_per_100g {
+                if let Some(grams) = food::serving_grams(&food.serving) {
+                    food = food.normalized_per_100g(grams);
+                }
+            }
+            db.add_food(&food)?;
+            if cli.json {
+                println!("{}", serde_json::to_string_pretty(&food)?);
+            } else {
+                let macros = output::MacroFormat::new(config, config.confirmations.shows_color());
+                confirm!(config, "Added: {}", macros.food_summary(&food, &display(&food.name)));
             }
         }
-        Some(Commands::Search { query }) => {
-            let results = db.search_foods(&query)?;
+        Some(Commands::Log { action: Some(LogAction::List { today: _, date, limit }), .. }) => {
+            let date = date.unwrap_or_else(|| chrono::Local::now().date_naive());
+            let mut entries = db.get_log_for_date(date)?;
+            if let Some(limit) = limit {
+                entries = entries.split_off(entries.len().saturating_sub(limit as usize));
+            }
             if cli.json {
-                println!("{}", serde_json::to_string_pretty(&results)?);
+                println!("{}", serde_json::to_string_pretty(&entries)?);
+            } else if entries.is_empty() {
+                println!("Nothing logged on {}", date);
             } else {
-                for food in results {
-                    println!("{}: {:.0}p/{:.0}f/{:.0}c per {}", 
-                        food.name, food.protein, food.fat, food.carbs, food.serving);
+                let macros = output::MacroFormat::for_stdout(config);
+                for entry in entries {
+                    println!("#{} | {}{} {} | {} — {}",
+                        entry.id.unwrap_or_default(),
+                        entry.time.as_deref().map(|t| format!("{} ", t)).unwrap_or_default(),
+                        entry.amount, display(&entry.food_name),
+                        macros.compact(entry.protein, entry.fat, entry.carbs),
+                        macros.kcal(entry.protein, entry.fat, entry.carbs, entry.calories));
                 }
             }
         }
-        Some(Commands::Today) => {
-            let totals = db.get_today_totals()?;
+        Some(Commands::Log { action: None, food, fraction, time, meal, date, future }) => {
+            let input = food.join(" ");
+            let (meta, warning) = dated_log_meta(config, time.as_deref(), meal, date, future)?;
+            let logged = match fraction {
+                Some(fraction) => vec![logging::log_fraction(&db, &input, &fraction, &meta)?.into()],
+                None => log_input(&db, config, &input, &meta, cli.json, cli.suggestions)?,
+            };
+            let logged = with_warning(logged, warning);
+
+            print_logged(&db, config, logged, cli.json, cli.show_today)?;
+        }
+        Some(Commands::Quick { calories, time, meal }) => {
+            let meta = logging::log_meta(time.as_deref(), meal, chrono::Local::now().naive_local())?;
+            let entry = logging::log_quick(&db, calories, &meta)?;
+            print_logged(&db, config, vec![entry.into()], cli.json, cli.show_today)?;
+        }
+        Some(Commands::Explain { input }) => {
+            let r = logging::resolve(&db, config, &input.join(" "))?;
+            if cli.json {
+                println!("{}", serde_json::to_string_pretty(&r)?);
+            } else {
+                println!("Input:       {}", r.input);
+                println!("Food name:   {}", r.parsed_name);
+                match (&r.parsed_amount, &r.parsed_unit) {
+                    (Some(amount), Some(unit)) => println!("Amount:      {} (unit: {})", amount, unit),
+                    _ => println!("Amount:      (none)"),
+                }
+                let matched = if r.fuzzy_matched { "fuzzy match" } else { "exact match" };
+                let mode = match r.log_match {
+                    config::LogMatch::Exact => "exact",
+                    config::LogMatch::Fuzzy => "fuzzy",
+                };
+                let macros = output::MacroFormat::for_stdout(config);
+                println!("Resolved to: {} ({}, log_match = {})", macros.food_summary(&r.food, &display(&r.food.name)), matched, mode);
+                let source = match r.amount_source {
+                    logging::AmountSource::Input => "from input",
+                    logging::AmountSource::DefaultAmount => "food's default amount",
+                    logging::AmountSource::LastUsed => "last amount logged",
+                    logging::AmountSource::Serving => "one serving",
+                };
+                println!("Using:       {} ({})", r.amount, source);
+                println!("Multiplier:  {:.4} × {}", r.multiplier, r.food.serving);
+                let m = &r.macros;
+                println!("Macros:      {} — {}",
+                    macros.precise().compact(m.protein, m.fat, m.carbs), macros.precise().kcal(m.protein, m.fat, m.carbs, m.calories));
+            }
+        }
+        Some(Commands::Recipe { action: RecipeAction::Add { name, ingredients, servings } }) => {
+            let recipe = recipe::Recipe::build(&db, config, &name, servings, &ingredients)?;
+            db.add_recipe(&recipe)?;
             if cli.json {
-                println!("{}", serde_json::to_string_pretty(&totals)?);
+                println!("{}", serde_json::to_string_pretty(&recipe)?);
             } else {
-                println!("Today: {:.0}p / {:.0}f / {:.0}c — {:.0} kcal",
-                    totals.protein, totals.fat, totals.carbs, totals.calories);
+                let macros = output::MacroFormat::new(config, config.confirmations.shows_color());
+                let m = recipe.per_serving();
+                confirm!(config, "Saved recipe {}: {}, {} per serving ({} servings)", display(&recipe.name),
+                    macros.compact(m.protein, m.fat, m.carbs), macros.kcal(m.protein, m.fat, m.carbs, m.calories),
+                    food::format_number(recipe.servings));
             }
         }
-        Some(Commands::History { days }) => {
-            let entries = db.get_history(days)?;
+        Some(Commands::Recipe { action: RecipeAction::Scale { name, to_calories } }) => {
+            let recipe = db.get_recipe(&name)?
+                .ok_or_else(|| anyhow::anyhow!("Recipe not found: '{}'. Save it with: chomp recipe add", name))?;
+            let portion = recipe.portion_for_calories(to_calories)?;
             if cli.json {
+                println!("{}", serde_json::to_string_pretty(&portion)?);
+            } else {
+                let servings = format!("{} × {}",
+                    food::format_number((portion.servings * 100.0).round() / 100.0), portion.serving);
+                let amount = match portion.grams {
+                    Some(grams) => format!("{}g ({})", food::format_number(grams.round()), servings),
+                    None => servings,
+                };
+                let macros = output::MacroFormat::for_stdout(config).precise();
+                let m = &portion.macros;
+                println!("{} {} — {} — {}", amount, display(&portion.food),
+                    macros.compact(m.protein, m.fat, m.carbs), macros.kcal(m.protein, m.fat, m.carbs, m.calories));
+            }
+        }
+        Some(Commands::Nutrition { input }) => {
+            let n = logging::nutrition(&db, config, &input.join(" "), None)?;
+            if cli.json {
+                println!("{}", serde_json::to_string_pretty(&n)?);
+            } else {
+                let macros = output::MacroFormat::for_stdout(config).precise();
+                let m = &n.macros;
+                println!("{} {} — {} — {} ({} × {})",
+                    n.amount, display(&n.food), macros.compact(m.protein, m.fat, m.carbs),
+                    macros.kcal(m.protein, m.fat, m.carbs, m.calories), food::format_number(n.multiplier), n.serving);
+            }
+        }
+        Some(Commands::Search { query, sort, no_aliases, alias_only, source, format }) => {
+            let mut options = config.search_options();
+            if no_aliases {
+                options.include_aliases = false;
+            }
+            options.aliases_only = alias_only;
+            options.source = source;
+            let mut matches = db.search_matches(&query, &options)?;
+            if sort == SearchSort::ProteinDensity {
+                matches.sort_by(|a, b| food::protein_density_order(&a.food, &b.food));
+            }
+
+            let format = OutputFormat::resolve(format, cli.json);
+            if format == OutputFormat::Json {
+                if alias_only {
+                    println!("{}", serde_json::to_string_pretty(&matches)?);
+                } else {
+                    let results: Vec<_> = matches.iter().map(|m| &m.food).collect();
+                    println!("{}", serde_json::to_string_pretty(&results)?);
+                }
+            } else if format != OutputFormat::Plain {
+                let mut headers = vec!["name", "protein", "fat", "carbs", "calories", "serving", "source"];
+                if alias_only {
+                    headers.insert(1, "alias");
+                }
+                if sort == SearchSort::ProteinDensity {
+                    headers.push("protein_per_100kcal");
+                }
+                let mut table = Table::new(&headers);
+                for db::SearchMatch { food, matched_alias } in &matches {
+                    let mut row = vec![
+                        display(&food.name),
+                        number_cell(food.protein, format),
+                        number_cell(food.fat, format),
+                        number_cell(food.carbs, format),
+                        number_cell(food.calories, format),
+                        food.serving.clone(),
+                        food.source.to_string(),
+                    ];
+                    if alias_only {
+                        row.insert(1, matched_alias.clone().unwrap_or_default());
+                    }
+                    if sort == SearchSort::ProteinDensity {
+                        row.push(food.protein_density().map(|d| number_cell(d, format)).unwrap_or_default());
+                    }
+                    table.push(row);
+                }
+                print_table(&table, format);
+            } else {
+                let macros = output::MacroFormat::for_stdout(config);
+                for db::SearchMatch { food, matched_alias } in matches {
+                    // Typed-in foods are the norm; imported ones say where they came from
+                    let source = match food.source {
+                        food::FoodSource::Manual => String::new(),
+                        source => format!(" [{}]", source),
+                    };
+                    let alias = matched_alias.map(|a| format!(" (alias: {})", a)).unwrap_or_default();
+                    if sort == SearchSort::ProteinDensity {
+                        let density = food.protein_density()
+                            .map(|d| format!("{:.1}g protein/100 kcal", d))
+                            .unwrap_or_else(|| "N/A".to_string());
+                        println!("{}{}: {} per {} ({}){}",
+                            display(&food.name), alias, macros.compact(food.protein, food.fat, food.carbs), food.serving, density, source);
+                    } else {
+                        println!("{}{}: {} per {}{}",
+                            display(&food.name), alias, macros.compact(food.protein, food.fat, food.carbs), food.serving, source);
+                    }
+                }
+            }
+        }
+        Some(Commands::Today { meal: Some(meal), format, .. }) => {
+            print_meal_today(&db, config, meal, OutputFormat::resolve(format, cli.json))?;
+        }
+        Some(Commands::Today { check_protein, meal: None, format }) => {
+            print_today(&db, config, OutputFormat::resolve(format, cli.json), check_protein)?;
+        }
+        Some(Commands::History { days, since, until, limit, verbose, relative, meal, format }) => {
+            let (since, until) = match (days, since, until) {
+                // --limit alone: the latest entries, however old
+                (None, None, None) if limit.is_some() => (None, None),
+                _ => db::history_window(chrono::Local::now().date_naive(), days.unwrap_or(7), since, until)?,
+            };
+            let entries = db.get_log_range(since, until, limit, meal)?;
+            let format = OutputFormat::resolve(format, cli.json);
+            if format == OutputFormat::Json {
+                let entries: Vec<logging::HistoryEntry> = entries.iter().map(Into::into).collect();
                 println!("{}", serde_json::to_string_pretty(&entries)?);
+            } else if format != OutputFormat::Plain {
+                let today = chrono::Local::now().date_naive();
+                let mut table = Table::new(&["id", "date", "amount", "food", "protein", "fat", "carbs", "net_carbs", "calories"]);
+                for entry in &entries {
+                    table.push(vec![
+                        entry.id.map(|id| id.to_string()).unwrap_or_default(),
+                        if relative { logging::relative_date(&entry.date, today) } else { entry.date.clone() },
+                        entry.amount.clone(),
+                        display(&entry.food_name),
+                        number_cell(entry.protein, format),
+                        number_cell(entry.fat, format),
+                        number_cell(entry.carbs, format),
+                        number_cell(entry.macros().net_carbs(), format),
+                        number_cell(entry.calories, format),
+                    ]);
+                }
+                print_table(&table, format);
             } else {
+                let foods: std::collections::HashMap<_, _> = if verbose {
+                    db.all_foods()?.into_iter().filter_map(|f| Some((f.id?, f))).collect()
+                } else {
+                    Default::default()
+                };
+                let today = chrono::Local::now().date_naive();
+                let macros = output::MacroFormat::for_stdout(config);
                 for entry in entries {
-                    println!("{} | {} {} | {:.0}p/{:.0}f/{:.0}c",
-                        entry.date, entry.amount, entry.food_name,
-                        entry.protein, entry.fat, entry.carbs);
+                    let basis = foods.get(&entry.food_id);
+                    let date = if relative { logging::relative_date(&entry.date, today) } else { entry.date.clone() };
+                    println!("{}", logging::history_line(&entry, &date, &display(&entry.food_name), basis, &macros));
                 }
             }
         }
-        Some(Commands::Export { format }) => {
-            match format.as_str() {
-                "csv" => db.export_csv()?,
-                "json" => db.export_json()?,
-                _ => anyhow::bail!("Unknown format: {}", format),
+        Some(Commands::Export { format, date, output, force }) => {
+            anyhow::ensure!(matches!(format.as_str(), "csv" | "json" | "text" | "sqlite"), "Unknown format: {}", format);
+            anyhow::ensure!(date.is_none() || format == "text", "--date only applies to --format text");
+            anyhow::ensure!(!force || format == "sqlite", "--force only applies to --format sqlite");
+            let today = chrono::Local::now().date_naive();
+            if format == "sqlite" {
+                let path = output.map(std::path::PathBuf::from)
+                    .or_else(|| config.export_dir().map(|dir| dir.join(db::export_filename(&format, today))))
+                    .ok_or_else(|| anyhow::anyhow!("--format sqlite writes a file; pass --output (or set export_dir)"))?;
+                export_sqlite(&db, &config.db_path()?, &path, force)?;
+                confirm!(config, "Wrote {}", path.display());
+                return Ok(());
+            }
+            let write = |mut out: &mut dyn std::io::Write| -> Result<()> {
+                match format.as_str() {
+                    "text" => write!(out, "{}", report::format_day_summary(&db, date.unwrap_or(today))?)?,
+                    _ => db.export(&format, &mut out)?,
+                }
+                Ok(())
+            };
+            let path = output.map(std::path::PathBuf::from).or_else(|| {
+                let day = if format == "text" { date.unwrap_or(today) } else { today };
+                config.export_dir().map(|dir| dir.join(db::export_filename(&format, day)))
+            });
+            match path {
+                Some(path) => {
+                    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+                        std::fs::create_dir_all(parent)?;
+                    }
+                    let mut file = std::io::BufWriter::new(std::fs::File::create(&path)?);
+                    write(&mut file)?;
+                    std::io::Write::flush(&mut file)?;
+                    confirm!(config, "Wrote {}", path.display());
+                }
+                None => write(&mut std::io::stdout().lock())?,
             }
         }
-        Some(Commands::Import { source, path }) => {
+        Some(Commands::Import { source, path, query, dedupe_aliases }) => {
             match source.as_str() {
-                "usda" => db.import_usda()?,
+                "usda" => match path {
+                    Some(p) => {
+                        let foods = usda::read_foods(std::path::Path::new(&p), query.as_deref())?;
+                        let summary = db.import_foods(&foods, dedupe_aliases)?;
+                        if cli.json {
+                            println!("{}", serde_json::to_string_pretty(&summary)?);
+                        } else {
+                            for skipped in &summary.aliases_skipped {
+                                eprintln!("Warning: skipped alias {}", skipped);
+                            }
+                            confirm!(config, "Imported {} USDA foods ({} already existed)",
+                                summary.foods_added, summary.foods_existing);
+                        }
+                    }
+                    None => db.import_usda()?,
+                },
+                "json" => {
+                    let p = path.ok_or_else(|| anyhow::anyhow!("--path required for json import"))?;
+                    let summary = db.import_json(&p, dedupe_aliases)?;
+                    if cli.json {
+                        println!("{}", serde_json::to_string_pretty(&summary)?);
+                    } else {
+                        for skipped in &summary.aliases_skipped {
+                            eprintln!("Warning: skipped alias {}", skipped);
+                        }
+                        let skipped = match summary.log_skipped {
+                            0 => String::new(),
+                            n => format!(" ({} already logged)", n),
+                        };
+                        confirm!(config, "Imported {} foods ({} already existed) and {} log entries{}",
+                            summary.foods_added, summary.foods_existing, summary.log_entries, skipped);
+                    }
+                }
                 "csv" => {
                     let p = path.ok_or_else(|| anyhow::anyhow!("--path required for csv import"))?;
                     db.import_csv(&p)?;
@@ -195,24 +1034,141 @@ fn main() -> Result<()> {
                 _ => anyhow::bail!("Unknown source: {}", source),
             }
         }
-        Some(Commands::Edit { name, protein, fat, carbs, per }) => {
-            db.edit_food(&name, protein.clone(), fat.clone(), carbs.clone(), per.as_deref())?;
-            let food = db.search_food(&name)?;
-            if let Some(f) = food {
-                println!("Updated: {} ({}p/{}f/{}c per {})", f.name, f.protein, f.fat, f.carbs, f.serving);
+        Some(Commands::Plan { action: PlanAction::Import { path, dry_run } }) => {
+            let text = std::fs::read_to_string(&path)
+                .map_err(|e| anyhow::anyhow!("Could not read {}: {}", path, e))?;
+            let rows = plan::parse_plan(&text, chrono::Local::now().date_naive(), &config.default_serving)?;
+            let summary = plan::import_plan(&db, config, &rows, dry_run)?;
+
+            if cli.json {
+                println!("{}", serde_json::to_string_pretty(&summary)?);
+            } else {
+                let macros = output::MacroFormat::for_stdout(config);
+                for entry in &summary.entries {
+                    println!("{}", logging::history_line(entry, &entry.date, &display(&entry.food_name), None, &macros));
+                }
+                for note in &summary.notes {
+                    eprintln!("Note: {}", note);
+                }
+                let created = match summary.foods_created.len() {
+                    0 => String::new(),
+                    _ => format!(" (new foods: {})", summary.foods_created.join(", ")),
+                };
+                if dry_run {
+                    println!("Would log {} entries{}", summary.entries.len(), created);
+                } else {
+                    confirm!(config, "Logged {} entries{}", summary.entries.len(), created);
+                }
+            }
+        }
+        Some(Commands::Dump { output }) => {
+            let dump = serde_json::to_string_pretty(&db.dump()?)?;
+            match output {
+                Some(path) => {
+                    std::fs::write(&path, dump)?;
+                    confirm!(config, "Wrote {}", path);
+                }
+                None => println!("{}", dump),
+            }
+        }
+        Some(Commands::Load { path }) => {
+            let dump: db::Dump = serde_json::from_str(&std::fs::read_to_string(&path)?)?;
+            db.load(&dump)?;
+            confirm!(config, "Loaded {} foods and {} log entries", dump.foods.len(), dump.log.len());
+        }
+        Some(Commands::Edit { all: true, matching, scale, yes, dry_run, .. }) => {
+            anyhow::ensure!(yes || dry_run,
+                "edit --all changes many foods at once; pass --dry-run to preview or --yes to apply");
+            let factor = scale.expect("clap requires --scale with --all");
+            if !dry_run {
+                backup_before(&db, config, "edit-all")?;
+            }
+            let scaled = db.scale_foods(matching.as_deref(), factor, dry_run)?;
+
+            if cli.json {
+                println!("{}", serde_json::to_string_pretty(&scaled)?);
+            } else {
+                let macros = output::MacroFormat::for_stdout(config).precise();
+                for f in &scaled {
+                    println!("{}: {}, {} per {}", display(&f.name), macros.compact(f.protein, f.fat, f.carbs),
+                        macros.kcal(f.protein, f.fat, f.carbs, f.calories), f.serving);
+                }
+                let verb = if dry_run { "Would scale" } else { "Scaled" };
+                println!("{} {} foods by {}", verb, scaled.len(), food::format_number(factor));
+            }
+        }
+        Some(Commands::Edit { name, protein, fat, carbs, calories, fiber, per, add_alias, remove_alias, .. }) => {
+            let name = name.expect("clap requires a name without --all");
+            // By its real name, which still works after removing the alias it was given by
+            let name = db.get_food_by_name(&name)?
+                .ok_or_else(|| anyhow::anyhow!("Food not found: '{}'", name))?
+                .name;
+            let changes_macros = [protein, fat, carbs, calories, fiber].iter().any(Option::is_some) || per.is_some();
+            if !changes_macros && add_alias.is_empty() && remove_alias.is_empty() {
+                eprintln!("Nothing changed. Pass --protein, --fat, --carbs, --calories, --fiber, --per, --add-alias, or --remove-alias.");
+            }
+            // Aliases first: a conflict there stops the edit before anything changes
+            let aliases = if add_alias.is_empty() && remove_alias.is_empty() {
+                None
+            } else {
+                Some(db.edit_aliases(&name, &add_alias, &remove_alias)?)
+            };
+            db.edit_food(&name, protein, fat, carbs, calories, fiber, per.as_deref())?;
+            let mut f = db.search_food(&name)?
+                .ok_or_else(|| anyhow::anyhow!("Food not found: '{}'", name))?;
+            if cli.json {
+                f.aliases = db.all_aliases()?.into_iter()
+                    .filter(|(id, _)| f.id == Some(*id))
+                    .map(|(_, alias)| alias)
+                    .collect();
+                println!("{}", serde_json::to_string_pretty(&f)?);
+            } else {
+                if changes_macros {
+                    confirm!(config, "Updated: {} ({}p/{}f/{}c, {} kcal per {})",
+                        display(&f.name), f.protein, f.fat, f.carbs, food::format_number(f.calories), f.serving);
+                }
+                if let Some(aliases) = aliases {
+                    confirm!(config, "Aliases: {}", if aliases.is_empty() { "(none)".to_string() } else { aliases.join(", ") });
+                }
             }
         }
         Some(Commands::Delete { name }) => {
+            backup_before(&db, config, "delete")?;
             db.delete_food(&name)?;
-            println!("Deleted: {}", name);
+            confirm!(config, "Deleted: {}", name);
+        }
+        Some(Commands::Merge { from, into }) => {
+            backup_before(&db, config, "merge")?;
+            let summary = db.merge_foods(&from, &into)?;
+            if cli.json {
+                println!("{}", serde_json::to_string_pretty(&summary)?);
+            } else {
+                confirm!(config, "Merged {} into {}: {} log {} moved",
+                    display(&summary.from), display(&summary.into), summary.log_entries,
+                    if summary.log_entries == 1 { "entry" } else { "entries" });
+                if summary.recipe_items > 0 {
+                    confirm!(config, "Recipe ingredients moved: {}", summary.recipe_items);
+                }
+                // Quoted, since names like "chicken, breast" have commas of their own
+                let quoted = |aliases: &[String]| {
+                    aliases.iter().map(|a| format!("'{}'", a)).collect::<Vec<_>>().join(", ")
+                };
+                if !summary.aliases_moved.is_empty() {
+                    confirm!(config, "Aliases added: {}", quoted(&summary.aliases_moved));
+                }
+                if !summary.aliases_dropped.is_empty() {
+                    confirm!(config, "Aliases dropped (already taken): {}", quoted(&summary.aliases_dropped));
+                }
+            }
         }
         Some(Commands::Unlog { id }) => {
             let entry = db.delete_log_entry(id)?;
             if cli.json {
                 println!("{}", serde_json::to_string_pretty(&entry)?);
             } else {
-                println!("Deleted log entry: {} {} — {:.0}p/{:.0}f/{:.0}c",
-                    entry.amount, entry.food_name, entry.protein, entry.fat, entry.carbs);
+                let macros = output::MacroFormat::new(config, config.confirmations.shows_color());
+                confirm!(config, "Deleted log entry: {} {} — {}",
+                    entry.amount, display(&entry.food_name), macros.compact(entry.protein, entry.fat, entry.carbs));
             }
         }
         Some(Commands::UnlogLast) => {
@@ -220,54 +1176,803 @@ fn main() -> Result<()> {
             if cli.json {
                 println!("{}", serde_json::to_string_pretty(&entry)?);
             } else {
-                println!("Deleted last log entry: {} {} — {:.0}p/{:.0}f/{:.0}c",
-                    entry.amount, entry.food_name, entry.protein, entry.fat, entry.carbs);
+                let macros = output::MacroFormat::new(config, config.confirmations.shows_color());
+                confirm!(config, "Deleted last log entry: {} {} — {}",
+                    entry.amount, display(&entry.food_name), macros.compact(entry.protein, entry.fat, entry.carbs));
             }
         }
-        Some(Commands::EditLog { id, amount, protein, fat, carbs }) => {
-            let entry = db.edit_log_entry(id, amount, protein, fat, carbs)?;
+        Some(Commands::Last) => {
+            let entry = db.last_log_entry()?;
             if cli.json {
                 println!("{}", serde_json::to_string_pretty(&entry)?);
+            } else if let Some(entry) = entry {
+                let id = entry.id.map(|id| format!("#{} | ", id)).unwrap_or_default();
+                let macros = output::MacroFormat::for_stdout(config);
+                println!("{}{}", id, logging::history_line(&entry, &entry.date, &display(&entry.food_name), None, &macros));
+            } else {
+                println!("Nothing logged yet.");
+            }
+        }
+        Some(Commands::EditLog { id, amount, protein, fat, carbs, calories, fiber }) => {
+            let entry = logging::edit_log_entry(&db, config, id, amount.as_deref(), protein, fat, carbs, calories, fiber)?;
+            if cli.json {
+                println!("{}", serde_json::to_string_pretty(&entry)?);
+            } else {
+                let macros = output::MacroFormat::new(config, config.confirmations.shows_color());
+                confirm!(config, "Updated log entry: {} {} — {}",
+                    entry.amount, display(&entry.food_name), macros.compact(entry.protein, entry.fat, entry.carbs));
+            }
+        }
+        Some(Commands::Doctor { target: DoctorTarget::Foods { fix } }) => {
+            let problems = doctor::diagnose_foods(&db)?;
+            if fix && !problems.is_empty() {
+                backup_before(&db, config, "doctor-fix")?;
+            }
+            let fixed = if fix { doctor::fix_foods(&db, &problems)? } else { vec![] };
+
+            if cli.json {
+                println!("{}", serde_json::to_string_pretty(&serde_json::json!({
+                    "problems": problems,
+                    "fixed": fixed,
+                }))?);
+            } else if problems.is_empty() {
+                println!("No problems found");
+            } else {
+                for problem in &problems {
+                    println!("{}: {} — {}", problem.food, problem.issue, problem.suggestion);
+                }
+                if fix {
+                    println!("Fixed {} of {} problems", fixed.len(), problems.len());
+                } else if problems.iter().any(|p| p.issue.is_fixable()) {
+                    println!("Run with --fix to recompute calories");
+                }
+            }
+        }
+        Some(Commands::Doctor { target: DoctorTarget::Log { dedupe } }) => {
+            let duplicates = doctor::diagnose_log(&db)?;
+            if dedupe && !duplicates.is_empty() {
+                backup_before(&db, config, "dedupe")?;
+            }
+            let deleted = if dedupe { db.dedupe_log_entries()? } else { vec![] };
+
+            if cli.json {
+                println!("{}", serde_json::to_string_pretty(&serde_json::json!({
+                    "duplicates": duplicates,
+                    "deleted": deleted,
+                }))?);
+            } else if duplicates.is_empty() {
+                println!("No duplicate entries found");
+            } else {
+                for group in &duplicates {
+                    let extras: Vec<String> = group.extras.iter()
+                        .map(|e| format!("#{}", e.id.unwrap_or_default()))
+                        .collect();
+                    println!("{} {} {}: #{} logged again as {}",
+                        group.keep.date, group.keep.amount, display(&group.keep.food_name),
+                        group.keep.id.unwrap_or_default(), extras.join(", "));
+                }
+                if dedupe {
+                    confirm!(config, "Deleted {} duplicate entries", deleted.len());
+                } else {
+                    println!("Run with --dedupe to delete the repeats, keeping the first of each");
+                }
+            }
+        }
+        Some(Commands::Normalize) => {
+            backup_before(&db, config, "normalize")?;
+            let normalized = db.normalize_foods()?;
+            if cli.json {
+                println!("{}", serde_json::to_string_pretty(&normalized)?);
+            } else if normalized.is_empty() {
+                println!("No foods to normalize");
+            } else {
+                let macros = output::MacroFormat::new(config, config.confirmations.shows_color());
+                for food in &normalized {
+                    confirm!(config, "Normalized: {} ({} per 100g, default {})",
+                        display(&food.name), macros.compact(food.protein, food.fat, food.carbs),
+                        food.default_amount.as_deref().unwrap_or("100g"));
+                }
+            }
+        }
+        Some(Commands::Goals { action: None }) => {
+            let goals = db.get_goals()?;
+            let overrides = db.get_goal_overrides()?;
+            if cli.json {
+                println!("{}", serde_json::to_string_pretty(&goals_json(goals.as_ref(), &overrides))?);
+            } else if goals.is_some() || !overrides.is_empty() {
+                let show = |label: &str, value: Option<f64>, unit: &str| {
+                    if let Some(v) = value {
+                        println!("{}: {:.0}{}", label, v, unit);
+                    }
+                };
+                if let Some(g) = goals {
+                    show("Protein", g.protein, "g");
+                    show("Fat", g.fat, "g");
+                    show("Carbs", g.carbs, "g");
+                    show("Calories", g.calories, " kcal");
+                    show("Weekly calories", g.weekly_calories, " kcal");
+                }
+                for day in &overrides {
+                    println!("{}: {}", day.weekday, describe_goals(&day.goals));
+                }
+            } else {
+                println!("No goals set. Set them with: chomp goals set --protein 180 --calories 2200");
+            }
+        }
+        Some(Commands::Goals { action: Some(GoalsAction::Set { protein, fat, carbs, calories, weekly_calories, day }) }) => {
+            let goals = db::Goals { protein, fat, carbs, calories, weekly_calories };
+            for value in [protein, fat, carbs, calories, weekly_calories].into_iter().flatten() {
+                anyhow::ensure!(value >= 0.0 && value.is_finite(), "Goals must be non-negative numbers");
+            }
+            if day.is_empty() {
+                db.set_goals(&goals)?;
+            } else {
+                db.set_goal_overrides(&day, &goals)?;
+            }
+            if cli.json {
+                println!("{}", serde_json::to_string_pretty(&goals)?);
+            } else {
+                confirm!(config, "Goals updated");
+            }
+        }
+        Some(Commands::Goals { action: Some(GoalsAction::Clear { day }) }) => {
+            if day.is_empty() {
+                db.clear_goals(chrono::Local::now().date_naive())?;
+            } else {
+                db.clear_goal_overrides(&day)?;
+            }
+            if cli.json {
+                println!("{}", serde_json::json!({ "cleared": true }));
+            } else {
+                confirm!(config, "Goals cleared");
+            }
+        }
+        Some(Commands::Goals { action: Some(GoalsAction::History) }) => {
+            let history = db.goal_history()?;
+            if cli.json {
+                println!("{}", serde_json::to_string_pretty(&history)?);
+            } else if history.is_empty() {
+                println!("No goals set yet.");
+            } else {
+                for change in &history {
+                    let goals = if change.cleared { "cleared".to_string() } else { describe_goals(&change.goals) };
+                    println!("{}  {}", change.effective_date, goals);
+                }
+            }
+        }
+        Some(Commands::Audit { days }) => {
+            let entries = db.audit_entries(days)?;
+            if cli.json {
+                println!("{}", serde_json::to_string_pretty(&entries)?);
+            } else if entries.is_empty() && !config.audit_log {
+                println!("The audit log is off. Turn it on with: chomp config set audit_log true");
+            } else {
+                for entry in &entries {
+                    println!("{}  {}  {}", entry.at, entry.action, entry.payload);
+                }
+            }
+        }
+        Some(Commands::Info) => {
+            let info = db.info(config.db_path()?, config::Config::path()?)?;
+            if cli.json {
+                println!("{}", serde_json::to_string_pretty(&info)?);
             } else {
-                println!("Updated log entry: {} {} — {:.0}p/{:.0}f/{:.0}c",
-                    entry.amount, entry.food_name, entry.protein, entry.fat, entry.carbs);
+                println!("chomp {}", info.version);
+                println!("Database: {}", info.db_path.display());
+                println!("Schema version: {}", info.schema_version);
+                println!("Foods: {}", info.food_count);
+                println!("Log entries: {}", info.log_count);
+                println!("Config: {}", info.config_path.display());
             }
         }
-        Some(Commands::Stats) => {
+        Some(Commands::Stats { format }) => {
             let stats = db.get_stats()?;
-            println!("Foods: {}", stats.food_count);
-            println!("Log entries: {}", stats.log_count);
-            println!("First entry: {}", stats.first_entry.unwrap_or_default());
-            println!("Last entry: {}", stats.last_entry.unwrap_or_default());
+            let format = OutputFormat::resolve(format, cli.json);
+            if format == OutputFormat::Json {
+                println!("{}", serde_json::to_string_pretty(&stats)?);
+            } else if format != OutputFormat::Plain {
+                let mut table = Table::new(&["stat", "value"]);
+                let mut row = |name: &str, value: String| table.push(vec![name.to_string(), value]);
+                row("foods", stats.food_count.to_string());
+                row("log_entries", stats.log_count.to_string());
+                row("first_entry", stats.first_entry.unwrap_or_default());
+                row("last_entry", stats.last_entry.unwrap_or_default());
+                row("days_logged", stats.days_logged.to_string());
+                row("adherence", stats.adherence.map(|a| number_cell(a, format)).unwrap_or_default());
+                print_table(&table, format);
+            } else {
+                println!("Foods: {}", stats.food_count);
+                println!("Log entries: {}", stats.log_count);
+                println!("First entry: {}", stats.first_entry.unwrap_or_default());
+                println!("Last entry: {}", stats.last_entry.unwrap_or_default());
+                println!("Days logged: {}", stats.days_logged);
+                if let Some(adherence) = stats.adherence {
+                    println!("Adherence: {:.0}%", adherence);
+                }
+            }
         }
-        Some(Commands::Serve) => {
-            mcp::serve()?;
+        Some(Commands::Report { days, format }) => {
+            let to = chrono::Local::now().date_naive();
+            let from = db::history_start(to, days).unwrap_or(chrono::NaiveDate::MIN);
+            let report = report::build_report(&db, from, to)?;
+            print_report(&report, config, OutputFormat::resolve(format, cli.json))?;
+        }
+        Some(Commands::Top { nutrient, limit, per100, format }) => {
+            let foods = db.top_foods(nutrient, per100, limit as usize)?;
+            let format = OutputFormat::resolve(format, cli.json);
+            if format == OutputFormat::Json {
+                println!("{}", serde_json::to_string_pretty(&foods)?);
+            } else if format != OutputFormat::Plain {
+                let mut table = Table::new(&["rank", "name", "protein", "fat", "carbs", "calories", "serving"]);
+                for (i, food) in foods.iter().enumerate() {
+                    table.push(vec![
+                        (i + 1).to_string(),
+                        display(&food.name),
+                        number_cell(food.protein, format),
+                        number_cell(food.fat, format),
+                        number_cell(food.carbs, format),
+                        number_cell(food.calories, format),
+                        food.serving.clone(),
+                    ]);
+                }
+                print_table(&table, format);
+            } else if foods.is_empty() {
+                println!("No foods to rank.");
+            } else {
+                let unit = if nutrient == food::Nutrient::Calories { " kcal" } else { "g" };
+                for (i, food) in foods.iter().enumerate() {
+                    println!("{:>2}. {}: {:.1}{} {} per {}",
+                        i + 1, display(&food.name), nutrient.of(food), unit, nutrient, food.serving);
+                }
+            }
+        }
+        Some(Commands::Serve { print_schema: true }) => {
+            println!("{}", mcp::tools_schema(config)?);
+        }
+        Some(Commands::Serve { print_schema: false }) => {
+            mcp::serve(config)?;
+        }
+        Some(Commands::Config { action }) => {
+            run_config(action.as_ref(), cli.json)?;
         }
         None => {
             // Default action: log food
             if cli.food.is_empty() {
                 // No args, show today's totals
-                let totals = db.get_today_totals()?;
-                if cli.json {
-                    println!("{}", serde_json::to_string_pretty(&totals)?);
-                } else {
-                    println!("Today: {:.0}p / {:.0}f / {:.0}c — {:.0} kcal",
-                        totals.protein, totals.fat, totals.carbs, totals.calories);
-                }
+                print_today(&db, config, OutputFormat::resolve(None, cli.json), false)?;
             } else {
                 // Log the food
                 let input = cli.food.join(" ");
-                let entry = logging::parse_and_log(&db, &input)?;
-                
-                if cli.json {
-                    println!("{}", serde_json::to_string_pretty(&entry)?);
-                } else {
-                    println!("Logged: {} {} — {:.0}p/{:.0}f/{:.0}c",
-                        entry.amount, entry.food_name, entry.protein, entry.fat, entry.carbs);
+                let (meta, warning) = dated_log_meta(config, cli.time.as_deref(), cli.meal, cli.date, cli.future)?;
+                let logged = with_warning(log_input(&db, config, &input, &meta, cli.json, cli.suggestions)?, warning);
+                print_logged(&db, config, logged, cli.json, cli.show_today)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Copy the database at `db_path` to a new file at `path`. An existing file is
+/// only replaced with `force`, and never when it's the database itself.
+fn export_sqlite(db: &db::Database, db_path: &std::path::Path, path: &std::path::Path, force: bool) -> Result<()> {
+    if path.exists() {
+        let same = std::fs::canonicalize(path).ok() == std::fs::canonicalize(db_path).ok();
+        anyhow::ensure!(!same, "{} is the database itself; export somewhere else", path.display());
+        anyhow::ensure!(force, "{} already exists; pass --force to replace it", path.display());
+        std::fs::remove_file(path)?;
+    }
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        std::fs::create_dir_all(parent)?;
+    }
+    db.backup_to(path)
+}
+
+/// When to log from `--time`, `--meal`, and `--date`, checked against `future_dates`
+/// (or allowed by `--future`). Returns the warning to show, if any.
+fn dated_log_meta(
+    config: &config::Config,
+    time: Option<&str>,
+    meal: Option<db::Meal>,
+    date: Option<chrono::NaiveDate>,
+    future: bool,
+) -> Result<(db::LogMeta, Option<String>)> {
+    let now = chrono::Local::now().naive_local();
+    // Another day at the current time of day, so --time and --meal still apply
+    let at = date.map_or(now, |date| date.and_time(now.time()));
+    let meta = logging::log_meta(time, meal, at)?;
+    let policy = if future { config::FutureDates::Allow } else { config.future_dates };
+    let warning = logging::check_log_date(meta.date, now.date(), policy)?;
+    Ok((meta, warning))
+}
+
+/// `logged` with `warning` added to its first entry, ahead of any it already has
+fn with_warning(mut logged: Vec<logging::LogConfirmation>, warning: Option<String>) -> Vec<logging::LogConfirmation> {
+    if let (Some(warning), Some(first)) = (warning, logged.first_mut()) {
+        first.warning = Some(match first.warning.take() {
+            Some(other) => format!("{}; {}", warning, other),
+            None => warning,
+        });
+    }
+    logged
+}
+
+/// Log `input`. An unknown food's error suggests close matches, except under --json,
+/// where they're left out unless --suggestions asks for them as JSON on stdout.
+fn log_input(
+    db: &db::Database,
+    config: &config::Config,
+    input: &str,
+    meta: &db::LogMeta,
+    json: bool,
+    suggestions: bool,
+) -> Result<Vec<logging::LogConfirmation>> {
+    // With quantity_words = "confirm", check a guessed amount if there's someone to ask
+    let check_guess = |resolution: &logging::Resolution| {
+        use std::io::IsTerminal;
+        if config.quantity_words != config::QuantityWords::Confirm || !std::io::stdin().is_terminal() {
+            return Ok(true);
+        }
+        confirm(&format!("Log {} {} for '{}'?", resolution.amount, resolution.food.name, resolution.input))
+    };
+    logging::parse_and_log_confirmed(db, config, input, meta, check_guess).map_err(|err| {
+        if !json {
+            return err;
+        }
+        match err.downcast::<logging::FoodNotFound>() {
+            Ok(not_found) if suggestions => {
+                let value = serde_json::json!({
+                    "error": format!("Food not found: '{}'", not_found.name),
+                    "suggestions": not_found.suggestions,
+                });
+                println!("{}", serde_json::to_string_pretty(&value).unwrap_or_default());
+                not_found.into()
+            }
+            Ok(not_found) => logging::FoodNotFound { suggestions: vec![], ..not_found }.into(),
+            Err(err) => err,
+        }
+    })
+}
+
+fn print_logged(db: &db::Database, config: &config::Config, logged: Vec<logging::LogConfirmation>, json: bool, show_today: bool) -> Result<()> {
+    let today = match logged.last() {
+        Some(last) if show_today || config.show_today_after_log => Some(logging::day_totals(db, &last.entry)?),
+        _ => None,
+    };
+    let macros = output::MacroFormat::new(config, config.confirmations.shows_color());
+    write_logged(config, logged, today, json, &macros, &mut std::io::stdout().lock(), &mut std::io::stderr().lock())
+}
+
+/// `print_logged` to the given stdout and stderr, with the day's totals if they're to be shown
+fn write_logged(
+    config: &config::Config,
+    logged: Vec<logging::LogConfirmation>,
+    today: Option<food::Macros>,
+    json: bool,
+    macros: &output::MacroFormat,
+    out: &mut dyn std::io::Write,
+    err: &mut dyn std::io::Write,
+) -> Result<()> {
+    if json {
+        writeln!(out, "{}", serde_json::to_string_pretty(&logging::logged_json(logged, today)?)?)?;
+        return Ok(());
+    }
+    let stream = config.confirmations;
+    for confirmation in &logged {
+        if let Some(note) = &confirmation.note {
+            writeln!(err, "Note: {}", note)?;
+        }
+        if let Some(warning) = &confirmation.warning {
+            writeln!(err, "Warning: {}", warning)?;
+        }
+        let entry = &confirmation.entry;
+        stream.write_line(out, err, &format!("Logged: {} {} — {}",
+            entry.amount, config.name_case.apply(&entry.food_name), macros.compact(entry.protein, entry.fat, entry.carbs)))?;
+    }
+    if logged.len() > 1 {
+        let total = logging::combo_total(&logged);
+        stream.write_line(out, err, &format!("Total: {} — {}",
+            macros.compact(total.protein, total.fat, total.carbs), macros.kcal(total.protein, total.fat, total.carbs, total.calories)))?;
+    }
+    if let Some(today) = &today {
+        stream.write_line(out, err, &format!("Today now: {} — {}",
+            macros.compact(today.protein, today.fat, today.carbs), macros.kcal(today.protein, today.fat, today.carbs, today.calories)))?;
+    }
+    Ok(())
+}
+
+/// A number for a table cell: rounded for display, full precision for CSV
+fn number_cell(value: f64, format: OutputFormat) -> String {
+    match format {
+        OutputFormat::Csv => value.to_string(),
+        _ => format!("{:.0}", value),
+    }
+}
+
+fn print_table(table: &Table, format: OutputFormat) {
+    match format {
+        OutputFormat::Csv => println!("{}", table.to_csv()),
+        _ => println!("{}", table.render()),
+    }
+}
+
+/// With `auto_backup_before_destructive` on, back the database up before `operation`
+fn backup_before(db: &db::Database, config: &config::Config, operation: &str) -> Result<()> {
+    if config.auto_backup_before_destructive {
+        let path = db.backup_rotated(&config.backup_dir()?, operation, config.backups_kept)?;
+        eprintln!("Backed up to {}", path.display());
+    }
+    Ok(())
+}
+
+/// `goals --json`, one shape whatever is set: `{"default": goals or null, "days": {"Mon": goals, ...}}`
+fn goals_json(goals: Option<&db::Goals>, overrides: &[db::DayGoals]) -> serde_json::Value {
+    let days: serde_json::Map<String, serde_json::Value> = overrides.iter()
+        .map(|day| (day.weekday.to_string(), serde_json::json!(day.goals)))
+        .collect();
+    serde_json::json!({ "default": goals, "days": days })
+}
+
+/// Goals as one line: "protein 180g, calories 2200 kcal"
+fn describe_goals(goals: &db::Goals) -> String {
+    let parts: Vec<String> = [
+        ("protein", goals.protein, "g"),
+        ("fat", goals.fat, "g"),
+        ("carbs", goals.carbs, "g"),
+        ("calories", goals.calories, " kcal"),
+        ("weekly calories", goals.weekly_calories, " kcal"),
+    ]
+    .iter()
+    .filter_map(|(label, value, unit)| value.map(|v| format!("{} {:.0}{}", label, v, unit)))
+    .collect();
+    if parts.is_empty() {
+        "no targets".to_string()
+    } else {
+        parts.join(", ")
+    }
+}
+
+/// Clap parser for date flags, relative to today (see `logging::parse_date`)
+fn parse_date_arg(input: &str) -> Result<chrono::NaiveDate, String> {
+    logging::parse_date(input, chrono::Local::now().date_naive()).map_err(|e| e.to_string())
+}
+
+/// Ask a yes/no question on the terminal. Without a terminal there's nobody to
+/// answer, so fail and point at `--yes` rather than guessing.
+fn confirm(prompt: &str) -> Result<bool> {
+    use std::io::{IsTerminal, Write};
+
+    let stdin = std::io::stdin();
+    if !stdin.is_terminal() {
+        anyhow::bail!("{} Pass --yes to confirm non-interactively.", prompt);
+    }
+    eprint!("{} [y/N] ", prompt);
+    std::io::stderr().flush()?;
+    let mut answer = String::new();
+    stdin.read_line(&mut answer)?;
+    Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+}
+
+fn print_report(report: &report::Report, config: &config::Config, format: OutputFormat) -> Result<()> {
+    let day = |day: &Option<db::DayTotals>| {
+        day.as_ref().map(|d| format!("{} ({:.0} kcal)", d.date, d.totals.calories)).unwrap_or_default()
+    };
+    let most_logged = report.most_logged_food.as_ref()
+        .map(|f| format!("{} ({}×)", config.name_case.apply(&f.food), f.times))
+        .unwrap_or_default();
+    match format {
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(report)?),
+        OutputFormat::Csv | OutputFormat::Table => {
+            let mut table = Table::new(&["stat", "value"]);
+            let mut row = |name: &str, value: String| table.push(vec![name.to_string(), value]);
+            row("from", report.from.clone());
+            row("to", report.to.clone());
+            row("days_logged", report.days_logged.to_string());
+            row("avg_protein", number_cell(report.average.protein, format));
+            row("avg_fat", number_cell(report.average.fat, format));
+            row("avg_carbs", number_cell(report.average.carbs, format));
+            row("avg_calories", number_cell(report.average.calories, format));
+            row("protein_goal_hit", report.protein_goal_hit.map(|p| number_cell(p, format)).unwrap_or_default());
+            row("highest_calorie_day", day(&report.highest_calorie_day));
+            row("lowest_calorie_day", day(&report.lowest_calorie_day));
+            row("most_logged_food", most_logged);
+            print_table(&table, format);
+        }
+        OutputFormat::Plain => {
+            println!("Report {} to {}: {} logged", report.from, report.to,
+                if report.days_logged == 1 { "1 day".to_string() } else { format!("{} days", report.days_logged) });
+            if report.days_logged == 0 {
+                return Ok(());
+            }
+            let avg = &report.average;
+            let macros = output::MacroFormat::for_stdout(config);
+            println!("Daily average: {} — {}", macros.spaced(avg.protein, avg.fat, avg.carbs),
+                macros.kcal(avg.protein, avg.fat, avg.carbs, avg.calories));
+            if let Some(hit) = report.protein_goal_hit {
+                println!("Protein goal met: {:.0}% of days", hit);
+            }
+            println!("Highest: {}", day(&report.highest_calorie_day));
+            println!("Lowest: {}", day(&report.lowest_calorie_day));
+            if !most_logged.is_empty() {
+                println!("Most logged: {}", most_logged);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// `today --meal`: just that meal's totals, since goals are for whole days
+fn print_meal_today(db: &db::Database, config: &config::Config, meal: db::Meal, format: OutputFormat) -> Result<()> {
+    let totals = db.get_totals_for_date(chrono::Local::now().date_naive(), Some(meal))?;
+    match format {
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&totals)?),
+        OutputFormat::Csv | OutputFormat::Table => {
+            let mut table = Table::new(&["macro", "eaten"]);
+            for (name, eaten) in [("protein", totals.protein), ("fat", totals.fat), ("carbs", totals.carbs), ("calories", totals.calories)] {
+                table.push(vec![name.to_string(), number_cell(eaten, format)]);
+            }
+            print_table(&table, format);
+        }
+        OutputFormat::Plain => {
+            let macros = output::MacroFormat::for_stdout(config);
+            println!("Today ({}): {} — {}", meal, macros.spaced(totals.protein, totals.fat, totals.carbs),
+                macros.kcal(totals.protein, totals.fat, totals.carbs, totals.calories));
+        }
+    }
+    Ok(())
+}
+
+fn print_today(db: &db::Database, config: &config::Config, format: OutputFormat, check_protein: bool) -> Result<()> {
+    let today = chrono::Local::now().date_naive();
+    let mut progress = goals::day_progress(db, config, today)?;
+    if check_protein {
+        progress.protein_check = goals::protein_check(db, today)?;
+        anyhow::ensure!(progress.protein_check.is_some(),
+            "No protein goal set. Set one with: chomp goals set --protein 150");
+    }
+    match format {
+        OutputFormat::Json => {
+            println!("{}", serde_json::to_string_pretty(&progress)?);
+            return Ok(());
+        }
+        OutputFormat::Csv | OutputFormat::Table => {
+            let goals = progress.goals.clone().unwrap_or_default();
+            let mut table = Table::new(&["macro", "eaten", "goal", "left"]);
+            let totals = &progress.totals;
+            for (name, eaten, goal) in [
+                ("protein", totals.protein, goals.protein),
+                ("fat", totals.fat, goals.fat),
+                ("carbs", totals.carbs, goals.carbs),
+                ("calories", totals.calories, goals.calories),
+            ] {
+                table.push(vec![
+                    name.to_string(),
+                    number_cell(eaten, format),
+                    goal.map(|g| number_cell(g, format)).unwrap_or_default(),
+                    goal.map(|g| number_cell(g - eaten, format)).unwrap_or_default(),
+                ]);
+            }
+            if totals.fiber > 0.0 {
+                for (name, eaten) in [("fiber", totals.fiber), ("net_carbs", progress.net_carbs)] {
+                    table.push(vec![name.to_string(), number_cell(eaten, format), String::new(), String::new()]);
                 }
             }
+            if let Some(week) = &progress.weekly {
+                for (name, eaten, goal, left) in [
+                    ("week_calories", week.used, week.weekly_calories, week.remaining),
+                    ("today_share", totals.calories, week.today_target, week.today_remaining),
+                ] {
+                    table.push(vec![name.to_string(), number_cell(eaten, format), number_cell(goal, format), number_cell(left, format)]);
+                }
+            }
+            if let Some(s) = progress.protein_check.as_ref().and_then(|c| c.suggestion.as_ref()) {
+                let food = format!("{} {} (+{:.0} kcal)", s.amount, config.name_case.apply(&s.food), s.calories);
+                table.push(vec!["protein_suggestion".to_string(), food, String::new(), String::new()]);
+            }
+            print_table(&table, format);
+            return Ok(());
         }
+        OutputFormat::Plain => {}
     }
 
+    let totals = &progress.totals;
+    let macros = output::MacroFormat::for_stdout(config);
+    println!("Today: {} — {}", macros.spaced(totals.protein, totals.fat, totals.carbs),
+        macros.kcal(totals.protein, totals.fat, totals.carbs, totals.calories));
+    if totals.fiber > 0.0 {
+        println!("Net carbs: {:.0}g ({:.0}g fiber)", progress.net_carbs, totals.fiber);
+    }
+
+    if let Some(goals) = &progress.goals {
+        // Whole percent reached, rounded down so 100% means the goal is met
+        let parts: Vec<String> = [("protein", totals.protein, goals.protein), ("fat", totals.fat, goals.fat), ("carbs", totals.carbs, goals.carbs)]
+            .iter()
+            .filter_map(|(name, eaten, goal)| {
+                let goal = (*goal)?;
+                let percent = if goal > 0.0 { format!(" ({:.0}%)", (eaten / goal * 100.0).floor()) } else { String::new() };
+                Some(format!("{} {:.0}/{:.0}g{}", name, eaten, goal, percent))
+            })
+            .collect();
+        if !parts.is_empty() {
+            println!("Goals: {}", parts.join(", "));
+        }
+    }
+    if let Some(goal) = progress.goals.as_ref().and_then(|g| g.calories) {
+        let eaten = macros.calories(totals.protein, totals.fat, totals.carbs, totals.calories);
+        println!("Daily: {:.0}/{:.0} kcal ({:.0} left)", eaten, goal, goal - eaten);
+    }
+    if let Some(split) = &progress.derived_goals {
+        let basis = match split.bodyweight_lb {
+            Some(weight) => format!("{} lb × {} g/lb protein", food::format_number(weight), food::format_number(config.protein_per_lb)),
+            None => "30% protein".to_string(),
+        };
+        println!("Suggested split (derived from {:.0} kcal, {}): {} — {} left",
+            split.calories, basis, macros.spaced(split.protein, split.fat, split.carbs),
+            macros.spaced(split.protein - totals.protein, split.fat - totals.fat, split.carbs - totals.carbs));
+    }
+    if let Some(week) = &progress.weekly {
+        println!("Week: {:.0}/{:.0} kcal ({:.0} left over {} days) — today's share {:.0} kcal ({:.0} left)",
+            week.used, week.weekly_calories, week.remaining, week.days_left,
+            week.today_target, week.today_remaining);
+    }
+    if let Some(check) = &progress.protein_check {
+        if check.deficit > 0.0 {
+            print!("Protein: {:.0}/{:.0}g — {:.0}g short", check.eaten, check.goal, check.deficit);
+            match &check.suggestion {
+                Some(s) => println!("; try {} {} (+{:.0} kcal)", s.amount, config.name_case.apply(&s.food), s.calories),
+                None => println!(),
+            }
+        } else {
+            println!("Protein: {:.0}/{:.0}g — goal met", check.eaten, check.goal);
+        }
+    }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_goals_json() {
+        let goals = db::Goals { protein: Some(180.0), calories: Some(2200.0), ..Default::default() };
+        assert_eq!(goals_json(Some(&goals), &[]), serde_json::json!({
+            "default": { "protein": 180.0, "calories": 2200.0 },
+            "days": {},
+        }));
+
+        let training = db::Goals { carbs: Some(300.0), ..Default::default() };
+        let overrides = [db::DayGoals { weekday: chrono::Weekday::Mon, goals: training }];
+        assert_eq!(goals_json(None, &overrides), serde_json::json!({
+            "default": null,
+            "days": { "Mon": { "carbs": 300.0 } },
+        }));
+    }
+
+    #[test]
+    fn test_history_range_flags() {
+        let cli = Cli::try_parse_from(["chomp", "history", "--since", "2024-06-01", "--until", "2024-06-30"]).unwrap();
+        assert!(matches!(cli.command, Some(Commands::History { since: Some(_), until: Some(_), .. })));
+        // --until combines with --days; --since replaces it
+        assert!(Cli::try_parse_from(["chomp", "history", "--until", "2024-06-30", "--days", "3"]).is_ok());
+        assert!(Cli::try_parse_from(["chomp", "history", "--since", "2024-06-01", "--days", "3"]).is_err());
+        assert!(Cli::try_parse_from(["chomp", "history", "--since", "June"]).is_err());
+        assert!(Cli::try_parse_from(["chomp", "history", "--since", "3 days ago", "--until", "yesterday"]).is_ok());
+        assert!(Cli::try_parse_from(["chomp", "log", "list", "--date", "monday"]).is_ok());
+        let cli = Cli::try_parse_from(["chomp", "--date", "yesterday", "salmon", "6oz"]).unwrap();
+        assert!(cli.date.is_some() && !cli.future);
+        assert_eq!(cli.food, vec!["salmon", "6oz"]);
+        assert!(Cli::try_parse_from(["chomp", "log", "salmon", "--date", "2099-01-01", "--future"]).is_ok());
+        assert!(Cli::try_parse_from(["chomp", "log", "salmon", "--future"]).is_err());
+        assert!(Cli::try_parse_from(["chomp", "export", "--format", "text", "--date", "today"]).is_ok());
+        // --limit works alone or with any window
+        assert!(Cli::try_parse_from(["chomp", "history", "--limit", "10", "--days", "3"]).is_ok());
+        assert!(Cli::try_parse_from(["chomp", "history", "-n", "10", "--since", "2024-06-01"]).is_ok());
+        assert!(Cli::try_parse_from(["chomp", "history", "--limit", "0"]).is_err());
+    }
+
+    #[test]
+    fn test_confirmations_to_stderr() {
+        let db = db::Database::open_in_memory().unwrap();
+        db.add_food(&food::Food::new("oats", 13.0, 7.0, 68.0, 389.0, "100g", vec![])).unwrap();
+        let cli = Cli::try_parse_from(["chomp", "--confirm-stderr", "oats 50g"]).unwrap();
+        assert!(cli.confirm_stderr);
+
+        let mut config = config::Config::default();
+        let run = |config: &config::Config, json: bool| {
+            let logged = logging::parse_and_log(&db, config, "oats 50g").unwrap();
+            let (mut out, mut err) = (Vec::new(), Vec::new());
+            let macros = output::MacroFormat::new(config, false);
+            write_logged(config, logged, None, json, &macros, &mut out, &mut err).unwrap();
+            (String::from_utf8(out).unwrap(), String::from_utf8(err).unwrap())
+        };
+
+        let (out, err) = run(&config, false);
+        assert_eq!(out, "Logged: 50g oats — 6p/4f/34c\n");
+        assert!(err.is_empty());
+
+        config.confirmations = output::ConfirmStream::Stderr;
+        let (out, err) = run(&config, false);
+        assert!(out.is_empty());
+        assert_eq!(err, "Logged: 50g oats — 6p/4f/34c\n");
+
+        // Data stays on stdout
+        let (out, err) = run(&config, true);
+        assert!(out.contains("\"food_name\": \"oats\""), "{}", out);
+        assert!(err.is_empty());
+    }
+
+    #[test]
+    fn test_logged_with_protein_emphasized() {
+        let db = db::Database::open_in_memory().unwrap();
+        db.add_food(&food::Food::new("oats", 13.0, 7.0, 68.0, 389.0, "100g", vec![])).unwrap();
+        let config = config::Config { emphasize: config::Emphasize::Protein, ..Default::default() };
+        let logged = logging::parse_and_log(&db, &config, "oats 50g + oats 100g").unwrap();
+        let today = Some(db.get_today_totals().unwrap());
+        let (mut out, mut err) = (Vec::new(), Vec::new());
+        let bold = output::MacroFormat::new(&config, true);
+        write_logged(&config, logged, today, false, &bold, &mut out, &mut err).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "\
+Logged: 50g oats — \x1b[1m6p\x1b[0m/4f/34c
+Logged: 100g oats — \x1b[1m13p\x1b[0m/7f/68c
+Total: \x1b[1m20p\x1b[0m/10f/102c — 584 kcal
+Today now: \x1b[1m20p\x1b[0m/10f/102c — 584 kcal
+");
+        assert_eq!(output::MacroFormat { bold: false, ..bold }.spaced(19.5, 10.5, 102.0), "20p / 10f / 102c");
+    }
+
+    #[test]
+    fn test_export_sqlite_overwrite() {
+        let dir = db::TempDir::new("export-sqlite");
+        let (db_path, path) = (dir.join("foods.db"), dir.join("out").join("backup.db"));
+        let db = db::Database::open_at(&db_path).unwrap();
+        db.init().unwrap();
+        db.add_food(&food::Food::new("egg", 6.0, 5.0, 0.5, 72.0, "1 large", vec![])).unwrap();
+
+        export_sqlite(&db, &db_path, &path, false).unwrap();
+        let err = export_sqlite(&db, &db_path, &path, false).unwrap_err().to_string();
+        assert!(err.contains("already exists; pass --force"), "{}", err);
+        db.add_food(&food::Food::new("oats", 13.0, 7.0, 68.0, 389.0, "100g", vec![])).unwrap();
+        export_sqlite(&db, &db_path, &path, true).unwrap();
+        assert_eq!(db::Database::open_at(&path).unwrap().get_stats().unwrap().food_count, 2);
+        // Never over the live database, even with --force
+        assert!(export_sqlite(&db, &db_path, &db_path, true).unwrap_err().to_string().contains("database itself"));
+    }
+
+    #[test]
+    fn test_backup_before_destructive() {
+        let dir = db::TempDir::new("auto-backup");
+        let db_path = dir.join("foods.db");
+        let db = db::Database::open_at(&db_path).unwrap();
+        db.init().unwrap();
+        db.add_food(&food::Food::new("egg", 6.0, 5.0, 0.5, 72.0, "1 large", vec![])).unwrap();
+        let backups = || -> Vec<_> {
+            std::fs::read_dir(dir.join("backups")).map(|d| d.map(|e| e.unwrap().path()).collect()).unwrap_or_default()
+        };
+
+        // Off by default
+        let mut config = config::Config { db_path: Some(db_path.clone()), ..config::Config::default() };
+        backup_before(&db, &config, "delete").unwrap();
+        assert!(backups().is_empty());
+
+        config.auto_backup_before_destructive = true;
+        config.backups_kept = 2;
+        backup_before(&db, &config, "delete").unwrap();
+        db.delete_food("egg").unwrap();
+        let saved = backups();
+        assert_eq!(saved.len(), 1);
+        assert!(saved[0].to_string_lossy().ends_with("-delete.db"));
+        // The backup has what was deleted
+        assert!(db::Database::open_at(&saved[0]).unwrap().get_food_by_name("egg").unwrap().is_some());
+
+        // Only the newest backups_kept are kept
+        for _ in 0..3 {
+            std::thread::sleep(std::time::Duration::from_millis(5));
+            backup_before(&db, &config, "merge").unwrap();
+        }
+        let mut saved = backups();
+        saved.sort();
+        assert_eq!(saved.len(), 2);
+        assert!(saved.iter().all(|p| p.to_string_lossy().ends_with("-merge.db")));
+    }
+}
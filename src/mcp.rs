@@ -1,18 +1,49 @@
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
+use chrono::NaiveDate;
+use std::collections::HashMap;
 use std::io::{BufRead, Write};
 
-use crate::db::Database;
-use crate::food::Food;
-use crate::logging::parse_and_log;
+use crate::config::Config;
+use crate::db::{Database, Meal};
+use crate::food::{macros_for_new_food, Food, MacroKind, Macros};
+use crate::logging::{day_totals, logged_json, nutrition, parse_and_log};
+use crate::output::MacroFormat;
 
 const SERVER_NAME: &str = "chomp";
 const SERVER_VERSION: &str = env!("CARGO_PKG_VERSION");
 
+/// State kept for the life of one server process
+#[derive(Debug, Default)]
+struct Session {
+    /// Day totals for get_today, keyed by date, with the database's data version
+    /// when they were read so that writes from other chomp processes are noticed
+    totals: HashMap<NaiveDate, (i64, Macros)>,
+}
+
+impl Session {
+    /// Unfiltered totals for `date`, from the cache when nothing has been written since
+    fn totals_for_date(&mut self, db: &Database, date: NaiveDate) -> Result<Macros> {
+        let version = db.data_version()?;
+        if let Some((cached_version, totals)) = self.totals.get(&date) {
+            if *cached_version == version {
+                return Ok(totals.clone());
+            }
+        }
+        let totals = db.get_totals_for_date(date, None)?;
+        self.totals.insert(date, (version, totals.clone()));
+        Ok(totals)
+    }
+}
+
+/// Tools that only read. Any other tool drops the cached totals before it runs.
+const READ_ONLY_TOOLS: &[&str] = &[
+    "calculate_macros", "search_food", "parse_label", "get_today", "get_remaining", "get_history", "get_stats",
+];
+
 #[derive(Debug, Deserialize)]
 struct JsonRpcRequest {
-    jsonrpc: String,
     id: Option<Value>,
     method: String,
     #[serde(default)]
@@ -35,12 +66,24 @@ struct JsonRpcError {
     message: String,
 }
 
-pub fn serve() -> Result<()> {
-    let db = Database::open()?;
+/// Serve MCP on stdio. Everything comes from `config`, so a client that can only
+/// set environment variables (Claude Desktop's `env` block) can still pick the
+/// database (`CHOMP_DB_PATH`), make it read-only (`CHOMP_MCP_READ_ONLY`), and
+/// turn on a request log (`CHOMP_MCP_LOG_FILE`).
+pub fn serve(config: &Config) -> Result<()> {
+    let mut db = Database::open(&config.db_path()?)?;
     db.init()?;
+    db.set_audit(config.audit_log);
+
+    let mut log = match config.mcp_log_file() {
+        Some(path) => Some(std::fs::OpenOptions::new().create(true).append(true).open(&path)
+            .map_err(|e| anyhow::anyhow!("Could not open mcp_log_file {}: {}", path.display(), e))?),
+        None => None,
+    };
 
     let stdin = std::io::stdin();
     let mut stdout = std::io::stdout();
+    let mut session = Session::default();
 
     for line in stdin.lock().lines() {
         let line = line?;
@@ -66,7 +109,11 @@ pub fn serve() -> Result<()> {
             }
         };
 
-        let response = handle_request(&db, &request);
+        let response = handle_request(&db, config, &mut session, &request);
+        if let Some(log) = &mut log {
+            // A failed log write shouldn't take the server down
+            let _ = writeln!(log, "{}", log_line(&request, &response));
+        }
         writeln!(stdout, "{}", serde_json::to_string(&response)?)?;
         stdout.flush()?;
     }
@@ -74,13 +121,13 @@ pub fn serve() -> Result<()> {
     Ok(())
 }
 
-fn handle_request(db: &Database, request: &JsonRpcRequest) -> JsonRpcResponse {
+fn handle_request(db: &Database, config: &Config, session: &mut Session, request: &JsonRpcRequest) -> JsonRpcResponse {
     let id = request.id.clone().unwrap_or(Value::Null);
 
     let result = match request.method.as_str() {
         "initialize" => handle_initialize(),
-        "tools/list" => handle_tools_list(),
-        "tools/call" => handle_tools_call(db, &request.params),
+        "tools/list" => handle_tools_list(config),
+        "tools/call" => handle_tools_call(db, config, session, &request.params),
         "notifications/initialized" => return JsonRpcResponse {
             jsonrpc: "2.0".to_string(),
             id,
@@ -122,176 +169,679 @@ fn handle_initialize() -> Result<Value> {
     }))
 }
 
-fn handle_tools_list() -> Result<Value> {
-    Ok(json!({
-        "tools": [
-            {
-                "name": "log_food",
-                "description": "Log food consumption. Returns calculated macros.",
-                "inputSchema": {
-                    "type": "object",
-                    "properties": {
-                        "food": {
-                            "type": "string",
-                            "description": "Food name and optional amount, e.g. 'salmon 4oz' or 'bare bar'"
-                        }
+/// One line for `mcp_log_file`: when, the method (and tool), and how it went
+fn log_line(request: &JsonRpcRequest, response: &JsonRpcResponse) -> String {
+    let mut line = format!("{} {}", chrono::Local::now().format("%Y-%m-%dT%H:%M:%S"), request.method);
+    if let Some(tool) = request.params["name"].as_str().filter(|_| request.method == "tools/call") {
+        line.push(' ');
+        line.push_str(tool);
+    }
+    match &response.error {
+        Some(error) => line.push_str(&format!(" error: {}", error.message)),
+        None => line.push_str(" ok"),
+    }
+    line
+}
+
+/// The tools, without the write tools when the server is read-only
+fn handle_tools_list(config: &Config) -> Result<Value> {
+    let mut tools = tool_definitions();
+    if config.mcp_read_only {
+        if let Some(tools) = tools.as_array_mut() {
+            tools.retain(|tool| tool["name"].as_str().is_some_and(|name| READ_ONLY_TOOLS.contains(&name)));
+        }
+    }
+    Ok(json!({ "tools": tools }))
+}
+
+/// The `tools/list` result as pretty JSON, for `chomp serve --print-schema`
+pub fn tools_schema(config: &Config) -> Result<String> {
+    Ok(serde_json::to_string_pretty(&handle_tools_list(config)?)?)
+}
+
+/// Every tool's name, description, and input schema, as listed by `tools/list`
+fn tool_definitions() -> Value {
+    json!([
+        {
+            "name": "log_food",
+            "description": "Log food consumption. Returns calculated macros and the day's new totals (`today`).",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "food": {
+                        "type": "string",
+                        "description": "Food name and optional amount, e.g. 'salmon 4oz' or 'bare bar'"
+                    }
+                },
+                "required": ["food"]
+            }
+        },
+        {
+            "name": "calculate_macros",
+            "description": "Calculate macros for an amount of a food without logging it. Returns the food's serving and the multiplier used.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "food": {
+                        "type": "string",
+                        "description": "Food name, e.g. 'salmon'"
                     },
-                    "required": ["food"]
-                }
-            },
-            {
-                "name": "search_food",
-                "description": "Search for foods in the database. Returns matching foods with nutrition info.",
-                "inputSchema": {
-                    "type": "object",
-                    "properties": {
-                        "query": {
-                            "type": "string",
-                            "description": "Search query (fuzzy matching supported)"
-                        }
+                    "amount": {
+                        "type": "string",
+                        "description": "Amount, e.g. '6oz' or '2' (default: the food's default amount or serving)"
+                    }
+                },
+                "required": ["food"]
+            }
+        },
+        {
+            "name": "search_food",
+            "description": "Search for foods in the database. Returns matching foods with nutrition info.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "query": {
+                        "type": "string",
+                        "description": "Search query (fuzzy matching supported)"
                     },
-                    "required": ["query"]
-                }
-            },
-            {
-                "name": "add_food",
-                "description": "Add a new food to the database.",
-                "inputSchema": {
-                    "type": "object",
-                    "properties": {
-                        "name": {
-                            "type": "string",
-                            "description": "Food name"
-                        },
-                        "protein": {
-                            "type": "number",
-                            "description": "Protein in grams per serving"
-                        },
-                        "fat": {
-                            "type": "number",
-                            "description": "Fat in grams per serving"
-                        },
-                        "carbs": {
-                            "type": "number",
-                            "description": "Carbs in grams per serving"
-                        },
-                        "serving": {
-                            "type": "string",
-                            "description": "Serving size, e.g. '100g', '1 bar', '4oz'"
-                        },
-                        "calories": {
-                            "type": "number",
-                            "description": "Calories per serving (calculated if not provided)"
-                        },
-                        "aliases": {
-                            "type": "array",
-                            "items": { "type": "string" },
-                            "description": "Alternative names for this food"
-                        }
+                    "include_aliases": {
+                        "type": "boolean",
+                        "description": "Also match against food aliases (default from config, normally true)"
+                    },
+                    "aliases_only": {
+                        "type": "boolean",
+                        "description": "Match against aliases only, not names; each result says which alias matched (default false)"
+                    }
+                },
+                "required": ["query"]
+            }
+        },
+        {
+            "name": "add_food",
+            "description": "Add a new food to the database. Serving defaults to the configured default_serving (normally 100g) when omitted.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "name": {
+                        "type": "string",
+                        "description": "Food name"
+                    },
+                    "protein": {
+                        "type": "number",
+                        "description": "Protein in grams per serving"
+                    },
+                    "fat": {
+                        "type": "number",
+                        "description": "Fat in grams per serving"
+                    },
+                    "carbs": {
+                        "type": "number",
+                        "description": "Carbs in grams per serving"
+                    },
+                    "serving": {
+                        "type": "string",
+                        "description": "Serving size, e.g. '100g', '1 bar', '4oz' (default: 100g, or the configured default_serving)"
+                    },
+                    "calories": {
+                        "type": "number",
+                        "description": "Calories per serving (calculated if not provided; required with solve)"
                     },
-                    "required": ["name", "protein", "fat", "carbs", "serving"]
+                    "solve": {
+                        "type": "string",
+                        "enum": ["protein", "fat", "carbs"],
+                        "description": "Compute this macro from calories and the other two instead of passing it"
+                    },
+                    "fix_calories": {
+                        "type": "boolean",
+                        "description": "If calories is 0 but the macros aren't (a label error), use the calculated calories instead"
+                    },
+                    "density": {
+                        "type": "number",
+                        "description": "Density in g/ml, to convert between weight and volume"
+                    },
+                    "unit_mode": {
+                        "type": "string",
+                        "enum": ["auto", "serving"],
+                        "description": "\"serving\" to scale only by the number of servings (for foods like \"1 scoop\" with no known weight); default \"auto\""
+                    },
+                    "aliases": {
+                        "type": "array",
+                        "items": { "type": "string" },
+                        "description": "Alternative names for this food"
+                    }
+                },
+                "required": ["name"]
+            }
+        },
+        {
+            "name": "parse_label",
+            "description": "Read serving size, calories, protein, fat, and carbs from nutrition label text. Fields not found are null. Doesn't add anything; pass the values to add_food.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "text": {
+                        "type": "string",
+                        "description": "The label text, e.g. \"Serving size 1 bar (60g) Calories 240 Total Fat 8g ...\""
+                    }
+                },
+                "required": ["text"]
+            }
+        },
+        {
+            "name": "get_today",
+            "description": "Get today's nutrition totals.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "meal": {
+                        "type": "string",
+                        "enum": ["breakfast", "lunch", "dinner", "snack"],
+                        "description": "Only count entries logged as this meal"
+                    }
                 }
-            },
-            {
-                "name": "get_today",
-                "description": "Get today's nutrition totals.",
-                "inputSchema": {
-                    "type": "object",
-                    "properties": {}
+            }
+        },
+        {
+            "name": "get_remaining",
+            "description": "Get protein, fat, carbs, and calories remaining against the day's goals (negative when over), with a one-sentence summary.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "date": {
+                        "type": "string",
+                        "description": "Date as YYYY-MM-DD, \"yesterday\", a weekday like \"monday\" (the last one), or \"3 days ago\" (default: today)"
+                    }
                 }
-            },
-            {
-                "name": "get_history",
-                "description": "Get recent food log entries.",
-                "inputSchema": {
-                    "type": "object",
-                    "properties": {
-                        "days": {
-                            "type": "integer",
-                            "description": "Number of days to look back (default: 7)"
-                        }
+            }
+        },
+        {
+            "name": "get_history",
+            "description": "Get recent food log entries.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "days": {
+                        "type": "integer",
+                        "minimum": 1,
+                        "description": "Number of days to show, counting today (default: 7, or every day when limit is given)"
+                    },
+                    "limit": {
+                        "type": "integer",
+                        "minimum": 1,
+                        "description": "Return only the N most recent entries, within days if given"
+                    },
+                    "meal": {
+                        "type": "string",
+                        "enum": ["breakfast", "lunch", "dinner", "snack"],
+                        "description": "Only return entries logged as this meal"
                     }
                 }
             }
-        ]
-    }))
+        },
+        {
+            "name": "get_stats",
+            "description": "Get database stats: food and log counts, first/last entry, days logged, and adherence percentage.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {}
+            }
+        }
+    ])
 }
 
-fn handle_tools_call(db: &Database, params: &Value) -> Result<Value> {
+/// Check a call's arguments against the tool's input schema, plus rules the schema
+/// can't express, listing every missing or invalid field in one error so the caller
+/// can fix them all at once. Arguments the tool doesn't know are ignored.
+fn validate_arguments(tool: &str, arguments: &Value) -> Result<()> {
+    let definitions = tool_definitions();
+    let Some(schema) = definitions.as_array()
+        .and_then(|tools| tools.iter().find(|t| t["name"] == tool))
+        .map(|t| &t["inputSchema"])
+    else {
+        return Ok(());
+    };
+    let empty = serde_json::Map::new();
+    let args = match arguments {
+        Value::Null => &empty,
+        Value::Object(args) => args,
+        _ => anyhow::bail!("Invalid arguments for {}: arguments must be an object", tool),
+    };
+    let given = |name: &str| args.get(name).is_some_and(|v| !v.is_null());
+
+    let mut problems = Vec::new();
+    for name in schema["required"].as_array().into_iter().flatten().filter_map(Value::as_str) {
+        if !given(name) {
+            problems.push(format!("missing '{}'", name));
+        }
+    }
+    if tool == "add_food" {
+        let solve = args.get("solve").and_then(Value::as_str);
+        for name in ["protein", "fat", "carbs"] {
+            if !given(name) && solve != Some(name) {
+                problems.push(format!("missing '{}' (or pass solve: \"{}\")", name, name));
+            }
+        }
+    }
+    for (name, value) in args {
+        if let Some(problem) = schema["properties"].get(name).and_then(|p| argument_problem(p, value)) {
+            problems.push(format!("'{}' {}", name, problem));
+        }
+    }
+
+    anyhow::ensure!(problems.is_empty(), "Invalid arguments for {}: {}", tool, problems.join("; "));
+    Ok(())
+}
+
+/// Why `value` doesn't fit the schema `property`, if it doesn't. Null counts as absent.
+fn argument_problem(property: &Value, value: &Value) -> Option<String> {
+    if value.is_null() {
+        return None;
+    }
+    let (fits, expected) = match property["type"].as_str() {
+        Some("string") => (value.is_string(), "a string"),
+        Some("number") => (value.is_number(), "a number"),
+        Some("integer") => (value.is_i64() || value.is_u64(), "an integer"),
+        Some("boolean") => (value.is_boolean(), "true or false"),
+        Some("array") => (
+            value.as_array().is_some_and(|items| items.iter().all(|i| argument_problem(&property["items"], i).is_none())),
+            "an array of strings",
+        ),
+        _ => (true, ""),
+    };
+    if !fits {
+        return Some(format!("must be {}, got {}", expected, value));
+    }
+    if let Some(options) = property["enum"].as_array() {
+        if !options.contains(value) {
+            let options: Vec<String> = options.iter().map(Value::to_string).collect();
+            return Some(format!("must be one of {}, got {}", options.join(", "), value));
+        }
+    }
+    if let (Some(minimum), Some(n)) = (property["minimum"].as_f64(), value.as_f64()) {
+        if n < minimum {
+            return Some(format!("must be at least {}, got {}", minimum, value));
+        }
+    }
+    None
+}
+
+fn handle_tools_call(db: &Database, config: &Config, session: &mut Session, params: &Value) -> Result<Value> {
     let tool_name = params["name"].as_str().unwrap_or("");
     let arguments = &params["arguments"];
+    validate_arguments(tool_name, arguments)?;
+    if !READ_ONLY_TOOLS.contains(&tool_name) {
+        let exists = tool_definitions().as_array().is_some_and(|tools| tools.iter().any(|t| t["name"] == tool_name));
+        anyhow::ensure!(!(config.mcp_read_only && exists),
+            "{} is not available: this server is read-only (mcp_read_only is set)", tool_name);
+        session.totals.clear();
+    }
 
     match tool_name {
         "log_food" => {
             let food = arguments["food"].as_str()
                 .ok_or_else(|| anyhow::anyhow!("Missing 'food' argument"))?;
-            let entry = parse_and_log(db, food)?;
+            let logged = parse_and_log(db, config, food)?;
+            let today = match logged.last() {
+                Some(last) => Some(day_totals(db, &last.entry)?),
+                None => None,
+            };
+            let confirmation = logged_json(logged, today)?;
             Ok(json!({
                 "content": [{
                     "type": "text",
-                    "text": serde_json::to_string_pretty(&entry)?
+                    "text": serde_json::to_string_pretty(&confirmation)?
                 }]
             }))
         }
-        "search_food" => {
-            let query = arguments["query"].as_str()
-                .ok_or_else(|| anyhow::anyhow!("Missing 'query' argument"))?;
-            let results = db.search_foods(query)?;
+        "calculate_macros" => {
+            let food = arguments["food"].as_str()
+                .ok_or_else(|| anyhow::anyhow!("Missing 'food' argument"))?;
+            let result = nutrition(db, config, food, arguments["amount"].as_str())?;
             Ok(json!({
                 "content": [{
                     "type": "text",
-                    "text": serde_json::to_string_pretty(&results)?
+                    "text": serde_json::to_string_pretty(&result)?
                 }]
             }))
         }
+        "search_food" => {
+            let query = arguments["query"].as_str()
+                .ok_or_else(|| anyhow::anyhow!("Missing 'query' argument"))?;
+            let mut options = config.search_options();
+            if let Some(include) = arguments["include_aliases"].as_bool() {
+                options.include_aliases = include;
+            }
+            options.aliases_only = arguments["aliases_only"].as_bool().unwrap_or(false);
+            let results = db.search_matches(query, &options)?;
+            // As with the CLI, which alias matched is only reported when searching aliases alone
+            if options.aliases_only {
+                structured_result(&results, "foods")
+            } else {
+                structured_result(&results.iter().map(|m| &m.food).collect::<Vec<_>>(), "foods")
+            }
+        }
         "add_food" => {
             let name = arguments["name"].as_str()
                 .ok_or_else(|| anyhow::anyhow!("Missing 'name' argument"))?;
-            let protein = arguments["protein"].as_f64()
-                .ok_or_else(|| anyhow::anyhow!("Missing 'protein' argument"))?;
-            let fat = arguments["fat"].as_f64()
-                .ok_or_else(|| anyhow::anyhow!("Missing 'fat' argument"))?;
-            let carbs = arguments["carbs"].as_f64()
-                .ok_or_else(|| anyhow::anyhow!("Missing 'carbs' argument"))?;
-            let serving = arguments["serving"].as_str()
-                .ok_or_else(|| anyhow::anyhow!("Missing 'serving' argument"))?;
-            let calories = arguments["calories"].as_f64()
-                .unwrap_or_else(|| protein * 4.0 + fat * 9.0 + carbs * 4.0);
+            let solve = arguments["solve"].as_str()
+                .map(|s| s.parse::<MacroKind>().map_err(|e| anyhow::anyhow!(e)))
+                .transpose()?;
+            let serving = arguments["serving"].as_str().unwrap_or(&config.default_serving);
+            let (macros, warning) = macros_for_new_food(
+                arguments["protein"].as_f64(),
+                arguments["fat"].as_f64(),
+                arguments["carbs"].as_f64(),
+                arguments["calories"].as_f64(),
+                solve,
+                arguments["fix_calories"].as_bool().unwrap_or(false),
+            )?;
             let aliases: Vec<String> = arguments["aliases"]
                 .as_array()
                 .map(|arr| arr.iter().filter_map(|v| v.as_str().map(String::from)).collect())
                 .unwrap_or_default();
 
-            let food = Food::new(name, protein, fat, carbs, calories, serving, aliases);
+            let mut food = Food::new(name, macros.protein, macros.fat, macros.carbs, macros.calories, serving, aliases);
+            food.density = arguments["density"].as_f64().filter(|d| *d > 0.0);
+            food.unit_mode = arguments["unit_mode"].as_str().and_then(|m| m.parse().ok()).unwrap_or_default();
             db.add_food(&food)?;
 
+            let mut text = format!("Added: {}", MacroFormat::plain().food_summary(&food, &food.name));
+            if let Some(warning) = warning {
+                text.push_str(&format!("\nWarning: {}", warning));
+            }
             Ok(json!({
                 "content": [{
                     "type": "text",
-                    "text": format!("Added: {} ({:.0}p/{:.0}f/{:.0}c per {})", 
-                        name, protein, fat, carbs, serving)
+                    "text": text
                 }]
             }))
         }
+        "parse_label" => {
+            let text = arguments["text"].as_str()
+                .ok_or_else(|| anyhow::anyhow!("Missing 'text' argument"))?;
+            structured_result(&crate::label::parse_label(text), "label")
+        }
         "get_today" => {
-            let totals = db.get_today_totals()?;
-            Ok(json!({
-                "content": [{
-                    "type": "text",
-                    "text": serde_json::to_string_pretty(&totals)?
-                }]
-            }))
+            let totals = match meal_argument(arguments)? {
+                Some(meal) => db.get_totals_for_date(chrono::Local::now().date_naive(), Some(meal))?,
+                None => session.totals_for_date(db, chrono::Local::now().date_naive())?,
+            };
+            structured_result(&totals, "totals")
+        }
+        "get_remaining" => {
+            let date = match arguments["date"].as_str() {
+                Some(date) => crate::logging::parse_date(date, chrono::Local::now().date_naive())?,
+                None => chrono::Local::now().date_naive(),
+            };
+            match crate::goals::remaining(db, date)? {
+                Some(remaining) => Ok(json!({
+                    "content": [{
+                        "type": "text",
+                        "text": remaining.summary
+                    }],
+                    "structuredContent": remaining
+                })),
+                None => Ok(json!({
+                    "content": [{
+                        "type": "text",
+                        "text": "No goals set. Set them with: chomp goals set --protein 150 --calories 2000"
+                    }]
+                })),
+            }
         }
         "get_history" => {
-            let days = arguments["days"].as_u64().unwrap_or(7) as u32;
-            let entries = db.get_history(days)?;
+            let limit = arguments["limit"].as_u64().map(|n| n.min(u32::MAX as u64) as u32);
+            let meal = meal_argument(arguments)?;
+            let entries = match arguments["days"].as_u64() {
+                None if limit.is_some() => db.get_log_range(None, None, limit, meal)?,
+                days => db.get_history(days.unwrap_or(7).min(u32::MAX as u64) as u32, limit, meal)?,
+            };
+            structured_result(&entries, "entries")
+        }
+        "get_stats" => {
+            let stats = db.get_stats()?;
             Ok(json!({
                 "content": [{
                     "type": "text",
-                    "text": serde_json::to_string_pretty(&entries)?
+                    "text": serde_json::to_string_pretty(&stats)?
                 }]
             }))
         }
         _ => Err(anyhow::anyhow!("Unknown tool: {}", tool_name)),
     }
 }
+
+/// The optional `meal` argument of get_today and get_history
+fn meal_argument(arguments: &Value) -> Result<Option<Meal>> {
+    arguments["meal"].as_str()
+        .map(|m| m.parse::<Meal>().map_err(|e| anyhow::anyhow!(e)))
+        .transpose()
+}
+
+/// A tool result with `value` both as pretty JSON text, for clients that only read
+/// text, and as `structuredContent`. Structured content must be an object, so
+/// anything else is wrapped under `key`.
+fn structured_result(value: &impl Serialize, key: &str) -> Result<Value> {
+    let text = serde_json::to_string_pretty(value)?;
+    let structured = match serde_json::to_value(value)? {
+        object @ Value::Object(_) => object,
+        other => json!({ key: other }),
+    };
+    Ok(json!({
+        "content": [{
+            "type": "text",
+            "text": text
+        }],
+        "structuredContent": structured
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_add_food_without_serving() {
+        let db = Database::open_in_memory().unwrap();
+        let params = json!({
+            "name": "add_food",
+            "arguments": { "name": "lentils", "protein": 9.0, "fat": 0.4, "carbs": 20.0 }
+        });
+        handle_tools_call(&db, &Config::default(), &mut Session::default(), &params).unwrap();
+        assert_eq!(db.get_food_by_name("lentils").unwrap().unwrap().serving, "100g");
+
+        let config = Config { default_serving: "1 cup".to_string(), ..Config::default() };
+        let params = json!({
+            "name": "add_food",
+            "arguments": { "name": "rice", "protein": 4.3, "fat": 0.4, "carbs": 45.0 }
+        });
+        handle_tools_call(&db, &config, &mut Session::default(), &params).unwrap();
+        assert_eq!(db.get_food_by_name("rice").unwrap().unwrap().serving, "1 cup");
+    }
+
+    #[test]
+    fn test_add_food_reports_all_missing_fields() {
+        let db = Database::open_in_memory().unwrap();
+        let call = |arguments: Value| {
+            handle_tools_call(&db, &Config::default(), &mut Session::default(), &json!({ "name": "add_food", "arguments": arguments }))
+        };
+
+        let err = call(json!({ "protein": "lots", "fat": 0.4, "aliases": ["dal", 3] })).unwrap_err().to_string();
+        assert_eq!(err, "Invalid arguments for add_food: missing 'name'; missing 'carbs' (or pass solve: \"carbs\"); \
+            'aliases' must be an array of strings, got [\"dal\",3]; 'protein' must be a number, got \"lots\"");
+
+        let err = call(json!({ "name": "lentils", "solve": "fiber" })).unwrap_err().to_string();
+        assert!(err.contains("missing 'protein'") && err.contains("missing 'fat'") && err.contains("missing 'carbs'"));
+        assert!(err.contains("'solve' must be one of \"protein\", \"fat\", \"carbs\", got \"fiber\""));
+
+        // Solving for a macro stands in for passing it; unknown extras are ignored
+        call(json!({ "name": "lentils", "protein": 9.0, "fat": 0.4, "calories": 116.0, "solve": "carbs", "brand": "x" })).unwrap();
+        assert!(call(Value::String("lentils".into())).unwrap_err().to_string().contains("must be an object"));
+        assert!(db.get_food_by_name("lentils").unwrap().is_some());
+    }
+
+    #[test]
+    fn test_validate_other_tools() {
+        assert_eq!(validate_arguments("log_food", &Value::Null).unwrap_err().to_string(),
+            "Invalid arguments for log_food: missing 'food'");
+        assert!(validate_arguments("get_history", &json!({ "days": 0 })).unwrap_err().to_string()
+            .contains("'days' must be at least 1"));
+        assert!(validate_arguments("get_history", &json!({ "days": 1.5 })).is_err());
+        assert!(validate_arguments("search_food", &json!({ "query": "egg", "include_aliases": null })).is_ok());
+        assert!(validate_arguments("get_today", &json!({})).is_ok());
+    }
+
+    #[test]
+    fn test_structured_content() {
+        let db = Database::open_in_memory().unwrap();
+        db.add_food(&Food::new("salmon", 20.0, 13.0, 0.0, 208.0, "100g", vec![])).unwrap();
+        db.add_food(&Food::new("smoked salmon", 18.0, 4.0, 0.0, 117.0, "100g", vec!["lox".to_string()])).unwrap();
+        let call = |name: &str, arguments: Value| {
+            handle_tools_call(&db, &Config::default(), &mut Session::default(), &json!({ "name": name, "arguments": arguments })).unwrap()
+        };
+
+        let result = call("search_food", json!({ "query": "salmon" }));
+        assert_eq!(result["structuredContent"]["foods"][0]["name"], "salmon");
+        // The text block is still there, holding the same data
+        let text: Value = serde_json::from_str(result["content"][0]["text"].as_str().unwrap()).unwrap();
+        assert_eq!(text, result["structuredContent"]["foods"]);
+        assert!(result["structuredContent"]["foods"][0].get("matched_alias").is_none());
+        let result = call("search_food", json!({ "query": "salmon", "aliases_only": true }));
+        assert_eq!(result["structuredContent"]["foods"], json!([]));
+        let result = call("search_food", json!({ "query": "lox" }));
+        assert_eq!(result["structuredContent"]["foods"][0]["name"], "smoked salmon");
+        assert!(result["structuredContent"]["foods"][0].get("matched_alias").is_none());
+        let result = call("search_food", json!({ "query": "lox", "aliases_only": true }));
+        assert_eq!(result["structuredContent"]["foods"][0]["matched_alias"], "lox");
+
+        call("log_food", json!({ "food": "salmon 200g" }));
+        let result = call("get_today", json!({}));
+        assert_eq!(result["structuredContent"]["protein"], 40.0);
+        let result = call("get_history", json!({ "days": 1 }));
+        assert_eq!(result["structuredContent"]["entries"].as_array().unwrap().len(), 1);
+        assert_eq!(result["structuredContent"]["entries"][0]["food_name"], "salmon");
+
+        call("log_food", json!({ "food": "salmon 100g" }));
+        let result = call("get_history", json!({ "limit": 1 }));
+        assert_eq!(result["structuredContent"]["entries"].as_array().unwrap().len(), 1);
+        assert_eq!(result["structuredContent"]["entries"][0]["amount"], "100g");
+
+        // Meal filters: neither entry above has a meal
+        let salmon = db.get_food_by_name("salmon").unwrap().unwrap();
+        let lunch = crate::db::LogMeta { meal: Some(Meal::Lunch), ..crate::db::LogMeta::on(chrono::Local::now().date_naive()) };
+        db.log_food_at(&lunch, salmon.id.unwrap(), "50g", &salmon.scale(0.5)).unwrap();
+        let result = call("get_history", json!({ "days": 1, "meal": "lunch" }));
+        assert_eq!(result["structuredContent"]["entries"].as_array().unwrap().len(), 1);
+        assert_eq!(result["structuredContent"]["entries"][0]["amount"], "50g");
+        assert_eq!(call("get_today", json!({ "meal": "lunch" }))["structuredContent"]["protein"], 10.0);
+        assert_eq!(call("get_today", json!({ "meal": "dinner" }))["structuredContent"]["calories"], 0.0);
+        assert!(validate_arguments("get_today", &json!({ "meal": "brunch" })).is_err());
+    }
+
+    #[test]
+    fn test_get_remaining() {
+        let db = Database::open_in_memory().unwrap();
+        db.add_food(&Food::new("salmon", 20.0, 13.0, 0.0, 208.0, "100g", vec![])).unwrap();
+        let call = |arguments: Value| {
+            handle_tools_call(&db, &Config::default(), &mut Session::default(), &json!({ "name": "get_remaining", "arguments": arguments }))
+        };
+
+        let result = call(json!({})).unwrap();
+        assert!(result["content"][0]["text"].as_str().unwrap().starts_with("No goals set"));
+        assert!(result.get("structuredContent").is_none());
+
+        let day = chrono::NaiveDate::from_ymd_opt(2024, 6, 1).unwrap();
+        db.set_goals_from(&crate::db::Goals { protein: Some(150.0), fat: Some(20.0), ..Default::default() }, day).unwrap();
+        let salmon = db.get_food_by_name("salmon").unwrap().unwrap();
+        db.log_food_on(day, salmon.id.unwrap(), "200g", &salmon.scale(2.0)).unwrap();
+
+        let result = call(json!({ "date": "2024-06-01" })).unwrap();
+        assert_eq!(result["structuredContent"]["protein"], 110.0);
+        assert_eq!(result["structuredContent"]["fat"], -6.0);
+        assert_eq!(result["structuredContent"]["calories"], Value::Null);
+        assert_eq!(result["content"][0]["text"], "For 2024-06-01: 110g protein left, 6g fat over.");
+        // Nothing logged today
+        assert_eq!(call(json!({})).unwrap()["structuredContent"]["protein"], 150.0);
+        assert!(call(json!({ "date": "June 1" })).unwrap_err().to_string().contains("expected YYYY-MM-DD"));
+        assert_eq!(call(json!({ "date": "yesterday" })).unwrap()["structuredContent"]["protein"], 150.0);
+    }
+
+    #[test]
+    fn test_logging_invalidates_today_cache() {
+        let db = Database::open_in_memory().unwrap();
+        db.add_food(&Food::new("egg", 6.0, 5.0, 0.5, 72.0, "1 large", vec![])).unwrap();
+        let mut session = Session::default();
+        let mut call = |name: &str, arguments: Value| {
+            handle_tools_call(&db, &Config::default(), &mut session, &json!({ "name": name, "arguments": arguments })).unwrap()
+        };
+
+        assert_eq!(call("get_today", json!({}))["structuredContent"]["calories"], 0.0);
+        call("log_food", json!({ "food": "2 egg" }));
+        assert_eq!(call("get_today", json!({}))["structuredContent"]["calories"], 144.0);
+        call("log_food", json!({ "food": "egg" }));
+        assert_eq!(call("get_today", json!({}))["structuredContent"]["calories"], 216.0);
+    }
+
+    #[test]
+    fn test_today_cache_sees_other_processes() {
+        let dir = crate::db::TempDir::new("mcp-cache-test");
+        let path = dir.join("foods.db");
+        let server = Database::open_at(&path).unwrap();
+        server.init().unwrap();
+        let cli = Database::open_at(&path).unwrap();
+        let mut session = Session::default();
+        let today = chrono::Local::now().date_naive();
+        assert_eq!(session.totals_for_date(&server, today).unwrap().calories, 0.0);
+
+        // A write from another connection doesn't go through a tool, but still shows up
+        cli.add_food(&Food::new("egg", 6.0, 5.0, 0.5, 72.0, "1 large", vec![])).unwrap();
+        let egg = cli.get_food_by_name("egg").unwrap().unwrap();
+        cli.log_food_on(today, egg.id.unwrap(), "1 large", &egg.scale(1.0)).unwrap();
+        assert_eq!(session.totals_for_date(&server, today).unwrap().calories, 72.0);
+    }
+
+    #[test]
+    fn test_read_only_server() {
+        let db = Database::open_in_memory().unwrap();
+        db.add_food(&Food::new("egg", 6.0, 5.0, 0.5, 72.0, "1 large", vec![])).unwrap();
+        let config = Config { mcp_read_only: true, ..Config::default() };
+        let call = |name: &str, arguments: Value| {
+            handle_tools_call(&db, &config, &mut Session::default(), &json!({ "name": name, "arguments": arguments }))
+        };
+
+        let listed = handle_tools_list(&config).unwrap();
+        let names: Vec<&str> = listed["tools"].as_array().unwrap().iter().map(|t| t["name"].as_str().unwrap()).collect();
+        assert!(names.contains(&"get_today") && names.contains(&"search_food"));
+        assert!(!names.contains(&"log_food") && !names.contains(&"add_food"));
+
+        let err = call("log_food", json!({ "food": "egg" })).unwrap_err().to_string();
+        assert!(err.contains("read-only"), "{}", err);
+        assert!(call("add_food", json!({ "name": "toast", "protein": 3, "fat": 1, "carbs": 13 })).is_err());
+        assert!(db.get_food_by_name("toast").unwrap().is_none());
+        assert_eq!(call("get_today", json!({})).unwrap()["structuredContent"]["calories"], 0.0);
+        assert!(call("nope", json!({})).unwrap_err().to_string().starts_with("Unknown tool"));
+    }
+
+    #[test]
+    fn test_tools_schema() {
+        let schema: Value = serde_json::from_str(&tools_schema(&Config::default()).unwrap()).unwrap();
+        let tools = schema["tools"].as_array().unwrap();
+        let names: Vec<&str> = tools.iter().map(|t| t["name"].as_str().unwrap()).collect();
+        for tool in ["log_food", "add_food"].iter().chain(READ_ONLY_TOOLS) {
+            assert!(names.contains(tool), "{} missing from {:?}", tool, names);
+        }
+        assert_eq!(names.len(), READ_ONLY_TOOLS.len() + 2);
+        assert!(tools.iter().all(|t| t["inputSchema"]["type"] == "object"));
+    }
+
+    #[test]
+    fn test_log_line() {
+        let request: JsonRpcRequest = serde_json::from_value(json!({
+            "jsonrpc": "2.0", "id": 3, "method": "tools/call", "params": { "name": "log_food" }
+        })).unwrap();
+        let db = Database::open_in_memory().unwrap();
+        let response = handle_request(&db, &Config::default(), &mut Session::default(), &request);
+        let line = log_line(&request, &response);
+        assert!(line.ends_with(" tools/call log_food error: Invalid arguments for log_food: missing 'food'"), "{}", line);
+    }
+}
@@ -0,0 +1,352 @@
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::io::{IsTerminal, Write};
+use unicode_width::UnicodeWidthStr;
+
+use crate::config::{Config, Emphasize};
+use crate::food::{atwater_calories, Food};
+
+/// How read commands print their results
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// Human-readable lines
+    #[default]
+    Plain,
+    Json,
+    Csv,
+    /// Aligned columns
+    Table,
+}
+
+impl OutputFormat {
+    /// The command's `--format`, with the global `--json` as a deprecated alias for `--format json`
+    pub fn resolve(format: Option<OutputFormat>, json: bool) -> Self {
+        match format {
+            Some(format) => format,
+            None if json => OutputFormat::Json,
+            None => OutputFormat::Plain,
+        }
+    }
+}
+
+/// Where confirmations like "Logged: ..." are printed. Data (JSON, tables, exports)
+/// always goes to stdout; sending confirmations to stderr keeps pipelines clean.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConfirmStream {
+    #[default]
+    Stdout,
+    Stderr,
+}
+
+impl ConfirmStream {
+    /// Write a confirmation line to `out` (stdout) or `err` (stderr), whichever this picks
+    pub fn write_line(self, out: &mut dyn Write, err: &mut dyn Write, line: &str) -> std::io::Result<()> {
+        match self {
+            ConfirmStream::Stdout => writeln!(out, "{}", line),
+            ConfirmStream::Stderr => writeln!(err, "{}", line),
+        }
+    }
+
+    /// Whether this stream is a terminal that should get color and bold (see no-color.org)
+    pub fn shows_color(self) -> bool {
+        let terminal = match self {
+            ConfirmStream::Stdout => std::io::stdout().is_terminal(),
+            ConfirmStream::Stderr => std::io::stderr().is_terminal(),
+        };
+        terminal && std::env::var_os("NO_COLOR").is_none()
+    }
+
+    /// Print a confirmation line to the real stdout or stderr
+    pub fn print(self, line: &str) {
+        let _ = self.write_line(&mut std::io::stdout().lock(), &mut std::io::stderr().lock(), line);
+    }
+}
+
+/// The one place macros are formatted for people: "12p/10f/1c", protein first.
+/// With protein emphasized, its figure is bold when `bold` is set. With `reconcile`,
+/// calories shown next to macros move with the macros' rounding; otherwise they're
+/// the stored calories.
+#[derive(Debug, Clone, Copy)]
+pub struct MacroFormat {
+    pub emphasize: Emphasize,
+    pub bold: bool,
+    pub reconcile: bool,
+    /// Decimal places shown for grams: 0, or 1 for lookups (see `precise`)
+    pub decimals: usize,
+}
+
+impl MacroFormat {
+    /// As `config` says, with bold figures if `bold` (the stream shows color)
+    pub fn new(config: &Config, bold: bool) -> Self {
+        MacroFormat { emphasize: config.emphasize, bold, reconcile: config.reconcile_calories, decimals: 0 }
+    }
+
+    /// The same, with grams to one decimal place: "12.5p/10.0f/1.2c"
+    pub fn precise(self) -> Self {
+        MacroFormat { decimals: 1, ..self }
+    }
+
+    /// For printing to stdout: bold only if stdout shows color
+    pub fn for_stdout(config: &Config) -> Self {
+        Self::new(config, ConfirmStream::Stdout.shows_color())
+    }
+
+    /// Stored calories and nothing emphasized, whatever the config says, for text
+    /// read by programs rather than people (MCP replies)
+    pub fn plain() -> Self {
+        MacroFormat { emphasize: Emphasize::None, bold: false, reconcile: false, decimals: 0 }
+    }
+
+    /// "12p/10f/1c"
+    pub fn compact(&self, protein: f64, fat: f64, carbs: f64) -> String {
+        let d = self.decimals;
+        format!("{}/{:.*}f/{:.*}c", self.protein(protein), d, fat, d, carbs)
+    }
+
+    /// "12p / 10f / 1c", for totals
+    pub fn spaced(&self, protein: f64, fat: f64, carbs: f64) -> String {
+        let d = self.decimals;
+        format!("{} / {:.*}f / {:.*}c", self.protein(protein), d, fat, d, carbs)
+    }
+
+    /// "144 kcal", to go with the macros
+    pub fn kcal(&self, protein: f64, fat: f64, carbs: f64, calories: f64) -> String {
+        format!("{:.0} kcal", self.calories(protein, fat, carbs, calories))
+    }
+
+    /// Stored `calories`, or with `reconcile`, 4/9/4 kcal per gram of the macros
+    /// rounded the way they're shown. An entry with no macros at all, like a quick
+    /// add, keeps its stored calories.
+    pub fn calories(&self, protein: f64, fat: f64, carbs: f64, calories: f64) -> f64 {
+        if !self.reconcile || [protein, fat, carbs].iter().all(|grams| *grams == 0.0) {
+            return calories;
+        }
+        let shown = |grams: f64| shown(grams, self.decimals);
+        atwater_calories(shown(protein), shown(fat), shown(carbs))
+    }
+
+    /// One-line summary of `food` under `name` (e.g. title-cased for display), like
+    /// "salmon 25p/13f/0c, 217 kcal per 100g"
+    pub fn food_summary(&self, food: &Food, name: &str) -> String {
+        let fiber = if food.fiber > 0.0 { format!(" ({:.0}g fiber)", food.fiber) } else { String::new() };
+        format!("{} {}{}, {} per {}",
+            name, self.compact(food.protein, food.fat, food.carbs), fiber,
+            self.kcal(food.protein, food.fat, food.carbs, food.calories), food.serving)
+    }
+
+    fn protein(&self, protein: f64) -> String {
+        match self.emphasize {
+            Emphasize::Protein if self.bold => format!("\x1b[1m{:.*}p\x1b[0m", self.decimals, protein),
+            _ => format!("{:.*}p", self.decimals, protein),
+        }
+    }
+}
+
+/// A value as `{:.N}` displays it, which rounds halves to even (0.5 -> 0, 1.5 -> 2)
+fn shown(value: f64, decimals: usize) -> f64 {
+    format!("{:.*}", decimals, value).parse().unwrap_or(value)
+}
+
+/// Rows of text cells under a header, printable as CSV or aligned columns
+pub struct Table {
+    headers: Vec<String>,
+    rows: Vec<Vec<String>>,
+}
+
+impl Table {
+    pub fn new(headers: &[&str]) -> Self {
+        Self {
+            headers: headers.iter().map(|h| h.to_string()).collect(),
+            rows: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, row: Vec<String>) {
+        debug_assert_eq!(row.len(), self.headers.len());
+        self.rows.push(row);
+    }
+
+    pub fn render(&self) -> String {
+        render_table(&self.headers, &self.rows)
+    }
+
+    pub fn to_csv(&self) -> String {
+        std::iter::once(&self.headers)
+            .chain(&self.rows)
+            .map(|row| row.iter().map(|cell| csv_field(cell)).collect::<Vec<_>>().join(","))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+/// Lay out rows under a header row and a rule, each column padded to its widest
+/// cell. Columns whose cells are all numbers are right-aligned, the rest left-aligned.
+/// Widths are display columns, not bytes or characters: "jalapeño" pads like
+/// "jalapeno" however its ñ is encoded, and an emoji takes two columns.
+pub fn render_table<S: AsRef<str>>(headers: &[S], rows: &[Vec<String>]) -> String {
+    let width = |s: &str| UnicodeWidthStr::width(s);
+    let mut widths: Vec<usize> = headers.iter().map(|h| width(h.as_ref())).collect();
+    for row in rows {
+        for (w, cell) in widths.iter_mut().zip(row) {
+            *w = (*w).max(width(cell));
+        }
+    }
+    let numeric: Vec<bool> = (0..headers.len())
+        .map(|i| !rows.is_empty() && rows.iter().all(|r| is_numeric(&r[i])))
+        .collect();
+
+    let format_row = |row: &[&str]| -> String {
+        let cells: Vec<String> = row.iter().enumerate()
+            .map(|(i, cell)| {
+                let pad = " ".repeat(widths[i] - width(cell));
+                if numeric[i] { format!("{}{}", pad, cell) } else { format!("{}{}", cell, pad) }
+            })
+            .collect();
+        cells.join("  ").trim_end().to_string()
+    };
+
+    let header: Vec<&str> = headers.iter().map(|h| h.as_ref()).collect();
+    let mut lines = vec![format_row(&header)];
+    lines.push(widths.iter().map(|w| "-".repeat(*w)).collect::<Vec<_>>().join("  "));
+    for row in rows {
+        let row: Vec<&str> = row.iter().map(String::as_str).collect();
+        lines.push(format_row(&row));
+    }
+    lines.join("\n")
+}
+
+fn is_numeric(cell: &str) -> bool {
+    cell.is_empty() || cell.parse::<f64>().is_ok()
+}
+
+/// Quote a CSV field if it contains a comma, quote, or newline
+fn csv_field(cell: &str) -> String {
+    if cell.contains([',', '"', '\n']) {
+        format!("\"{}\"", cell.replace('"', "\"\""))
+    } else {
+        cell.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> Table {
+        let mut table = Table::new(&["food", "protein", "serving"]);
+        table.push(vec!["egg".to_string(), "6".to_string(), "1 large".to_string()]);
+        table.push(vec!["chicken breast".to_string(), "31.5".to_string(), "100g".to_string()]);
+        table
+    }
+
+    #[test]
+    fn test_table_alignment() {
+        let rendered = sample().render();
+        let lines: Vec<&str> = rendered.lines().collect();
+        assert_eq!(lines, vec![
+            "food            protein  serving",
+            "--------------  -------  -------",
+            "egg                   6  1 large",
+            "chicken breast     31.5  100g",
+        ]);
+    }
+
+    #[test]
+    fn test_render_table_varied_widths() {
+        let rows = vec![
+            vec!["jalapeño".to_string(), "1".to_string(), "12.5".to_string()],
+            vec!["crème fraîche".to_string(), "300".to_string(), "0".to_string()],
+            vec!["tofu".to_string(), "45".to_string(), "".to_string()],
+        ];
+        let rendered = render_table(&["food", "kcal", "g"], &rows);
+        let lines: Vec<&str> = rendered.lines().collect();
+        assert_eq!(lines, vec![
+            "food           kcal     g",
+            "-------------  ----  ----",
+            "jalapeño          1  12.5",
+            "crème fraîche   300     0",
+            "tofu             45",
+        ]);
+        // Every full row lines up by character count despite multi-byte names
+        assert_eq!(lines[2].chars().count(), lines[0].chars().count());
+
+        // No rows: just the header
+        assert_eq!(render_table(&["food", "kcal"], &[]), "food  kcal\n----  ----");
+    }
+
+    #[test]
+    fn test_render_table_display_width() {
+        // ñ as n + combining tilde, and emoji built from several code points
+        let rows = vec![
+            vec!["jalapen\u{303}o".to_string(), "4".to_string()],
+            vec!["👨\u{200d}🍳 special".to_string(), "650".to_string()],
+            vec!["☕\u{fe0f}".to_string(), "2".to_string()],
+            vec!["🍓 jam".to_string(), "56".to_string()],
+        ];
+        let rendered = render_table(&["food", "kcal"], &rows);
+        let lines: Vec<&str> = rendered.lines().collect();
+        assert_eq!(lines, vec![
+            "food        kcal",
+            "----------  ----",
+            "jalapen\u{303}o       4",
+            "👨\u{200d}🍳 special   650",
+            "☕\u{fe0f}             2",
+            "🍓 jam        56",
+        ]);
+        // Every row is as wide on screen as the header
+        assert!(lines.iter().all(|line| UnicodeWidthStr::width(*line) == 16));
+    }
+
+    #[test]
+    fn test_table_csv_quotes_fields() {
+        let mut table = Table::new(&["food", "amount"]);
+        table.push(vec!["chicken, grilled".to_string(), "4\"".to_string()]);
+        assert_eq!(table.to_csv(), "food,amount\n\"chicken, grilled\",\"4\"\"\"");
+    }
+
+    #[test]
+    fn test_resolve_format() {
+        assert_eq!(OutputFormat::resolve(None, false), OutputFormat::Plain);
+        assert_eq!(OutputFormat::resolve(None, true), OutputFormat::Json);
+        assert_eq!(OutputFormat::resolve(Some(OutputFormat::Table), true), OutputFormat::Table);
+    }
+
+    #[test]
+    fn test_food_summary() {
+        let salmon = Food::new("salmon", 25.0, 13.0, 0.0, atwater_calories(25.0, 13.0, 0.0), "100g", vec![]);
+        assert_eq!(MacroFormat::plain().food_summary(&salmon, "Salmon"), "Salmon 25p/13f/0c, 217 kcal per 100g");
+        let oats = Food { fiber: 10.0, ..Food::new("oats", 13.0, 7.0, 68.0, 389.0, "100g", vec![]) };
+        assert_eq!(MacroFormat::plain().food_summary(&oats, "oats"), "oats 13p/7f/68c (10g fiber), 389 kcal per 100g");
+    }
+
+    #[test]
+    fn test_reconciled_calories() {
+        let stored = MacroFormat { emphasize: Emphasize::None, bold: false, reconcile: false, decimals: 0 };
+        let reconciled = MacroFormat { reconcile: true, ..stored };
+
+        // One egg shows as 6p/5f/0c, which is 69 kcal, not the 72 on the label
+        assert_eq!(stored.compact(6.0, 5.0, 0.5), "6p/5f/0c");
+        assert_eq!(stored.kcal(6.0, 5.0, 0.5, 72.0), "72 kcal");
+        assert_eq!(reconciled.kcal(6.0, 5.0, 0.5, 72.0), "69 kcal");
+        // Two: 12p/10f/1c
+        assert_eq!(stored.kcal(12.0, 10.0, 1.0, 144.0), "144 kcal");
+        assert_eq!(reconciled.kcal(12.0, 10.0, 1.0, 144.0), "142 kcal");
+        // Halves round as displayed, to even
+        assert_eq!(reconciled.kcal(2.5, 0.0, 1.5, 0.0), "16 kcal");
+        // A quick-add entry has calories but no macros
+        assert_eq!(reconciled.kcal(0.0, 0.0, 0.0, 500.0), "500 kcal");
+
+        // Whatever the rounding, the calories shown are 4/9/4 of the macros shown
+        for format in [reconciled, reconciled.precise()] {
+            for (p, f, c, kcal) in [(6.0, 5.0, 0.5, 72.0), (23.46, 17.75, 0.04, 260.0), (0.3, 0.45, 20.55, 90.0)] {
+                let grams: Vec<f64> = format.compact(p, f, c)
+                    .split('/')
+                    .map(|part| part[..part.len() - 1].parse().unwrap())
+                    .collect();
+                let expected = format!("{:.0} kcal", 4.0 * grams[0] + 9.0 * grams[1] + 4.0 * grams[2]);
+                assert_eq!(format.kcal(p, f, c, kcal), expected);
+            }
+        }
+    }
+}
@@ -0,0 +1,209 @@
+use anyhow::{anyhow, Result};
+use chrono::NaiveDate;
+use serde::Serialize;
+
+use crate::config::Config;
+use crate::db::{Database, LogEntry, LogMeta, Meal};
+use crate::food::{macros_for_new_food, parse_number, Food};
+use crate::logging::{parse_date, resolve};
+use crate::usda::parse_csv_line;
+
+/// Columns of a plan file, in the order used when it has no header row
+const COLUMNS: [&str; 8] = ["date", "food", "amount", "meal", "protein", "fat", "carbs", "serving"];
+
+/// One row of a plan file: a food to log on a (usually future) date
+#[derive(Debug, Clone)]
+pub struct PlanRow {
+    /// Line number in the file, for error messages
+    pub line: usize,
+    pub date: NaiveDate,
+    pub food: String,
+    /// Amount as typed after the name; None logs the food's default
+    pub amount: Option<String>,
+    pub meal: Option<Meal>,
+    /// The food to create if none by this name exists, when the row gives macros
+    pub new_food: Option<Food>,
+}
+
+/// What a plan import logged (or, in a dry run, would log)
+#[derive(Debug, Default, Serialize)]
+pub struct PlanSummary {
+    pub entries: Vec<LogEntry>,
+    /// Foods that didn't exist and were created from the plan's macros
+    pub foods_created: Vec<String>,
+    /// Adjustments and warnings from resolving rows, as "Line 3: ..."
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub notes: Vec<String>,
+}
+
+/// Read a plan CSV: `date,food,amount,meal`, optionally followed by `protein,fat,carbs`
+/// (and `serving`) for foods not in the database yet. A header row naming the columns
+/// may put them in any order. Dates take anything `--date` does, relative to `today`.
+pub fn parse_plan(text: &str, today: NaiveDate, default_serving: &str) -> Result<Vec<PlanRow>> {
+    let mut lines = text.lines().enumerate().filter(|(_, line)| !line.trim().is_empty()).peekable();
+
+    let mut indexes: Vec<Option<usize>> = (0..COLUMNS.len()).map(Some).collect();
+    if let Some((_, first)) = lines.peek() {
+        let header: Vec<String> = parse_csv_line(first).iter().map(|h| h.trim().to_lowercase()).collect();
+        if header.first().is_some_and(|h| h == "date") {
+            for name in &header {
+                anyhow::ensure!(COLUMNS.contains(&name.as_str()), "Unknown plan column '{}' (expected {})", name, COLUMNS.join(", "));
+            }
+            indexes = COLUMNS.iter().map(|c| header.iter().position(|h| h == c)).collect();
+            anyhow::ensure!(indexes[1].is_some(), "Plan has no 'food' column");
+            lines.next();
+        }
+    }
+
+    let mut rows = Vec::new();
+    for (index, line) in lines {
+        let line_number = index + 1;
+        let fields = parse_csv_line(line);
+        let field = |column: usize| {
+            indexes[column]
+                .and_then(|i| fields.get(i))
+                .map(|f| f.trim())
+                .filter(|f| !f.is_empty())
+        };
+        let invalid = |message: String| anyhow!("Line {}: {}", line_number, message);
+
+        let date = parse_date(field(0).ok_or_else(|| invalid("missing date".to_string()))?, today)
+            .map_err(|e| invalid(e.to_string()))?;
+        let food = field(1).ok_or_else(|| invalid("missing food".to_string()))?.to_string();
+        let meal = field(3).map(|m| m.parse::<Meal>()).transpose().map_err(invalid)?;
+
+        let macro_field = |column: usize| {
+            field(column)
+                .map(|v| parse_number(v).ok_or_else(|| invalid(format!("invalid {} '{}'", COLUMNS[column], v))))
+                .transpose()
+        };
+        let new_food = match (macro_field(4)?, macro_field(5)?, macro_field(6)?) {
+            (None, None, None) => None,
+            (Some(protein), Some(fat), Some(carbs)) => {
+                let (macros, _) = macros_for_new_food(Some(protein), Some(fat), Some(carbs), None, None, false)
+                    .map_err(|e| invalid(e.to_string()))?;
+                let serving = field(7).unwrap_or(default_serving);
+                Some(Food::new(&food, macros.protein, macros.fat, macros.carbs, macros.calories, serving, vec![]))
+            }
+            _ => return Err(invalid("give all of protein, fat, and carbs to create a food".to_string())),
+        };
+
+        rows.push(PlanRow {
+            line: line_number,
+            date,
+            food,
+            amount: field(2).map(String::from),
+            meal,
+            new_food,
+        });
+    }
+    Ok(rows)
+}
+
+/// Log every row of a plan, creating foods the plan gives macros for. All or
+/// nothing: if any row fails, nothing is written. With `dry_run` the summary is
+/// worked out the same way and then rolled back.
+pub fn import_plan(db: &Database, config: &Config, rows: &[PlanRow], dry_run: bool) -> Result<PlanSummary> {
+    db.transaction(!dry_run, || {
+        let mut summary = PlanSummary::default();
+        for row in rows {
+            if let Some(food) = &row.new_food {
+                if db.get_food_by_name(&food.name)?.is_none() {
+                    db.add_food(food)?;
+                    summary.foods_created.push(food.name.clone());
+                }
+            }
+            // A bare number is a count, as in "3 egg", rather than a trailing amount
+            let input = match &row.amount {
+                Some(amount) if parse_number(amount).is_some() => format!("{} {}", amount, row.food),
+                Some(amount) => format!("{} {}", row.food, amount),
+                None => row.food.clone(),
+            };
+            let resolution = resolve(db, config, &input).map_err(|e| anyhow!("Line {}: {}", row.line, e))?;
+            let meta = LogMeta { date: row.date, time: row.meal.and_then(Meal::default_time), meal: row.meal };
+            let entry = db.log_food_at(&meta, resolution.food.id.unwrap(), &resolution.amount, &resolution.macros)?;
+            if let Some(note) = resolution.note {
+                summary.notes.push(format!("Line {}: {}", row.line, note));
+            }
+            summary.entries.push(entry);
+        }
+        Ok(summary)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn day(d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 6, d).unwrap()
+    }
+
+    #[test]
+    fn test_import_two_day_plan() {
+        let db = Database::open_in_memory().unwrap();
+        db.add_food(&Food::new("oats", 13.0, 7.0, 68.0, 389.0, "100g", vec![])).unwrap();
+        db.add_food(&Food::new("egg", 6.0, 5.0, 0.5, 72.0, "1 large", vec![])).unwrap();
+        let plan = "\
+date,food,amount,meal,protein,fat,carbs,serving
+2024-06-10,oats,80g,breakfast,,,,
+2024-06-10,chicken breast,200g,lunch,31,3.6,0,100g
+2024-06-11,egg,3,breakfast
+2024-06-11,chicken breast,150g,dinner,,,,
+";
+        let rows = parse_plan(plan, day(9), "100g").unwrap();
+        assert_eq!(rows.len(), 4);
+        assert_eq!(rows[2].date, day(11));
+        assert_eq!(rows[2].line, 4);
+        assert!(rows[1].new_food.is_some());
+
+        // A dry run reports everything but writes nothing
+        let preview = import_plan(&db, &Config::default(), &rows, true).unwrap();
+        assert_eq!(preview.entries.len(), 4);
+        assert_eq!(preview.foods_created, vec!["chicken breast"]);
+        assert!(db.get_food_by_name("chicken breast").unwrap().is_none());
+        assert!(db.get_log_for_date(day(10)).unwrap().is_empty());
+
+        let summary = import_plan(&db, &Config::default(), &rows, false).unwrap();
+        assert_eq!(summary.foods_created, vec!["chicken breast"]);
+        let first = db.get_log_for_date(day(10)).unwrap();
+        assert_eq!(first.len(), 2);
+        assert_eq!(first[1].meal, Some(Meal::Lunch));
+        assert!((first[1].protein - 62.0).abs() < 1e-9);
+        let second = db.get_log_for_date(day(11)).unwrap();
+        assert_eq!(second.len(), 2);
+        assert_eq!(second.iter().map(|e| e.amount.as_str()).collect::<Vec<_>>(), vec!["3 large", "150g"]);
+    }
+
+    #[test]
+    fn test_plan_errors() {
+        let db = Database::open_in_memory().unwrap();
+        db.add_food(&Food::new("oats", 13.0, 7.0, 68.0, 389.0, "100g", vec![])).unwrap();
+
+        // No header: columns in the usual order. Relative dates work too.
+        assert_eq!(parse_plan("yesterday,oats", day(10), "100g").unwrap()[0].date, day(9));
+        let rows = parse_plan("2024-06-10,oats,50g\n2024-06-10,mystery stew,200g", day(10), "100g").unwrap();
+        let err = import_plan(&db, &Config::default(), &rows, false).unwrap_err().to_string();
+        assert!(err.starts_with("Line 2: Food not found: 'mystery stew'"), "{}", err);
+        // The first row was rolled back with it
+        assert!(db.get_log_for_date(day(10)).unwrap().is_empty());
+
+        let err = parse_plan("2024-06-10,oats,50g,brunch", day(10), "100g").unwrap_err().to_string();
+        assert!(err.starts_with("Line 1:"), "{}", err);
+        let err = parse_plan("date,food\nsoon,oats", day(10), "100g").unwrap_err().to_string();
+        assert!(err.starts_with("Line 2:"), "{}", err);
+        let err = parse_plan("2024-06-10,stew,1 bowl,,20", day(10), "100g").unwrap_err().to_string();
+        assert!(err.contains("all of protein, fat, and carbs"), "{}", err);
+        assert!(parse_plan("date,food,kcal", day(10), "100g").is_err());
+    }
+
+    #[test]
+    fn test_plan_notes() {
+        let db = Database::open_in_memory().unwrap();
+        db.add_food(&Food::new("oats", 13.0, 7.0, 68.0, 389.0, "100g", vec![])).unwrap();
+        let rows = parse_plan("2024-06-10,oats,50g\n2024-06-10,oats,2 handfuls", day(10), "100g").unwrap();
+        let summary = import_plan(&db, &Config::default(), &rows, false).unwrap();
+        assert_eq!(summary.entries.len(), 2);
+        assert_eq!(summary.notes, vec!["Line 2: Unknown unit 'handfuls' in '2 handfuls', treated as grams"]);
+    }
+}
@@ -0,0 +1,152 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::config::Config;
+use crate::db::Database;
+use crate::food::{format_number, serving_grams, Food, Macros};
+use crate::logging::{portion_for_calories, resolve, Portion};
+
+/// A saved recipe: its ingredients as they'd be logged, making `servings` portions
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Recipe {
+    pub name: String,
+    pub servings: f64,
+    pub items: Vec<RecipeItem>,
+}
+
+/// One ingredient of a recipe, with its macros worked out when the recipe was saved
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecipeItem {
+    pub food_id: i64,
+    pub food_name: String,
+    pub amount: String,
+    #[serde(flatten)]
+    pub macros: Macros,
+}
+
+impl Recipe {
+    /// Resolve each ingredient the way logging would ("ground beef 500g", "2 onion")
+    pub fn build(db: &Database, config: &Config, name: &str, servings: f64, ingredients: &[String]) -> Result<Recipe> {
+        let name = name.trim();
+        anyhow::ensure!(!name.is_empty(), "Recipe name can't be empty");
+        anyhow::ensure!(servings.is_finite() && servings > 0.0, "Servings must be a positive number");
+        anyhow::ensure!(!ingredients.is_empty(), "A recipe needs at least one ingredient");
+        let items = ingredients
+            .iter()
+            .map(|input| {
+                let r = resolve(db, config, input).map_err(|e| anyhow!("Ingredient '{}': {}", input, e))?;
+                Ok(RecipeItem {
+                    food_id: r.food.id.ok_or_else(|| anyhow!("Food has no id"))?,
+                    food_name: r.food.name,
+                    amount: r.amount,
+                    macros: r.macros,
+                })
+            })
+            .collect::<Result<_>>()?;
+        Ok(Recipe { name: name.to_string(), servings, items })
+    }
+
+    /// Macros of one serving
+    pub fn per_serving(&self) -> Macros {
+        let mut total = Macros::default();
+        for item in &self.items {
+            total.add(&item.macros);
+        }
+        Macros {
+            protein: total.protein / self.servings,
+            fat: total.fat / self.servings,
+            carbs: total.carbs / self.servings,
+            calories: total.calories / self.servings,
+            fiber: total.fiber / self.servings,
+        }
+    }
+
+    /// Weight of one serving, when every ingredient is given by weight
+    pub fn serving_grams(&self) -> Option<f64> {
+        let total: Option<f64> = self.items.iter().map(|item| serving_grams(&item.amount)).sum();
+        total.map(|grams| grams / self.servings)
+    }
+
+    /// Servings of the recipe (and grams, when its weight is known) that come to `calories`
+    pub fn portion_for_calories(&self, calories: f64) -> Result<Portion> {
+        let serving = match self.serving_grams() {
+            Some(grams) => format!("{}g", format_number(grams)),
+            None => "1 serving".to_string(),
+        };
+        let m = self.per_serving();
+        let food = Food { fiber: m.fiber, ..Food::new(&self.name, m.protein, m.fat, m.carbs, m.calories, &serving, vec![]) };
+        portion_for_calories(&food, calories)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chili(db: &Database, servings: f64) -> Recipe {
+        let ingredients = ["ground beef 500g".to_string(), "kidney beans 400g".to_string()];
+        Recipe::build(db, &Config::default(), "chili", servings, &ingredients).unwrap()
+    }
+
+    fn kitchen() -> Database {
+        let db = Database::open_in_memory().unwrap();
+        db.add_food(&Food::new("ground beef", 26.0, 15.0, 0.0, 250.0, "100g", vec![])).unwrap();
+        db.add_food(&Food::new("kidney beans", 8.0, 0.5, 22.0, 125.0, "100g", vec![])).unwrap();
+        db.add_food(&Food::new("onion", 1.0, 0.0, 10.0, 44.0, "1 medium", vec![])).unwrap();
+        db
+    }
+
+    #[test]
+    fn test_scale_recipe_to_calories() {
+        let db = kitchen();
+        // 1250 + 500 kcal over 900g, in 4 servings of 225g and 437.5 kcal
+        let recipe = chili(&db, 4.0);
+        assert!((recipe.per_serving().calories - 437.5).abs() < 1e-9);
+        assert!((recipe.per_serving().protein - 40.5).abs() < 1e-9);
+        assert_eq!(recipe.serving_grams(), Some(225.0));
+
+        let portion = recipe.portion_for_calories(875.0).unwrap();
+        assert!((portion.servings - 2.0).abs() < 1e-9);
+        assert!((portion.grams.unwrap() - 450.0).abs() < 1e-9);
+        assert!((portion.macros.protein - 81.0).abs() < 1e-9);
+        assert!((portion.macros.calories - 875.0).abs() < 1e-9);
+
+        // A counted ingredient leaves the weight unknown: servings only
+        let ingredients = ["ground beef 500g".to_string(), "2 onion".to_string()];
+        let recipe = Recipe::build(&db, &Config::default(), "beef and onions", 2.0, &ingredients).unwrap();
+        assert_eq!(recipe.serving_grams(), None);
+        let portion = recipe.portion_for_calories(669.0).unwrap();
+        assert!((portion.servings - 1.0).abs() < 1e-9);
+        assert_eq!(portion.serving, "1 serving");
+        assert!(portion.grams.is_none());
+    }
+
+    #[test]
+    fn test_recipe_errors() {
+        let db = kitchen();
+        db.add_food(&Food::new("water", 0.0, 0.0, 0.0, 0.0, "250ml", vec![])).unwrap();
+        let broth = Recipe::build(&db, &Config::default(), "broth", 2.0, &["water 500ml".to_string()]).unwrap();
+        let err = broth.portion_for_calories(600.0).unwrap_err().to_string();
+        assert!(err.contains("no calories per serving"), "{}", err);
+
+        let err = Recipe::build(&db, &Config::default(), "stew", 2.0, &["mystery meat 200g".to_string()]).unwrap_err();
+        assert!(err.to_string().starts_with("Ingredient 'mystery meat 200g': Food not found"), "{}", err);
+        assert!(Recipe::build(&db, &Config::default(), "chili", 0.0, &["ground beef 500g".to_string()]).is_err());
+        assert!(Recipe::build(&db, &Config::default(), "chili", 1.0, &[]).is_err());
+        assert!(chili(&db, 4.0).portion_for_calories(0.0).is_err());
+    }
+
+    #[test]
+    fn test_save_recipe() {
+        let db = kitchen();
+        db.add_recipe(&chili(&db, 4.0)).unwrap();
+        let saved = db.get_recipe("Chili").unwrap().unwrap();
+        assert_eq!(saved.name, "chili");
+        assert_eq!(saved.servings, 4.0);
+        assert_eq!(saved.items.iter().map(|i| i.amount.as_str()).collect::<Vec<_>>(), vec!["500g", "400g"]);
+        assert!((saved.per_serving().calories - 437.5).abs() < 1e-9);
+
+        assert!(db.add_recipe(&chili(&db, 2.0)).unwrap_err().to_string().contains("already exists"));
+        assert!(db.get_recipe("gumbo").unwrap().is_none());
+    }
+}
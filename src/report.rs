@@ -0,0 +1,225 @@
+use anyhow::Result;
+use chrono::NaiveDate;
+use serde::Serialize;
+
+use crate::db::{Database, DayTotals, QUICK_ADD_FOOD};
+use crate::food::Macros;
+
+/// A look back over a range of days, as shown by `chomp report`
+#[derive(Debug, Clone, Serialize)]
+pub struct Report {
+    pub from: String,
+    pub to: String,
+    /// Days in the range with at least one entry
+    pub days_logged: usize,
+    /// Mean of the logged days' totals; days with nothing logged don't count
+    pub average: Macros,
+    /// Share of logged days with a protein goal on which it was met, as a percentage
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub protein_goal_hit: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub highest_calorie_day: Option<DayTotals>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lowest_calorie_day: Option<DayTotals>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub most_logged_food: Option<FoodCount>,
+}
+
+/// A food and how many times it was logged
+#[derive(Debug, Clone, Serialize)]
+pub struct FoodCount {
+    pub food: String,
+    pub times: usize,
+}
+
+/// Summarize the log for dates in `from..=to`
+pub fn build_report(db: &Database, from: NaiveDate, to: NaiveDate) -> Result<Report> {
+    let days = db.get_daily_totals(from, to)?;
+
+    let mut average = Macros::default();
+    for day in &days {
+        average.add(&day.totals);
+    }
+    if !days.is_empty() {
+        let n = days.len() as f64;
+        average = Macros {
+            protein: average.protein / n,
+            fat: average.fat / n,
+            carbs: average.carbs / n,
+            calories: average.calories / n,
+            fiber: average.fiber / n,
+        };
+    }
+
+    let mut goal_days = 0;
+    let mut hit_days = 0;
+    for day in &days {
+        let date = NaiveDate::parse_from_str(&day.date, "%Y-%m-%d")?;
+        if let Some(goal) = db.get_goals_for(date)?.and_then(|g| g.protein) {
+            goal_days += 1;
+            if day.totals.protein >= goal {
+                hit_days += 1;
+            }
+        }
+    }
+    let protein_goal_hit = (goal_days > 0).then(|| hit_days as f64 * 100.0 / goal_days as f64);
+
+    // Earliest day wins a tie, since the days come oldest first
+    let highest_calorie_day = days.iter()
+        .reduce(|best, day| if day.totals.calories > best.totals.calories { day } else { best })
+        .cloned();
+    let lowest_calorie_day = days.iter()
+        .reduce(|best, day| if day.totals.calories < best.totals.calories { day } else { best })
+        .cloned();
+
+    // Quick adds aren't a food anyone chose, so they don't compete
+    let mut counts: std::collections::BTreeMap<String, usize> = Default::default();
+    for entry in db.get_log_range(Some(from), Some(to), None, None)? {
+        if entry.food_name != QUICK_ADD_FOOD {
+            *counts.entry(entry.food_name).or_default() += 1;
+        }
+    }
+    // Ties go to the alphabetically first name
+    let most_logged_food = counts.into_iter()
+        .reduce(|best, item| if item.1 > best.1 { item } else { best })
+        .map(|(food, times)| FoodCount { food, times });
+
+    Ok(Report {
+        from: from.format("%Y-%m-%d").to_string(),
+        to: to.format("%Y-%m-%d").to_string(),
+        days_logged: days.len(),
+        average,
+        protein_goal_hit,
+        highest_calorie_day,
+        lowest_calorie_day,
+        most_logged_food,
+    })
+}
+
+/// A day's log as readable text, for sharing (`export --format text`): each entry,
+/// the day's totals, and how they compare with the day's goals if any are set
+pub fn format_day_summary(db: &Database, date: NaiveDate) -> Result<String> {
+    let entries = db.get_log_for_date(date)?;
+    let totals = db.get_totals_for_date(date, None)?;
+    let mut out = format!("Food log for {}\n\n", date.format("%a %Y-%m-%d"));
+
+    if entries.is_empty() {
+        out.push_str("Nothing logged.\n");
+    }
+    for entry in &entries {
+        let when: Vec<String> = [entry.time.clone(), entry.meal.map(|m| m.to_string())].into_iter().flatten().collect();
+        let when = if when.is_empty() { String::new() } else { format!("{}: ", when.join(" ")) };
+        out.push_str(&format!("- {}{} {} — {:.0}p/{:.0}f/{:.0}c, {:.0} kcal\n",
+            when, entry.amount, entry.food_name, entry.protein, entry.fat, entry.carbs, entry.calories));
+    }
+    out.push_str(&format!("\nTotal: {:.0}p / {:.0}f / {:.0}c — {:.0} kcal\n",
+        totals.protein, totals.fat, totals.carbs, totals.calories));
+
+    if let Some(goals) = db.get_goals_for(date)? {
+        let rows = [
+            ("Protein", totals.protein, goals.protein, "g"),
+            ("Fat", totals.fat, goals.fat, "g"),
+            ("Carbs", totals.carbs, goals.carbs, "g"),
+            ("Calories", totals.calories, goals.calories, " kcal"),
+        ];
+        let set: Vec<_> = rows.into_iter().filter_map(|(name, eaten, goal, unit)| Some((name, eaten, goal?, unit))).collect();
+        if !set.is_empty() {
+            out.push_str("\nVs goal:\n");
+        }
+        for (name, eaten, goal, unit) in set {
+            let diff = goal - eaten;
+            let status = format!("{:.0}{} {}", diff.abs(), unit, if diff >= 0.0 { "left" } else { "over" });
+            out.push_str(&format!("- {}: {:.0} / {:.0}{} ({})\n", name, eaten, goal, unit, status));
+        }
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::Goals;
+    use crate::food::Food;
+
+    fn date(s: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap()
+    }
+
+    #[test]
+    fn test_build_report_for_a_month() {
+        let db = Database::open_in_memory().unwrap();
+        db.add_food(&Food::new("chicken breast", 31.0, 3.6, 0.0, 165.0, "100g", vec![])).unwrap();
+        db.add_food(&Food::new("oats", 13.0, 7.0, 68.0, 389.0, "100g", vec![])).unwrap();
+        db.set_goals_from(&Goals { protein: Some(150.0), ..Goals::default() }, date("2024-06-01")).unwrap();
+        let chicken = db.get_food_by_name("chicken breast").unwrap().unwrap();
+        let oats = db.get_food_by_name("oats").unwrap().unwrap();
+
+        // June 2024: oats every day, and 500g of chicken (155g protein) every third day
+        let from = date("2024-06-01");
+        let to = date("2024-06-30");
+        for day in from.iter_days().take_while(|d| *d <= to) {
+            db.log_food_on(day, oats.id.unwrap(), "100g", &oats.scale(1.0)).unwrap();
+            if day.format("%d").to_string().parse::<u32>().unwrap() % 3 == 0 {
+                db.log_food_on(day, chicken.id.unwrap(), "500g", &chicken.scale(5.0)).unwrap();
+            }
+        }
+        // Outside the range: ignored
+        db.log_food_on(date("2024-07-01"), chicken.id.unwrap(), "1000g", &chicken.scale(10.0)).unwrap();
+
+        let report = build_report(&db, from, to).unwrap();
+        assert_eq!(report.days_logged, 30);
+        // 10 chicken days out of 30
+        assert!((report.average.calories - (389.0 + 825.0 / 3.0)).abs() < 1e-9);
+        assert!((report.average.protein - (13.0 + 155.0 / 3.0)).abs() < 1e-9);
+        assert!((report.protein_goal_hit.unwrap() - 100.0 / 3.0).abs() < 1e-9);
+        let highest = report.highest_calorie_day.unwrap();
+        assert_eq!(highest.date, "2024-06-03");
+        assert!((highest.totals.calories - 1214.0).abs() < 1e-9);
+        assert_eq!(report.lowest_calorie_day.unwrap().date, "2024-06-01");
+        let most = report.most_logged_food.unwrap();
+        assert_eq!(most.food, "oats");
+        assert_eq!(most.times, 30);
+    }
+
+    #[test]
+    fn test_format_day_summary() {
+        use crate::db::{LogMeta, Meal};
+        let db = Database::open_in_memory().unwrap();
+        db.add_food(&Food::new("egg", 6.0, 5.0, 0.6, 72.0, "1 large", vec![])).unwrap();
+        db.add_food(&Food::new("oats", 13.0, 7.0, 68.0, 389.0, "100g", vec![])).unwrap();
+        let day = date("2024-06-01");
+        let breakfast = LogMeta { date: day, time: chrono::NaiveTime::from_hms_opt(8, 15, 0), meal: Some(Meal::Breakfast) };
+        crate::logging::parse_and_log_at(&db, &Default::default(), "2 egg", &breakfast).unwrap();
+        crate::logging::parse_and_log_at(&db, &Default::default(), "oats 50g", &LogMeta::on(day)).unwrap();
+
+        let summary = format_day_summary(&db, day).unwrap();
+        assert_eq!(summary, "\
+Food log for Sat 2024-06-01
+
+- 50g oats — 6p/4f/34c, 194 kcal
+- 08:15 breakfast: 2 large egg — 12p/10f/1c, 144 kcal
+
+Total: 18p / 14f / 35c — 338 kcal
+");
+
+        // Only the goals that are set are compared
+        db.set_goals_from(&Goals { protein: Some(150.0), calories: Some(300.0), ..Goals::default() }, day).unwrap();
+        let summary = format_day_summary(&db, day).unwrap();
+        assert!(summary.ends_with("\nVs goal:\n- Protein: 18 / 150g (132g left)\n- Calories: 338 / 300 kcal (38 kcal over)\n"),
+            "{}", summary);
+
+        let empty = format_day_summary(&db, date("2024-06-02")).unwrap();
+        assert!(empty.starts_with("Food log for Sun 2024-06-02\n\nNothing logged.\n\nTotal: 0p / 0f / 0c — 0 kcal\n"));
+    }
+
+    #[test]
+    fn test_build_report_empty_range() {
+        let db = Database::open_in_memory().unwrap();
+        let report = build_report(&db, date("2024-06-01"), date("2024-06-30")).unwrap();
+        assert_eq!(report.days_logged, 0);
+        assert_eq!(report.average.calories, 0.0);
+        assert!(report.protein_goal_hit.is_none());
+        assert!(report.highest_calorie_day.is_none());
+        assert!(report.most_logged_food.is_none());
+    }
+}
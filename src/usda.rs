@@ -0,0 +1,346 @@
+use anyhow::Result;
+use serde::de::{DeserializeSeed, Deserializer, MapAccess, SeqAccess, Visitor};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Read};
+use std::path::Path;
+
+use crate::food::{atwater_calories, Food, FoodSource};
+
+// FDC nutrient ids
+const PROTEIN: i64 = 1003;
+const FAT: i64 = 1004;
+const CARBS_BY_DIFFERENCE: i64 = 1005;
+const ENERGY_KCAL: i64 = 1008;
+const CARBS_BY_SUMMATION: i64 = 1050;
+const ENERGY_ATWATER_GENERAL: i64 = 2047;
+const ENERGY_ATWATER_SPECIFIC: i64 = 2048;
+
+/// Foods in a downloaded USDA FoodData Central dataset at `path`, optionally only
+/// those whose description contains every word of `query`. A `.json` file is read
+/// as FDC JSON (Foundation, SR Legacy, Survey, or Branded); anything else as the
+/// CSV bundle, given either its directory or its `food.csv`. FDC amounts are per
+/// 100g, so every food comes out with a 100g serving.
+pub fn read_foods(path: &Path, query: Option<&str>) -> Result<Vec<Food>> {
+    let open = |path: &Path| {
+        std::fs::File::open(path)
+            .map(BufReader::new)
+            .map_err(|e| anyhow::anyhow!("Can't read {}: {}", path.display(), e))
+    };
+    if path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("json")) {
+        return read_json(open(path)?, query);
+    }
+    let dir = if path.is_dir() { path } else { path.parent().unwrap_or(Path::new(".")) };
+    read_csv(open(&dir.join("food.csv"))?, open(&dir.join("food_nutrient.csv"))?, query)
+}
+
+#[derive(Deserialize)]
+struct FdcFood {
+    description: String,
+    #[serde(default, rename = "foodNutrients")]
+    food_nutrients: Vec<FdcFoodNutrient>,
+}
+
+#[derive(Deserialize)]
+struct FdcFoodNutrient {
+    nutrient: Option<FdcNutrient>,
+    amount: Option<f64>,
+}
+
+#[derive(Deserialize)]
+struct FdcNutrient {
+    id: i64,
+}
+
+/// Foods from FDC JSON: an object holding one array of foods, e.g. `{"FoundationFoods": [...]}`.
+/// The file is read a food at a time and only matches are kept, so a Branded
+/// dataset of several gigabytes never has to fit in memory.
+pub fn read_json(reader: impl Read, query: Option<&str>) -> Result<Vec<Food>> {
+    let mut deserializer = serde_json::Deserializer::from_reader(reader);
+    let foods = deserializer.deserialize_map(Dataset { query })
+        .and_then(|foods| deserializer.end().map(|_| foods))
+        .map_err(|e| anyhow::anyhow!("Not a FoodData Central JSON file: {}", e))?;
+    Ok(foods)
+}
+
+/// Visits the top-level object, collecting the matching foods of every array in it
+struct Dataset<'a> {
+    query: Option<&'a str>,
+}
+
+impl<'de> Visitor<'de> for Dataset<'_> {
+    type Value = Vec<Food>;
+
+    fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str("an object of food arrays")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Vec<Food>, A::Error> {
+        let mut foods = Vec::new();
+        while map.next_key::<serde::de::IgnoredAny>()?.is_some() {
+            map.next_value_seed(FoodArray { query: self.query, foods: &mut foods })?;
+        }
+        Ok(foods)
+    }
+}
+
+/// One array of FDC foods, each converted (or dropped) as soon as it's read
+struct FoodArray<'a> {
+    query: Option<&'a str>,
+    foods: &'a mut Vec<Food>,
+}
+
+impl<'de> DeserializeSeed<'de> for FoodArray<'_> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de> Visitor<'de> for FoodArray<'_> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str("an array of foods")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+        while let Some(food) = seq.next_element::<FdcFood>()? {
+            if matches_query(&food.description, self.query) {
+                self.foods.extend(food.into_food());
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FdcFood {
+    fn into_food(self) -> Option<Food> {
+        let mut nutrients = Nutrients::default();
+        for n in &self.food_nutrients {
+            if let (Some(nutrient), Some(amount)) = (&n.nutrient, n.amount) {
+                nutrients.set(nutrient.id, amount);
+            }
+        }
+        nutrients.food(&self.description)
+    }
+}
+
+/// Foods from the CSV bundle's `food.csv` and `food_nutrient.csv`
+pub fn read_csv(foods: impl BufRead, food_nutrients: impl BufRead, query: Option<&str>) -> Result<Vec<Food>> {
+    let mut wanted: HashMap<String, (String, Nutrients)> = HashMap::new();
+    let mut order = Vec::new();
+    for_each_row(foods, "food.csv", &["fdc_id", "description"], |row| {
+        if matches_query(&row[1], query) {
+            order.push(row[0].clone());
+            wanted.insert(row[0].clone(), (row[1].clone(), Nutrients::default()));
+        }
+    })?;
+    for_each_row(food_nutrients, "food_nutrient.csv", &["fdc_id", "nutrient_id", "amount"], |row| {
+        if let (Some((_, nutrients)), Ok(id), Ok(amount)) =
+            (wanted.get_mut(&row[0]), row[1].parse(), row[2].parse())
+        {
+            nutrients.set(id, amount);
+        }
+    })?;
+    Ok(order.iter()
+        .filter_map(|id| wanted.remove(id))
+        .filter_map(|(description, nutrients)| nutrients.food(&description))
+        .collect())
+}
+
+/// Call `f` with the `columns` of each data row, located by the header row
+fn for_each_row(reader: impl BufRead, file: &str, columns: &[&str], mut f: impl FnMut(Vec<String>)) -> Result<()> {
+    let mut lines = reader.lines();
+    let header = parse_csv_line(&lines.next().transpose()?.unwrap_or_default());
+    let indexes = columns.iter()
+        .map(|c| header.iter().position(|h| h == c)
+            .ok_or_else(|| anyhow::anyhow!("{} has no '{}' column", file, c)))
+        .collect::<Result<Vec<_>>>()?;
+    for line in lines {
+        let fields = parse_csv_line(&line?);
+        if let Some(row) = indexes.iter().map(|i| fields.get(*i).cloned()).collect::<Option<Vec<_>>>() {
+            f(row);
+        }
+    }
+    Ok(())
+}
+
+/// Split one CSV line into fields, unquoting `"..."` fields (with `""` for a quote).
+/// FDC fields don't contain newlines, so a record is always one line.
+pub fn parse_csv_line(line: &str) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.trim_end_matches('\r').chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(std::mem::take(&mut field)),
+            c => field.push(c),
+        }
+    }
+    fields.push(field);
+    fields
+}
+
+/// Whether `description` contains every word of `query` (case-insensitive)
+fn matches_query(description: &str, query: Option<&str>) -> bool {
+    let description = description.to_lowercase();
+    query.is_none_or(|q| q.to_lowercase().split_whitespace().all(|word| description.contains(word)))
+}
+
+/// The nutrients chomp keeps, per 100g, as found in the dataset
+#[derive(Default)]
+struct Nutrients {
+    protein: Option<f64>,
+    fat: Option<f64>,
+    carbs: Option<f64>,
+    carbs_by_summation: Option<f64>,
+    energy: Option<f64>,
+    energy_specific: Option<f64>,
+    energy_general: Option<f64>,
+}
+
+impl Nutrients {
+    fn set(&mut self, nutrient_id: i64, amount: f64) {
+        let slot = match nutrient_id {
+            PROTEIN => &mut self.protein,
+            FAT => &mut self.fat,
+            CARBS_BY_DIFFERENCE => &mut self.carbs,
+            CARBS_BY_SUMMATION => &mut self.carbs_by_summation,
+            ENERGY_KCAL => &mut self.energy,
+            ENERGY_ATWATER_SPECIFIC => &mut self.energy_specific,
+            ENERGY_ATWATER_GENERAL => &mut self.energy_general,
+            _ => return,
+        };
+        *slot = Some(amount);
+    }
+
+    /// A food with these nutrients, or None if the dataset gave none of them.
+    /// Missing macros count as zero; missing energy is estimated from the macros.
+    fn food(&self, description: &str) -> Option<Food> {
+        let carbs = self.carbs.or(self.carbs_by_summation);
+        let energy = self.energy.or(self.energy_specific).or(self.energy_general);
+        if self.protein.is_none() && self.fat.is_none() && carbs.is_none() && energy.is_none() {
+            return None;
+        }
+        let (protein, fat, carbs) = (self.protein.unwrap_or(0.0), self.fat.unwrap_or(0.0), carbs.unwrap_or(0.0));
+        let calories = energy.unwrap_or_else(|| atwater_calories(protein, fat, carbs));
+        let mut food = Food::new(description.trim(), protein, fat, carbs, calories, "100g", vec![]);
+        food.source = FoodSource::Usda;
+        Some(food)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_JSON: &str = r#"{"FoundationFoods": [
+        {"fdcId": 1, "description": "Egg, whole, raw, fresh", "foodNutrients": [
+            {"nutrient": {"id": 1003, "number": "203", "name": "Protein", "unitName": "g"}, "amount": 12.4},
+            {"nutrient": {"id": 1004, "number": "204", "name": "Total lipid (fat)", "unitName": "g"}, "amount": 8.65},
+            {"nutrient": {"id": 1050, "number": "205.2", "name": "Carbohydrate, by summation", "unitName": "g"}, "amount": 0.96},
+            {"nutrient": {"id": 2047, "number": "957", "name": "Energy (Atwater General Factors)", "unitName": "kcal"}, "amount": 136},
+            {"nutrient": {"id": 1093, "number": "307", "name": "Sodium, Na", "unitName": "mg"}, "amount": 129}
+        ]},
+        {"fdcId": 2, "description": "Chicken, breast, boneless, skinless, raw", "foodNutrients": [
+            {"nutrient": {"id": 1003}, "amount": 22.5},
+            {"nutrient": {"id": 1004}, "amount": 1.93},
+            {"nutrient": {"id": 1005}, "amount": 0}
+        ]},
+        {"fdcId": 3, "description": "Salt, table, iodized", "foodNutrients": [
+            {"nutrient": {"id": 1093}, "amount": 38758}
+        ]}
+    ]}"#;
+
+    const SAMPLE_FOOD_CSV: &str = "\
+\"fdc_id\",\"data_type\",\"description\",\"food_category_id\",\"publication_date\"
+\"167512\",\"sr_legacy_food\",\"Pillsbury Golden Layer Buttermilk Biscuits, Artificial Flavor, refrigerated dough\",\"18\",\"2019-04-01\"
+\"171077\",\"sr_legacy_food\",\"Chicken, broilers or fryers, breast, meat only, raw\",\"5\",\"2019-04-01\"
+\"173424\",\"sr_legacy_food\",\"Egg, whole, raw, fresh\",\"1\",\"2019-04-01\"
+";
+
+    const SAMPLE_FOOD_NUTRIENT_CSV: &str = "\
+\"id\",\"fdc_id\",\"nutrient_id\",\"amount\",\"data_points\"
+\"1\",\"167512\",\"1003\",\"5.88\",\"\"
+\"2\",\"167512\",\"1004\",\"13.24\",\"\"
+\"3\",\"167512\",\"1005\",\"41.18\",\"\"
+\"4\",\"167512\",\"1008\",\"307\",\"\"
+\"5\",\"171077\",\"1003\",\"22.5\",\"\"
+\"6\",\"171077\",\"1004\",\"2.62\",\"\"
+\"7\",\"171077\",\"1005\",\"0\",\"\"
+\"8\",\"171077\",\"1008\",\"120\",\"\"
+\"9\",\"173424\",\"1003\",\"12.56\",\"\"
+";
+
+    #[test]
+    fn test_read_json() {
+        let foods = read_json(SAMPLE_JSON.as_bytes(), None).unwrap();
+        // Salt has none of the nutrients chomp keeps
+        assert_eq!(foods.len(), 2);
+
+        let egg = &foods[0];
+        assert_eq!(egg.name, "Egg, whole, raw, fresh");
+        assert_eq!((egg.protein, egg.fat, egg.carbs, egg.calories), (12.4, 8.65, 0.96, 136.0));
+        assert_eq!(egg.serving, "100g");
+        assert_eq!(egg.source, FoodSource::Usda);
+
+        // No energy given: estimated from the macros
+        let chicken = &foods[1];
+        assert!((chicken.calories - atwater_calories(22.5, 1.93, 0.0)).abs() < 1e-9);
+
+        let foods = read_json(SAMPLE_JSON.as_bytes(), Some("CHICKEN raw")).unwrap();
+        assert_eq!(foods.len(), 1);
+        assert!(read_json("[1, 2]".as_bytes(), None).is_err());
+        assert!(read_json(format!("{} {{}}", SAMPLE_JSON).as_bytes(), None).is_err());
+    }
+
+    #[test]
+    fn test_read_json_streams() {
+        // Every array in the object counts, and matches come out in file order
+        let json = r#"{
+            "SurveyFoods": [{"description": "Oatmeal, cooked", "foodNutrients": [{"nutrient": {"id": 1003}, "amount": 2.5}]}],
+            "FoundationFoods": [
+                {"description": "Oats, rolled", "foodNutrients": [{"nutrient": {"id": 1003}, "amount": 13.2}]},
+                {"description": "Rice, white", "foodNutrients": [{"nutrient": {"id": 1003}, "amount": 2.7}]}
+            ]
+        }"#;
+        let names: Vec<String> = read_json(json.as_bytes(), None).unwrap().into_iter().map(|f| f.name).collect();
+        assert_eq!(names, ["Oatmeal, cooked", "Oats, rolled", "Rice, white"]);
+        let names: Vec<String> = read_json(json.as_bytes(), Some("oat")).unwrap().into_iter().map(|f| f.name).collect();
+        assert_eq!(names, ["Oatmeal, cooked", "Oats, rolled"]);
+    }
+
+    #[test]
+    fn test_read_csv() {
+        let foods = read_csv(SAMPLE_FOOD_CSV.as_bytes(), SAMPLE_FOOD_NUTRIENT_CSV.as_bytes(), None).unwrap();
+        let names: Vec<&str> = foods.iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(names, vec![
+            "Pillsbury Golden Layer Buttermilk Biscuits, Artificial Flavor, refrigerated dough",
+            "Chicken, broilers or fryers, breast, meat only, raw",
+            "Egg, whole, raw, fresh",
+        ]);
+        assert_eq!((foods[1].protein, foods[1].fat, foods[1].calories), (22.5, 2.62, 120.0));
+        assert_eq!((foods[2].protein, foods[2].fat), (12.56, 0.0));
+
+        let foods = read_csv(SAMPLE_FOOD_CSV.as_bytes(), SAMPLE_FOOD_NUTRIENT_CSV.as_bytes(), Some("breast")).unwrap();
+        assert_eq!(foods.len(), 1);
+        assert_eq!(foods[0].calories, 120.0);
+
+        let err = read_csv("id,name\n".as_bytes(), SAMPLE_FOOD_NUTRIENT_CSV.as_bytes(), None).unwrap_err();
+        assert_eq!(err.to_string(), "food.csv has no 'fdc_id' column");
+    }
+
+    #[test]
+    fn test_parse_csv_line() {
+        assert_eq!(parse_csv_line(r#""1","Beans, ""baked""",,x"#), vec!["1", r#"Beans, "baked""#, "", "x"]);
+        assert_eq!(parse_csv_line("a,b\r"), vec!["a", "b"]);
+    }
+}